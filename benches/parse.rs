@@ -0,0 +1,99 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+#[cfg(feature = "simd-json")]
+use criterion::BatchSize;
+use ecs_metadata::{ECSContainerStats, ECSMetadata, ECSPartialMetadata, ECSTaskMetadata, FieldSet};
+
+// The largest documents as served by an agent
+const TASK_FIXTURE: &str = include_str!("../testdata/tasks/cni_pause.json");
+const STATS_FIXTURE: &str = include_str!("../testdata/stats/cgroup_v1.json");
+
+const CONTAINER_JSON: &str = r#"
+{
+    "DockerId": "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0",
+    "Image": "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production",
+    "Labels": {
+        "com.amazonaws.ecs.cluster": "production",
+        "com.amazonaws.ecs.container-name": "streamer",
+        "com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0",
+        "com.amazonaws.ecs.task-definition-family": "streamer",
+        "com.amazonaws.ecs.task-definition-version": "12"
+    },
+    "Limits": {"CPU": 2, "Memory": 4096}
+}"#;
+
+fn task_json() -> String {
+    let containers = [CONTAINER_JSON; 3].join(",");
+    format!(r#"{{"AvailabilityZone": "us-east-1b", "Limits": {{"CPU": 4, "Memory": 8192}}, "Containers": [{containers}]}}"#)
+}
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("parse/container_document", |b| {
+        b.iter(|| ECSMetadata::from_json(black_box(CONTAINER_JSON)).unwrap())
+    });
+
+    c.bench_function("parse/container_document_limits_only", |b| {
+        b.iter(|| ECSPartialMetadata::from_json(black_box(CONTAINER_JSON), FieldSet::LIMITS).unwrap())
+    });
+
+    let task = task_json();
+    c.bench_function("parse/task_document", |b| {
+        b.iter(|| serde_json::from_str::<ECSTaskMetadata>(black_box(&task)).unwrap())
+    });
+
+    c.bench_function("parse/task_fixture", |b| {
+        b.iter(|| serde_json::from_str::<ECSTaskMetadata>(black_box(TASK_FIXTURE)).unwrap())
+    });
+
+    c.bench_function("parse/stats_fixture", |b| {
+        b.iter(|| serde_json::from_str::<ECSContainerStats>(black_box(STATS_FIXTURE)).unwrap())
+    });
+}
+
+fn refresh(c: &mut Criterion) {
+    // identical bytes take the fast path and never reach the deserializer
+    let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+    c.bench_function("refresh/unchanged", |b| {
+        b.iter(|| metadata.refresh_from_json(black_box(CONTAINER_JSON.as_bytes()), None).unwrap())
+    });
+
+    // alternating bodies force a full parse plus diff on every iteration
+    let deployed = CONTAINER_JSON.replace("latest-production", "v2-production");
+    let bodies = [CONTAINER_JSON.as_bytes(), deployed.as_bytes()];
+    let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+    let mut next = 0;
+    c.bench_function("refresh/changed", |b| {
+        b.iter(|| {
+            next = (next + 1) % bodies.len();
+            metadata.refresh_from_json(black_box(bodies[next]), None).unwrap()
+        })
+    });
+}
+
+// serde_json against simd-json, which the `simd-json` feature uses for the stats documents, as
+// `fetch_stats` parses them and typed
+#[cfg(feature = "simd-json")]
+fn backends(c: &mut Criterion) {
+    let mut served = c.benchmark_group("backend/stats_fixture");
+    served.bench_function("serde_json", |b| {
+        b.iter(|| serde_json::from_slice::<serde_json::Value>(black_box(STATS_FIXTURE.as_bytes())).unwrap())
+    });
+    served.bench_function("simd_json", |b| {
+        b.iter_batched(|| STATS_FIXTURE.as_bytes().to_vec(), |mut body| simd_json::serde::from_slice::<serde_json::Value>(&mut body).unwrap(), BatchSize::SmallInput)
+    });
+    served.finish();
+
+    let mut typed = c.benchmark_group("backend/stats_fixture_typed");
+    typed.bench_function("serde_json", |b| {
+        b.iter(|| serde_json::from_slice::<ECSContainerStats>(black_box(STATS_FIXTURE.as_bytes())).unwrap())
+    });
+    typed.bench_function("simd_json", |b| {
+        b.iter_batched(|| STATS_FIXTURE.as_bytes().to_vec(), |mut body| simd_json::serde::from_slice::<ECSContainerStats>(&mut body).unwrap(), BatchSize::SmallInput)
+    });
+    typed.finish();
+}
+
+#[cfg(not(feature = "simd-json"))]
+criterion_group!(benches, parse, refresh);
+#[cfg(feature = "simd-json")]
+criterion_group!(benches, parse, refresh, backends);
+criterion_main!(benches);
@@ -0,0 +1,262 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+
+/// Task ARN split into its components, `arn:<partition>:ecs:<region>:<account>:task/<cluster>/<task-id>`
+/// or, in the old format without a cluster segment, `…:task/<task-id>`.
+/// `Display` gives the ARN back as parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TaskArn {
+    partition: String,
+    region: String,
+    account_id: String,
+    cluster: Option<String>,
+    task_id: String,
+}
+
+impl TaskArn {
+    /// `aws`, or e.g. `aws-cn` and `aws-us-gov`
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// Short cluster name, `None` in the old ARN format
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.cluster.as_deref()
+    }
+
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+}
+
+impl FromStr for TaskArn {
+    type Err = ECSMetadataError;
+
+    /// Fails with `InvalidArn` for anything but an ECS task ARN with a region, a 12-digit
+    /// account ID and non-empty path segments
+    fn from_str(arn: &str) -> Result<Self, Self::Err> {
+        let parts = ArnParts::parse(arn, "task")?;
+        let (cluster, task_id) = match parts.segments[..] {
+            [task_id] => (None, task_id),
+            [cluster, task_id] => (Some(cluster.to_string()), task_id),
+            _ => return Err(invalid_arn(arn, "expected task/<cluster>/<task-id> or task/<task-id>")),
+        };
+        Ok(Self {
+            partition: parts.partition.to_string(),
+            region: parts.region.to_string(),
+            account_id: parts.account_id.to_string(),
+            cluster,
+            task_id: task_id.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for TaskArn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arn:{}:ecs:{}:{}:task/", self.partition, self.region, self.account_id)?;
+        if let Some(cluster) = &self.cluster {
+            write!(f, "{cluster}/")?;
+        }
+        f.write_str(&self.task_id)
+    }
+}
+
+/// Container ARN split into its components, `arn:<partition>:ecs:<region>:<account>:container/<cluster>/<task-id>/<container-id>`
+/// or, in the old format, `…:container/<container-id>`.
+/// `Display` gives the ARN back as parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContainerArn {
+    partition: String,
+    region: String,
+    account_id: String,
+    // cluster and task ID, only in the new ARN format
+    task: Option<(String, String)>,
+    container_id: String,
+}
+
+impl ContainerArn {
+    /// `aws`, or e.g. `aws-cn` and `aws-us-gov`
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// Short cluster name, `None` in the old ARN format
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.task.as_ref().map(|(cluster, _)| cluster.as_str())
+    }
+
+    /// ID of the container's task, `None` in the old ARN format
+    pub fn task_id(&self) -> Option<&str> {
+        self.task.as_ref().map(|(_, task_id)| task_id.as_str())
+    }
+
+    pub fn container_id(&self) -> &str {
+        &self.container_id
+    }
+}
+
+impl FromStr for ContainerArn {
+    type Err = ECSMetadataError;
+
+    /// Fails with `InvalidArn` for anything but an ECS container ARN with a region, a 12-digit
+    /// account ID and non-empty path segments
+    fn from_str(arn: &str) -> Result<Self, Self::Err> {
+        let parts = ArnParts::parse(arn, "container")?;
+        let (task, container_id) = match parts.segments[..] {
+            [container_id] => (None, container_id),
+            [cluster, task_id, container_id] => (Some((cluster.to_string(), task_id.to_string())), container_id),
+            _ => return Err(invalid_arn(arn, "expected container/<cluster>/<task-id>/<container-id> or container/<container-id>")),
+        };
+        Ok(Self {
+            partition: parts.partition.to_string(),
+            region: parts.region.to_string(),
+            account_id: parts.account_id.to_string(),
+            task,
+            container_id: container_id.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ContainerArn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arn:{}:ecs:{}:{}:container/", self.partition, self.region, self.account_id)?;
+        if let Some((cluster, task_id)) = &self.task {
+            write!(f, "{cluster}/{task_id}/")?;
+        }
+        f.write_str(&self.container_id)
+    }
+}
+
+// components shared by the ECS resource ARNs, the resource path split on `/`
+struct ArnParts<'a> {
+    partition: &'a str,
+    region: &'a str,
+    account_id: &'a str,
+    segments: Vec<&'a str>,
+}
+
+impl<'a> ArnParts<'a> {
+    fn parse(arn: &'a str, resource_type: &str) -> Result<Self, ECSMetadataError> {
+        let [prefix, partition, service, region, account_id, resource] = arn.splitn(6, ':').collect::<Vec<_>>()[..] else {
+            return Err(invalid_arn(arn, "expected arn:<partition>:ecs:<region>:<account>:<resource>"));
+        };
+        if prefix != "arn" || partition.is_empty() {
+            return Err(invalid_arn(arn, "expected arn:<partition>:ecs:<region>:<account>:<resource>"));
+        }
+        if service != "ecs" {
+            return Err(invalid_arn(arn, &format!("service {service:?} is not ecs")));
+        }
+        if region.is_empty() {
+            return Err(invalid_arn(arn, "region is empty"));
+        }
+        if account_id.len() != 12 || !account_id.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid_arn(arn, &format!("account {account_id:?} is not a 12-digit ID")));
+        }
+        let mut segments = resource.split('/');
+        if segments.next() != Some(resource_type) {
+            return Err(invalid_arn(arn, &format!("resource is not a {resource_type}")));
+        }
+        let segments: Vec<_> = segments.collect();
+        if segments.is_empty() || segments.iter().any(|segment| segment.is_empty()) {
+            return Err(invalid_arn(arn, &format!("empty segment in {resource_type} path")));
+        }
+        Ok(Self { partition, region, account_id, segments })
+    }
+}
+
+fn invalid_arn(arn: &str, reason: &str) -> ECSMetadataError {
+    ECSMetadataError::InvalidArn { arn: arn.to_string(), reason: reason.to_string() }
+}
+
+impl ECSMetadata {
+    /// Task ARN of the labels, parsed, `None` when it is missing or malformed; parse
+    /// `task_arn()` for the reason
+    pub fn parsed_task_arn(&self) -> Option<TaskArn> {
+        self.task_arn().parse().ok()
+    }
+
+    /// Container ARN, parsed, `None` when the document has none (the v2 endpoint) or it is
+    /// malformed
+    pub fn parsed_container_arn(&self) -> Option<ContainerArn> {
+        self.container_arn()?.parse().ok()
+    }
+
+    /// AWS account ID, taken from the task ARN
+    pub fn account_id(&self) -> Option<&str> {
+        self.task_arn().split(':').nth(4).filter(|account| !account.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_task_arn() {
+        let new: TaskArn = "arn:aws:ecs:us-west-2:111122223333:task/default/cd189a933e5849daa93386466019ab50".parse().unwrap();
+        assert_eq!(
+            (new.partition(), new.region(), new.account_id(), new.cluster_name(), new.task_id()),
+            ("aws", "us-west-2", "111122223333", Some("default"), "cd189a933e5849daa93386466019ab50")
+        );
+        let old: TaskArn = "arn:aws-cn:ecs:cn-north-1:111122223333:task/13a0d2a5-02e8-4bc1-bc8a-cd0e4c4bbb1e".parse().unwrap();
+        assert_eq!((old.partition(), old.cluster_name(), old.task_id()), ("aws-cn", None, "13a0d2a5-02e8-4bc1-bc8a-cd0e4c4bbb1e"));
+        for arn in [&new, &old] {
+            assert_eq!(arn.to_string().parse::<TaskArn>().unwrap(), *arn);
+        }
+        assert_eq!(old.to_string(), "arn:aws-cn:ecs:cn-north-1:111122223333:task/13a0d2a5-02e8-4bc1-bc8a-cd0e4c4bbb1e");
+
+        for (arn, reason) in [
+            ("", "expected arn:"),
+            ("arn:aws:s3:us-west-2:111122223333:task/abc", "service \"s3\""),
+            ("arn:aws:ecs::111122223333:task/abc", "region is empty"),
+            ("arn:aws:ecs:us-west-2:1111:task/abc", "12-digit"),
+            ("arn:aws:ecs:us-west-2:111122223333:container/abc", "not a task"),
+            ("arn:aws:ecs:us-west-2:111122223333:task/default/", "empty segment"),
+            ("arn:aws:ecs:us-west-2:111122223333:task/a/b/c", "expected task/"),
+        ] {
+            let err = arn.parse::<TaskArn>().unwrap_err();
+            assert!(matches!(&err, ECSMetadataError::InvalidArn { arn: given, reason: got } if given == arn && got.contains(reason)), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_container_arn() {
+        let new: ContainerArn = "arn:aws:ecs:us-west-2:111122223333:container/default/cd189a933e5849daa93386466019ab50/05966557-f16c-49cb-9352-24b3a0dcd0e1".parse().unwrap();
+        assert_eq!(
+            (new.cluster_name(), new.task_id(), new.container_id()),
+            (Some("default"), Some("cd189a933e5849daa93386466019ab50"), "05966557-f16c-49cb-9352-24b3a0dcd0e1")
+        );
+        assert_eq!(new.to_string().parse::<ContainerArn>().unwrap(), new);
+        assert!(matches!("arn:aws:ecs:us-west-2:111122223333:container/default/abc".parse::<ContainerArn>(), Err(ECSMetadataError::InvalidArn { .. })));
+
+        let metadata = metadata_from_json(include_str!("../testdata/versions/v4_container.json"), None);
+        let container = metadata.parsed_container_arn().unwrap();
+        assert_eq!((container.region(), container.task_id(), container.container_id()), ("us-west-2", None, "05966557-f16c-49cb-9352-24b3a0dcd0e1"));
+        assert_eq!(metadata.parsed_task_arn().unwrap().cluster_name(), Some("default"));
+        assert_eq!(metadata.account_id(), Some("111122223333"));
+
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.parsed_task_arn().unwrap().account_id(), "939885537497");
+        assert_eq!(metadata.parsed_container_arn(), None);
+        assert_eq!(ECSMetadata::degraded(None).parsed_task_arn(), None);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::container::ECSContainerLimits;
+use crate::metadata::ECSMetadata;
+
+/// Selects which components end up in the startup banner. The container name, task definition
+/// revision and cluster (when known) are always included, everything else is optional.
+#[derive(Debug, Clone)]
+pub struct BannerOptions {
+    /// CPU & memory limits, e.g. `2 vCPU / 4096 MiB`
+    pub limits: bool,
+    /// Container image
+    pub image: bool,
+    /// Availability zone, omitted when the task document was not fetched
+    pub availability_zone: bool,
+    /// AWS region derived from the task ARN
+    pub region: bool,
+    /// Truncate the task ID to `ECSMetadata::SHORT_TASK_ID_LEN` characters, see `task_id_short`
+    pub short_task_id: bool,
+}
+
+impl Default for BannerOptions {
+    fn default() -> Self {
+        Self {
+            limits: true,
+            image: false,
+            availability_zone: false,
+            region: false,
+            short_task_id: true,
+        }
+    }
+}
+
+impl ECSMetadata {
+    /// Single line describing this container, meant to be logged at startup, e.g.
+    /// `starting streamer rev 12 on cluster production (task 02144797, 2 vCPU / 4096 MiB)`.
+    /// Optional components that are selected but unknown are left out.
+    pub fn startup_banner(&self, options: &BannerOptions) -> String {
+        let mut banner = format!("starting {} rev {}", self.container_name(), self.task_definition_revision());
+        if let Some(cluster) = self.cluster_name() {
+            banner.push_str(&format!(" on cluster {cluster}"));
+        }
+
+        let mut details = Vec::new();
+        let task_id = if options.short_task_id {
+            self.task_id_short(ECSMetadata::SHORT_TASK_ID_LEN)
+        } else {
+            self.task_id()
+        };
+        if let Some(task_id) = task_id {
+            details.push(format!("task {task_id}"));
+        }
+        if options.limits {
+            details.push(format_limits(self.limits()));
+        }
+        if options.image {
+            details.push(format!("image {}", self.image()));
+        }
+        if options.region {
+            if let Some(region) = self.region() {
+                details.push(format!("region {region}"));
+            }
+        }
+        if options.availability_zone {
+            if let Some(az) = self.availability_zone() {
+                details.push(format!("az {az}"));
+            }
+        }
+
+        if !details.is_empty() {
+            banner.push_str(&format!(" ({})", details.join(", ")));
+        }
+        banner
+    }
+}
+
+/// An absent or zero limit means no limit was set at the container level
+fn format_limits(limits: &ECSContainerLimits) -> String {
+    let cpu = match limits.vcpus() {
+        Some(cpu) => format!("{} vCPU", format_vcpus(cpu)),
+        None => "unlimited vCPU".to_string(),
+    };
+    let mem = match limits.memory_mib() {
+        Some(mem) => format!("{mem} MiB"),
+        None => "unlimited memory".to_string(),
+    };
+    format!("{cpu} / {mem}")
+}
+
+/// Fractional vCPUs are printed with up to two decimals and no trailing zeros (0.25, 0.5, 2)
+fn format_vcpus(vcpus: f64) -> String {
+    let formatted = format!("{vcpus:.2}");
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_default_banner() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(
+            metadata.startup_banner(&BannerOptions::default()),
+            "starting streamer rev 12 on cluster production (task 02144797, 2 vCPU / 4096 MiB)"
+        );
+    }
+
+    #[test]
+    fn test_banner_all_components() {
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(r#"{"AvailabilityZone": "us-east-1b"}"#));
+        let options = BannerOptions {
+            limits: true,
+            image: true,
+            availability_zone: true,
+            region: true,
+            short_task_id: false,
+        };
+        assert_eq!(
+            metadata.startup_banner(&options),
+            "starting streamer rev 12 on cluster production (task 021447970bce4bd58069f1925cd87bc0, \
+             2 vCPU / 4096 MiB, image 939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production, \
+             region us-east-1, az us-east-1b)"
+        );
+    }
+
+    #[test]
+    fn test_banner_omits_unknown_components() {
+        // no task document, so the AZ is unknown and must not show up
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let options = BannerOptions {
+            limits: false,
+            availability_zone: true,
+            ..BannerOptions::default()
+        };
+        assert_eq!(
+            metadata.startup_banner(&options),
+            "starting streamer rev 12 on cluster production (task 02144797)"
+        );
+    }
+
+    #[test]
+    fn test_format_limits() {
+        assert_eq!(format_limits(&ECSContainerLimits::new(Some(2.0), Some(4096))), "2 vCPU / 4096 MiB");
+        assert_eq!(format_limits(&ECSContainerLimits::new(Some(0.0), Some(0))), "unlimited vCPU / unlimited memory");
+        assert_eq!(format_limits(&ECSContainerLimits::new(Some(0.25), None)), "0.25 vCPU / unlimited memory");
+        assert_eq!(format_vcpus(0.25), "0.25");
+        assert_eq!(format_vcpus(0.5), "0.5");
+        assert_eq!(format_vcpus(4.0), "4");
+    }
+}
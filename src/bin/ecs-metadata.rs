@@ -0,0 +1,149 @@
+//! Prints the metadata of the container it runs in, for entrypoint scripts:
+//!
+//! ```text
+//! ecs-metadata              # container and task documents, pretty JSON
+//! ecs-metadata env          # KEY=VALUE lines, e.g. eval "$(ecs-metadata env)"
+//! ecs-metadata task-id      # a single field, see `ecs-metadata --help`
+//! ```
+
+use std::process::ExitCode;
+use ecs_metadata::ECSMetadata;
+
+const USAGE: &str = "Usage: ecs-metadata [json | env | <field>]
+
+  json     the container and task metadata documents as pretty JSON (default)
+  env      KEY=VALUE lines, shell-quoted for eval, of the fields that are known
+  <field>  the value of a single field, exiting with 1 when it is not known
+
+Fields:";
+
+type Field = (&'static str, &'static str, fn(&ECSMetadata) -> Option<String>);
+
+// name on the command line, key of the env lines, value
+const FIELDS: &[Field] = &[
+    ("cluster", "ECS_CLUSTER", |metadata| metadata.cluster_name().map(str::to_string)),
+    ("task-arn", "ECS_TASK_ARN", |metadata| Some(metadata.task_arn().to_string())),
+    ("task-id", "ECS_TASK_ID", ECSMetadata::task_id),
+    ("family", "ECS_TASK_FAMILY", |metadata| Some(metadata.task_definition_family().to_string())),
+    ("revision", "ECS_TASK_REVISION", |metadata| Some(metadata.task_definition_revision().to_string())),
+    ("container-name", "ECS_CONTAINER_NAME", |metadata| Some(metadata.container_name().to_string())),
+    ("docker-id", "ECS_DOCKER_ID", |metadata| Some(metadata.docker_id().to_string())),
+    ("image", "ECS_IMAGE", |metadata| Some(metadata.image().to_string())),
+    ("image-tag", "ECS_IMAGE_TAG", |metadata| metadata.image_tag().map(str::to_string)),
+    ("region", "ECS_REGION", |metadata| metadata.region().map(str::to_string)),
+    ("account-id", "ECS_ACCOUNT_ID", |metadata| metadata.account_id().map(str::to_string)),
+    ("availability-zone", "ECS_AVAILABILITY_ZONE", |metadata| metadata.availability_zone().map(str::to_string)),
+    ("launch-type", "ECS_LAUNCH_TYPE", |metadata| metadata.launch_type().map(|launch_type| launch_type.to_string())),
+    ("cpu", "ECS_CPU_VCPUS", |metadata| metadata.effective_cpu_limit_vcpus().map(|vcpus| vcpus.to_string())),
+    ("memory-mib", "ECS_MEMORY_MIB", |metadata| metadata.effective_memory_limit_mib().map(|mib| mib.to_string())),
+    ("ipv4-address", "ECS_IPV4_ADDRESS", |metadata| metadata.ipv4_addresses().next().map(str::to_string)),
+];
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Json,
+    Env,
+    Field(&'static Field),
+    Help,
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Json),
+        [arg] => match arg.as_str() {
+            "json" => Ok(Command::Json),
+            "env" => Ok(Command::Env),
+            "-h" | "--help" | "help" => Ok(Command::Help),
+            name => FIELDS.iter().find(|(field, ..)| *field == name).map(Command::Field).ok_or_else(|| format!("unknown field {name}")),
+        },
+        _ => Err("expected a single argument".to_string()),
+    }
+}
+
+fn value(metadata: &ECSMetadata, field: &Field) -> Option<String> {
+    (field.2)(metadata).filter(|value| !value.is_empty())
+}
+
+fn env_lines(metadata: &ECSMetadata) -> String {
+    FIELDS
+        .iter()
+        .filter_map(|field| Some(format!("{}={}\n", field.1, shell_quote(&value(metadata, field)?))))
+        .collect()
+}
+
+// Single quotes keep everything literal, a quote itself closes, escapes and reopens them
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn usage() -> String {
+    let fields = FIELDS.iter().map(|(name, key, _)| format!("  {name:<18} {key}")).collect::<Vec<_>>();
+    format!("{USAGE}\n{}\n", fields.join("\n"))
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let command = match parse_args(&args) {
+        Ok(Command::Help) => {
+            print!("{}", usage());
+            return ExitCode::SUCCESS;
+        }
+        Ok(command) => command,
+        Err(err) => {
+            eprint!("ecs-metadata: {err}\n\n{}", usage());
+            return ExitCode::from(2);
+        }
+    };
+    let metadata = match ECSMetadata::builder().init_with_task_blocking() {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            eprintln!("ecs-metadata: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match command {
+        Command::Json => println!("{:#}", metadata.to_json()),
+        Command::Env => print!("{}", env_lines(&metadata)),
+        Command::Field(field) => match value(&metadata, field) {
+            Some(value) => println!("{value}"),
+            None => {
+                eprintln!("ecs-metadata: {} is not known", field.0);
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::Help => unreachable!("handled before the fetch"),
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&args(&[])), Ok(Command::Json));
+        assert_eq!(parse_args(&args(&["env"])), Ok(Command::Env));
+        assert!(matches!(parse_args(&args(&["task-id"])), Ok(Command::Field(("task-id", "ECS_TASK_ID", _)))));
+        assert_eq!(parse_args(&args(&["task_id"])), Err("unknown field task_id".to_string()));
+        assert!(parse_args(&args(&["env", "json"])).is_err());
+    }
+
+    #[test]
+    fn test_env_lines() {
+        let container = r#"{"DockerId": "abc", "Image": "nginx:1.27", "Labels": {
+            "com.amazonaws.ecs.cluster": "production",
+            "com.amazonaws.ecs.container-name": "it's",
+            "com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:111122223333:task/production/0123456789abcdef"}}"#;
+        let lines = env_lines(&ECSMetadata::from_json(container).unwrap());
+        assert!(lines.starts_with("ECS_CLUSTER='production'\nECS_TASK_ARN='arn:aws:ecs:us-east-1:111122223333:task/production/0123456789abcdef'\n"), "{lines}");
+        assert!(lines.contains("ECS_CONTAINER_NAME='it'\\''s'\n"), "{lines}");
+        assert!(lines.contains("ECS_IMAGE_TAG='1.27'\n"), "{lines}");
+        // unknown without the task document, empty without the task definition labels
+        assert!(!lines.contains("ECS_AVAILABILITY_ZONE") && !lines.contains("ECS_TASK_FAMILY"), "{lines}");
+    }
+}
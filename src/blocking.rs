@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use crate::client::{ECSMetadataBuilder, RequestPolicy};
+use crate::container::ECSContainerMetadata;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+use crate::partial::{ECSPartialMetadata, FieldSet};
+use crate::refresh::RefreshOutcome;
+use crate::stats::ECSContainerStats;
+
+/// Drives `future` to completion on the crate's runtime. Panics when called from within an
+/// async context, use the async call there.
+fn block_on<F: Future>(future: F) -> F::Output {
+    crate::runtime::crate_runtime().block_on(future)
+}
+
+impl ECSMetadataBuilder {
+    /// `init` for synchronous callers, e.g. a CLI without a tokio runtime. Like every blocking
+    /// call, it runs on a runtime of the crate, started on first use with a single worker
+    /// thread, and panics when called from within an async context.
+    pub fn init_blocking(self) -> Result<ECSMetadata, ECSMetadataError> {
+        block_on(self.init())
+    }
+
+    /// `init_with_task`, blocking
+    pub fn init_with_task_blocking(self) -> Result<ECSMetadata, ECSMetadataError> {
+        block_on(self.init_with_task())
+    }
+
+    /// `init_partial`, blocking
+    pub fn init_partial_blocking(self, fields: FieldSet) -> Result<ECSPartialMetadata, ECSMetadataError> {
+        block_on(self.init_partial(fields))
+    }
+
+    /// `fetch_container`, blocking
+    pub fn fetch_container_blocking(self, docker_id: &str, policy: Option<&RequestPolicy>) -> Result<ECSContainerMetadata, ECSMetadataError> {
+        block_on(self.fetch_container(docker_id, policy))
+    }
+
+    /// `fetch_stats`, blocking
+    pub fn fetch_stats_blocking(&self, policy: Option<&RequestPolicy>) -> Result<serde_json::Value, ECSMetadataError> {
+        block_on(self.fetch_stats(policy))
+    }
+
+    /// `fetch_task_stats`, blocking
+    pub fn fetch_task_stats_blocking(&self, policy: Option<&RequestPolicy>) -> Result<serde_json::Value, ECSMetadataError> {
+        block_on(self.fetch_task_stats(policy))
+    }
+}
+
+impl ECSMetadata {
+    /// `init`, blocking, see `ECSMetadataBuilder::init_blocking`
+    pub fn init_blocking() -> Result<Self, ECSMetadataError> {
+        Self::builder().init_blocking()
+    }
+
+    /// `init_with_task`, blocking
+    pub fn init_with_task_blocking() -> Result<Self, ECSMetadataError> {
+        Self::builder().init_with_task_blocking()
+    }
+
+    /// `refresh`, blocking
+    pub fn refresh_blocking(&mut self) -> Result<RefreshOutcome, ECSMetadataError> {
+        block_on(self.refresh())
+    }
+
+    /// `container_stats`, blocking
+    pub fn container_stats_blocking(&self) -> Result<ECSContainerStats, ECSMetadataError> {
+        block_on(self.container_stats())
+    }
+
+    /// `task_stats`, blocking
+    pub fn task_stats_blocking(&self) -> Result<BTreeMap<String, ECSContainerStats>, ECSMetadataError> {
+        block_on(self.task_stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::time::Duration;
+
+    #[test]
+    fn test_blocking_calls() {
+        // the agent needs a runtime of its own, the calls run outside of it
+        let agent_runtime = tokio::runtime::Runtime::new().unwrap();
+        let agent = agent_runtime.block_on(MockAgent::start());
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])));
+        agent.set("/v4/abc/stats", MockResponse::json(r#"{"read": "2024-01-01T00:00:00Z"}"#));
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).min_refresh_interval(Duration::ZERO);
+
+        let mut metadata = builder.clone().init_with_task_blocking().unwrap();
+        assert_eq!(metadata.container_name(), "streamer");
+        assert!(metadata.task().is_some());
+        assert_eq!(metadata.refresh_blocking().unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(metadata.container_stats_blocking().unwrap().read(), "2024-01-01T00:00:00Z");
+        assert_eq!(builder.fetch_stats_blocking(None).unwrap()["read"], "2024-01-01T00:00:00Z");
+        // the pooled client outlives each call
+        assert_eq!(metadata.client_stats().unwrap().pooled_requests, 6);
+
+        agent.set("/v4/abc", MockResponse::status(404, "gone"));
+        assert!(matches!(builder.init_blocking(), Err(ECSMetadataError::HttpStatus { status: 404, .. })));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "runtime")]
+    async fn test_refused_within_async_context() {
+        let _ = ECSMetadata::builder().endpoint("http://127.0.0.1:1/v4/abc").init_blocking();
+    }
+}
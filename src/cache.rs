@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use crate::client::ECSMetadataBuilder;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+
+/// Format version written by `to_cache_bytes`. Readers accept every version up to their own.
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// Envelope of a cached snapshot. The documents are kept as served, as JSON strings, so that a
+/// restored instance parses them with the model of the reading version and skips the parse
+/// of an unchanged body on its first refresh.
+#[derive(Serialize, Deserialize)]
+struct CacheEnvelope {
+    format: u32,
+    // `None` for a degraded instance
+    container: Option<String>,
+    #[serde(default)]
+    task: Option<String>,
+    #[serde(default)]
+    v3: bool,
+}
+
+// Just the version, readable whatever the rest of a newer envelope looks like
+#[derive(Deserialize)]
+struct CacheFormat {
+    format: u32,
+}
+
+impl ECSMetadata {
+    /// Snapshot for a cache outside the process, e.g. a file on a volume shared by the
+    /// containers of a task, read back with `from_cache_bytes`. A small JSON envelope with
+    /// `CACHE_FORMAT_VERSION`, holding the documents as served.
+    pub fn to_cache_bytes(&self) -> Vec<u8> {
+        let body = |raw: &[u8]| String::from_utf8_lossy(raw).into_owned();
+        let envelope = CacheEnvelope {
+            format: CACHE_FORMAT_VERSION,
+            container: (!self.is_degraded()).then(|| body(&self.raw.container)),
+            task: self.raw.task.as_deref().map(body),
+            v3: self.raw.v3,
+        };
+        serde_json::to_vec(&envelope).expect("the envelope always serializes")
+    }
+
+    /// Instance restored from `to_cache_bytes`, not refreshable, see
+    /// `ECSMetadataBuilder::restore` for one that is.
+    ///
+    /// Snapshots written by any earlier version of this crate are read: the documents are
+    /// parsed again like `from_json`, leniently, so fields a newer version models fall back as
+    /// they would for an older agent. A newer format fails with `CacheFormatMismatch`, an
+    /// envelope that doesn't parse with `CorruptCache`.
+    pub fn from_cache_bytes(bytes: &[u8]) -> Result<Self, ECSMetadataError> {
+        restore(bytes, None)
+    }
+}
+
+impl ECSMetadataBuilder {
+    /// `ECSMetadata::from_cache_bytes`, refreshing from the endpoint of this builder, e.g. to
+    /// start from the snapshot of a previous run of the container and refresh in the background
+    pub fn restore(self, bytes: &[u8]) -> Result<ECSMetadata, ECSMetadataError> {
+        restore(bytes, Some(self))
+    }
+}
+
+fn restore(bytes: &[u8], source: Option<ECSMetadataBuilder>) -> Result<ECSMetadata, ECSMetadataError> {
+    let corrupt = |err: serde_json::Error| ECSMetadataError::CorruptCache(err.to_string());
+    let CacheFormat { format } = serde_json::from_slice(bytes).map_err(corrupt)?;
+    if format > CACHE_FORMAT_VERSION {
+        return Err(ECSMetadataError::CacheFormatMismatch { found: format, supported: CACHE_FORMAT_VERSION });
+    }
+    let envelope: CacheEnvelope = serde_json::from_slice(bytes).map_err(corrupt)?;
+    let Some(container) = envelope.container else {
+        return Ok(ECSMetadata::degraded(source));
+    };
+    let task = envelope.task.map(String::into_bytes);
+    ECSMetadata::from_versioned_documents(container.into_bytes(), task, envelope.v3, source)
+        .map_err(|err| ECSMetadataError::CorruptCache(format!("cached documents don't parse: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::refresh::RefreshOutcome;
+    use crate::task::tests::task_json;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::time::Duration;
+
+    // Written by format 1, must stay readable by every later version
+    const FORMAT_1: &[u8] = include_bytes!("../testdata/cache/format_1.json");
+
+    #[test]
+    fn test_cache_round_trip() {
+        let task = task_json(&[CONTAINER_JSON.to_string()]);
+        let metadata = ECSMetadata::from_documents(CONTAINER_JSON.into(), Some(task.into_bytes()), None).unwrap();
+        let restored = ECSMetadata::from_cache_bytes(&metadata.to_cache_bytes()).unwrap();
+        assert_eq!(restored, metadata);
+        assert_eq!(restored.raw, metadata.raw);
+
+        let degraded = ECSMetadata::from_cache_bytes(&ECSMetadata::degraded(None).to_cache_bytes()).unwrap();
+        assert!(degraded.is_degraded());
+    }
+
+    #[test]
+    fn test_reads_earlier_formats() {
+        let restored = ECSMetadata::from_cache_bytes(FORMAT_1).unwrap();
+        assert_eq!(restored.container_name(), "streamer");
+        assert_eq!(restored.availability_zone(), Some("us-east-1b"));
+        assert!(restored.warnings().is_empty());
+
+        // keys added by a later writer of the same format are ignored
+        let mut envelope: serde_json::Value = serde_json::from_slice(FORMAT_1).unwrap();
+        envelope["written_at"] = "2024-10-01T12:00:00Z".into();
+        let restored = ECSMetadata::from_cache_bytes(&serde_json::to_vec(&envelope).unwrap()).unwrap();
+        assert_eq!(restored.container_name(), "streamer");
+    }
+
+    #[test]
+    fn test_cache_errors() {
+        let newer = br#"{"format": 2, "documents": []}"#;
+        assert!(matches!(
+            ECSMetadata::from_cache_bytes(newer),
+            Err(ECSMetadataError::CacheFormatMismatch { found: 2, supported: CACHE_FORMAT_VERSION })
+        ));
+
+        for corrupt in [&FORMAT_1[..FORMAT_1.len() / 2], b"", br#"{"container": "{}"}"#, br#"{"format": 1, "container": "{\"DockerId\": 1}"}"#] {
+            let err = ECSMetadata::from_cache_bytes(corrupt).unwrap_err();
+            assert!(matches!(err, ECSMetadataError::CorruptCache(_)), "{err:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restored_instance_refreshes() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let cached = ECSMetadata::from_json(CONTAINER_JSON).unwrap().to_cache_bytes();
+        assert!(matches!(ECSMetadata::from_cache_bytes(&cached).unwrap().refresh().await, Err(ECSMetadataError::NotRefreshable)));
+
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).min_refresh_interval(Duration::ZERO);
+        let mut restored = builder.restore(&cached).unwrap();
+        assert_eq!(restored.refresh().await.unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(agent.hits("/v4/abc"), 1);
+    }
+}
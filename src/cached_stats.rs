@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::time::Instant;
+use crate::client::ECSMetadataBuilder;
+use crate::error::ECSMetadataError;
+use crate::stats::ECSContainerStats;
+
+/// Max stale of `CachedStats::init`
+pub const DEFAULT_STATS_MAX_STALE: Duration = Duration::from_secs(60);
+
+/// Stats of this container for hot paths, e.g. an admission-control check per request, which
+/// must never wait for the agent. `get` returns the cached sample right away; once it is older
+/// than the TTL, it also starts a fetch in the background, one at a time, whose sample serves
+/// the calls after it.
+///
+/// A failed fetch keeps the stale sample, retried a TTL later at the earliest. Past the TTL
+/// plus the max stale, `get` fails with `StatsUnavailable` until a fetch succeeds again.
+#[derive(Debug, Clone)]
+pub struct CachedStats {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    builder: ECSMetadataBuilder,
+    ttl: Duration,
+    max_stale: Duration,
+    // the revalidations run there, so that `get` doesn't need to be called from a runtime
+    runtime: Handle,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    stats: Arc<ECSContainerStats>,
+    updated: Instant,
+    revalidating: bool,
+    next_attempt: Instant,
+    last_error: Option<ECSMetadataError>,
+}
+
+impl CachedStats {
+    /// Fetches the first sample with `builder`, whose stats policy bounds every fetch, with the
+    /// default max stale. Needs a tokio runtime without the `runtime-agnostic` feature.
+    pub async fn init(builder: ECSMetadataBuilder, ttl: Duration) -> Result<Self, ECSMetadataError> {
+        Self::init_with_max_stale(builder, ttl, DEFAULT_STATS_MAX_STALE).await
+    }
+
+    /// `init`, serving a sample for at most `max_stale` past the TTL while the fetches fail
+    pub async fn init_with_max_stale(builder: ECSMetadataBuilder, ttl: Duration, max_stale: Duration) -> Result<Self, ECSMetadataError> {
+        let stats = fetch(&builder).await?;
+        let now = Instant::now();
+        let state = State { stats: Arc::new(stats), updated: now, revalidating: false, next_attempt: now, last_error: None };
+        Ok(Self {
+            inner: Arc::new(Inner { builder, ttl, max_stale, runtime: crate::runtime::handle(), state: Mutex::new(state) }),
+        })
+    }
+
+    /// The cached sample, without waiting; starts a revalidation when it is stale
+    pub fn get(&self) -> Result<Arc<ECSContainerStats>, ECSMetadataError> {
+        let mut state = self.inner.state();
+        let now = Instant::now();
+        let age = now.duration_since(state.updated);
+        if age >= self.inner.ttl && !state.revalidating && now >= state.next_attempt {
+            state.revalidating = true;
+            self.inner.runtime.spawn(revalidate(self.inner.clone()));
+        }
+        if age >= self.inner.ttl + self.inner.max_stale {
+            return Err(ECSMetadataError::StatsUnavailable { age, source: state.last_error.clone().map(Box::new) });
+        }
+        Ok(state.stats.clone())
+    }
+
+    /// When the cached sample was fetched
+    pub fn last_updated(&self) -> Instant {
+        self.inner.state().updated
+    }
+
+    /// Whether the cached sample is older than the TTL
+    pub fn is_stale(&self) -> bool {
+        self.inner.state().updated.elapsed() >= self.inner.ttl
+    }
+}
+
+impl Inner {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+async fn fetch(builder: &ECSMetadataBuilder) -> Result<ECSContainerStats, ECSMetadataError> {
+    Ok(serde_json::from_value(builder.fetch_stats(None).await?)?)
+}
+
+async fn revalidate(inner: Arc<Inner>) {
+    let fetched = fetch(&inner.builder).await;
+    let mut state = inner.state();
+    state.revalidating = false;
+    match fetched {
+        Ok(stats) => {
+            state.stats = Arc::new(stats);
+            state.updated = Instant::now();
+            state.last_error = None;
+        }
+        Err(err) => {
+            state.next_attempt = Instant::now() + inner.ttl;
+            state.last_error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RequestPolicy;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    const TTL: Duration = Duration::from_secs(2);
+    const STATS_PATH: &str = "/v4/abc/stats";
+
+    fn sample(read: &str) -> MockResponse {
+        MockResponse::json(format!(r#"{{"read": "{read}", "memory_stats": {{"usage": 1048576}}}}"#))
+    }
+
+    async fn cached(agent: &MockAgent, max_stale: Duration) -> CachedStats {
+        agent.set(STATS_PATH, sample("first"));
+        // with the time paused, a timeout would fire while waiting on the socket
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).stats_policy(RequestPolicy::new(None, 0));
+        CachedStats::init_with_max_stale(builder, TTL, max_stale).await.unwrap()
+    }
+
+    async fn settle(stats: &CachedStats) {
+        while stats.inner.state().revalidating {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl() {
+        let agent = MockAgent::start().await;
+        let stats = cached(&agent, DEFAULT_STATS_MAX_STALE).await;
+        agent.set(STATS_PATH, sample("second"));
+        let first_updated = stats.last_updated();
+
+        tokio::time::advance(TTL - Duration::from_millis(1)).await;
+        assert!(!stats.is_stale());
+        assert_eq!(stats.get().unwrap().read(), "first");
+        settle(&stats).await;
+        assert_eq!(agent.hits(STATS_PATH), 1);
+
+        // the stale sample is returned, the next calls get the revalidated one
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(stats.is_stale());
+        assert_eq!(stats.get().unwrap().read(), "first");
+        settle(&stats).await;
+        assert_eq!(agent.hits(STATS_PATH), 2);
+        assert_eq!(stats.get().unwrap().read(), "second");
+        assert!(!stats.is_stale());
+        assert!(stats.last_updated() >= first_updated + TTL);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight() {
+        let agent = MockAgent::start().await;
+        let stats = cached(&agent, DEFAULT_STATS_MAX_STALE).await;
+        tokio::time::advance(TTL).await;
+
+        let handles = (0..4).map(|_| stats.clone()).collect::<Vec<_>>();
+        for _ in 0..50 {
+            for handle in &handles {
+                assert_eq!(handle.get().unwrap().memory_usage_bytes(), Some(1048576));
+            }
+        }
+        settle(&stats).await;
+        assert_eq!(agent.hits(STATS_PATH), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_stale() {
+        let agent = MockAgent::start().await;
+        let max_stale = Duration::from_secs(10);
+        let stats = cached(&agent, max_stale).await;
+        agent.set(STATS_PATH, MockResponse::status(500, "agent restarting"));
+
+        tokio::time::advance(TTL).await;
+        stats.get().unwrap();
+        settle(&stats).await;
+        assert_eq!(agent.hits(STATS_PATH), 2);
+        // the failure extends the stale sample, and holds off the next attempt for a TTL
+        for _ in 0..10 {
+            assert_eq!(stats.get().unwrap().read(), "first");
+        }
+        settle(&stats).await;
+        assert_eq!(agent.hits(STATS_PATH), 2);
+
+        tokio::time::advance(max_stale).await;
+        let err = stats.get().unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::StatsUnavailable { age, source: Some(_) } if *age == TTL + max_stale), "{err:?}");
+        settle(&stats).await;
+
+        agent.set(STATS_PATH, sample("recovered"));
+        tokio::time::advance(TTL).await;
+        assert!(stats.get().is_err());
+        settle(&stats).await;
+        assert_eq!(stats.get().unwrap().read(), "recovered");
+    }
+}
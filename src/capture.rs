@@ -0,0 +1,189 @@
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use url::Url;
+use crate::container::ECSContainerMetadata;
+use crate::error::{ECSMetadataError, Phase};
+use crate::version::RawDocument;
+use crate::warning::{ParseWarning, ParseWarningKind};
+
+/// Bytes of the body kept by a `RawCapture`
+pub const MAX_CAPTURE_LEN: usize = 64 * 1024;
+
+/// Response whose body failed to parse, handed to `ECSMetadataBuilder::on_parse_failure`.
+/// Serializes to a single JSON object, e.g. to dump it to a file for a postmortem.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RawCapture {
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The first `MAX_CAPTURE_LEN` bytes of the body, as lossy UTF-8
+    pub body: String,
+    /// Length of the whole body, longer than `body` when it was cut
+    pub body_len: usize,
+    pub body_truncated: bool,
+    /// The parse error, as displayed
+    pub error: String,
+}
+
+/// Callback of `ECSMetadataBuilder::on_parse_failure`
+#[derive(Clone)]
+pub(crate) struct ParseFailureHook(pub(crate) Arc<dyn Fn(&RawCapture) + Send + Sync>);
+
+impl fmt::Debug for ParseFailureHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParseFailureHook")
+    }
+}
+
+/// Where a document came from and how it was served, for its capture
+#[derive(Debug, Clone)]
+pub(crate) struct ResponseMeta {
+    url: Url,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl ResponseMeta {
+    pub(crate) fn new(url: Url, status: u16, headers: &HeaderMap) -> Self {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+        Self { url, status, headers }
+    }
+}
+
+impl ParseFailureHook {
+    /// Runs the hook when `err` is a parse failure of `body`. A panicking hook doesn't unwind
+    /// into the caller, its panic is returned as a warning instead.
+    pub(crate) fn capture(&self, meta: &ResponseMeta, body: &[u8], err: &ECSMetadataError) -> Option<ParseWarning> {
+        if err.phase() != Phase::Parse {
+            return None;
+        }
+        let kept = &body[..body.len().min(MAX_CAPTURE_LEN)];
+        let capture = RawCapture {
+            url: meta.url.to_string(),
+            status: meta.status,
+            headers: meta.headers.clone(),
+            body: String::from_utf8_lossy(kept).into_owned(),
+            body_len: body.len(),
+            body_truncated: kept.len() < body.len(),
+            error: err.to_string(),
+        };
+        let hook = &self.0;
+        catch_unwind(AssertUnwindSafe(|| hook(&capture))).err().map(|_| {
+            ParseWarning::new(ParseWarningKind::CaptureHookPanicked, format!("on_parse_failure hook panicked capturing {}", capture.url)).emit()
+        })
+    }
+
+    /// `capture` of whichever of the v4 documents failed to parse with `err`
+    pub(crate) fn capture_documents(
+        &self,
+        container: (&ResponseMeta, &[u8]),
+        task: Option<(&ResponseMeta, &[u8])>,
+        v3: bool,
+        err: &ECSMetadataError,
+    ) -> Option<ParseWarning> {
+        // only parsed again on this failure path, the container document is parsed first
+        let container_fails = || {
+            RawDocument::new(container.1, v3).parse::<ECSContainerMetadata>().and_then(|metadata| metadata.labels.require_task_arn()).is_err()
+        };
+        match task {
+            Some((meta, body)) if !container_fails() => self.capture(meta, body, err),
+            _ => self.capture(container.0, container.1, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ECSMetadataBuilder;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::metadata::ECSMetadata;
+    use crate::task::tests::task_json;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn capturing(agent: &MockAgent) -> (ECSMetadataBuilder, Arc<Mutex<Vec<RawCapture>>>) {
+        let captures = Arc::new(Mutex::new(Vec::new()));
+        let sink = captures.clone();
+        let builder = ECSMetadataBuilder::new()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .on_parse_failure(move |capture| sink.lock().unwrap().push(capture.clone()));
+        (builder, captures)
+    }
+
+    #[tokio::test]
+    async fn test_captures_only_parse_failures() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON).with_header("X-Agent", "1.86"));
+        agent.set("/v4/abc/task", MockResponse::json(r#"{"Containers": 7}"#));
+        let (builder, captures) = capturing(&agent);
+
+        builder.clone().init().await.unwrap();
+        assert!(captures.lock().unwrap().is_empty());
+        let err = builder.clone().init_with_task().await.unwrap_err();
+        let capture = captures.lock().unwrap().pop().unwrap();
+        assert_eq!(capture.url, agent.url("/v4/abc/task"));
+        assert_eq!((capture.status, capture.body.as_str(), capture.body_len, capture.body_truncated), (200, r#"{"Containers": 7}"#, 17, false));
+        assert!(capture.headers.contains(&("content-type".to_string(), "application/json".to_string())), "{:?}", capture.headers);
+        assert_eq!(capture.error, err.to_string());
+
+        // a failed fetch has no body to capture
+        agent.set("/v4/abc", MockResponse::status(500, "agent restarting"));
+        builder.clone().init().await.unwrap_err();
+        assert!(captures.lock().unwrap().is_empty());
+
+        let huge = format!("<html>{}</html>", "x".repeat(MAX_CAPTURE_LEN));
+        agent.set("/v4/abc", MockResponse::status(200, huge.as_str()).with_header("X-Agent", "1.86"));
+        builder.init().await.unwrap_err();
+        let capture = captures.lock().unwrap().pop().unwrap();
+        assert_eq!((capture.body.len(), capture.body_len, capture.body_truncated), (MAX_CAPTURE_LEN, huge.len(), true));
+        assert!(capture.headers.contains(&("x-agent".to_string(), "1.86".to_string())));
+        assert!(serde_json::to_value(&capture).unwrap()["body"].as_str().unwrap().starts_with("<html>"));
+    }
+
+    #[tokio::test]
+    async fn test_panicking_hook() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadataBuilder::new()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .on_parse_failure(|_| panic!("bucket unreachable"));
+        let mut metadata = builder.clone().init().await.unwrap();
+
+        // the init still fails with the parse error, the refresh keeps the snapshot and warns
+        agent.set("/v4/abc", MockResponse::json("[]"));
+        assert!(matches!(builder.init().await, Err(ECSMetadataError::Deserialize { .. })));
+        assert!(matches!(metadata.refresh().await, Err(ECSMetadataError::Deserialize { .. })));
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(metadata.warnings().last().map(|warning| warning.kind), Some(ParseWarningKind::CaptureHookPanicked));
+    }
+
+    #[test]
+    fn test_blames_the_failing_document() {
+        let hook_captures = Arc::new(Mutex::new(Vec::new()));
+        let sink = hook_captures.clone();
+        let hook = ParseFailureHook(Arc::new(move |capture: &RawCapture| sink.lock().unwrap().push(capture.url.clone())));
+        let meta = |path: &str| ResponseMeta::new(Url::parse(&format!("http://127.0.0.1{path}")).unwrap(), 200, &HeaderMap::new());
+        let (container_meta, task_meta) = (meta("/v4/abc"), meta("/v4/abc/task"));
+        let task = task_json(&[CONTAINER_JSON.to_string()]);
+
+        for (container, task, blamed) in [
+            (CONTAINER_JSON, r#"{"Containers": 7}"#, "/v4/abc/task"),
+            ("[]", task.as_str(), "/v4/abc"),
+            (r#"{"DockerId": "abc", "Labels": {}}"#, task.as_str(), "/v4/abc"),
+        ] {
+            let err = ECSMetadata::from_documents(container.into(), Some(task.into()), None).unwrap_err();
+            hook.capture_documents((&container_meta, container.as_bytes()), Some((&task_meta, task.as_bytes())), false, &err);
+            assert_eq!(hook_captures.lock().unwrap().pop().unwrap(), format!("http://127.0.0.1{blamed}"));
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::metadata::ECSMetadata;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// Only present on the unified (v2) hierarchy
+const CGROUP_V2_MARKER: &str = "cgroup.controllers";
+
+/// Filesystem access of the cgroup lookups, replaced in tests
+pub(crate) trait CgroupFs {
+    fn exists(&self, path: &Path) -> bool;
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+}
+
+struct HostFs;
+
+impl CgroupFs for HostFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Controller {
+    Memory,
+    Cpu,
+}
+
+impl Controller {
+    // v1 mounts one hierarchy per controller
+    fn v1_dir(self) -> &'static str {
+        match self {
+            Controller::Memory => "memory",
+            Controller::Cpu => "cpu",
+        }
+    }
+}
+
+impl ECSMetadata {
+    /// Memory usage file of this container's cgroup: `memory.current` on cgroup v2,
+    /// `memory.usage_in_bytes` on v1. `None` when the cgroup is not visible, as on Fargate, which
+    /// hides the host hierarchy.
+    pub fn cgroup_memory_current_path(&self) -> Option<PathBuf> {
+        self.cgroup_file_with(&HostFs, Controller::Memory, "memory.current", "memory.usage_in_bytes")
+    }
+
+    /// Memory limit file of this container's cgroup: `memory.max` on v2, `memory.limit_in_bytes` on v1
+    pub fn cgroup_memory_max_path(&self) -> Option<PathBuf> {
+        self.cgroup_file_with(&HostFs, Controller::Memory, "memory.max", "memory.limit_in_bytes")
+    }
+
+    /// `cpu.stat` of this container's cgroup. On v2 it has the usage and throttling counters,
+    /// on v1 only the throttling ones.
+    pub fn cgroup_cpu_stat_path(&self) -> Option<PathBuf> {
+        self.cgroup_file_with(&HostFs, Controller::Cpu, "cpu.stat", "cpu.stat")
+    }
+
+    /// Current memory usage of this container in bytes, read from `cgroup_memory_current_path`.
+    /// `None` when the cgroup is not visible or the file can't be read.
+    pub fn read_memory_current(&self) -> Option<u64> {
+        self.read_memory_current_with(&HostFs)
+    }
+
+    pub(crate) fn read_memory_current_with(&self, fs: &impl CgroupFs) -> Option<u64> {
+        let path = self.cgroup_file_with(fs, Controller::Memory, "memory.current", "memory.usage_in_bytes")?;
+        fs.read_to_string(&path).ok()?.trim().parse().ok()
+    }
+
+    fn cgroup_file_with(&self, fs: &impl CgroupFs, controller: Controller, v2_file: &str, v1_file: &str) -> Option<PathBuf> {
+        let root = Path::new(CGROUP_ROOT);
+        let v2 = fs.exists(&root.join(CGROUP_V2_MARKER));
+        let base = if v2 { root.to_path_buf() } else { root.join(controller.v1_dir()) };
+        let dir = self.cgroup_candidates(&base, v2)?.into_iter().find(|dir| fs.exists(dir))?;
+        Some(dir.join(if v2 { v2_file } else { v1_file }))
+    }
+
+    // Where the agent and Docker put the container, most specific first: the task's cgroup when
+    // the agent manages task-level limits, then Docker's own cgroupfs and systemd layouts
+    fn cgroup_candidates(&self, base: &Path, v2: bool) -> Option<Vec<PathBuf>> {
+        let docker_id = self.docker_id();
+        // also keeps the ID from walking out of the hierarchy
+        if docker_id.is_empty() || !docker_id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let mut candidates = Vec::with_capacity(3);
+        if let Some(task_id) = self.task_id().filter(|id| !id.contains(['/', '.'])) {
+            candidates.push(match v2 {
+                true => base
+                    .join("ecstasks.slice")
+                    .join(format!("ecstasks-{}.slice", task_id.replace('-', "")))
+                    .join(format!("docker-{docker_id}.scope")),
+                false => base.join("ecs").join(task_id).join(docker_id),
+            });
+        }
+        candidates.push(base.join("docker").join(docker_id));
+        candidates.push(base.join("system.slice").join(format!("docker-{docker_id}.scope")));
+        Some(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use std::collections::HashMap;
+
+    const DOCKER_ID: &str = "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0";
+    const TASK_ID: &str = "021447970bce4bd58069f1925cd87bc0";
+
+    // directories and files that exist, with the file contents
+    #[derive(Default)]
+    struct FakeFs {
+        dirs: Vec<PathBuf>,
+        files: HashMap<PathBuf, String>,
+    }
+
+    impl FakeFs {
+        fn with_dir(mut self, dir: &str) -> Self {
+            self.dirs.push(PathBuf::from(dir));
+            self
+        }
+
+        fn with_file(mut self, path: &str, contents: &str) -> Self {
+            self.files.insert(PathBuf::from(path), contents.to_string());
+            self
+        }
+    }
+
+    impl CgroupFs for FakeFs {
+        fn exists(&self, path: &Path) -> bool {
+            self.dirs.iter().any(|dir| dir == path) || self.files.contains_key(path)
+        }
+
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            self.files.get(path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+    }
+
+    fn memory_current(metadata: &ECSMetadata, fs: &FakeFs) -> Option<PathBuf> {
+        metadata.cgroup_file_with(fs, Controller::Memory, "memory.current", "memory.usage_in_bytes")
+    }
+
+    #[test]
+    fn test_cgroup_v2_task_slice() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let scope = format!("/sys/fs/cgroup/ecstasks.slice/ecstasks-{TASK_ID}.slice/docker-{DOCKER_ID}.scope");
+        let fs = FakeFs::default()
+            .with_file("/sys/fs/cgroup/cgroup.controllers", "cpuset cpu io memory pids")
+            .with_dir(&scope)
+            .with_file(&format!("{scope}/memory.current"), "52428800\n");
+
+        assert_eq!(memory_current(&metadata, &fs), Some(PathBuf::from(format!("{scope}/memory.current"))));
+        let cpu_stat = metadata.cgroup_file_with(&fs, Controller::Cpu, "cpu.stat", "cpu.stat");
+        assert_eq!(cpu_stat, Some(PathBuf::from(format!("{scope}/cpu.stat"))));
+        assert_eq!(metadata.read_memory_current_with(&fs), Some(52_428_800));
+    }
+
+    #[test]
+    fn test_cgroup_v2_systemd_docker_scope() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let scope = format!("/sys/fs/cgroup/system.slice/docker-{DOCKER_ID}.scope");
+        let fs = FakeFs::default().with_file("/sys/fs/cgroup/cgroup.controllers", "").with_dir(&scope);
+        assert_eq!(memory_current(&metadata, &fs), Some(PathBuf::from(format!("{scope}/memory.current"))));
+        // the file itself is missing
+        assert_eq!(metadata.read_memory_current_with(&fs), None);
+    }
+
+    #[test]
+    fn test_cgroup_v1_layouts() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let task_dir = format!("/sys/fs/cgroup/memory/ecs/{TASK_ID}/{DOCKER_ID}");
+        let fs = FakeFs::default()
+            .with_dir(&task_dir)
+            .with_file(&format!("{task_dir}/memory.usage_in_bytes"), "1048576");
+        assert_eq!(memory_current(&metadata, &fs), Some(PathBuf::from(format!("{task_dir}/memory.usage_in_bytes"))));
+        assert_eq!(metadata.read_memory_current_with(&fs), Some(1_048_576));
+
+        // controllers are separate hierarchies, the CPU one is looked up on its own
+        let cpu_dir = format!("/sys/fs/cgroup/cpu/docker/{DOCKER_ID}");
+        let fs = FakeFs::default().with_dir(&cpu_dir);
+        let cpu_stat = metadata.cgroup_file_with(&fs, Controller::Cpu, "cpu.stat", "cpu.stat");
+        assert_eq!(cpu_stat, Some(PathBuf::from(format!("{cpu_dir}/cpu.stat"))));
+        assert_eq!(memory_current(&metadata, &fs), None);
+    }
+
+    #[test]
+    fn test_cgroup_not_resolvable() {
+        // Fargate: the container sees its own cgroup namespace only
+        let fargate = FakeFs::default()
+            .with_file("/sys/fs/cgroup/cgroup.controllers", "cpu memory")
+            .with_file("/sys/fs/cgroup/memory.current", "4096");
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(memory_current(&metadata, &fargate), None);
+        assert_eq!(metadata.read_memory_current_with(&fargate), None);
+
+        let traversal = CONTAINER_JSON.replace(DOCKER_ID, "../../etc");
+        let fs = FakeFs::default().with_dir("/sys/fs/cgroup/memory/docker/../../etc");
+        assert_eq!(memory_current(&metadata_from_json(&traversal, None), &fs), None);
+    }
+}
@@ -0,0 +1,1448 @@
+use std::env;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use url::{Host, Url};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::capture::{ParseFailureHook, RawCapture, ResponseMeta};
+use crate::error::{parse_document, parse_stats_document, status_error, ECSMetadataError};
+use crate::identity::IdentityExpectation;
+use crate::runtime;
+#[cfg(feature = "test-util")]
+use crate::failure::{self, FailurePolicy};
+use crate::health::DEFAULT_MAX_HEALTH_OUTPUT_LEN;
+use crate::connection::{ClientPool, ConnectionPolicy};
+use crate::container::ECSContainerMetadata;
+use crate::metadata::ECSMetadata;
+use crate::refresh::RefreshCooldown;
+use crate::response::ECSResponseInfo;
+use crate::task::ECSTaskMetadata;
+
+pub(crate) const ECS_METADATA_V4_ENV_VAR: &str = "ECS_CONTAINER_METADATA_URI_V4";
+/// Set on its own by agents before 1.39.0, which serve no v4 endpoint
+const ECS_METADATA_V3_ENV_VAR: &str = "ECS_CONTAINER_METADATA_URI";
+const TASK_METADATA_PATH: &str = "task";
+const CONTAINER_STATS_PATH: &str = "stats";
+const TASK_STATS_PATH: &str = "task/stats";
+/// The stats endpoints take 300-900ms on a busy host, well below this
+const DEFAULT_STATS_TIMEOUT: Duration = Duration::from_secs(5);
+/// Of the metadata and task policies, spanning about 0.7s with the default backoff
+const DEFAULT_RETRIES: u32 = 3;
+/// Connecting to the agent on the host takes well under a millisecond, this only cuts short an
+/// address that is not routable (yet), which the OS would wait on for minutes
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const V2_METADATA_ENDPOINT: &str = "http://169.254.170.2/v2/metadata";
+#[cfg(feature = "task-protection")]
+const ECS_AGENT_URI_ENV_VAR: &str = "ECS_AGENT_URI";
+/// The agent forwards the protection requests to the ECS API
+#[cfg(feature = "task-protection")]
+const DEFAULT_PROTECTION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Host the agent serves `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` on
+#[cfg(feature = "credentials")]
+const CREDENTIALS_HOST: &str = "http://169.254.170.2";
+#[cfg(feature = "credentials")]
+const DEFAULT_CREDENTIALS_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(feature = "imds")]
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+/// IMDS answers within milliseconds on EC2, anywhere else the address is not routed at all
+#[cfg(feature = "imds")]
+const DEFAULT_IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where the metadata endpoint was found, see `ECSMetadata::endpoint_source`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointSource {
+    /// Set with `ECSMetadataBuilder::endpoint`
+    Configured,
+    /// `ECS_CONTAINER_METADATA_URI_V4`
+    V4Env,
+    /// `ECS_CONTAINER_METADATA_URI`, when the agent predates v4. The v3 documents lack the keys
+    /// added since, see `ECSMetadata::from_v3_json`.
+    V3Env,
+    /// The fixed v2 address, see `ECSMetadataBuilder::enable_v2_fallback`
+    V2Fixed,
+}
+
+pub(crate) struct Documents {
+    pub(crate) container: Vec<u8>,
+    pub(crate) task: Option<Vec<u8>>,
+    pub(crate) source: EndpointSource,
+    // of the container document, or of the task document standing in for it on v2
+    pub(crate) response: ECSResponseInfo,
+    pub(crate) container_meta: ResponseMeta,
+    pub(crate) task_meta: Option<ResponseMeta>,
+}
+
+struct Endpoint {
+    url: Url,
+    source: EndpointSource,
+}
+
+/// Timeout and retry budget of a fetch. Retries wait for the `backoff` and only happen on the
+/// failures of `retry_on`, by default connection failures, timeouts and 5xx responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequestPolicy {
+    /// Per attempt, `None` waits as long as the agent takes
+    pub timeout: Option<Duration>,
+    /// Attempts on top of the first one
+    pub retries: u32,
+    pub backoff: Backoff,
+    pub retry_on: RetryOn,
+}
+
+impl RequestPolicy {
+    pub fn new(timeout: Option<Duration>, retries: u32) -> Self {
+        Self { timeout, retries, ..Self::default() }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_retry_on(mut self, retry_on: RetryOn) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// Delay before the retry following the failed attempt `attempt` (0 for the first one),
+    /// `None` once the budget is spent or when `err` is not retried
+    fn retry_delay(&self, attempt: u32, err: &ECSMetadataError) -> Option<Duration> {
+        (attempt < self.retries && self.retry_on.matches(err)).then(|| self.backoff.delay(attempt))
+    }
+}
+
+/// Delays between the attempts of a `RequestPolicy`: `base` doubling with every retry up to
+/// `max`. With `jitter` each delay is drawn between half and all of it, so that the containers
+/// of a host started together don't retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: bool,
+}
+
+impl Default for Backoff {
+    /// 100ms doubling up to 2s, with jitter
+    fn default() -> Self {
+        Self { base: Duration::from_millis(100), max: Duration::from_secs(2), jitter: true }
+    }
+}
+
+impl Backoff {
+    /// Retries right away
+    pub const NONE: Self = Self { base: Duration::ZERO, max: Duration::ZERO, jitter: false };
+
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max, jitter: true }
+    }
+
+    /// Delay before the retry `retry`, 0 for the first one
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self.base.saturating_mul(2u32.saturating_pow(retry)).min(self.max);
+        match self.jitter {
+            true => delay / 2 + delay.mul_f64(random_fraction() / 2.0),
+            false => delay,
+        }
+    }
+}
+
+// in [0, 1), every `RandomState` is keyed anew, which is all the randomness a jitter needs
+fn random_fraction() -> f64 {
+    use std::hash::BuildHasher;
+    let random = std::collections::hash_map::RandomState::new().hash_one(());
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Failures a `RequestPolicy` retries, all of them by default. Other failures, e.g. a 404 or a
+/// document that doesn't parse, are never retried, see `ECSMetadataError::is_retriable` for a
+/// wider classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryOn {
+    /// Refused or unreachable connections, e.g. while the agent restarts
+    pub connect: bool,
+    /// Attempts that ran into the policy's `timeout` or the builder's `read_timeout`
+    pub timeout: bool,
+    /// 5xx responses
+    pub server_error: bool,
+}
+
+impl Default for RetryOn {
+    fn default() -> Self {
+        Self { connect: true, timeout: true, server_error: true }
+    }
+}
+
+impl RetryOn {
+    pub fn matches(&self, err: &ECSMetadataError) -> bool {
+        match err {
+            ECSMetadataError::HttpError(err) => (self.timeout && err.is_timeout()) || (self.connect && err.is_connect()),
+            ECSMetadataError::HttpStatus { status, .. } => self.server_error && (500..=599).contains(status),
+            _ => false,
+        }
+    }
+}
+
+/// Customizes where and how the metadata is fetched, see `ECSMetadata::builder()`
+#[derive(Debug, Clone)]
+pub struct ECSMetadataBuilder {
+    endpoint: Option<String>,
+    allow_any_endpoint: bool,
+    metadata_policy: RequestPolicy,
+    task_policy: RequestPolicy,
+    stats_policy: RequestPolicy,
+    pub(crate) min_refresh_interval: Duration,
+    pub(crate) refresh_cooldown: RefreshCooldown,
+    pub(crate) snapshot_ttl: Option<Duration>,
+    pub(crate) max_health_output_len: usize,
+    pub(crate) strict: bool,
+    pub(crate) give_up_after: Option<Duration>,
+    pub(crate) redacted_log_option_keys: Vec<String>,
+    pub(crate) identity: Option<IdentityExpectation>,
+    pub(crate) parse_failure_hook: Option<ParseFailureHook>,
+    pub(crate) connection_policy: ConnectionPolicy,
+    // reset by the setters of the client's settings, clones made before keep the old client
+    pub(crate) pool: ClientPool,
+    v2_fallback: bool,
+    env_lookup: bool,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    dns_resolution: bool,
+    http_client: Option<reqwest::Client>,
+    // only differs from `V2_METADATA_ENDPOINT` in tests
+    v2_endpoint: String,
+    #[cfg(feature = "rustls")]
+    root_certificates: Vec<reqwest::Certificate>,
+    #[cfg(feature = "task-protection")]
+    agent_endpoint: Option<String>,
+    #[cfg(feature = "task-protection")]
+    protection_policy: RequestPolicy,
+    #[cfg(feature = "credentials")]
+    credentials_endpoint: Option<String>,
+    #[cfg(feature = "credentials")]
+    credentials_policy: RequestPolicy,
+    #[cfg(feature = "imds")]
+    imds_endpoint: Option<String>,
+    #[cfg(feature = "imds")]
+    imds_policy: RequestPolicy,
+    #[cfg(feature = "test-util")]
+    failures: Option<FailurePolicy>,
+}
+
+impl Default for ECSMetadataBuilder {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            allow_any_endpoint: false,
+            metadata_policy: RequestPolicy::new(None, DEFAULT_RETRIES),
+            task_policy: RequestPolicy::new(None, DEFAULT_RETRIES),
+            stats_policy: RequestPolicy::new(Some(DEFAULT_STATS_TIMEOUT), 0),
+            min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            refresh_cooldown: RefreshCooldown::ReturnCached,
+            snapshot_ttl: None,
+            max_health_output_len: DEFAULT_MAX_HEALTH_OUTPUT_LEN,
+            strict: false,
+            give_up_after: None,
+            redacted_log_option_keys: Vec::new(),
+            identity: None,
+            parse_failure_hook: None,
+            connection_policy: ConnectionPolicy::Reuse,
+            pool: ClientPool::default(),
+            v2_fallback: false,
+            env_lookup: true,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: None,
+            dns_resolution: true,
+            http_client: None,
+            v2_endpoint: V2_METADATA_ENDPOINT.to_string(),
+            #[cfg(feature = "rustls")]
+            root_certificates: Vec::new(),
+            #[cfg(feature = "task-protection")]
+            agent_endpoint: None,
+            #[cfg(feature = "task-protection")]
+            protection_policy: RequestPolicy::new(Some(DEFAULT_PROTECTION_TIMEOUT), 0),
+            #[cfg(feature = "credentials")]
+            credentials_endpoint: None,
+            #[cfg(feature = "credentials")]
+            credentials_policy: RequestPolicy::new(Some(DEFAULT_CREDENTIALS_TIMEOUT), DEFAULT_RETRIES),
+            #[cfg(feature = "imds")]
+            imds_endpoint: None,
+            #[cfg(feature = "imds")]
+            imds_policy: RequestPolicy::new(Some(DEFAULT_IMDS_TIMEOUT), 1),
+            #[cfg(feature = "test-util")]
+            failures: None,
+        }
+    }
+}
+
+impl ECSMetadataBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetch from this URI instead of the one in `ECS_CONTAINER_METADATA_URI_V4`. The environment
+    /// is then not read at all.
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// By default only `http` endpoints on link-local (169.254.0.0/16) or loopback (127.0.0.0/8, ::1)
+    /// addresses are fetched, which is where the agent lives. This lifts the restriction, e.g. for
+    /// tests against a recorded endpoint or unusual setups.
+    pub fn allow_any_endpoint(mut self, allow: bool) -> Self {
+        self.allow_any_endpoint = allow;
+        self
+    }
+
+    /// Policy of container metadata document fetches, by default no timeout and 3 retries with
+    /// the default `Backoff`, which bridge the agent's failures right after the container start
+    pub fn metadata_policy(mut self, policy: RequestPolicy) -> Self {
+        self.metadata_policy = policy;
+        self
+    }
+
+    /// Policy of task metadata document fetches, by default the same as the metadata policy's
+    pub fn task_policy(mut self, policy: RequestPolicy) -> Self {
+        self.task_policy = policy;
+        self
+    }
+
+    /// Policy of stats fetches, by default a 5s timeout and no retries
+    pub fn stats_policy(mut self, policy: RequestPolicy) -> Self {
+        self.stats_policy = policy;
+        self
+    }
+
+    /// Minimum time between two fetches of `ECSMetadata::refresh`, 1s by default. The agent serves
+    /// every task on the host, so a caller refreshing in a loop must not turn into a flood.
+    pub fn min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// What a refresh arriving within `min_refresh_interval` does, `ReturnCached` by default
+    pub fn refresh_cooldown(mut self, cooldown: RefreshCooldown) -> Self {
+        self.refresh_cooldown = cooldown;
+        self
+    }
+
+    /// Age after which `ECSMetadata::is_stale` flags the snapshot, see `refresh_if_stale`. Unset
+    /// by default: the snapshot never goes stale.
+    pub fn snapshot_ttl(mut self, ttl: Duration) -> Self {
+        self.snapshot_ttl = Some(ttl);
+        self
+    }
+
+    /// Health check outputs longer than this many bytes are cut and marked with `…[truncated]`,
+    /// 1 KiB by default. Check commands can print whole HTTP bodies or stack traces.
+    pub fn max_health_output_len(mut self, max_len: usize) -> Self {
+        self.max_health_output_len = max_len;
+        self
+    }
+
+    /// Fail with `MissingField` when the container document lacks any of the fields the agent
+    /// always serves, instead of defaulting them with a warning. Off by default, so that minimal
+    /// documents (e.g. of a mock agent) parse, see `ECSMetadata` for the accessors' fallbacks.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Fail with `IdentityMismatch` when the documents don't belong to the expected service,
+    /// see `ECSMetadata::assert_identity`, so that a container started with another service's
+    /// task definition fails its startup. Also checked on refresh.
+    pub fn expect_identity(mut self, expected: IdentityExpectation) -> Self {
+        self.identity = Some(expected);
+        self
+    }
+
+    /// Masks the values of the log option `key` as well as those of `REDACTED_LOG_OPTION_KEYS`,
+    /// e.g. a custom header of a Firelens output. Compared ignoring case.
+    pub fn redact_log_option(mut self, key: impl Into<String>) -> Self {
+        self.redacted_log_option_keys.push(key.into());
+        self
+    }
+
+    /// Called with the response of every document that fails to parse, e.g. to keep the body for
+    /// a postmortem, which the error alone doesn't carry. Only parse failures are captured, not
+    /// failed fetches. A panic of `hook` is caught: the call fails with the parse error as it
+    /// would without the hook, and a refresh adds a `CaptureHookPanicked` warning.
+    pub fn on_parse_failure(mut self, hook: impl Fn(&RawCapture) + Send + Sync + 'static) -> Self {
+        self.parse_failure_hook = Some(ParseFailureHook(std::sync::Arc::new(hook)));
+        self
+    }
+
+    /// Whether the HTTP client outlives the fetches, `Reuse` by default. `CloseAfterInit` leaves
+    /// no socket open once `init` returns, for hosts with tight conntrack limits; refreshing the
+    /// snapshot then fails with `ClosedAfterInit`.
+    pub fn connection_policy(mut self, policy: ConnectionPolicy) -> Self {
+        self.connection_policy = policy;
+        self
+    }
+
+    /// How long `background_init` keeps retrying, by default until it succeeds
+    pub fn give_up_after(mut self, deadline: Duration) -> Self {
+        self.give_up_after = Some(deadline);
+        self
+    }
+
+    /// Injects failures into every fetch, see `FailurePolicy`
+    #[cfg(feature = "test-util")]
+    pub fn failure_policy(mut self, failures: FailurePolicy) -> Self {
+        self.failures = Some(failures);
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// When neither `endpoint` nor any of the env vars is set, fall back to the v2
+    /// endpoint at its fixed address `http://169.254.170.2/v2/metadata`, which very old platforms
+    /// expose without any env var. Off by default: probing a fixed link-local address from a host
+    /// outside ECS only ends in a confusing timeout.
+    pub fn enable_v2_fallback(mut self, enable: bool) -> Self {
+        self.v2_fallback = enable;
+        self
+    }
+
+    /// Whether the environment may be read when no `endpoint` is set, true by default. The
+    /// endpoint is then resolved from, in order:
+    ///
+    /// 1. `endpoint`
+    /// 2. `ECS_CONTAINER_METADATA_URI_V4`
+    /// 3. `ECS_CONTAINER_METADATA_URI`, the v3 endpoint of agents predating v4
+    /// 4. with `enable_v2_fallback`, the v2 address, reading `HOSTNAME` to tell this container's
+    ///    entry of the task
+    ///
+    /// With `false`, an unset `endpoint` fails with `EndpointNotConfigured`, and the HTTP client
+    /// ignores the `HTTP_PROXY` family of variables it reads otherwise.
+    pub fn env_lookup(mut self, enable: bool) -> Self {
+        self.env_lookup = enable;
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Per connection attempt, 2s by default, apart from the timeout of the request policies. An
+    /// address that is not routable yet, e.g. right after the container started in bridge mode,
+    /// then fails the attempt quickly and leaves the retries their chance.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Longest wait for the next bytes of a response, e.g. an agent that accepted the connection
+    /// but hangs, by default none. Unlike the timeout of the request policies it doesn't bound
+    /// a response that trickles in.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Whether host names may be resolved, true by default. The endpoints of the agent are IP
+    /// literals, which never go through the resolver; with `false` the resolver is replaced by
+    /// one refusing every name, so that a domain endpoint (see `allow_any_endpoint`) fails
+    /// rather than waits on DNS.
+    pub fn dns_resolution(mut self, enable: bool) -> Self {
+        self.dns_resolution = enable;
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Trust this root CA for `https` endpoints, e.g. a recorded endpoint on an internal host
+    /// (see `allow_any_endpoint`, which `https` needs). With the `rustls` feature the client
+    /// trusts nothing but the roots added here.
+    #[cfg(feature = "rustls")]
+    pub fn root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Fetch with this client, e.g. one shared with the rest of the application, rather than
+    /// one built by the builder. The client's own settings then apply instead of
+    /// `connect_timeout`, `read_timeout`, `dns_resolution`, `root_certificate` and the proxy
+    /// handling of `env_lookup`; the request policies still do. Its connections outlive `init`
+    /// whatever the `connection_policy`.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self.pool = ClientPool::default();
+        self
+    }
+
+    /// Send the task protection requests to this URI instead of the one in `ECS_AGENT_URI`,
+    /// validated like `endpoint`
+    #[cfg(feature = "task-protection")]
+    pub fn agent_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.agent_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Timeout and retries of `get_protection_state` and `set_protection`, 10s without retries
+    /// by default
+    #[cfg(feature = "task-protection")]
+    pub fn protection_policy(mut self, policy: RequestPolicy) -> Self {
+        self.protection_policy = policy;
+        self
+    }
+
+    /// Fetch the task role credentials from this URI instead of the one in
+    /// `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` or `AWS_CONTAINER_CREDENTIALS_FULL_URI`, validated
+    /// like `endpoint`. No authorization token is sent then.
+    #[cfg(feature = "credentials")]
+    pub fn credentials_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.credentials_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Timeout and retries of `fetch_credentials`, 5s and 3 retries by default
+    #[cfg(feature = "credentials")]
+    pub fn credentials_policy(mut self, policy: RequestPolicy) -> Self {
+        self.credentials_policy = policy;
+        self
+    }
+
+    /// Send the EC2 instance metadata requests to this URI instead of `http://169.254.169.254`,
+    /// validated like `endpoint`
+    #[cfg(feature = "imds")]
+    pub fn imds_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.imds_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Timeout and retries of each EC2 instance metadata request, 1s and 1 retry by default
+    #[cfg(feature = "imds")]
+    pub fn imds_policy(mut self, policy: RequestPolicy) -> Self {
+        self.imds_policy = policy;
+        self
+    }
+
+    /// Fetches the container metadata document.
+    ///
+    /// Cancellation safe: the builder is the only state involved and the instance only exists once
+    /// every document is fetched and parsed, so a dropped future (e.g. a startup deadline winning
+    /// a `tokio::select!`) has no effect and the call can simply be retried.
+    pub async fn init(self) -> Result<ECSMetadata, ECSMetadataError> {
+        let fetched_at = Instant::now();
+        let documents = self.fetch_documents(false).await?;
+        let mut metadata = self.parse_fetched(documents)?;
+        metadata.last_fetch = Some(fetched_at);
+        metadata.fetched_at = Some(fetched_at);
+        Ok(metadata)
+    }
+
+    /// Fetches both the container and the task metadata documents, cancellation safe like `init`
+    pub async fn init_with_task(self) -> Result<ECSMetadata, ECSMetadataError> {
+        let fetched_at = Instant::now();
+        let documents = self.fetch_documents(true).await?;
+        let mut metadata = self.parse_fetched(documents)?;
+        metadata.last_fetch = Some(fetched_at);
+        metadata.fetched_at = Some(fetched_at);
+        Ok(metadata)
+    }
+
+    /// `init` for binaries that also run outside ECS, locally or in plain Docker: `None` when no
+    /// endpoint is configured and none of the env vars is set, i.e. not on ECS. Every other
+    /// failure, including that of an endpoint that is set but unreachable, is an error.
+    pub async fn try_init(self) -> Result<Option<ECSMetadata>, ECSMetadataError> {
+        match self.init().await {
+            Err(err) if is_outside_ecs(&err) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    /// Parses the documents of `init`, capturing them for `on_parse_failure` when they fail
+    fn parse_fetched(self, documents: Documents) -> Result<ECSMetadata, ECSMetadataError> {
+        let Documents { container, task, source, response, container_meta, task_meta } = documents;
+        let hook = self.parse_failure_hook.clone();
+        let bodies = hook.is_some().then(|| (container.clone(), task.clone()));
+        let v3 = source == EndpointSource::V3Env;
+        let mut metadata = ECSMetadata::from_versioned_documents(container, task, v3, Some(self)).inspect_err(|err| {
+            if let (Some(hook), Some((container, task))) = (&hook, &bodies) {
+                hook.capture_documents((&container_meta, container), task_meta.as_ref().zip(task.as_deref()), v3, err);
+            }
+        })?;
+        metadata.endpoint_source = Some(source);
+        metadata.response_info = Some(response);
+        Ok(metadata)
+    }
+
+    /// Fetches the task document and returns the entry of the container with the given Docker ID.
+    /// `policy` overrides the task policy for this call.
+    pub async fn fetch_container(
+        self,
+        docker_id: &str,
+        policy: Option<&RequestPolicy>,
+    ) -> Result<ECSContainerMetadata, ECSMetadataError> {
+        let endpoint = self.resolve_endpoint()?;
+        let task = self.fetch_task(&self.client()?, &endpoint, policy.unwrap_or(&self.task_policy)).await?;
+        task.container_by_docker_id(docker_id).cloned()
+    }
+
+    /// Docker stats document of this container, as served.
+    /// `policy` overrides the stats policy for this call.
+    pub async fn fetch_stats(&self, policy: Option<&RequestPolicy>) -> Result<serde_json::Value, ECSMetadataError> {
+        let endpoint = self.resolve_endpoint()?;
+        let client = self.client()?;
+        let url = match endpoint.source {
+            // the v2 stats are per Docker ID, which only the task document tells
+            EndpointSource::V2Fixed => {
+                let task = self.fetch_task(&client, &endpoint, &self.task_policy).await?;
+                v2_url(&endpoint.url, &format!("/v2/stats/{}", v2_container(&task, v2_hostname().as_deref())?.docker_id()))
+            }
+            _ => sub_url(&endpoint.url, CONTAINER_STATS_PATH),
+        };
+        let body = fetch(&client, url, policy.unwrap_or(&self.stats_policy)).await?;
+        parse_stats_document(&body)
+    }
+
+    /// Docker stats documents of all containers of the task keyed by Docker ID, as served.
+    /// `policy` overrides the stats policy for this call.
+    pub async fn fetch_task_stats(&self, policy: Option<&RequestPolicy>) -> Result<serde_json::Value, ECSMetadataError> {
+        let endpoint = self.resolve_endpoint()?;
+        let url = match endpoint.source {
+            EndpointSource::V2Fixed => v2_url(&endpoint.url, "/v2/stats"),
+            _ => sub_url(&endpoint.url, TASK_STATS_PATH),
+        };
+        let body = fetch(&self.client()?, url, policy.unwrap_or(&self.stats_policy)).await?;
+        parse_stats_document(&body)
+    }
+
+    /// Raw bodies of the container document and, if requested, the task document
+    pub(crate) async fn fetch_documents(&self, with_task: bool) -> Result<Documents, ECSMetadataError> {
+        let endpoint = self.resolve_endpoint()?;
+        let client = self.client()?;
+        if endpoint.source == EndpointSource::V2Fixed {
+            // only the task document exists, this container's entry stands in for its document
+            let Fetched { body: task, response, meta } = fetch_response(&client, endpoint.url.clone(), &self.metadata_policy).await?;
+            let parsed = parse_document(&task).inspect_err(|err| {
+                if let Some(hook) = &self.parse_failure_hook {
+                    hook.capture(&meta, &task, err);
+                }
+            })?;
+            let container = serde_json::to_vec(v2_container(&parsed, v2_hostname().as_deref())?)?;
+            let task_meta = with_task.then(|| meta.clone());
+            return Ok(Documents { container, task: with_task.then_some(task), source: endpoint.source, response, container_meta: meta, task_meta });
+        }
+
+        let Fetched { body: container, response, meta: container_meta } = fetch_response(&client, endpoint.url.clone(), &self.metadata_policy).await?;
+        let (task, task_meta) = match with_task {
+            true => {
+                let fetched = fetch_response(&client, sub_url(&endpoint.url, TASK_METADATA_PATH), &self.task_policy).await?;
+                (Some(fetched.body), Some(fetched.meta))
+            }
+            false => (None, None),
+        };
+        Ok(Documents { container, task, source: endpoint.source, response, container_meta, task_meta })
+    }
+
+    async fn fetch_task(
+        &self,
+        client: &HttpClient,
+        endpoint: &Endpoint,
+        policy: &RequestPolicy,
+    ) -> Result<ECSTaskMetadata, ECSMetadataError> {
+        let url = match endpoint.source {
+            EndpointSource::V2Fixed => endpoint.url.clone(),
+            _ => sub_url(&endpoint.url, TASK_METADATA_PATH),
+        };
+        parse_document(&fetch(client, url, policy).await?)
+    }
+
+    /// The configured endpoint, falling back to the env vars and then, if enabled, to v2; validated
+    fn resolve_endpoint(&self) -> Result<Endpoint, ECSMetadataError> {
+        let (endpoint, source) = match &self.endpoint {
+            Some(endpoint) => (endpoint.clone(), EndpointSource::Configured),
+            None if !self.env_lookup => return Err(ECSMetadataError::EndpointNotConfigured),
+            None => match env_var(ECS_METADATA_V4_ENV_VAR) {
+                Ok(endpoint) => (endpoint, EndpointSource::V4Env),
+                Err(source) => match env_var(ECS_METADATA_V3_ENV_VAR) {
+                    Ok(endpoint) => (endpoint, EndpointSource::V3Env),
+                    Err(_) if self.v2_fallback => (self.v2_endpoint.clone(), EndpointSource::V2Fixed),
+                    Err(_) => {
+                        return Err(ECSMetadataError::EnvVarNotSet { name: ECS_METADATA_V4_ENV_VAR.to_string(), source })
+                    }
+                },
+            },
+        };
+        let url = base_url(validate_endpoint(&endpoint, self.allow_any_endpoint)?);
+        Ok(Endpoint { url, source })
+    }
+
+    fn client(&self) -> Result<HttpClient, ECSMetadataError> {
+        match self.connection_policy {
+            ConnectionPolicy::Reuse => self.pool.get_or_try_init(|requests| self.build_client(Some(requests))),
+            ConnectionPolicy::CloseAfterInit => self.build_client(None),
+        }
+    }
+
+    fn build_client(&self, requests: Option<std::sync::Arc<AtomicU64>>) -> Result<HttpClient, ECSMetadataError> {
+        if let Some(client) = &self.http_client {
+            return Ok(HttpClient {
+                inner: client.clone(),
+                requests,
+                #[cfg(feature = "test-util")]
+                failures: self.failures.clone(),
+            });
+        }
+        let mut client = reqwest::Client::builder().gzip(true).deflate(true).connect_timeout(self.connect_timeout);
+        if let Some(timeout) = self.read_timeout {
+            client = client.read_timeout(timeout);
+        }
+        if !self.env_lookup {
+            client = client.no_proxy();
+        }
+        if !self.dns_resolution {
+            client = client.dns_resolver(std::sync::Arc::new(NoResolver));
+        }
+        #[cfg(feature = "rustls")]
+        let client = self
+            .root_certificates
+            .iter()
+            .fold(client.use_rustls_tls(), |client, certificate| client.add_root_certificate(certificate.clone()));
+        Ok(HttpClient {
+            inner: client.build()?,
+            requests,
+            #[cfg(feature = "test-util")]
+            failures: self.failures.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "task-protection")]
+impl ECSMetadataBuilder {
+    /// `path` under the configured agent endpoint or `ECS_AGENT_URI`, validated
+    pub(crate) fn agent_url(&self, path: &str) -> Result<Url, ECSMetadataError> {
+        let endpoint = match &self.agent_endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None if !self.env_lookup => return Err(ECSMetadataError::EndpointNotConfigured),
+            None => env_var(ECS_AGENT_URI_ENV_VAR)
+                .map_err(|source| ECSMetadataError::EnvVarNotSet { name: ECS_AGENT_URI_ENV_VAR.to_string(), source })?,
+        };
+        Ok(sub_url(&base_url(validate_endpoint(&endpoint, self.allow_any_endpoint)?), path))
+    }
+
+    /// Status and body of the agent's response to a JSON request under the protection policy.
+    /// Error statuses are left to the caller, whose bodies tell the failure.
+    pub(crate) async fn send_to_agent(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+    ) -> Result<(reqwest::StatusCode, Vec<u8>), ECSMetadataError> {
+        self.send(method, url, &[], body, &self.protection_policy).await
+    }
+}
+
+#[cfg(feature = "credentials")]
+impl ECSMetadataBuilder {
+    /// The configured credentials endpoint or the one of the environment, validated, with the
+    /// authorization token to send
+    pub(crate) fn credentials_url(&self) -> Result<(Url, Option<String>), ECSMetadataError> {
+        let (endpoint, authorization) = match &self.credentials_endpoint {
+            Some(endpoint) => (endpoint.clone(), None),
+            None if !self.env_lookup => return Err(ECSMetadataError::EndpointNotConfigured),
+            None => crate::credentials::endpoint_from_env(&env_var, CREDENTIALS_HOST)?,
+        };
+        Ok((validate_endpoint(&endpoint, self.allow_any_endpoint)?, authorization))
+    }
+
+    /// Status and body of the response of the credentials endpoint under the credentials policy
+    pub(crate) async fn send_to_credentials_endpoint(
+        &self,
+        url: Url,
+        authorization: Option<&str>,
+    ) -> Result<(reqwest::StatusCode, Vec<u8>), ECSMetadataError> {
+        let headers = authorization.map(|token| ("authorization", token));
+        self.send(reqwest::Method::GET, url, headers.as_slice(), None, &self.credentials_policy).await
+    }
+}
+
+#[cfg(any(feature = "task-protection", feature = "credentials", feature = "imds"))]
+impl ECSMetadataBuilder {
+    /// Status and body of the response to a request, with a JSON body if any, retried like a
+    /// fetch under `policy`
+    pub(crate) async fn send(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        headers: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+        policy: &RequestPolicy,
+    ) -> Result<(reqwest::StatusCode, Vec<u8>), ECSMetadataError> {
+        let client = self.client()?;
+        let mut attempt = 0;
+        loop {
+            client.count_request();
+            let mut request = client.inner.request(method.clone(), url.clone());
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            if let Some(body) = &body {
+                request = request.header(reqwest::header::CONTENT_TYPE, "application/json").body(body.clone());
+            }
+            if let Some(timeout) = policy.timeout {
+                request = request.timeout(timeout);
+            }
+            let sent = runtime::enter(async {
+                match request.send().await {
+                    Ok(response) => {
+                        let status = response.status();
+                        response.bytes().await.map(|body| (status, body.to_vec())).map_err(ECSMetadataError::from)
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            })
+            .await;
+            let delay = match &sent {
+                Err(err) => policy.retry_delay(attempt, err),
+                Ok((status, _)) if status.is_server_error() && policy.retry_on.server_error && attempt < policy.retries => {
+                    Some(policy.backoff.delay(attempt))
+                }
+                Ok(_) => None,
+            };
+            match delay {
+                Some(delay) => {
+                    runtime::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => return sent,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "imds")]
+impl ECSMetadataBuilder {
+    /// `path` under the configured IMDS endpoint or its fixed address, validated
+    pub(crate) fn imds_url(&self, path: &str) -> Result<Url, ECSMetadataError> {
+        let endpoint = self.imds_endpoint.as_deref().unwrap_or(IMDS_ENDPOINT);
+        Ok(sub_url(&base_url(validate_endpoint(endpoint, self.allow_any_endpoint)?), path))
+    }
+
+    /// Status and body of the response of IMDS under the IMDS policy
+    pub(crate) async fn send_to_imds(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        headers: &[(&str, &str)],
+    ) -> Result<(reqwest::StatusCode, Vec<u8>), ECSMetadataError> {
+        self.send(method, url, headers, None, &self.imds_policy).await
+    }
+}
+
+/// The failure of `init` on a host without any of the metadata env vars, i.e. not on ECS
+pub(crate) fn is_outside_ecs(err: &ECSMetadataError) -> bool {
+    matches!(err, ECSMetadataError::EnvVarNotSet { source: env::VarError::NotPresent, .. })
+}
+
+/// Rejects anything that could not be the agent, unless `allow_any` is set
+fn validate_endpoint(endpoint: &str, allow_any: bool) -> Result<Url, ECSMetadataError> {
+    let invalid = |reason: String| ECSMetadataError::InvalidEndpoint {
+        uri: endpoint.to_string(),
+        reason,
+        source: None,
+    };
+
+    let url = Url::parse(endpoint).map_err(|source| ECSMetadataError::InvalidEndpoint {
+        uri: endpoint.to_string(),
+        reason: "not a valid URL".to_string(),
+        source: Some(source),
+    })?;
+    if allow_any {
+        return Ok(url);
+    }
+
+    if url.scheme() != "http" {
+        return Err(invalid(format!("scheme {} is not allowed, only http is", url.scheme())));
+    }
+    let ip = match url.host() {
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(Host::Domain(domain)) => {
+            return Err(invalid(format!("host {domain} is a domain name, only link-local or loopback IPs are allowed")))
+        }
+        None => return Err(invalid("no host".to_string())),
+    };
+    let allowed = match ip {
+        IpAddr::V4(ip) => ip.is_link_local() || ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    };
+    if !allowed {
+        return Err(invalid(format!(
+            "host {ip} is neither link-local (169.254.0.0/16) nor loopback (127.0.0.0/8)"
+        )));
+    }
+    Ok(url)
+}
+
+/// This container's entry of a v2 task document. There is no container document to tell which
+/// one it is: in bridge mode the host name is the short Docker ID, otherwise the task must have
+/// a single non-infrastructure container.
+fn v2_container<'a>(task: &'a ECSTaskMetadata, hostname: Option<&str>) -> Result<&'a ECSContainerMetadata, ECSMetadataError> {
+    if let Some(hostname) = hostname.filter(|hostname| !hostname.is_empty()) {
+        if let Some(container) = task.containers().iter().find(|container| container.docker_id().starts_with(hostname)) {
+            return Ok(container);
+        }
+    }
+    let mut application = task.normal_containers();
+    match (application.next(), application.next()) {
+        (Some(container), None) => Ok(container),
+        (Some(_), Some(_)) => Err(ECSMetadataError::AmbiguousContainer("this container".to_string())),
+        (None, _) => Err(ECSMetadataError::ContainerNotFound("this container".to_string())),
+    }
+}
+
+fn v2_hostname() -> Option<String> {
+    env_var("HOSTNAME").ok()
+}
+
+// Every read of the environment goes through here
+fn env_var(name: &str) -> Result<String, env::VarError> {
+    #[cfg(test)]
+    {
+        crate::test_support::record_env_read(name);
+        if let Some(value) = crate::test_support::fake_env_var(name) {
+            return value;
+        }
+    }
+    env::var(name)
+}
+
+fn v2_url(metadata_url: &Url, path: &str) -> Url {
+    let mut url = metadata_url.clone();
+    url.set_path(path);
+    url
+}
+
+// Some agents serve the env var with a trailing slash, which they 404 themselves
+fn base_url(mut metadata_url: Url) -> Url {
+    let trimmed = metadata_url.path().trim_end_matches('/').to_string();
+    metadata_url.set_path(&trimmed);
+    metadata_url
+}
+
+fn sub_url(metadata_url: &Url, path: &str) -> Url {
+    let mut url = metadata_url.clone();
+    url.set_path(&format!("{}/{}", metadata_url.path().trim_end_matches('/'), path));
+    url
+}
+
+/// Resolver of `dns_resolution(false)`
+struct NoResolver;
+
+impl reqwest::dns::Resolve for NoResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let refused = format!("DNS resolution of {} is disabled", name.as_str());
+        Box::pin(async move { Err(refused.into()) })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    inner: reqwest::Client,
+    // requests sent with a pooled client, see `ClientStats`
+    requests: Option<std::sync::Arc<AtomicU64>>,
+    #[cfg(feature = "test-util")]
+    failures: Option<FailurePolicy>,
+}
+
+impl HttpClient {
+    fn count_request(&self) {
+        if let Some(requests) = &self.requests {
+            requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Fetched {
+    body: Vec<u8>,
+    response: ECSResponseInfo,
+    meta: ResponseMeta,
+}
+
+async fn fetch(client: &HttpClient, url: Url, policy: &RequestPolicy) -> Result<Vec<u8>, ECSMetadataError> {
+    Ok(fetch_response(client, url, policy).await?.body)
+}
+
+async fn fetch_response(client: &HttpClient, url: Url, policy: &RequestPolicy) -> Result<Fetched, ECSMetadataError> {
+    let mut attempt = 0;
+    loop {
+        let fetched = runtime::enter(fetch_once(client, url.clone(), policy.timeout)).await;
+        match fetched.as_ref().err().and_then(|err| policy.retry_delay(attempt, err)) {
+            Some(delay) => {
+                runtime::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return fetched,
+        }
+    }
+}
+
+async fn fetch_once(client: &HttpClient, url: Url, timeout: Option<Duration>) -> Result<Fetched, ECSMetadataError> {
+    #[cfg(feature = "test-util")]
+    let injected = client.failures.as_ref().map(FailurePolicy::next_fetch).unwrap_or_default();
+    #[cfg(feature = "test-util")]
+    if injected.fail {
+        return Err(failure::unavailable(url));
+    }
+
+    client.count_request();
+    let mut request = client.inner.get(url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().await?;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        let (status, url) = (response.status().as_u16(), response.url().clone());
+        // the body tells why, when it can be read at all
+        let body = response.bytes().await.unwrap_or_default();
+        return Err(status_error(status, &url, &body));
+    }
+    let info = ECSResponseInfo::new(response.status().as_u16(), response.headers(), SystemTime::now());
+    let meta = ResponseMeta::new(response.url().clone(), response.status().as_u16(), response.headers());
+    // the request timeout is still running and covers the body
+    #[cfg(feature = "test-util")]
+    tokio::time::sleep(injected.latency).await;
+
+    // the content type is not checked, proxies may well serve the JSON as text/plain
+    let body = response.bytes().await?;
+    let body = body.strip_prefix(UTF8_BOM).unwrap_or(&body).to_vec();
+    #[cfg(feature = "test-util")]
+    let body = if injected.corrupt_body { failure::corrupted(body) } else { body };
+    Ok(Fetched { body, response: info, meta })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+    use crate::test_support::{EnvReadGuard, FakeEnv, MockAgent, MockResponse};
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn rejection(endpoint: &str) -> String {
+        match validate_endpoint(endpoint, false) {
+            Err(ECSMetadataError::InvalidEndpoint { reason, .. }) => reason,
+            other => panic!("expected {endpoint} to be rejected, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_agent_endpoints_are_allowed() {
+        for endpoint in [
+            "http://169.254.170.2/v4/2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0",
+            "http://169.254.170.2/v3/abc",
+            "http://127.0.0.1:51679/v4/abc",
+            "http://[::1]:8080/",
+        ] {
+            assert!(validate_endpoint(endpoint, false).is_ok(), "{endpoint} should be allowed");
+        }
+    }
+
+    #[test]
+    fn test_surprising_endpoints_are_rejected() {
+        assert_eq!(rejection("https://169.254.170.2/v4/abc"), "scheme https is not allowed, only http is");
+        assert_eq!(
+            rejection("http://203.0.113.10/v4/abc"),
+            "host 203.0.113.10 is neither link-local (169.254.0.0/16) nor loopback (127.0.0.0/8)"
+        );
+        assert_eq!(
+            rejection("http://metadata.example.com/v4"),
+            "host metadata.example.com is a domain name, only link-local or loopback IPs are allowed"
+        );
+        assert!(rejection("169.254.170.2/v4").starts_with("not a valid URL"));
+    }
+
+    #[test]
+    fn test_allow_any_endpoint() {
+        assert!(validate_endpoint("https://metadata.example.com/v4", true).is_ok());
+        // still has to be a URL
+        assert!(validate_endpoint("not a url", true).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_builder_endpoint_is_validated() {
+        let result = ECSMetadataBuilder::new().endpoint("https://metadata.example.com/v4").init().await;
+        let err = result.expect_err("remote endpoint must be rejected");
+        assert_eq!(
+            err.to_string(),
+            "[phase=validate] Refusing metadata endpoint https://metadata.example.com/v4: scheme https is not allowed, only http is"
+        );
+    }
+
+    #[test]
+    fn test_sub_url() {
+        for base in ["http://169.254.170.2/v4/abc", "http://169.254.170.2/v4/abc/", "http://169.254.170.2/v4/abc//"] {
+            let url = base_url(Url::parse(base).unwrap());
+            assert_eq!(url.as_str(), "http://169.254.170.2/v4/abc", "{base}");
+            assert_eq!(sub_url(&url, TASK_METADATA_PATH).as_str(), "http://169.254.170.2/v4/abc/task", "{base}");
+            assert_eq!(sub_url(&url, CONTAINER_STATS_PATH).as_str(), "http://169.254.170.2/v4/abc/stats", "{base}");
+            assert_eq!(sub_url(&url, TASK_STATS_PATH).as_str(), "http://169.254.170.2/v4/abc/task/stats", "{base}");
+        }
+
+        // no path component at all, as with a bare agent address
+        for base in ["http://127.0.0.1:51678", "http://127.0.0.1:51678/"] {
+            let url = base_url(Url::parse(base).unwrap());
+            assert_eq!(url.as_str(), "http://127.0.0.1:51678/", "{base}");
+            assert_eq!(sub_url(&url, TASK_METADATA_PATH).as_str(), "http://127.0.0.1:51678/task", "{base}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_with_trailing_slash() {
+        // the env var takes the same path through `resolve_endpoint` as a configured endpoint
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])));
+        agent.set("/v4/abc/stats", MockResponse::json(r#"{"read": "now"}"#));
+
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc/"));
+        let metadata = builder.clone().init_with_task().await.expect("the trailing slash must not reach the agent");
+        assert_eq!(metadata.container_name(), "streamer");
+        assert!(metadata.task().is_some());
+        assert_eq!(builder.fetch_stats(None).await.unwrap()["read"], "now");
+        assert_eq!((agent.hits("/v4/abc"), agent.hits("/v4/abc/task"), agent.hits("/v4/abc/stats")), (1, 1, 1));
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn test_https_endpoint_with_custom_root() {
+        let agent = MockAgent::start_tls().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc"));
+
+        let ca = reqwest::Certificate::from_pem(crate::test_support::TEST_CA_PEM).unwrap();
+        let metadata = builder.clone().allow_any_endpoint(true).root_certificate(ca).init().await.expect("the CA is trusted");
+        assert_eq!(metadata.container_name(), "streamer");
+
+        let err = builder.clone().allow_any_endpoint(true).init().await.expect_err("self-signed without the CA");
+        assert!(matches!(err, ECSMetadataError::TlsError(_)), "{err:?}");
+        assert_eq!(agent.total_hits(), 1);
+
+        // the link-local default is unchanged, https needs the override
+        let err = builder.init().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::InvalidEndpoint { .. }), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_init_raced_against_deadline() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])).with_delay(Duration::from_secs(5)));
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc"));
+
+        // dropped after the container document is in, while the task document is in flight
+        let task_requested = async {
+            while agent.hits("/v4/abc/task") == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            _ = builder.clone().init_with_task() => panic!("the deadline should win"),
+            _ = task_requested => {}
+        }
+        assert_eq!(agent.hits("/v4/abc"), 1);
+
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])));
+        let metadata = builder.init_with_task().await.expect("the retry starts from scratch");
+        assert!(metadata.task().is_some());
+        assert_eq!(agent.hits("/v4/abc"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stats_policy_override() {
+        let agent = MockAgent::start().await;
+        let slow_stats = MockResponse::json(r#"{"read": "2024-01-01T00:00:00Z"}"#).with_delay(Duration::from_millis(300));
+        agent.set("/v4/abc/stats", slow_stats);
+        let builder = ECSMetadataBuilder::new()
+            .endpoint(agent.url("/v4/abc"))
+            .stats_policy(RequestPolicy::new(Some(Duration::from_millis(50)), 0));
+
+        let err = builder.fetch_stats(None).await.expect_err("the stats policy timeout applies");
+        assert!(matches!(err, ECSMetadataError::HttpError(err) if err.is_timeout()));
+
+        let generous = RequestPolicy::new(Some(Duration::from_secs(2)), 0);
+        let stats = builder.fetch_stats(Some(&generous)).await.expect("the override applies");
+        assert_eq!(stats["read"], "2024-01-01T00:00:00Z");
+    }
+
+    async fn init_with(response: MockResponse) -> ECSMetadata {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", response);
+        ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).init().await.expect("the document should be read")
+    }
+
+    #[tokio::test]
+    async fn test_compressed_bodies_are_decoded() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(CONTAINER_JSON.as_bytes()).unwrap();
+        let metadata = init_with(MockResponse::json(gzip.finish().unwrap()).with_header("Content-Encoding", "gzip")).await;
+        assert_eq!(metadata.container_name(), "streamer");
+
+        let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(CONTAINER_JSON.as_bytes()).unwrap();
+        let metadata = init_with(MockResponse::json(deflate.finish().unwrap()).with_header("Content-Encoding", "deflate")).await;
+        assert_eq!(metadata.container_name(), "streamer");
+    }
+
+    #[tokio::test]
+    async fn test_response_info() {
+        let response = MockResponse::json(CONTAINER_JSON)
+            .with_header("Server", "mock-agent/1.0")
+            .with_header("Date", "Sun, 06 Nov 1994 08:49:37 GMT");
+        let metadata = init_with(response).await;
+        let info = metadata.response_info().unwrap();
+        assert_eq!((info.status(), info.server()), (200, Some("mock-agent/1.0")));
+        assert_eq!(info.content_length(), Some(CONTAINER_JSON.len() as u64));
+        assert!(info.clock_skew_secs().unwrap() < -86_400);
+
+        let metadata = init_with(MockResponse::json(CONTAINER_JSON)).await;
+        assert_eq!(metadata.response_info().unwrap().clock_skew_secs(), None);
+        assert!(ECSMetadata::from_json(CONTAINER_JSON).unwrap().response_info().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_content_type_is_ignored() {
+        let text = MockResponse::status(200, CONTAINER_JSON).with_header("Content-Type", "text/plain; charset=utf-8");
+        assert_eq!(init_with(text).await.container_name(), "streamer");
+    }
+
+    #[tokio::test]
+    async fn test_bom_is_stripped() {
+        let metadata = init_with(MockResponse::json([UTF8_BOM, CONTAINER_JSON.as_bytes()].concat())).await;
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(metadata.raw.container, CONTAINER_JSON.as_bytes());
+
+        // the plain agent response is kept as is
+        assert_eq!(init_with(MockResponse::json(CONTAINER_JSON)).await.raw.container, CONTAINER_JSON.as_bytes());
+    }
+
+    fn v2_task_json() -> String {
+        let pause = CONTAINER_JSON
+            .replace("2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0", "731a0d6a3b4210e2448339bc7015aaa79bfe4fa256384f4102db86ef94cbbc4c")
+            .replace(r#""com.amazonaws.ecs.container-name": "streamer""#, r#""com.amazonaws.ecs.container-name": "~internal~ecs~pause""#);
+        format!(
+            r#"{{"Cluster": "production", "TaskARN": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0",
+                "Family": "streamer", "Revision": "12", "DesiredStatus": "RUNNING", "KnownStatus": "RUNNING",
+                "Containers": [{pause}, {CONTAINER_JSON}], "Limits": {{"CPU": 0.25, "Memory": 512}}, "AvailabilityZone": "us-east-1b"}}"#
+        )
+    }
+
+    // call with a `FakeEnv` without the v4 and v3 env vars
+    fn v2_builder(agent: &MockAgent) -> ECSMetadataBuilder {
+        ECSMetadataBuilder { v2_endpoint: agent.url("/v2/metadata"), ..ECSMetadataBuilder::new() }
+    }
+
+    #[tokio::test]
+    async fn test_v2_fallback() {
+        let agent = MockAgent::start().await;
+        let _env = FakeEnv::new(&[]);
+        agent.set("/v2/metadata", MockResponse::json(v2_task_json()));
+        agent.set("/v2/stats/2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0", MockResponse::json(r#"{"read": "now"}"#));
+
+        let metadata = v2_builder(&agent).enable_v2_fallback(true).init_with_task().await.expect("v2 should be read");
+        assert_eq!(metadata.endpoint_source(), Some(EndpointSource::V2Fixed));
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(metadata.cluster(), Some("production"));
+        assert_eq!(metadata.availability_zone(), Some("us-east-1b"));
+        assert_eq!(metadata.effective_memory_limit_mib(), Some(512));
+        assert_eq!(metadata.task().unwrap().containers().len(), 2);
+        assert_eq!(agent.hits("/v2/metadata"), 1);
+
+        let stats = v2_builder(&agent).enable_v2_fallback(true).fetch_stats(None).await.unwrap();
+        assert_eq!(stats["read"], "now");
+    }
+
+    #[tokio::test]
+    async fn test_v3_env_fallback() {
+        let agent = MockAgent::start().await;
+        let v3_container = include_str!("../testdata/versions/v3_container.json");
+        agent.set("/v3/abc", MockResponse::json(v3_container));
+        agent.set("/v3/abc/task", MockResponse::json(include_str!("../testdata/versions/v3_task.json")));
+        let _env = FakeEnv::new(&[(ECS_METADATA_V3_ENV_VAR, &agent.url("/v3/abc"))]);
+        let builder = ECSMetadataBuilder::new().min_refresh_interval(Duration::ZERO);
+
+        let mut metadata = builder.init_with_task().await.unwrap();
+        assert_eq!(metadata.endpoint_source(), Some(EndpointSource::V3Env));
+        assert_eq!((metadata.container_name(), metadata.container_arn(), metadata.log_driver()), ("curl", None, None));
+        assert_eq!(metadata.availability_zone(), Some("us-west-2d"));
+        agent.set("/v3/abc", MockResponse::json(v3_container.replace("\"KnownStatus\": \"RUNNING\"", "\"KnownStatus\": \"STOPPED\"")));
+        assert!(matches!(metadata.refresh().await.unwrap(), crate::refresh::RefreshOutcome::Changed(_)));
+        assert_eq!(metadata.container().known_status(), Some("STOPPED"));
+        assert!(metadata.raw.v3);
+    }
+
+    #[tokio::test]
+    async fn test_try_init_outside_ecs() {
+        let agent = MockAgent::start().await;
+        let _env = FakeEnv::new(&[]);
+        // neither env var, nor the v2 fallback
+        assert!(v2_builder(&agent).try_init().await.unwrap().is_none());
+        assert_eq!(agent.total_hits(), 0);
+
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).try_init().await.unwrap();
+        assert_eq!(metadata.unwrap().container_name(), "streamer");
+        let result = ECSMetadata::builder().endpoint(agent.url("/v4/missing")).try_init().await;
+        assert!(matches!(result, Err(ECSMetadataError::HttpStatus { status: 404, .. })), "{result:?}");
+        let result = ECSMetadata::builder().env_lookup(false).try_init().await;
+        assert!(matches!(result, Err(ECSMetadataError::EndpointNotConfigured)));
+    }
+
+    #[tokio::test]
+    async fn test_v2_fallback_is_opt_in() {
+        let agent = MockAgent::start().await;
+        let _env = FakeEnv::new(&[]);
+        agent.set("/v2/metadata", MockResponse::json(v2_task_json()));
+
+        let result = v2_builder(&agent).init().await;
+        assert!(matches!(result, Err(ECSMetadataError::EnvVarNotSet { .. })));
+        assert_eq!(agent.total_hits(), 0);
+
+        // an explicit endpoint wins over the fallback
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = v2_builder(&agent).enable_v2_fallback(true).endpoint(agent.url("/v4/abc")).init().await.unwrap();
+        assert_eq!(metadata.endpoint_source(), Some(EndpointSource::Configured));
+        assert_eq!(agent.hits("/v2/metadata"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_explicit_endpoint_reads_no_env() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])));
+        let reads = EnvReadGuard::start();
+
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).enable_v2_fallback(true).min_refresh_interval(Duration::ZERO);
+        let mut metadata = builder.init_with_task().await.unwrap();
+        metadata.refresh().await.unwrap();
+        assert!(reads.reads().is_empty(), "{:?}", reads.reads());
+
+        let result = ECSMetadata::builder().env_lookup(false).enable_v2_fallback(true).init().await;
+        assert!(matches!(result, Err(ECSMetadataError::EndpointNotConfigured)));
+        assert!(reads.reads().is_empty(), "{:?}", reads.reads());
+
+        let _env = FakeEnv::new(&[]);
+        let _ = ECSMetadata::builder().init().await;
+        assert_eq!(reads.reads()[0], ECS_METADATA_V4_ENV_VAR);
+    }
+
+    #[tokio::test]
+    async fn test_unroutable_address_fails_fast() {
+        // TEST-NET-1, never routed: without a connect timeout each attempt waits on the OS
+        let builder = ECSMetadata::builder()
+            .endpoint("http://192.0.2.1/v4/abc")
+            .allow_any_endpoint(true)
+            .connect_timeout(Duration::from_millis(50))
+            .metadata_policy(RequestPolicy::new(Some(Duration::from_secs(30)), 2));
+        let started = std::time::Instant::now();
+        let err = builder.init().await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        assert!(RetryOn::default().matches(&err), "{err:?}");
+        assert_eq!(err.phase(), crate::error::Phase::Connect);
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff { jitter: false, ..Backoff::default() };
+        let delays = (0..6).map(|retry| backoff.delay(retry).as_millis()).collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 2000]);
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(2));
+        assert_eq!(Backoff::NONE.delay(3), Duration::ZERO);
+
+        let jittered = Backoff::default();
+        for retry in 0..6 {
+            let delay = jittered.delay(retry);
+            assert!(delay >= backoff.delay(retry) / 2 && delay <= backoff.delay(retry), "{delay:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_default_retries_with_backoff() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::status(503, "agent starting"));
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc"));
+
+        let started = Instant::now();
+        let err = builder.clone().init().await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::HttpStatus { status: 503, snippet, .. } if snippet == "agent starting"), "{err:?}");
+        assert_eq!(agent.hits("/v4/abc"), 4);
+        // at least half of 100ms + 200ms + 400ms
+        assert!(started.elapsed() >= Duration::from_millis(350), "{:?}", started.elapsed());
+
+        let no_server_errors = RetryOn { server_error: false, ..RetryOn::default() };
+        let policy = RequestPolicy::new(None, 3).with_backoff(Backoff::NONE).with_retry_on(no_server_errors);
+        builder.clone().metadata_policy(policy).init().await.unwrap_err();
+        assert_eq!(agent.hits("/v4/abc"), 5);
+
+        // a document that doesn't parse is not retried
+        agent.set("/v4/abc", MockResponse::json("[]"));
+        builder.init().await.unwrap_err();
+        assert_eq!(agent.hits("/v4/abc"), 6);
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_and_injected_client() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON).with_delay(Duration::from_millis(300)));
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc"));
+
+        let err = builder.clone().read_timeout(Duration::from_millis(50)).init().await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::HttpError(err) if err.is_timeout()), "{err:?}");
+
+        // the caller's client brings its own timeout, and the builder's read timeout is not applied
+        let impatient = reqwest::Client::builder().timeout(Duration::from_millis(50)).build().unwrap();
+        let err = builder.clone().http_client(impatient).init().await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::HttpError(err) if err.is_timeout()), "{err:?}");
+        let metadata = builder.read_timeout(Duration::from_millis(50)).http_client(reqwest::Client::new()).init().await.unwrap();
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(metadata.client_stats().unwrap().pooled_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_ip_literals_skip_the_resolver() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadata::builder().allow_any_endpoint(true).dns_resolution(false);
+        builder.clone().endpoint(agent.url("/v4/abc")).init().await.unwrap();
+
+        let by_name = agent.url("/v4/abc").replace("127.0.0.1", "localhost");
+        let err = builder.endpoint(by_name).init().await.unwrap_err();
+        assert_eq!(err.phase(), crate::error::Phase::Connect);
+        assert_eq!(agent.total_hits(), 1);
+    }
+
+    #[test]
+    fn test_v2_container_selection() {
+        let task: ECSTaskMetadata = serde_json::from_str(&v2_task_json()).unwrap();
+        assert_eq!(v2_container(&task, None).unwrap().container_name(), "streamer");
+        // bridge mode host name: the short Docker ID
+        assert_eq!(v2_container(&task, Some("731a0d6a3b42")).unwrap().container_name(), "~internal~ecs~pause");
+        assert_eq!(v2_container(&task, Some("ip-10-0-0-1")).unwrap().container_name(), "streamer");
+
+        let two_apps = crate::task::tests::task_json(&[CONTAINER_JSON.to_string(), crate::task::tests::sidecar_json()]);
+        let task: ECSTaskMetadata = serde_json::from_str(&two_apps).unwrap();
+        assert!(matches!(v2_container(&task, None), Err(ECSMetadataError::AmbiguousContainer(_))));
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_server_errors_only() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::status(503, "busy"));
+        agent.set("/v4/abc/task/stats", MockResponse::status(404, "nope"));
+        let builder = ECSMetadataBuilder::new()
+            .endpoint(agent.url("/v4/abc"))
+            .metadata_policy(RequestPolicy::new(None, 2))
+            .stats_policy(RequestPolicy::new(None, 2));
+
+        assert!(builder.clone().init().await.is_err());
+        assert_eq!(agent.hits("/v4/abc"), 3);
+
+        assert!(builder.fetch_task_stats(None).await.is_err());
+        assert_eq!(agent.hits("/v4/abc/task/stats"), 1);
+    }
+}
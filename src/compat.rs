@@ -0,0 +1,130 @@
+//! Backward reads of persisted snapshots: `testdata/compat` holds one artifact per released
+//! version, each with a `to_cache_bytes` snapshot and the `Serialize` output of the instance it
+//! was taken from. Every artifact must still restore, to the same values.
+//!
+//! `ADD_COMPAT_ARTIFACT=1 cargo test compat` writes the artifact of the current crate version,
+//! to commit with each release; the test fails until it exists.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use crate::cache::CACHE_FORMAT_VERSION;
+use crate::metadata::ECSMetadata;
+
+const ARTIFACTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/compat");
+const CONTAINER: &[u8] = include_bytes!("../testdata/versions/v4_container.json");
+const TASK: &[u8] = include_bytes!("../testdata/versions/v4_task.json");
+
+#[derive(Serialize, Deserialize)]
+struct Artifact {
+    crate_version: String,
+    cache_format: u32,
+    snapshot: Value,
+    serialized: Value,
+}
+
+fn artifact_path(version: &str) -> PathBuf {
+    Path::new(ARTIFACTS).join(format!("{version}.json"))
+}
+
+fn current_artifact() -> Artifact {
+    let metadata = ECSMetadata::from_documents(CONTAINER.to_vec(), Some(TASK.to_vec()), None).unwrap();
+    Artifact {
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        cache_format: CACHE_FORMAT_VERSION,
+        snapshot: serde_json::from_slice(&metadata.to_cache_bytes()).unwrap(),
+        serialized: serde_json::to_value(&metadata).unwrap(),
+    }
+}
+
+/// Every field of `expected` that `actual` lacks or holds another value for, by path. Fields
+/// only `actual` has are additions, which don't break a reader.
+fn backward_diff(expected: &Value, actual: &Value, path: &str) -> Vec<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .flat_map(|(key, expected)| match actual.get(key) {
+                Some(actual) => backward_diff(expected, actual, &join(path, key)),
+                None => vec![format!("{} is missing, was {expected}", join(path, key))],
+            })
+            .collect(),
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => expected
+            .iter()
+            .zip(actual)
+            .enumerate()
+            .flat_map(|(index, (expected, actual))| backward_diff(expected, actual, &format!("{path}[{index}]")))
+            .collect(),
+        _ if expected == actual => Vec::new(),
+        _ => vec![format!("{} is {actual}, was {expected}", if path.is_empty() { "the snapshot" } else { path })],
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        _ => format!("{path}.{key}"),
+    }
+}
+
+#[test]
+fn test_committed_artifacts_read_back() {
+    let mut artifacts = fs::read_dir(ARTIFACTS)
+        .expect("testdata/compat missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect::<Vec<_>>();
+    artifacts.sort();
+    assert!(!artifacts.is_empty(), "no artifact in testdata/compat");
+
+    for path in artifacts {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let artifact: Artifact = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap_or_else(|err| panic!("{name}: not an artifact, {err}"));
+        assert_eq!(name, format!("{}.json", artifact.crate_version), "{name}: named after another version");
+        assert_eq!(artifact.snapshot["format"], artifact.cache_format, "{name}: the envelope has another format");
+        assert!(artifact.cache_format <= CACHE_FORMAT_VERSION, "{name}: format {} is newer than this crate", artifact.cache_format);
+
+        let restored = ECSMetadata::from_cache_bytes(&serde_json::to_vec(&artifact.snapshot).unwrap())
+            .unwrap_or_else(|err| panic!("{name}: the snapshot no longer restores, {err}"));
+        let mut expected = artifact.serialized;
+        let mut actual = serde_json::to_value(&restored).unwrap();
+        // later versions may well warn about fields the documents of the artifact lack
+        for serialized in [&mut expected, &mut actual] {
+            serialized.as_object_mut().unwrap().remove("warnings");
+        }
+        let diff = backward_diff(&expected, &actual, "");
+        assert!(diff.is_empty(), "{name}: the restored snapshot differs:\n{}", diff.join("\n"));
+    }
+}
+
+#[test]
+fn test_current_version_has_an_artifact() {
+    let current = current_artifact();
+    let path = artifact_path(&current.crate_version);
+    if env::var_os("ADD_COMPAT_ARTIFACT").is_some() && !path.exists() {
+        fs::write(&path, serde_json::to_string_pretty(&current).unwrap() + "\n").unwrap();
+    }
+    // a released artifact is never rewritten, only the crate version moving on adds one
+    assert!(path.exists(), "no artifact for {}, run ADD_COMPAT_ARTIFACT=1 cargo test compat", current.crate_version);
+}
+
+#[test]
+fn test_backward_diff() {
+    let expected = serde_json::json!({"container": {"Name": "web", "Labels": {"cluster": "prod"}, "Networks": [{"Mode": "awsvpc"}]}});
+    assert!(backward_diff(&expected, &expected, "").is_empty());
+
+    // additions don't break a reader
+    let added = serde_json::json!({"container": {"Name": "web", "Labels": {"cluster": "prod"}, "Networks": [{"Mode": "awsvpc", "Ipv6": true}]}, "task": {}});
+    assert!(backward_diff(&expected, &added, "").is_empty());
+
+    let renamed = serde_json::json!({"container": {"ContainerName": "web", "Labels": {"cluster": "dev"}, "Networks": [{"Mode": "bridge"}]}});
+    assert_eq!(
+        backward_diff(&expected, &renamed, ""),
+        vec![
+            r#"container.Labels.cluster is "dev", was "prod""#,
+            r#"container.Name is missing, was "web""#,
+            r#"container.Networks[0].Mode is "bridge", was "awsvpc""#,
+        ]
+    );
+    assert_eq!(backward_diff(&expected["container"]["Networks"], &serde_json::json!([]), "Networks"), vec![r#"Networks is [], was [{"Mode":"awsvpc"}]"#]);
+}
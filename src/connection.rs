@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use crate::client::HttpClient;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+
+/// What becomes of the HTTP client and its keep-alive connections, see
+/// `ECSMetadataBuilder::connection_policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionPolicy {
+    /// One client for every fetch of the builder and of the snapshots it builds (refreshes,
+    /// stats), whose pool keeps idle connections open for reuse
+    #[default]
+    Reuse,
+    /// A client per fetch, dropped with its connections once the response is read, so no socket
+    /// outlives `init`. The snapshot can't be refreshed nor watched.
+    CloseAfterInit,
+}
+
+/// Connection reuse of a snapshot's client, see `ECSMetadata::client_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientStats {
+    pub policy: ConnectionPolicy,
+    /// Whether a pooled client is held. Its idle keep-alive connections stay open until the pool
+    /// times them out, after 90s.
+    pub pooled_client: bool,
+    /// Requests sent with the pooled client, all but the first ones of each connection reusing it
+    pub pooled_requests: u64,
+}
+
+/// Client of `ConnectionPolicy::Reuse`, built on first use and shared by the clones of a
+/// builder, including the ones kept by its snapshots
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientPool(Arc<PooledClient>);
+
+#[derive(Debug, Default)]
+struct PooledClient {
+    client: OnceLock<HttpClient>,
+    requests: Arc<AtomicU64>,
+}
+
+impl ClientPool {
+    pub(crate) fn get_or_try_init(&self, build: impl FnOnce(Arc<AtomicU64>) -> Result<HttpClient, ECSMetadataError>) -> Result<HttpClient, ECSMetadataError> {
+        if let Some(client) = self.0.client.get() {
+            return Ok(client.clone());
+        }
+        let client = build(self.0.requests.clone())?;
+        // a concurrent first fetch may have won, its client is the one kept
+        Ok(self.0.client.get_or_init(|| client).clone())
+    }
+
+    fn stats(&self, policy: ConnectionPolicy) -> ClientStats {
+        ClientStats {
+            policy,
+            pooled_client: self.0.client.get().is_some(),
+            pooled_requests: self.0.requests.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl ECSMetadata {
+    /// Connection reuse of the client the snapshot refreshes with, `None` when it was not
+    /// fetched from an endpoint (e.g. `from_json`)
+    pub fn client_stats(&self) -> Option<ClientStats> {
+        let source = self.source.as_ref()?;
+        Some(source.pool.stats(source.connection_policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ECSMetadataBuilder;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reuse() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).min_refresh_interval(Duration::ZERO);
+        let mut metadata = builder.clone().init().await.unwrap();
+        assert_eq!(metadata.client_stats(), Some(ClientStats { policy: ConnectionPolicy::Reuse, pooled_client: true, pooled_requests: 1 }));
+        metadata.refresh().await.unwrap();
+        metadata.refresh().await.unwrap();
+        assert_eq!(metadata.client_stats().unwrap().pooled_requests, 3);
+
+        // a setter of the client gets the builder a client of its own
+        let other = builder.connect_timeout(Duration::from_secs(1)).init().await.unwrap();
+        assert_eq!(other.client_stats().unwrap().pooled_requests, 1);
+        assert_eq!(ECSMetadata::from_json(CONTAINER_JSON).unwrap().client_stats(), None);
+    }
+
+    #[tokio::test]
+    async fn test_close_after_init() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).connection_policy(ConnectionPolicy::CloseAfterInit);
+        let mut metadata = builder.init().await.unwrap();
+        assert_eq!(
+            metadata.client_stats(),
+            Some(ClientStats { policy: ConnectionPolicy::CloseAfterInit, pooled_client: false, pooled_requests: 0 })
+        );
+        let err = metadata.refresh().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::ClosedAfterInit), "{err:?}");
+        assert_eq!(agent.hits("/v4/abc"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_refuses_close_after_init() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).connection_policy(ConnectionPolicy::CloseAfterInit);
+        let metadata = builder.init().await.unwrap();
+        let err = crate::watcher::ECSMetadataWatcher::start(metadata.into(), Duration::from_secs(1)).unwrap_err();
+        assert!(matches!(err, ECSMetadataError::ClosedAfterInit), "{err:?}");
+    }
+}
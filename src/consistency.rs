@@ -0,0 +1,107 @@
+use std::fmt;
+use crate::metadata::ECSMetadata;
+
+/// A task definition label of a container that disagrees with the task document, see
+/// `ECSMetadata::consistency_check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelMismatch {
+    /// `task_definition_family` or `task_definition_revision`
+    pub field: &'static str,
+    pub container_name: String,
+    pub label_value: String,
+    pub task_value: String,
+}
+
+impl fmt::Display for LabelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} is {:?} in the labels, {:?} in the task document", self.field, self.container_name, self.label_value, self.task_value)
+    }
+}
+
+impl ECSMetadata {
+    /// Task definition as `family:revision`, from the task document when fetched, otherwise from
+    /// the labels of this container. `None` when neither names the family.
+    pub fn task_definition(&self) -> Option<String> {
+        let task = self.task();
+        let family = task.and_then(|task| task.family()).unwrap_or(self.task_definition_family());
+        let revision = task.and_then(|task| task.revision()).unwrap_or(self.task_definition_revision());
+        match (family, revision) {
+            ("", _) => None,
+            (family, "") => Some(family.to_string()),
+            (family, revision) => Some(format!("{family}:{revision}")),
+        }
+    }
+
+    /// Task definition labels of this container and of every entry of the task document that
+    /// disagree with the `Family` and `Revision` of the task document, e.g. during an in-place
+    /// agent upgrade. Labels a container lacks are not compared; empty without a task document.
+    pub fn consistency_check(&self) -> Vec<LabelMismatch> {
+        let Some(task) = self.task() else { return Vec::new() };
+        let mut mismatches = Vec::new();
+        // this container's entry of the task document is the container document again
+        let entries = task.containers().iter().filter(|entry| entry.docker_id() != self.docker_id());
+        for container in std::iter::once(self.container()).chain(entries) {
+            let mut check = |field, label: &str, task_value: Option<&str>| {
+                if let Some(task_value) = task_value.filter(|task_value| !label.is_empty() && label != *task_value) {
+                    mismatches.push(LabelMismatch {
+                        field,
+                        container_name: container.container_name().to_string(),
+                        label_value: label.to_string(),
+                        task_value: task_value.to_string(),
+                    });
+                }
+            };
+            check("task_definition_family", container.task_definition_family(), task.family());
+            check("task_definition_revision", container.task_definition_revision(), task.revision());
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+
+    fn metadata(task_fields: &str, entries: &[String]) -> ECSMetadata {
+        let task = task_json(entries).replacen('{', &format!("{{{task_fields}"), 1);
+        ECSMetadata::from_documents(CONTAINER_JSON.into(), Some(task.into_bytes()), None).unwrap()
+    }
+
+    #[test]
+    fn test_task_definition() {
+        let labelled = ECSMetadata::from_documents(CONTAINER_JSON.into(), None, None).unwrap();
+        assert_eq!(labelled.task_definition().as_deref(), Some("streamer:12"));
+        // the task document wins
+        let upgraded = metadata(r#""Family": "streamer", "Revision": "13","#, &[CONTAINER_JSON.to_string()]);
+        assert_eq!(upgraded.task_definition().as_deref(), Some("streamer:13"));
+        assert_eq!(metadata("", &[CONTAINER_JSON.to_string()]).task_definition().as_deref(), Some("streamer:12"));
+        assert_eq!(ECSMetadata::degraded(None).task_definition().as_deref(), Some("unknown:unknown"));
+    }
+
+    #[test]
+    fn test_consistency_check() {
+        let consistent = metadata(r#""Family": "streamer", "Revision": "12","#, &[CONTAINER_JSON.to_string()]);
+        assert!(consistent.consistency_check().is_empty());
+        assert!(ECSMetadata::from_documents(CONTAINER_JSON.into(), None, None).unwrap().consistency_check().is_empty());
+
+        let sidecar = CONTAINER_JSON
+            .replace("2969e5e20eda", "731a0d6a3b42")
+            .replace(r#""com.amazonaws.ecs.container-name": "streamer""#, r#""com.amazonaws.ecs.container-name": "envoy""#)
+            .replace(r#""com.amazonaws.ecs.task-definition-version": "12""#, r#""com.amazonaws.ecs.task-definition-version": "13""#);
+        let upgraded = metadata(r#""Family": "streamer", "Revision": "13","#, &[CONTAINER_JSON.to_string(), sidecar]);
+        let mismatches = upgraded.consistency_check();
+        // this container is reported once, not again for its entry of the task document
+        assert_eq!(
+            mismatches,
+            vec![LabelMismatch {
+                field: "task_definition_revision",
+                container_name: "streamer".to_string(),
+                label_value: "12".to_string(),
+                task_value: "13".to_string(),
+            }]
+        );
+        assert_eq!(mismatches[0].to_string(), r#"task_definition_revision of streamer is "12" in the labels, "13" in the task document"#);
+    }
+}
@@ -0,0 +1,560 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use crate::error::ECSMetadataError;
+use crate::health::{ECSContainerHealth, HealthStatus};
+use crate::image::{self, TagConvention};
+use crate::log_options::LogOptions;
+use crate::memory::MemorySize;
+use crate::metadata::ECSMetadata;
+use crate::network::{self, ECSNetwork, ECSPortMapping, NetworkMode};
+use crate::quantity;
+
+/// Container metadata document, as served for this container or listed in the task document.
+/// Only `DockerId` and the task ARN label are required, see `ECSMetadata` for what the other
+/// accessors return when a field is missing. Entries of the task document only need `DockerId`:
+/// the agent's containers, e.g. the `CNI_PAUSE` one, are served with few labels if any.
+/// The format is at
+/// <https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-metadata-endpoint-v4-response.html>
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "PascalCase")]
+pub struct ECSContainerMetadata {
+    pub(crate) docker_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "ContainerARN", default, skip_serializing_if = "Option::is_none")]
+    container_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) image: Option<String>,
+    #[serde(rename = "ImageID", default, skip_serializing_if = "Option::is_none")]
+    image_id: Option<String>,
+    #[serde(default)]
+    pub(crate) labels: ECSContainerLabels,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) limits: Option<ECSContainerLimits>,
+    #[serde(default)]
+    networks: Vec<ECSNetwork>,
+    #[serde(default)]
+    ports: Vec<ECSPortMapping>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    health: Option<ECSContainerHealth>,
+    #[serde(rename = "Type", default, skip_serializing_if = "Option::is_none")]
+    container_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    known_status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    desired_status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    restart_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_driver: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<BTreeMap<String, String>>"))]
+    log_options: Option<LogOptions>,
+    // keys this crate does not model, see `raw`
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Name prefix of the containers the agent adds to a task, e.g. `~internal~ecs~pause`
+pub(crate) const INTERNAL_CONTAINER_PREFIX: &str = "~internal~";
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ECSContainerLabels {
+    #[serde(rename = "com.amazonaws.ecs.cluster", skip_serializing_if = "Option::is_none")]
+    pub(crate) cluster: Option<String>,
+    #[serde(rename = "com.amazonaws.ecs.container-name", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) container_name: Option<String>,
+    // required in the container document, see `require_task_arn`
+    #[serde(rename = "com.amazonaws.ecs.task-arn", default, skip_serializing_if = "Option::is_none")]
+    task_arn: Option<String>,
+    #[serde(rename = "com.amazonaws.ecs.task-definition-family", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) task_definition_family: Option<String>,
+    #[serde(rename = "com.amazonaws.ecs.task-definition-version", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) task_definition_version: Option<String>,
+}
+
+impl ECSContainerLabels {
+    /// Empty when not labelled, which only entries of the task document may be
+    pub(crate) fn task_arn(&self) -> &str {
+        self.task_arn.as_deref().unwrap_or_default()
+    }
+
+    /// Fails like a parse without the label when the task ARN is missing, which only entries
+    /// of the task document may be
+    pub(crate) fn require_task_arn(&self) -> Result<(), ECSMetadataError> {
+        if self.task_arn.is_none() {
+            let source = <serde_json::Error as serde::de::Error>::missing_field("com.amazonaws.ecs.task-arn");
+            return Err(ECSMetadataError::Deserialize { path: "Labels".to_string(), source: Arc::new(source) });
+        }
+        Ok(())
+    }
+}
+
+/// Lifecycle status of a container, from its `KnownStatus` or `DesiredStatus`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContainerStatus {
+    /// Not started yet, e.g. waiting on a dependency
+    Pending,
+    /// Image pulled
+    Pulled,
+    /// Docker container created, not started
+    Created,
+    Running,
+    /// Resources of the task are set up, the status of the agent's `CNI_PAUSE` container
+    ResourcesProvisioned,
+    Stopped,
+    /// A status this crate does not know, as served
+    Other(String),
+}
+
+impl ContainerStatus {
+    pub(crate) fn parse(status: &str) -> Self {
+        match status.to_ascii_uppercase().as_str() {
+            "PENDING" | "NONE" => Self::Pending,
+            "PULLED" => Self::Pulled,
+            "CREATED" => Self::Created,
+            "RUNNING" => Self::Running,
+            "RESOURCES_PROVISIONED" => Self::ResourcesProvisioned,
+            "STOPPED" => Self::Stopped,
+            _ => Self::Other(status.to_string()),
+        }
+    }
+
+    /// The status as the agent spells it, e.g. `RUNNING`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Pending => "PENDING",
+            Self::Pulled => "PULLED",
+            Self::Created => "CREATED",
+            Self::Running => "RUNNING",
+            Self::ResourcesProvisioned => "RESOURCES_PROVISIONED",
+            Self::Stopped => "STOPPED",
+            Self::Other(status) => status,
+        }
+    }
+}
+
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// returned by `limits()` when the document has none
+static NO_LIMITS: ECSContainerLimits = ECSContainerLimits::new(None, None);
+
+// the JSON Schema is written by hand in schema.rs, schemars drops flattened maps
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ECSContainerLimits {
+    /// CPU limit in vCPUs as served, possibly fractional, see `vcpus`
+    #[serde(rename = "CPU", default, serialize_with = "serialize_vcpus", skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<f64>,
+    /// Memory limit in MiB as served, see `memory_mib`
+    #[serde(rename = "Memory", default, skip_serializing_if = "Option::is_none")]
+    pub mem: Option<u64>,
+    /// Limit keys this crate does not model (yet), as served
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Number>,
+}
+
+// whole vCPUs as integers, as the agent serves them
+fn serialize_vcpus<S: serde::Serializer>(cpu: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    match *cpu {
+        Some(cpu) if cpu.fract() == 0.0 && (0.0..=u64::MAX as f64).contains(&cpu) => serializer.serialize_u64(cpu as u64),
+        cpu => cpu.serialize(serializer),
+    }
+}
+
+impl ECSContainerLimits {
+    pub const fn new(cpu: Option<f64>, mem: Option<u64>) -> Self {
+        Self { cpu, mem, extra: BTreeMap::new() }
+    }
+
+    /// CPU limit in vCPUs, `None` when not set: absent or zero, as the agent serves a container
+    /// without a CPU limit of its own
+    pub fn vcpus(&self) -> Option<f64> {
+        self.cpu.filter(|cpu| *cpu > 0.0)
+    }
+
+    /// `vcpus` in millicores, rounded to the nearest one: 0.25 vCPU is 250
+    pub fn cpu_millicores(&self) -> Option<u64> {
+        self.vcpus().map(quantity::millicores)
+    }
+
+    /// Memory limit in MiB, `None` when not set (absent or zero)
+    pub fn memory_mib(&self) -> Option<u64> {
+        self.mem.filter(|mem| *mem > 0)
+    }
+
+    /// `memory_mib` in bytes
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_limit().map(|memory| memory.as_bytes())
+    }
+
+    /// Any limit by its key in the document, e.g. `CPU`, `Memory` or a resource kind added to the
+    /// agent after this crate's release
+    pub fn limit(&self, name: &str) -> Option<f64> {
+        match name {
+            "CPU" => self.cpu,
+            "Memory" => self.mem.map(|mem| mem as f64),
+            _ => self.extra.get(name)?.as_f64(),
+        }
+    }
+
+    /// CPU limit as a Kubernetes quantity, e.g. `2`, see `ECSTaskLimits::cpu_as_k8s_quantity`.
+    /// `None` when not set.
+    pub fn cpu_as_k8s_quantity(&self) -> Option<String> {
+        self.vcpus().map(quantity::cpu_quantity)
+    }
+
+    /// Memory limit as a Kubernetes quantity, always in `Mi`, e.g. `4096Mi`. `None` when unlimited.
+    pub fn memory_as_k8s_quantity(&self) -> Option<String> {
+        self.memory_mib().map(quantity::memory_quantity)
+    }
+
+    /// `memory_mib` with its unit, `None` when unlimited
+    pub fn memory_limit(&self) -> Option<MemorySize> {
+        self.memory_mib().map(MemorySize::from_mib)
+    }
+}
+
+impl ECSContainerMetadata {
+    // Stand-in document used by degraded instances, every identity field set to the sentinel
+    pub(crate) fn placeholder() -> Self {
+        Self {
+            docker_id: ECSMetadata::UNKNOWN.to_string(),
+            name: None,
+            container_arn: None,
+            image: Some(ECSMetadata::UNKNOWN.to_string()),
+            image_id: None,
+            labels: ECSContainerLabels {
+                cluster: Some(ECSMetadata::UNKNOWN.to_string()),
+                container_name: Some(ECSMetadata::UNKNOWN.to_string()),
+                task_arn: Some(ECSMetadata::UNKNOWN.to_string()),
+                task_definition_family: Some(ECSMetadata::UNKNOWN.to_string()),
+                task_definition_version: Some(ECSMetadata::UNKNOWN.to_string()),
+            },
+            limits: Some(ECSContainerLimits::new(None, None)),
+            networks: Vec::new(),
+            ports: Vec::new(),
+            health: None,
+            container_type: None,
+            known_status: None,
+            desired_status: None,
+            created_at: None,
+            started_at: None,
+            restart_count: None,
+            log_driver: None,
+            log_options: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    pub fn docker_id(&self) -> &str {
+        &self.docker_id
+    }
+
+    /// `Name` as served, the task definition's container name for the containers of the task
+    /// definition; `container_name` reads the label instead
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// ARN of the container, `None` when the document has none (the v2 endpoint)
+    pub fn container_arn(&self) -> Option<&str> {
+        self.container_arn.as_deref()
+    }
+
+    /// Image as served, empty when the document has none
+    pub fn image(&self) -> &str {
+        self.image.as_deref().unwrap_or_default()
+    }
+
+    /// Digest of the local image, e.g. `sha256:25f3...`
+    pub fn image_id(&self) -> Option<&str> {
+        self.image_id.as_deref()
+    }
+
+    /// Tag of the image, `None` without one or for a digest reference (`repo@sha256:...`)
+    pub fn image_tag(&self) -> Option<&str> {
+        image::split_image(self.image()).1
+    }
+
+    /// Tag split at every `separator`, e.g. `["v1.2.3", "staging"]` for `v1.2.3-staging` and `-`.
+    /// A tag without the separator is a single component, no tag at all gives none.
+    pub fn image_tag_components(&self, separator: &str) -> Vec<&str> {
+        match self.image_tag() {
+            Some(tag) if !separator.is_empty() => tag.split(separator).collect(),
+            Some(tag) => vec![tag],
+            None => Vec::new(),
+        }
+    }
+
+    /// Placeholder values of the tag following `convention`, see `TagConvention::captures`.
+    /// `None` without a tag or when it doesn't follow the convention.
+    pub fn image_tag_captures<'a>(&'a self, convention: &'a TagConvention) -> Option<BTreeMap<&'a str, &'a str>> {
+        convention.captures(self.image_tag()?)
+    }
+
+    /// Account ID of the private ECR registry the image comes from, e.g. to look up its scan
+    /// findings. `None` for other registries, public ECR included.
+    pub fn image_registry_account(&self) -> Option<&str> {
+        image::ecr_registry(self.image()).map(|(account, _)| account)
+    }
+
+    /// Region of the private ECR registry the image comes from, `None` for other registries
+    pub fn image_registry_region(&self) -> Option<&str> {
+        image::ecr_registry(self.image()).map(|(_, region)| region)
+    }
+
+    /// Container name as in the task definition, empty when not labelled
+    pub fn container_name(&self) -> &str {
+        self.labels.container_name.as_deref().unwrap_or_default()
+    }
+
+    /// ECS cluster as labelled, i.e. the cluster name or, on some platforms, the cluster ARN.
+    /// Some agent versions omit the label, the cluster is then taken from the task ARN, which is
+    /// only possible with the new ARN format (`task/<cluster>/<task-id>`).
+    pub fn cluster(&self) -> Option<&str> {
+        self.labels.cluster.as_deref().or_else(|| cluster_from_task_arn(self.labels.task_arn()))
+    }
+
+    /// Short cluster name, also when the cluster is only known by its ARN
+    pub fn cluster_name(&self) -> Option<&str> {
+        let cluster = self.cluster()?;
+        Some(cluster.rsplit_once(":cluster/").map_or(cluster, |(_, name)| name))
+    }
+
+    pub fn task_arn(&self) -> &str {
+        self.labels.task_arn()
+    }
+
+    /// Task definition family, empty when not labelled
+    pub fn task_definition_family(&self) -> &str {
+        self.labels.task_definition_family.as_deref().unwrap_or_default()
+    }
+
+    /// Task definition revision, empty when not labelled
+    pub fn task_definition_revision(&self) -> &str {
+        self.labels.task_definition_version.as_deref().unwrap_or_default()
+    }
+
+    /// CPU & Memory resource limits, zero (unlimited) when the document has none
+    pub fn limits(&self) -> &ECSContainerLimits {
+        self.limits.as_ref().unwrap_or(&NO_LIMITS)
+    }
+
+    /// Networks the container is attached to, in the agent's order
+    pub fn networks(&self) -> &[ECSNetwork] {
+        &self.networks
+    }
+
+    /// Network to register the container with: the first awsvpc interface with an IPv4 address,
+    /// otherwise the first network (bridge, host or an IPv6-only ENI)
+    pub fn primary_network(&self) -> Option<&ECSNetwork> {
+        network::primary_network(&self.networks)
+    }
+
+    /// Network mode of the container, from its first network entry. Without any, `Host` if ports
+    /// are mapped and `None` otherwise, see `NetworkMode`.
+    pub fn network_mode(&self) -> NetworkMode {
+        network::network_mode(self.networks.first(), !self.ports.is_empty())
+    }
+
+    /// Networks with the given mode, e.g. `awsvpc`, in the agent's order
+    pub fn networks_by_mode<'a>(&'a self, mode: &'a str) -> impl Iterator<Item = &'a ECSNetwork> + 'a {
+        self.networks.iter().filter(move |network| network.network_mode() == mode)
+    }
+
+    /// IPv4 addresses of every network, in the agent's order
+    pub fn ipv4_addresses(&self) -> impl Iterator<Item = &str> {
+        self.networks.iter().flat_map(ECSNetwork::ipv4_addresses).map(String::as_str)
+    }
+
+    /// IPv4 address of the primary network, the task ENI's in awsvpc mode and the bridge
+    /// address in bridge mode, see `primary_network`
+    pub fn private_ipv4(&self) -> Option<Ipv4Addr> {
+        self.primary_network()?.private_ipv4()
+    }
+
+    /// IPv6 address of the primary network, see `primary_network`
+    pub fn private_ipv6(&self) -> Option<Ipv6Addr> {
+        self.primary_network()?.private_ipv6()
+    }
+
+    /// See `ECSNetwork::private_dns_name`, of the primary network
+    pub fn private_dns_name(&self) -> Option<&str> {
+        self.primary_network()?.private_dns_name()
+    }
+
+    /// See `ECSNetwork::mac_address`, of the primary network
+    pub fn mac_address(&self) -> Option<&str> {
+        self.primary_network()?.mac_address()
+    }
+
+    /// Port mappings of the container
+    pub fn ports(&self) -> &[ECSPortMapping] {
+        &self.ports
+    }
+
+    /// Host address of the first port binding to a specific interface (not `0.0.0.0`).
+    /// Only bridge and host networking bind ports on the host, in awsvpc mode this is `None`.
+    pub fn host_ip(&self) -> Option<IpAddr> {
+        network::host_ip(&self.ports)
+    }
+
+    /// Address to register `container_port` at, e.g. as a health check target: the bound host
+    /// address and port, or the ENI address in awsvpc mode. `None` when the port is not mapped or
+    /// only bound to all interfaces, the host's own address is not part of the metadata then.
+    pub fn advertised_address(&self, container_port: u16) -> Option<SocketAddr> {
+        network::advertised_address(&self.ports, self.primary_network(), container_port)
+    }
+
+    /// Health check state, only served for containers with a health check in the task definition
+    pub fn health(&self) -> Option<&ECSContainerHealth> {
+        self.health.as_ref()
+    }
+
+    /// Output of the last health check, see `ECSContainerHealth::output`
+    pub fn health_output(&self) -> Option<&str> {
+        self.health.as_ref()?.output()
+    }
+
+    /// When the health status last changed, see `ECSContainerHealth::status_since`
+    pub fn health_status_since(&self) -> Option<&str> {
+        self.health.as_ref()?.status_since()
+    }
+
+    /// `Type` as served: `NORMAL` for the containers of the task definition, something else
+    /// (e.g. `CNI_PAUSE`) for the ones the agent adds
+    pub fn container_type(&self) -> Option<&str> {
+        self.container_type.as_deref()
+    }
+
+    /// Status the agent last saw, e.g. `PENDING`, `RUNNING` or `STOPPED`
+    pub fn known_status(&self) -> Option<&str> {
+        self.known_status.as_deref()
+    }
+
+    /// Status the agent is moving the container to
+    pub fn desired_status(&self) -> Option<&str> {
+        self.desired_status.as_deref()
+    }
+
+    /// `known_status` as a `ContainerStatus`
+    pub fn known_status_kind(&self) -> Option<ContainerStatus> {
+        self.known_status().map(ContainerStatus::parse)
+    }
+
+    /// `desired_status` as a `ContainerStatus`
+    pub fn desired_status_kind(&self) -> Option<ContainerStatus> {
+        self.desired_status().map(ContainerStatus::parse)
+    }
+
+    /// Whether the agent is stopping the container: desired `STOPPED` while it is not known
+    /// stopped yet, typically in the stop timeout between SIGTERM and SIGKILL
+    pub fn is_stopping(&self) -> bool {
+        self.desired_status_kind() == Some(ContainerStatus::Stopped) && !self.is_stopped()
+    }
+
+    /// Whether the agent knows the container stopped
+    pub fn is_stopped(&self) -> bool {
+        self.known_status_kind() == Some(ContainerStatus::Stopped)
+    }
+
+    /// Whether the health check reports `HEALTHY`, `false` without a health check
+    pub fn is_healthy(&self) -> bool {
+        self.health.as_ref().map(ECSContainerHealth::status_kind) == Some(HealthStatus::Healthy)
+    }
+
+    /// When Docker created the container, an RFC 3339 timestamp as served
+    pub fn created_at(&self) -> Option<&str> {
+        self.created_at.as_deref()
+    }
+
+    /// When the container started, an RFC 3339 timestamp as served; absent until it has
+    pub fn started_at(&self) -> Option<&str> {
+        self.started_at.as_deref()
+    }
+
+    /// Times the agent restarted the container under its restart policy, served only with one
+    pub fn restart_count(&self) -> Option<u64> {
+        self.restart_count
+    }
+
+    /// Whether the container is one of the task definition's rather than added by the agent.
+    /// Without a `Type` the agent's `~internal~` name prefix tells them apart.
+    pub fn is_normal(&self) -> bool {
+        match self.container_type() {
+            Some(container_type) => container_type == "NORMAL",
+            None => !self.container_name().starts_with(INTERNAL_CONTAINER_PREFIX),
+        }
+    }
+
+    /// Optional fields absent from the document, by their name in it
+    pub(crate) fn missing_fields(&self) -> Vec<&'static str> {
+        [
+            ("Image", self.image.is_none()),
+            ("Limits", self.limits.is_none()),
+            ("com.amazonaws.ecs.container-name", self.labels.container_name.is_none()),
+            ("com.amazonaws.ecs.task-definition-family", self.labels.task_definition_family.is_none()),
+            ("com.amazonaws.ecs.task-definition-version", self.labels.task_definition_version.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(field, missing)| missing.then_some(field))
+        .collect()
+    }
+
+    /// Log driver of the container, e.g. `awslogs`, `splunk` or `awsfirelens`
+    pub fn log_driver(&self) -> Option<&str> {
+        self.log_driver.as_deref()
+    }
+
+    /// Options of the log driver as served, credentials included (e.g. `splunk-token`), see
+    /// `log_options_redacted` for anything displayed or exported
+    pub fn log_options(&self) -> Option<&BTreeMap<String, String>> {
+        self.log_options.as_ref().map(LogOptions::raw)
+    }
+
+    /// `log_options` with the values of the sensitive keys replaced by `[redacted]`, see
+    /// `REDACTED_LOG_OPTION_KEYS`. Debug output and serialization of the document redact as well.
+    pub fn log_options_redacted(&self) -> Option<BTreeMap<String, String>> {
+        self.log_options.as_ref().map(LogOptions::redacted)
+    }
+
+    /// Top-level keys of the document that this crate does not model (yet), as served, e.g.
+    /// `DockerName` or a key added to the agent after this crate's release. They serialize back
+    /// along with the modeled ones, so the document can be forwarded whole.
+    pub fn raw(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    pub(crate) fn redact_log_options(&mut self, extra_keys: &Arc<[String]>) {
+        if let Some(options) = &mut self.log_options {
+            options.set_extra_keys(extra_keys.clone());
+        }
+    }
+
+    pub(crate) fn truncate_health_output(&mut self, max_len: usize) {
+        if let Some(health) = &mut self.health {
+            health.truncate_output(max_len);
+        }
+    }
+}
+
+/// `arn:aws:ecs:<region>:<account>:task/<cluster>/<task-id>`, the old format has no cluster segment
+pub(crate) fn cluster_from_task_arn(task_arn: &str) -> Option<&str> {
+    let (_, resource) = task_arn.split_once(":task/")?;
+    let (cluster, _) = resource.split_once('/')?;
+    Some(cluster).filter(|cluster| !cluster.is_empty())
+}
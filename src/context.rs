@@ -0,0 +1,164 @@
+use crate::container::ECSContainerLimits;
+use crate::metadata::ECSMetadata;
+
+/// Accessor surface of `ECSMetadata`, so applications can depend on `impl ECSContext` or
+/// `Arc<dyn ECSContext>` and plug in their own implementation in tests or outside ECS
+pub trait ECSContext: Send + Sync {
+    fn task_arn(&self) -> &str;
+    fn task_id(&self) -> Option<String>;
+    fn cluster(&self) -> Option<&str>;
+    fn cluster_name(&self) -> Option<&str>;
+    fn region(&self) -> Option<&str>;
+    fn availability_zone(&self) -> Option<&str>;
+    fn limits(&self) -> &ECSContainerLimits;
+    fn docker_id(&self) -> &str;
+    fn image(&self) -> &str;
+    fn task_definition_family(&self) -> &str;
+    fn task_definition_revision(&self) -> &str;
+    fn container_name(&self) -> &str;
+}
+
+impl ECSContext for ECSMetadata {
+    fn task_arn(&self) -> &str {
+        ECSMetadata::task_arn(self)
+    }
+
+    fn task_id(&self) -> Option<String> {
+        ECSMetadata::task_id(self)
+    }
+
+    fn cluster(&self) -> Option<&str> {
+        ECSMetadata::cluster(self)
+    }
+
+    fn cluster_name(&self) -> Option<&str> {
+        ECSMetadata::cluster_name(self)
+    }
+
+    fn region(&self) -> Option<&str> {
+        ECSMetadata::region(self)
+    }
+
+    fn availability_zone(&self) -> Option<&str> {
+        ECSMetadata::availability_zone(self)
+    }
+
+    fn limits(&self) -> &ECSContainerLimits {
+        ECSMetadata::limits(self)
+    }
+
+    fn docker_id(&self) -> &str {
+        ECSMetadata::docker_id(self)
+    }
+
+    fn image(&self) -> &str {
+        ECSMetadata::image(self)
+    }
+
+    fn task_definition_family(&self) -> &str {
+        ECSMetadata::task_definition_family(self)
+    }
+
+    fn task_definition_revision(&self) -> &str {
+        ECSMetadata::task_definition_revision(self)
+    }
+
+    fn container_name(&self) -> &str {
+        ECSMetadata::container_name(self)
+    }
+}
+
+static NO_LIMITS: ECSContainerLimits = ECSContainerLimits::new(None, None);
+
+/// Context for non-ECS environments: the same placeholders as a degraded `ECSMetadata`,
+/// i.e. `ECSMetadata::UNKNOWN` for strings, `None` for optional values and no limits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopECSContext;
+
+impl ECSContext for NoopECSContext {
+    fn task_arn(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+
+    fn task_id(&self) -> Option<String> {
+        None
+    }
+
+    fn cluster(&self) -> Option<&str> {
+        Some(ECSMetadata::UNKNOWN)
+    }
+
+    fn cluster_name(&self) -> Option<&str> {
+        Some(ECSMetadata::UNKNOWN)
+    }
+
+    fn region(&self) -> Option<&str> {
+        None
+    }
+
+    fn availability_zone(&self) -> Option<&str> {
+        None
+    }
+
+    fn limits(&self) -> &ECSContainerLimits {
+        &NO_LIMITS
+    }
+
+    fn docker_id(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+
+    fn image(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+
+    fn task_definition_family(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+
+    fn task_definition_revision(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+
+    fn container_name(&self) -> &str {
+        ECSMetadata::UNKNOWN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use std::sync::Arc;
+
+    fn describe(context: &dyn ECSContext) -> String {
+        format!("{}/{}", context.cluster_name().unwrap_or("-"), context.container_name())
+    }
+
+    #[test]
+    fn test_metadata_as_context() {
+        let metadata = ECSMetadata::from_json(CONTAINER_JSON).expect("fixture should parse");
+        assert_eq!(describe(&metadata), "production/streamer");
+
+        let shared: Arc<dyn ECSContext> = Arc::new(metadata);
+        assert_eq!(shared.task_id().as_deref(), Some("021447970bce4bd58069f1925cd87bc0"));
+        assert_eq!(shared.limits().mem, Some(4096));
+    }
+
+    #[test]
+    fn test_noop_context() {
+        let shared: Arc<dyn ECSContext> = Arc::new(NoopECSContext);
+        assert_eq!(describe(shared.as_ref()), "unknown/unknown");
+        assert_eq!(shared.task_id(), None);
+        assert_eq!(shared.region(), None);
+        assert_eq!(shared.limits(), &ECSContainerLimits::new(None, None));
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_documents() {
+        assert!(matches!(
+            ECSMetadata::from_json(r#"{"DockerId": "abc"}"#),
+            Err(crate::ECSMetadataError::Deserialize { .. })
+        ));
+    }
+}
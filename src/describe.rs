@@ -0,0 +1,153 @@
+use serde_json::{json, Map, Value};
+use crate::container::ECSContainerMetadata;
+use crate::metadata::ECSMetadata;
+use crate::task::vcpus_to_cpu_units;
+
+impl ECSMetadata {
+    /// Converts the metadata into the shape of a task entry of `aws ecs describe-tasks`, so it can
+    /// be joined with API output without a translation table. Mapped fields:
+    /// `taskArn`, `clusterArn`, `taskDefinitionArn`, `availabilityZone`, `cpu`, `memory` and
+    /// `containers[]` (`name`, `image`, `runtimeId`, `taskArn`, `cpu`, `memory`).
+    /// As in the API, `cpu` is expressed in CPU units and both `cpu` and `memory` are strings.
+    ///
+    /// The task-level fields and the sibling containers are only known when the task document
+    /// was fetched, otherwise only this container is listed. Fields with no metadata-endpoint
+    /// counterpart (e.g. `group`, `attachments`, `lastStatus`, `desiredStatus`, `containerArn`)
+    /// are omitted rather than guessed.
+    pub fn to_describe_tasks_like(&self) -> Value {
+        let mut task = Map::new();
+        task.insert("taskArn".to_string(), json!(self.task_arn()));
+        if let Some(cluster_arn) = self.cluster_arn() {
+            task.insert("clusterArn".to_string(), json!(cluster_arn));
+        }
+        if let Some(task_definition_arn) = self.task_definition_arn() {
+            task.insert("taskDefinitionArn".to_string(), json!(task_definition_arn));
+        }
+        if let Some(az) = self.availability_zone() {
+            task.insert("availabilityZone".to_string(), json!(az));
+        }
+        if let Some(limits) = self.task().and_then(|task| task.limits()) {
+            if let Some(cpu_units) = limits.cpu_units() {
+                task.insert("cpu".to_string(), json!(cpu_units.to_string()));
+            }
+            if let Some(mem) = limits.memory_mib() {
+                task.insert("memory".to_string(), json!(mem.to_string()));
+            }
+        }
+
+        let containers: Vec<Value> = match self.task() {
+            Some(task) if !task.containers().is_empty() => task.containers().iter().map(describe_container).collect(),
+            _ => vec![describe_container(self.container())],
+        };
+        task.insert("containers".to_string(), Value::Array(containers));
+
+        Value::Object(task)
+    }
+
+    /// Cluster ARN, either straight from the cluster label or built from the task ARN when
+    /// the label only carries the cluster name
+    pub(crate) fn cluster_arn(&self) -> Option<String> {
+        let cluster = self.cluster()?;
+        if cluster.starts_with("arn:") {
+            return Some(cluster.to_string());
+        }
+        let prefix = arn_prefix(self.task_arn())?;
+        Some(format!("{prefix}:cluster/{cluster}"))
+    }
+
+    fn task_definition_arn(&self) -> Option<String> {
+        let prefix = arn_prefix(self.task_arn())?;
+        Some(format!(
+            "{prefix}:task-definition/{}:{}",
+            self.task_definition_family(),
+            self.task_definition_revision()
+        ))
+    }
+}
+
+fn describe_container(container: &ECSContainerMetadata) -> Value {
+    json!({
+        "name": container.container_name(),
+        "image": container.image(),
+        "runtimeId": container.docker_id(),
+        "taskArn": container.task_arn(),
+        "cpu": vcpus_to_cpu_units(container.limits().vcpus().unwrap_or_default()).to_string(),
+        "memory": container.limits().memory_mib().unwrap_or_default().to_string(),
+    })
+}
+
+/// `arn:<partition>:ecs:<region>:<account>` part of an ECS ARN
+fn arn_prefix(arn: &str) -> Option<&str> {
+    if !arn.starts_with("arn:") {
+        return None;
+    }
+    let (resource_start, _) = arn.match_indices(':').nth(4)?;
+    Some(&arn[..resource_start])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use crate::task::tests::{sidecar_json, task_json};
+
+    #[test]
+    fn test_describe_tasks_container_only() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(
+            metadata.to_describe_tasks_like(),
+            json!({
+                "taskArn": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0",
+                "clusterArn": "arn:aws:ecs:us-east-1:939885537497:cluster/production",
+                "taskDefinitionArn": "arn:aws:ecs:us-east-1:939885537497:task-definition/streamer:12",
+                "containers": [{
+                    "name": "streamer",
+                    "image": "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production",
+                    "runtimeId": "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0",
+                    "taskArn": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0",
+                    "cpu": "2048",
+                    "memory": "4096"
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_describe_tasks_with_task_document() {
+        let task = task_json(&[CONTAINER_JSON.to_string(), sidecar_json()]);
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(&task));
+        let described = metadata.to_describe_tasks_like();
+
+        assert_eq!(described["availabilityZone"], "us-east-1b");
+        assert_eq!(described["cpu"], "4096");
+        assert_eq!(described["memory"], "8192");
+        let names: Vec<&str> = described["containers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|container| container["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["streamer", "envoy"]);
+    }
+
+    #[test]
+    fn test_cluster_arn_label() {
+        let metadata = metadata_from_json(
+            &CONTAINER_JSON.replace(
+                r#""com.amazonaws.ecs.cluster": "production""#,
+                r#""com.amazonaws.ecs.cluster": "arn:aws:ecs:us-east-1:939885537497:cluster/production""#,
+            ),
+            None,
+        );
+        assert_eq!(
+            metadata.to_describe_tasks_like()["clusterArn"],
+            "arn:aws:ecs:us-east-1:939885537497:cluster/production"
+        );
+    }
+
+    #[test]
+    fn test_arn_prefix() {
+        assert_eq!(arn_prefix("arn:aws-cn:ecs:cn-north-1:123:task/abc"), Some("arn:aws-cn:ecs:cn-north-1:123"));
+        assert_eq!(arn_prefix("unknown"), None);
+    }
+}
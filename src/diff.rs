@@ -0,0 +1,139 @@
+use serde::Serialize;
+use crate::container::ECSContainerLimits;
+use crate::metadata::ECSMetadata;
+
+/// Outcome of comparing one field across two snapshots
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum FieldChange<T> {
+    Unchanged,
+    Changed { old: T, new: T },
+}
+
+impl<T: PartialEq + Clone> FieldChange<T> {
+    fn compare(old: T, new: T) -> Self {
+        if old == new {
+            FieldChange::Unchanged
+        } else {
+            FieldChange::Changed { old, new }
+        }
+    }
+}
+
+impl<T> FieldChange<T> {
+    pub fn is_changed(&self) -> bool {
+        matches!(self, FieldChange::Changed { .. })
+    }
+}
+
+/// Per-field comparison of the stable identity fields of two snapshots.
+/// Volatile fields (statuses, health, timestamps) are intentionally left out.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MetadataDiff {
+    pub docker_id: FieldChange<String>,
+    pub image: FieldChange<String>,
+    pub cluster: FieldChange<Option<String>>,
+    pub container_name: FieldChange<String>,
+    pub task_arn: FieldChange<String>,
+    pub task_definition_family: FieldChange<String>,
+    pub task_definition_revision: FieldChange<String>,
+    pub limits: FieldChange<ECSContainerLimits>,
+    pub availability_zone: FieldChange<Option<String>>,
+}
+
+impl MetadataDiff {
+    /// True if at least one field differs between the two snapshots
+    pub fn has_changes(&self) -> bool {
+        self.docker_id.is_changed()
+            || self.image.is_changed()
+            || self.cluster.is_changed()
+            || self.container_name.is_changed()
+            || self.task_arn.is_changed()
+            || self.task_definition_family.is_changed()
+            || self.task_definition_revision.is_changed()
+            || self.limits.is_changed()
+            || self.availability_zone.is_changed()
+    }
+}
+
+impl ECSMetadata {
+    /// Compares this snapshot (old) against `other` (new), e.g. before and after a rolling deploy
+    pub fn diff(&self, other: &ECSMetadata) -> MetadataDiff {
+        let owned = |value: &str| value.to_string();
+        MetadataDiff {
+            docker_id: FieldChange::compare(owned(self.docker_id()), owned(other.docker_id())),
+            image: FieldChange::compare(owned(self.image()), owned(other.image())),
+            cluster: FieldChange::compare(self.cluster().map(owned), other.cluster().map(owned)),
+            container_name: FieldChange::compare(owned(self.container_name()), owned(other.container_name())),
+            task_arn: FieldChange::compare(owned(self.task_arn()), owned(other.task_arn())),
+            task_definition_family: FieldChange::compare(
+                owned(self.task_definition_family()),
+                owned(other.task_definition_family()),
+            ),
+            task_definition_revision: FieldChange::compare(
+                owned(self.task_definition_revision()),
+                owned(other.task_definition_revision()),
+            ),
+            limits: FieldChange::compare(self.limits().clone(), other.limits().clone()),
+            availability_zone: FieldChange::compare(
+                self.availability_zone().map(owned),
+                other.availability_zone().map(owned),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_diff_identical_snapshots() {
+        let old = metadata_from_json(CONTAINER_JSON, None);
+        let new = metadata_from_json(CONTAINER_JSON, None);
+        let diff = old.diff(&new);
+        assert!(!diff.has_changes());
+        assert_eq!(old, new);
+        assert_eq!(diff.cluster, FieldChange::Unchanged);
+    }
+
+    #[test]
+    fn test_diff_rolling_deploy() {
+        let old = metadata_from_json(CONTAINER_JSON, None);
+        let deployed = CONTAINER_JSON
+            .replace("streamer:latest-production", "streamer:v2-production")
+            .replace(r#""com.amazonaws.ecs.task-definition-version": "12""#, r#""com.amazonaws.ecs.task-definition-version": "13""#)
+            .replace(r#""Memory": 4096"#, r#""Memory": 8192"#);
+        let new = metadata_from_json(&deployed, None);
+
+        let diff = old.diff(&new);
+        assert!(diff.has_changes());
+        assert_eq!(diff.cluster, FieldChange::Unchanged);
+        assert_eq!(
+            diff.task_definition_revision,
+            FieldChange::Changed { old: "12".to_string(), new: "13".to_string() }
+        );
+        assert!(diff.image.is_changed());
+        assert_eq!(
+            diff.limits,
+            FieldChange::Changed {
+                old: ECSContainerLimits::new(Some(2.0), Some(4096)),
+                new: ECSContainerLimits::new(Some(2.0), Some(8192)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_serializes_for_audit_logs() {
+        let old = metadata_from_json(CONTAINER_JSON, None);
+        let new = metadata_from_json(CONTAINER_JSON, Some(r#"{"AvailabilityZone": "us-east-1b"}"#));
+
+        let json = serde_json::to_value(old.diff(&new)).expect("diff should serialize");
+        assert_eq!(json["cluster"], serde_json::json!({"state": "unchanged"}));
+        assert_eq!(
+            json["availability_zone"],
+            serde_json::json!({"state": "changed", "old": null, "new": "us-east-1b"})
+        );
+    }
+}
@@ -1,13 +1,544 @@
 use reqwest::Error as ReqwestError;
+use serde::de::IgnoredAny;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::env::VarError;
+use std::fmt::{self, Write};
+use std::sync::Arc;
 use thiserror::Error;
+use crate::identity::IdentityViolation;
 
-/// Context-based errors, plus wrapped reqwest errors
-#[derive(Error, Debug)]
+/// Context-based errors, plus wrapped reqwest errors.
+/// Underlying failures are kept as `source()` rather than repeated in the message, and shared
+/// behind an `Arc` so that errors can be cloned (e.g. to cache a failed initialization).
+/// Messages start with the `Phase` of the failure, e.g. `[phase=connect] HTTP error`.
+#[derive(Error, Debug, Clone)]
 pub enum ECSMetadataError {
-    #[error("Failed to fetch ECS metadata")]
-    FetchError,
-    #[error("HTTP error: {0}")]
-    HttpError(#[from] ReqwestError),
-    #[error("Environment variable {0} not set")]
-    EnvVarNotSet(String),
-}
\ No newline at end of file
+    /// Returned by `ReadinessHandle::wait_ready` when the initialization gave up before any
+    /// attempt completed, e.g. with the first attempt hanging past `give_up_after`
+    #[error("[phase={}] Initialization gave up before an attempt completed", self.phase())]
+    InitAborted,
+    /// Transport failure: connecting, timing out, reading the body, or a request that can't be
+    /// built. Error statuses are `HttpStatus`.
+    #[error("[phase={}] HTTP error", self.phase())]
+    HttpError(#[source] Arc<ReqwestError>),
+    /// Error status of the metadata endpoint, with the start of the body which usually tells
+    /// why, e.g. a 404 for a container the agent doesn't know or a 503 while it starts
+    #[error("[phase={}] HTTP status {status} from {url} (body starts with {snippet:?})", self.phase())]
+    HttpStatus { status: u16, url: String, snippet: String },
+    /// Certificate or handshake failure of an `https` endpoint override, only told apart from
+    /// `HttpError` with the `rustls` feature
+    #[error("[phase={}] TLS error", self.phase())]
+    TlsError(#[source] Arc<ReqwestError>),
+    /// JSON that doesn't convert, e.g. a stats entry of the wrong shape. Documents as served
+    /// fail with `Deserialize` or `UnexpectedContent` instead.
+    #[error("[phase={}] Failed to parse ECS metadata", self.phase())]
+    ParseError(#[source] Arc<serde_json::Error>),
+    /// Well-formed JSON document of the wrong shape, with the path of the offending value, e.g.
+    /// `Networks[0].IPv4Addresses[1]`, or of the object missing a field, `.` for the root
+    #[error("[phase={}] Metadata document does not match the expected shape at {path}", self.phase())]
+    Deserialize {
+        path: String,
+        #[source]
+        source: Arc<serde_json::Error>,
+    },
+    #[error("[phase={}] Environment variable {name} not set", self.phase())]
+    EnvVarNotSet {
+        name: String,
+        #[source]
+        source: VarError,
+    },
+    /// No endpoint was configured and `ECSMetadataBuilder::env_lookup` forbids reading one from
+    /// the environment
+    #[error("[phase={}] No metadata endpoint configured and environment lookup is disabled", self.phase())]
+    EndpointNotConfigured,
+    #[error("[phase={}] Refusing metadata endpoint {uri}: {reason}", self.phase())]
+    InvalidEndpoint {
+        uri: String,
+        reason: String,
+        #[source]
+        source: Option<url::ParseError>,
+    },
+    #[error("[phase={}] Metadata was not fetched from an endpoint and cannot be refreshed", self.phase())]
+    NotRefreshable,
+    /// Refresh or watcher of a snapshot built with `ConnectionPolicy::CloseAfterInit`
+    #[error("[phase={}] Metadata was built with ConnectionPolicy::CloseAfterInit and cannot be refreshed", self.phase())]
+    ClosedAfterInit,
+    #[error("[phase={}] No container with ID or name {0} in the task", self.phase())]
+    ContainerNotFound(String),
+    #[error("[phase={}] Multiple containers in the task match {0}", self.phase())]
+    AmbiguousContainer(String),
+    #[error("[phase={}] Invalid image tag pattern {pattern}: {reason}", self.phase())]
+    InvalidTagPattern { pattern: String, reason: String },
+    /// Returned by the `FromStr` of `TaskArn` and `ContainerArn`
+    #[error("[phase={}] Invalid ECS ARN {arn}: {reason}", self.phase())]
+    InvalidArn { arn: String, reason: String },
+    /// Returned in strict mode, see `ECSMetadataBuilder::strict`, and by `init_partial` for
+    /// selected `Labels` the document lacks
+    #[error("[phase={}] Field {0} missing from the container metadata document", self.phase())]
+    MissingField(String),
+    /// Accessor of a section left out of the `FieldSet` of `init_partial`
+    #[error("[phase={}] Section {0} was not fetched", self.phase())]
+    NotFetched(String),
+    /// Body that is not a JSON document at all, e.g. the HTML page of a proxy, rather than a
+    /// document of the wrong shape, which is a `Deserialize`
+    #[error("[phase={}] Unexpected metadata response, {hint} (body starts with {snippet:?})", self.phase())]
+    UnexpectedContent { hint: String, snippet: String },
+    /// Cached snapshot written by a newer version of this crate, see `from_cache_bytes`
+    #[error("[phase={}] Cached snapshot has format {found}, newer than the supported {supported}", self.phase())]
+    CacheFormatMismatch { found: u32, supported: u32 },
+    #[error("[phase={}] Corrupt cached snapshot: {0}", self.phase())]
+    CorruptCache(String),
+    /// Returned by `CachedStats::get` once the sample is past its max stale, with the error of
+    /// the last failed revalidation
+    #[error("[phase={}] Container stats unavailable, the last sample is {age:?} old", self.phase())]
+    StatsUnavailable {
+        age: std::time::Duration,
+        #[source]
+        source: Option<Box<ECSMetadataError>>,
+    },
+    /// `set_protection` expiry outside of the 1 to 2880 minutes (48 hours) ECS accepts
+    #[error("[phase={}] Task protection expiry of {0} minutes is not between 1 and 2880", self.phase())]
+    InvalidProtectionExpiry(u32),
+    /// Task protection request throttled by the agent or the ECS API, to retry later
+    #[error("[phase={}] Task protection request throttled: {0}", self.phase())]
+    ProtectionThrottled(String),
+    /// Task protection request refused by the agent or the ECS API, with the code or reason of
+    /// the response, e.g. `AccessDeniedException` when the task role lacks
+    /// `ecs:UpdateTaskProtection`
+    #[error("[phase={}] Task protection request rejected with {code}: {message}", self.phase())]
+    ProtectionRejected { code: String, message: String },
+    /// `AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE` names a file that can't be read
+    #[error("[phase={}] Failed to read the credentials authorization token file {path}", self.phase())]
+    CredentialsTokenUnreadable {
+        path: String,
+        #[source]
+        source: Arc<std::io::Error>,
+    },
+    /// Credentials request refused by the endpoint, with the code of the response, e.g.
+    /// `InvalidIdInRequest` when the task has no role or the relative URI is stale
+    #[error("[phase={}] Credentials request rejected with {code}: {message}", self.phase())]
+    CredentialsRejected { code: String, message: String },
+    /// EC2 instance metadata request refused, e.g. with `401 Unauthorized` for an expired token
+    #[error("[phase={}] EC2 instance metadata request rejected with {code}: {message}", self.phase())]
+    ImdsRejected { code: String, message: String },
+    /// Returned by `assert_identity`, every violated expectation listed
+    #[error("[phase={}] Metadata does not belong to the expected service: {}", self.phase(), join_violations(.0))]
+    IdentityMismatch(Vec<IdentityViolation>),
+}
+
+/// Step an error comes from, see `ECSMetadataError::phase`, e.g. to tell the host networking
+/// issues (`Connect`) from a mismatch between the agent and this crate (`Parse`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Resolving the endpoint, from the builder or the environment
+    Env,
+    /// Checking the endpoint, or another argument such as a tag pattern
+    Validate,
+    /// Connecting and sending the request, up to the response headers (TLS and timeouts included)
+    Connect,
+    /// The agent answered with an error status
+    Status,
+    /// Reading the response body
+    Body,
+    /// Parsing a document, including a cached one
+    Parse,
+    /// Checks of the parsed documents: strict mode, identity, container lookups
+    PostParse,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Env => "env",
+            Phase::Validate => "validate",
+            Phase::Connect => "connect",
+            Phase::Status => "status",
+            Phase::Body => "body",
+            Phase::Parse => "parse",
+            Phase::PostParse => "post-parse",
+        })
+    }
+}
+
+impl ECSMetadataError {
+    /// Step the error comes from
+    pub fn phase(&self) -> Phase {
+        match self {
+            Self::InitAborted | Self::TlsError(_) => Phase::Connect,
+            Self::HttpError(err) if err.is_status() => Phase::Status,
+            Self::HttpError(err) if err.is_body() || err.is_decode() => Phase::Body,
+            Self::HttpError(err) if err.is_builder() => Phase::Validate,
+            Self::HttpError(_) => Phase::Connect,
+            Self::ParseError(_) | Self::Deserialize { .. } | Self::UnexpectedContent { .. } | Self::CacheFormatMismatch { .. } | Self::CorruptCache(_) => Phase::Parse,
+            Self::EnvVarNotSet { .. } | Self::EndpointNotConfigured | Self::NotRefreshable | Self::ClosedAfterInit | Self::CredentialsTokenUnreadable { .. } => {
+                Phase::Env
+            }
+            Self::InvalidEndpoint { .. } | Self::InvalidTagPattern { .. } | Self::InvalidArn { .. } | Self::InvalidProtectionExpiry(_) => Phase::Validate,
+            Self::HttpStatus { .. } | Self::ProtectionThrottled(_) | Self::ProtectionRejected { .. } | Self::CredentialsRejected { .. } | Self::ImdsRejected { .. } => {
+                Phase::Status
+            }
+            Self::ContainerNotFound(_) | Self::AmbiguousContainer(_) | Self::MissingField(_) | Self::NotFetched(_) | Self::IdentityMismatch(_) => {
+                Phase::PostParse
+            }
+            // the revalidations failed in that phase
+            Self::StatsUnavailable { source, .. } => source.as_ref().map_or(Phase::Connect, |source| source.phase()),
+        }
+    }
+
+    /// Whether trying again later may succeed: connection failures and timeouts, statuses 408,
+    /// 429 and 5xx, a document cut short and throttled requests. Failures that would repeat
+    /// (a 404, a document of the wrong shape, the configuration or environment) are not.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Self::HttpError(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            Self::HttpStatus { status, .. } => is_retriable_status(*status),
+            Self::UnexpectedContent { hint, .. } => hint == TRUNCATED_HINT,
+            Self::ImdsRejected { code, .. } => code.split(' ').next().and_then(|status| status.parse().ok()).is_some_and(is_retriable_status),
+            Self::ProtectionThrottled(_) | Self::InitAborted => true,
+            Self::StatsUnavailable { source, .. } => source.as_ref().is_none_or(|source| source.is_retriable()),
+            _ => false,
+        }
+    }
+}
+
+fn is_retriable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+/// The error of an error status from `url`, quoting the start of `body`
+pub(crate) fn status_error(status: u16, url: &url::Url, body: &[u8]) -> ECSMetadataError {
+    ECSMetadataError::HttpStatus { status, url: url.to_string(), snippet: snippet(body) }
+}
+
+fn join_violations(violations: &[IdentityViolation]) -> String {
+    violations.iter().map(IdentityViolation::to_string).collect::<Vec<_>>().join("; ")
+}
+
+impl From<ReqwestError> for ECSMetadataError {
+    fn from(err: ReqwestError) -> Self {
+        #[cfg(feature = "rustls")]
+        if is_tls_error(&err) {
+            return Self::TlsError(Arc::new(err));
+        }
+        Self::HttpError(Arc::new(err))
+    }
+}
+
+// The rustls error sits down the chain, wrapped in `io::Error`s whose `source()` skips the
+// wrapped error, hence `get_ref` for those
+#[cfg(feature = "rustls")]
+fn is_tls_error(err: &ReqwestError) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if err.is::<rustls::Error>() {
+            return true;
+        }
+        source = match err.downcast_ref::<std::io::Error>() {
+            Some(io) => io.get_ref().map(|inner| inner as &(dyn std::error::Error + 'static)),
+            None => err.source(),
+        };
+    }
+    false
+}
+
+impl From<serde_json::Error> for ECSMetadataError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::ParseError(Arc::new(err))
+    }
+}
+
+// Bytes of the body quoted by `UnexpectedContent` and `HttpStatus`
+const SNIPPET_LEN: usize = 64;
+
+const TRUNCATED_HINT: &str = "the JSON document is cut short, the connection was probably closed early, retrying may help";
+
+/// `parse_document` of a Docker stats document, the largest and most often fetched. With the
+/// `simd-json` feature simd-json parses it, and serde_json only when that fails, for the error,
+/// which it locates. The metadata documents stay on serde_json, simd-json can't keep the raw
+/// container entries of the task document.
+pub(crate) fn parse_stats_document<T: DeserializeOwned>(body: &[u8]) -> Result<T, ECSMetadataError> {
+    #[cfg(feature = "simd-json")]
+    if let Ok(parsed) = simd_json::serde::from_slice(&mut body.to_vec()) {
+        return Ok(parsed);
+    }
+    parse_document(body)
+}
+
+/// Parses a document as served, telling bodies that are no JSON at all apart from mismatches
+pub(crate) fn parse_document<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, ECSMetadataError> {
+    serde_json::from_slice(body).map_err(|err| {
+        let hint = match body.trim_ascii_start().first() {
+            None => "the body is empty, check that the endpoint URI points at the ECS agent",
+            Some(b'<') => "the body is HTML, check that the endpoint URI points at the ECS agent rather than a proxy or web server",
+            // well-formed JSON of the wrong shape
+            _ if serde_json::from_slice::<IgnoredAny>(body).is_ok() => {
+                return ECSMetadataError::Deserialize { path: error_path(body, &err), source: Arc::new(err) };
+            }
+            _ if err.is_eof() => TRUNCATED_HINT,
+            _ => "the body is not JSON, check that the endpoint URI points at the ECS agent",
+        };
+        ECSMetadataError::UnexpectedContent { hint: hint.to_string(), snippet: snippet(body) }
+    })
+}
+
+// One level of the document around the error position
+enum Frame {
+    Object { key: Option<String>, in_key: bool },
+    Array(usize),
+}
+
+/// Path of the value a data error of `body` points at, from the keys and indexes open at the
+/// position serde_json reports: right after a scalar of the wrong type, before a map or a
+/// sequence of the wrong type, right after an object missing a field
+fn error_path(body: &[u8], err: &serde_json::Error) -> String {
+    let line_start = body.split(|byte| *byte == b'\n').take(err.line().saturating_sub(1)).map(|line| line.len() + 1).sum::<usize>();
+    let end = (line_start + err.column()).min(body.len());
+    let mut frames = Vec::new();
+    let mut string: Option<Vec<u8>> = None;
+    let mut escaped = false;
+    for &byte in &body[..end] {
+        if let Some(bytes) = &mut string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    if let Some(Frame::Object { key, in_key: true }) = frames.last_mut() {
+                        *key = Some(String::from_utf8_lossy(bytes).into_owned());
+                    }
+                    string = None;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(bytes) = &mut string {
+                bytes.push(byte);
+            }
+            continue;
+        }
+        match (byte, frames.last_mut()) {
+            (b'"', _) => string = Some(Vec::new()),
+            (b'{', _) => frames.push(Frame::Object { key: None, in_key: true }),
+            (b'[', _) => frames.push(Frame::Array(0)),
+            (b'}' | b']', _) => {
+                frames.pop();
+            }
+            (b':', Some(Frame::Object { in_key, .. })) => *in_key = false,
+            (b',', Some(Frame::Object { in_key, .. })) => *in_key = true,
+            (b',', Some(Frame::Array(index))) => *index += 1,
+            _ => {}
+        }
+    }
+
+    let mut path = String::new();
+    for frame in frames {
+        match frame {
+            Frame::Object { key: Some(key), .. } if path.is_empty() => path.push_str(&key),
+            Frame::Object { key: Some(key), .. } => write!(path, ".{key}").unwrap_or_default(),
+            Frame::Object { key: None, .. } => {}
+            Frame::Array(index) => write!(path, "[{index}]").unwrap_or_default(),
+        }
+    }
+    if path.is_empty() {
+        path.push('.');
+    }
+    path
+}
+
+fn snippet(body: &[u8]) -> String {
+    let mut snippet = String::from_utf8_lossy(&body[..body.len().min(SNIPPET_LEN)]).into_owned();
+    if body.len() > SNIPPET_LEN {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::time::Duration;
+
+    fn chain(err: &ECSMetadataError) -> Vec<String> {
+        let mut chain = vec![err.to_string()];
+        let mut source = err.source();
+        while let Some(err) = source {
+            chain.push(err.to_string());
+            source = err.source();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_source_chains() {
+        // reqwest error wrapping the URL parse error
+        let http: ECSMetadataError = reqwest::Client::new().get("http://").build().unwrap_err().into();
+        let parse: ECSMetadataError = serde_json::from_str::<u8>("{").unwrap_err().into();
+        let env_var = ECSMetadataError::EnvVarNotSet { name: "X".to_string(), source: VarError::NotPresent };
+        let endpoint = ECSMetadataError::InvalidEndpoint {
+            uri: "nope".to_string(),
+            reason: "not a valid URL".to_string(),
+            source: Some(url::ParseError::RelativeUrlWithoutBase),
+        };
+        let remote = ECSMetadataError::InvalidEndpoint {
+            uri: "https://169.254.170.2".to_string(),
+            reason: "scheme https is not allowed, only http is".to_string(),
+            source: None,
+        };
+
+        for (err, depth) in [
+            (http, 3),
+            (parse, 2),
+            (env_var, 2),
+            (endpoint, 2),
+            (remote, 1),
+            (ECSMetadataError::NotRefreshable, 1),
+            (ECSMetadataError::ContainerNotFound("abc".to_string()), 1),
+        ] {
+            let chain = chain(&err);
+            assert_eq!(chain.len(), depth, "unexpected chain {chain:?}");
+            // no level repeats the text of its source
+            for pair in chain.windows(2) {
+                assert!(!pair[0].contains(&pair[1]), "{:?} repeats {:?}", pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors_are_shareable() {
+        let err: ECSMetadataError = serde_json::from_str::<u8>("{").unwrap_err().into();
+        let cached = err.clone();
+        assert_eq!(chain(&cached), chain(&err));
+    }
+
+    #[test]
+    fn test_unexpected_content() {
+        let message = |body: &[u8]| parse_document::<crate::container::ECSContainerMetadata>(body).unwrap_err().to_string();
+        let html = message(b"<html><body><h1>502 Bad Gateway</h1></body></html>");
+        let empty = message(b" \n");
+        let truncated = message(br#"{"DockerId": "abc", "Name": "stre"#);
+        let text = message(b"404 page not found");
+        assert_eq!(
+            html,
+            "[phase=parse] Unexpected metadata response, the body is HTML, check that the endpoint URI points at the ECS agent \
+             rather than a proxy or web server (body starts with \"<html><body><h1>502 Bad Gateway</h1></body></html>\")"
+        );
+        assert!(empty.contains("the body is empty"), "{empty}");
+        assert!(truncated.contains("cut short") && truncated.contains(r#"{\"DockerId\""#), "{truncated}");
+        assert!(text.contains("the body is not JSON"), "{text}");
+
+        // the wrong shape is told by path
+        let err = parse_document::<crate::container::ECSContainerMetadata>(b"[1, 2]").unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::Deserialize { path, .. } if path == "."), "{err:?}");
+        assert_eq!(err.phase(), Phase::Parse);
+        assert!(!err.is_retriable());
+    }
+
+    #[test]
+    fn test_deserialize_paths() {
+        let path = |body: &str| match parse_document::<crate::container::ECSContainerMetadata>(body.as_bytes()) {
+            Err(ECSMetadataError::Deserialize { path, .. }) => path,
+            other => panic!("expected Deserialize, got {other:?}"),
+        };
+        let labels = r#""Labels": {"com.amazonaws.ecs.task-arn": "arn"}"#;
+        assert_eq!(path(&format!(r#"{{"DockerId": 5, {labels}}}"#)), "DockerId");
+        assert_eq!(path(&format!(r#"{{"DockerId": "a", {labels}, "Networks": [{{"NetworkMode": "awsvpc", "IPv4Addresses": ["x", 7]}}]}}"#)), "Networks[0].IPv4Addresses[1]");
+        // before a map or sequence of the wrong type, after an object missing a field
+        assert_eq!(path(&format!(r#"{{"DockerId": "a", {labels}, "Networks": {{"a": "b"}}}}"#)), "Networks");
+        assert_eq!(path(&format!(r#"{{"DockerId": "a", {labels}, "Networks": [{{"IPv4Addresses": []}}]}}"#)), "Networks[0]");
+        assert_eq!(path(&format!("{{\n  {labels}\n}}")), ".");
+        // quotes and brackets inside strings are no structure
+        assert_eq!(path(&format!(r#"{{"Name": "a\"[{{", "DockerId": "a", {labels}, "Limits": {{"Memory": "lots"}}}}"#)), "Limits.Memory");
+    }
+
+    #[test]
+    fn test_is_retriable() {
+        let status = |status| status_error(status, &"http://169.254.170.2/v4/abc".parse().unwrap(), b"agent starting");
+        assert!(status(503).is_retriable() && status(429).is_retriable() && !status(404).is_retriable());
+        assert_eq!(status(503).to_string(), r#"[phase=status] HTTP status 503 from http://169.254.170.2/v4/abc (body starts with "agent starting")"#);
+        let truncated = parse_document::<crate::container::ECSContainerMetadata>(br#"{"DockerId": "a"#).unwrap_err();
+        assert!(truncated.is_retriable());
+        assert!(!parse_document::<u8>(b"<html>").unwrap_err().is_retriable());
+        assert!(ECSMetadataError::ProtectionThrottled("slow down".to_string()).is_retriable());
+        assert!(!ECSMetadataError::EndpointNotConfigured.is_retriable());
+        let imds = |code: &str| ECSMetadataError::ImdsRejected { code: code.to_string(), message: String::new() }.is_retriable();
+        assert!(imds("503 Service Unavailable") && !imds("401 Unauthorized"));
+        let stale = |source: ECSMetadataError| ECSMetadataError::StatsUnavailable { age: Duration::from_secs(60), source: Some(Box::new(source)) };
+        assert!(stale(status(500)).is_retriable() && !stale(status(404)).is_retriable());
+    }
+
+    #[test]
+    fn test_snippet_is_capped() {
+        // a multi-byte character cut by the cap becomes a replacement character
+        let body = format!("<{}", "é".repeat(100));
+        let ECSMetadataError::UnexpectedContent { snippet, .. } = parse_document::<u8>(body.as_bytes()).unwrap_err() else {
+            panic!("expected UnexpectedContent");
+        };
+        assert_eq!(snippet, format!("<{}\u{FFFD}…", "é".repeat(31)));
+        assert_eq!(super::snippet(b"<p>"), "<p>");
+    }
+
+    #[tokio::test]
+    async fn test_phases() {
+        use crate::identity::IdentityExpectation;
+        use crate::metadata::tests::CONTAINER_JSON;
+        use crate::metadata::ECSMetadata;
+        use crate::test_support::{MockAgent, MockResponse};
+        use tokio::io::AsyncWriteExt;
+
+        let agent = MockAgent::start().await;
+        agent.set("/v4/ok", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/down", MockResponse::status(500, "agent restarting"));
+        agent.set("/v4/proxy", MockResponse::status(200, "<html><body>Sign in</body></html>"));
+        agent.set("/v4/array", MockResponse::json("[]"));
+        agent.set("/v4/sparse", MockResponse::json(r#"{"DockerId": "abc", "Labels": {"com.amazonaws.ecs.task-arn": "arn"}}"#));
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        // headers promising more body than is sent before the connection closes
+        let cut = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let cut_addr = cut.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = cut.accept().await.unwrap();
+            crate::test_support::read_request_path(&mut stream).await;
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n{\"DockerId\"").await.unwrap();
+        });
+
+        let builder = |path: &str| ECSMetadata::builder().endpoint(agent.url(path));
+        let cases = [
+            (ECSMetadata::builder().env_lookup(false), Phase::Env),
+            (ECSMetadata::builder().endpoint("http://metadata.example.com/v4"), Phase::Validate),
+            (ECSMetadata::builder().endpoint(format!("http://{closed}/v4")), Phase::Connect),
+            (builder("/v4/down"), Phase::Status),
+            (ECSMetadata::builder().endpoint(format!("http://{cut_addr}/v4")), Phase::Body),
+            (builder("/v4/proxy"), Phase::Parse),
+            (builder("/v4/array"), Phase::Parse),
+            (builder("/v4/sparse").strict(true), Phase::PostParse),
+            (builder("/v4/ok").expect_identity(IdentityExpectation::new().container_name("envoy")), Phase::PostParse),
+        ];
+        for (builder, phase) in cases {
+            let err = builder.init().await.unwrap_err();
+            assert_eq!(err.phase(), phase, "{err:?}");
+            assert!(err.to_string().starts_with(&format!("[phase={phase}] ")), "{err}");
+        }
+
+        assert_eq!(ECSMetadataError::ContainerNotFound("abc".to_string()).to_string(), "[phase=post-parse] No container with ID or name abc in the task");
+        let unavailable = ECSMetadataError::StatsUnavailable { age: Duration::from_secs(70), source: Some(Box::new(ECSMetadataError::InitAborted)) };
+        assert_eq!(unavailable.phase(), Phase::Connect);
+        assert_eq!(Phase::PostParse.to_string(), "post-parse");
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn test_simd_json_parses_stats_like_serde_json() {
+        use crate::ECSContainerStats;
+        let stats = include_bytes!("../testdata/stats/cgroup_v1.json");
+        // the fast path is taken, for the same result, as served and typed
+        assert!(simd_json::serde::from_slice::<serde_json::Value>(&mut stats.to_vec()).is_ok());
+        let served = parse_stats_document::<serde_json::Value>(stats).unwrap();
+        assert_eq!(served, serde_json::from_slice::<serde_json::Value>(stats).unwrap());
+        assert_eq!(parse_stats_document::<ECSContainerStats>(stats).unwrap(), serde_json::from_value(served).unwrap());
+
+        // serde_json's errors, with the path
+        let err = parse_stats_document::<ECSContainerStats>(br#"{"read": 1}"#).unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::Deserialize { path, .. } if path == "read"), "{err:?}");
+    }
+}
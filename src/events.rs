@@ -0,0 +1,174 @@
+use std::fmt;
+use std::io::Write;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use crate::metadata::ECSMetadata;
+
+/// `tracing_subscriber` layer writing every event as a JSON line with the `as_fields` of this
+/// container next to the event's own fields, e.g. to stdout for `awslogs` or FireLens, which
+/// stamp each line on their own:
+///
+/// `{"ecs.cluster":"production","ecs.task.id":"0214…","level":"INFO","message":"ready","port":8080,"spans":["serve"],"target":"app"}`
+///
+/// `spans` names the spans the event happened in, outermost first, and is left out outside of
+/// any. A field of the event wins over an ECS field of the same name. The identity fields are
+/// taken once, they don't change over the life of a container; degraded metadata adds none.
+#[derive(Debug)]
+pub struct ECSFieldsLayer<W> {
+    fields: Vec<(&'static str, String)>,
+    make_writer: W,
+}
+
+impl<W> ECSFieldsLayer<W> {
+    pub fn new(metadata: &ECSMetadata, make_writer: W) -> Self {
+        let fields = match metadata.is_degraded() {
+            true => Vec::new(),
+            false => metadata.as_fields(),
+        };
+        Self { fields, make_writer }
+    }
+}
+
+impl<S, W> Layer<S> for ECSFieldsLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut line = Map::new();
+        event.record(&mut JsonFields(&mut line));
+        line.insert("level".to_string(), event.metadata().level().as_str().into());
+        line.insert("target".to_string(), event.metadata().target().into());
+        if let Some(scope) = ctx.event_scope(event) {
+            let spans: Vec<Value> = scope.from_root().map(|span| span.name().into()).collect();
+            line.insert("spans".to_string(), spans.into());
+        }
+        for (key, value) in &self.fields {
+            line.entry(*key).or_insert_with(|| value.as_str().into());
+        }
+
+        let mut bytes = serde_json::to_vec(&line).unwrap_or_default();
+        bytes.push(b'\n');
+        // a log line that can't be written has nowhere to be reported
+        let _ = self.make_writer.make_writer_for(event.metadata()).write_all(&bytes);
+    }
+}
+
+// fields of an event as JSON values, numbers and booleans kept as such
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonFields<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Lines {
+        type Writer = Lines;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn logged(metadata: &ECSMetadata, log: impl FnOnce()) -> Vec<Value> {
+        let lines = Lines::default();
+        let subscriber = tracing_subscriber::registry().with(ECSFieldsLayer::new(metadata, lines.clone()));
+        tracing::subscriber::with_default(subscriber, log);
+        let written = lines.0.lock().unwrap().clone();
+        String::from_utf8(written).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_every_event_has_the_fields() {
+        let metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        let lines = logged(&metadata, || {
+            tracing::info!(port = 8080, tls = false, "ready");
+            let _ecs = metadata.span().entered();
+            tracing::info_span!("serve").in_scope(|| tracing::warn!(ecs.cluster = "overridden", latency = 1.5, "slow"));
+        });
+
+        assert_eq!(
+            lines[0],
+            serde_json::json!({
+                "message": "ready",
+                "port": 8080,
+                "tls": false,
+                "level": "INFO",
+                "target": "ecs_metadata::events::tests",
+                "ecs.cluster": "production",
+                "ecs.task.id": "021447970bce4bd58069f1925cd87bc0",
+                "ecs.container.name": "streamer",
+                "ecs.task_definition.family": "streamer",
+                "ecs.task_definition.revision": "12",
+                "container.image": "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production",
+            })
+        );
+        assert_eq!((&lines[1]["spans"], &lines[1]["ecs.cluster"], &lines[1]["latency"]), (&serde_json::json!(["ecs", "serve"]), &"overridden".into(), &1.5.into()));
+        assert_eq!(lines[1]["ecs.task.id"], "021447970bce4bd58069f1925cd87bc0");
+
+        let degraded = logged(&ECSMetadata::degraded(None), || tracing::info!("ready"));
+        assert!(degraded[0].as_object().unwrap().keys().all(|key| !key.starts_with("ecs.")), "{:?}", degraded[0]);
+    }
+
+    #[test]
+    fn test_parse_warnings_are_events() {
+        let without_image = CONTAINER_JSON.replace(r#""Image": "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production","#, "");
+        let mut metadata = None;
+        let lines = logged(&ECSMetadata::degraded(None), || {
+            metadata = Some(metadata_from_json(&without_image, Some(r#"{"Containers": [{"Name": "envoy", "DockerId": 1}]}"#)));
+        });
+        let events: Vec<_> = lines.iter().map(|line| (line["level"].as_str().unwrap(), line["kind"].as_str().unwrap(), line["message"].as_str().unwrap())).collect();
+        // one event per warning, those of the task document not repeated by the metadata
+        assert_eq!(events.len(), metadata.as_ref().unwrap().warnings().len());
+        assert_eq!(events[0].0, "WARN");
+        assert_eq!(events[0].1, "container_skipped");
+        assert!(events[0].2.starts_with("skipped container #0 (envoy)"), "{}", events[0].2);
+        assert_eq!(events[1..], [("WARN", "missing_field_defaulted", "container document has no Image, left empty")]);
+    }
+}
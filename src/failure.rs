@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+use crate::error::{status_error, ECSMetadataError};
+
+/// Failures injected into the fetches of a builder, see `ECSMetadataBuilder::failure_policy`.
+/// A handle: clones share the settings, which can be changed at any time, e.g. in the middle of
+/// a chaos experiment. Injected failures surface as the errors of the real ones: `HttpStatus`
+/// 503, `HttpError` timing out and `UnexpectedContent`.
+#[derive(Debug, Clone, Default)]
+pub struct FailurePolicy {
+    state: Arc<Mutex<FailureState>>,
+}
+
+#[derive(Debug, Default)]
+struct FailureState {
+    fail_next: u32,
+    latency: Duration,
+    corrupt_body: bool,
+}
+
+// What to do to one fetch
+#[derive(Debug, Default)]
+pub(crate) struct Injected {
+    pub(crate) fail: bool,
+    pub(crate) latency: Duration,
+    pub(crate) corrupt_body: bool,
+}
+
+impl FailurePolicy {
+    /// Injects nothing until configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The next `n` fetches fail with a 503 response, without reaching the agent. Retries count
+    /// as fetches. Replaces the count still pending.
+    pub fn fail_next(&self, n: u32) -> &Self {
+        self.state.lock().unwrap().fail_next = n;
+        self
+    }
+
+    /// Every fetch waits `latency` between the response headers and the body, as a slow agent
+    /// would, so that the timeout of the request policy applies to it
+    pub fn latency(&self, latency: Duration) -> &Self {
+        self.state.lock().unwrap().latency = latency;
+        self
+    }
+
+    /// Every fetch gets its body cut in half, which no parse survives
+    pub fn corrupt_body(&self, corrupt: bool) -> &Self {
+        self.state.lock().unwrap().corrupt_body = corrupt;
+        self
+    }
+
+    /// Back to injecting nothing
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = FailureState::default();
+    }
+
+    pub(crate) fn next_fetch(&self) -> Injected {
+        let mut state = self.state.lock().unwrap();
+        let fail = state.fail_next > 0;
+        state.fail_next = state.fail_next.saturating_sub(1);
+        Injected { fail, latency: state.latency, corrupt_body: state.corrupt_body }
+    }
+}
+
+/// The error of a 503 from `url`, as the fetch builds it for any error status
+pub(crate) fn unavailable(url: Url) -> ECSMetadataError {
+    status_error(503, &url, b"injected failure")
+}
+
+pub(crate) fn corrupted(mut body: Vec<u8>) -> Vec<u8> {
+    body.truncate(body.len() / 2);
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RequestPolicy;
+    use crate::error::ECSMetadataError;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::metadata::ECSMetadata;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    // What downstream code gets to match on
+    fn shape(err: &ECSMetadataError) -> (&'static str, Option<u16>, bool) {
+        match err {
+            ECSMetadataError::HttpError(err) => ("HttpError", None, err.is_timeout()),
+            ECSMetadataError::HttpStatus { status, .. } => ("HttpStatus", Some(*status), false),
+            ECSMetadataError::UnexpectedContent { .. } => ("UnexpectedContent", None, false),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    async fn init_error(agent: &MockAgent, path: &str, policy: RequestPolicy, failures: Option<&FailurePolicy>) -> ECSMetadataError {
+        let mut builder = ECSMetadata::builder().endpoint(agent.url(path)).metadata_policy(policy);
+        if let Some(failures) = failures {
+            builder = builder.failure_policy(failures.clone());
+        }
+        builder.init().await.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_injected_errors_match_real_ones() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/down", MockResponse::status(503, "down"));
+        agent.set("/v4/slow", MockResponse::json(CONTAINER_JSON).with_delay(Duration::from_millis(300)));
+        agent.set("/v4/garbled", MockResponse::json(&CONTAINER_JSON[..40]));
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let policy = RequestPolicy::new(Some(Duration::from_millis(100)), 0);
+        let failures = FailurePolicy::new();
+
+        let real = init_error(&agent, "/v4/down", policy, None).await;
+        failures.fail_next(1);
+        let injected = init_error(&agent, "/v4/abc", policy, Some(&failures)).await;
+        assert_eq!(shape(&injected), shape(&real));
+        assert_eq!(shape(&injected), ("HttpStatus", Some(503), false));
+        assert_eq!(agent.hits("/v4/abc"), 0);
+
+        let real = init_error(&agent, "/v4/slow", policy, None).await;
+        failures.latency(Duration::from_millis(300));
+        let injected = init_error(&agent, "/v4/abc", policy, Some(&failures)).await;
+        assert_eq!(shape(&injected), shape(&real));
+        assert_eq!(shape(&injected), ("HttpError", None, true));
+
+        let real = init_error(&agent, "/v4/garbled", policy, None).await;
+        failures.clear();
+        failures.corrupt_body(true);
+        let injected = init_error(&agent, "/v4/abc", policy, Some(&failures)).await;
+        assert_eq!(shape(&injected), shape(&real));
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures_are_retried() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let failures = FailurePolicy::new();
+        failures.fail_next(2);
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .metadata_policy(RequestPolicy::new(None, 2))
+            .failure_policy(failures.clone())
+            .init()
+            .await
+            .unwrap();
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(agent.hits("/v4/abc"), 1);
+
+        // a small latency within the timeout only delays
+        failures.latency(Duration::from_millis(20));
+        let started = tokio::time::Instant::now();
+        let policy = RequestPolicy::new(Some(Duration::from_secs(5)), 0);
+        ECSMetadata::builder().endpoint(agent.url("/v4/abc")).metadata_policy(policy).failure_policy(failures).init().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_toggled_at_runtime() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let failures = FailurePolicy::new();
+        let mut metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .metadata_policy(RequestPolicy::new(None, 0))
+            .failure_policy(failures.clone())
+            .init()
+            .await
+            .unwrap();
+
+        failures.fail_next(1);
+        assert!(matches!(metadata.refresh().await, Err(ECSMetadataError::HttpStatus { status: 503, .. })));
+        assert!(metadata.refresh().await.is_ok());
+        failures.corrupt_body(true);
+        assert!(matches!(metadata.refresh().await, Err(ECSMetadataError::UnexpectedContent { .. })));
+        failures.clear();
+        assert!(metadata.refresh().await.is_ok());
+        assert_eq!(metadata.container_name(), "streamer");
+    }
+}
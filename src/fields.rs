@@ -0,0 +1,124 @@
+use crate::metadata::ECSMetadata;
+
+/// Selects how `as_fields_with` and `select_fields` render the fields
+#[derive(Debug, Clone, Default)]
+pub struct FieldOptions {
+    /// Truncate `ecs.task.id` to `ECSMetadata::SHORT_TASK_ID_LEN` characters, see
+    /// `task_id_short`, e.g. for metric tags of bounded cardinality
+    pub short_task_id: bool,
+}
+
+impl ECSMetadata {
+    /// Identity of this container as flat `key → value` pairs in a fixed order, the common shape
+    /// for log context, span fields and metric tags:
+    /// `ecs.cluster`, `ecs.task.id`, `ecs.container.name`, `ecs.task_definition.family`,
+    /// `ecs.task_definition.revision` and `container.image`. Unknown and empty values are left out.
+    /// A `Vec` rather than a map: each key appears at most once, and a `BTreeMap` would sort
+    /// them out of this order, which sinks such as log lines keep.
+    pub fn as_fields(&self) -> Vec<(&'static str, String)> {
+        self.as_fields_with(&FieldOptions::default())
+    }
+
+    /// `as_fields` rendered as `options` selects, e.g. with the short task ID
+    pub fn as_fields_with(&self, options: &FieldOptions) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::with_capacity(6);
+        if let Some(cluster) = self.cluster_name() {
+            fields.push(("ecs.cluster", cluster.to_string()));
+        }
+        let task_id = if options.short_task_id {
+            self.task_id_short(ECSMetadata::SHORT_TASK_ID_LEN)
+        } else {
+            self.task_id()
+        };
+        if let Some(task_id) = task_id {
+            fields.push(("ecs.task.id", task_id));
+        }
+        for (key, value) in [
+            ("ecs.container.name", self.container_name()),
+            ("ecs.task_definition.family", self.task_definition_family()),
+            ("ecs.task_definition.revision", self.task_definition_revision()),
+            ("container.image", self.image()),
+        ] {
+            if !value.is_empty() {
+                fields.push((key, value.to_string()));
+            }
+        }
+        fields
+    }
+
+    /// The fields of `as_fields_with(options)` whose key is in `keys`, in `as_fields` order, e.g.
+    /// `ecs.cluster` and `ecs.task.id` with the short task ID for the tags of a metric. Keys that
+    /// are not canonical match nothing.
+    pub fn select_fields(&self, keys: &[&str], options: &FieldOptions) -> Vec<(&'static str, String)> {
+        self.as_fields_with(options).into_iter().filter(|(key, _)| keys.contains(key)).collect()
+    }
+
+    /// `ecs` info span carrying the `as_fields`, to enter around work whose events should have
+    /// the ECS context, as `ECSContextLayer` does per request. `Span::none()` for degraded
+    /// metadata.
+    #[cfg(any(feature = "tower", feature = "tracing"))]
+    pub fn span(&self) -> tracing::Span {
+        if self.is_degraded() {
+            return tracing::Span::none();
+        }
+        let span = tracing::info_span!(
+            "ecs",
+            ecs.cluster = tracing::field::Empty,
+            ecs.task.id = tracing::field::Empty,
+            ecs.container.name = tracing::field::Empty,
+            ecs.task_definition.family = tracing::field::Empty,
+            ecs.task_definition.revision = tracing::field::Empty,
+            container.image = tracing::field::Empty,
+        );
+        if !span.is_disabled() {
+            for (key, value) in self.as_fields() {
+                span.record(key, value.as_str());
+            }
+        }
+        span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_as_fields() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(
+            metadata.as_fields(),
+            [
+                ("ecs.cluster", "production".to_string()),
+                ("ecs.task.id", "021447970bce4bd58069f1925cd87bc0".to_string()),
+                ("ecs.container.name", "streamer".to_string()),
+                ("ecs.task_definition.family", "streamer".to_string()),
+                ("ecs.task_definition.revision", "12".to_string()),
+                ("container.image", "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production".to_string()),
+            ]
+        );
+        assert!(!ECSMetadata::degraded(None).as_fields().iter().any(|(key, _)| *key == "ecs.task.id"));
+
+        let minimal = r#"{"DockerId": "abc", "Labels": {"com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:939885537497:task/abc"}}"#;
+        assert_eq!(metadata_from_json(minimal, None).as_fields(), [("ecs.task.id", "abc".to_string())]);
+    }
+
+    #[test]
+    fn test_short_task_id_field() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let fields = metadata.as_fields_with(&FieldOptions { short_task_id: true });
+        assert_eq!(fields[1], ("ecs.task.id", "02144797".to_string()));
+        assert_eq!(fields[2..], metadata.as_fields()[2..]);
+    }
+
+    #[test]
+    fn test_select_fields() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let selected = metadata.select_fields(&["container.image", "ecs.cluster", "ecs.unknown"], &FieldOptions::default());
+        assert_eq!(selected.iter().map(|(key, _)| *key).collect::<Vec<_>>(), ["ecs.cluster", "container.image"]);
+        assert!(metadata.select_fields(&[], &FieldOptions::default()).is_empty());
+        let tags = metadata.select_fields(&["ecs.task.id"], &FieldOptions { short_task_id: true });
+        assert_eq!(tags, [("ecs.task.id", "02144797".to_string())]);
+    }
+}
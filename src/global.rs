@@ -0,0 +1,65 @@
+use tokio::sync::OnceCell;
+use crate::client::ECSMetadataBuilder;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+
+// Set once for the life of the process, and never refreshed
+static GLOBAL: OnceCell<ECSMetadata> = OnceCell::const_new();
+
+impl ECSMetadata {
+    /// The process-wide metadata, fetched with the default builder on first use (see
+    /// `ECSMetadataBuilder::init_global` to choose it). Concurrent first calls wait on a single
+    /// fetch. A failure is returned without being kept: the next call fetches again.
+    pub async fn global() -> Result<&'static ECSMetadata, ECSMetadataError> {
+        GLOBAL.get_or_try_init(|| ECSMetadataBuilder::new().init()).await
+    }
+
+    /// The process-wide metadata if it was fetched already, without fetching
+    pub fn try_global() -> Option<&'static ECSMetadata> {
+        GLOBAL.get()
+    }
+}
+
+impl ECSMetadataBuilder {
+    /// Fetches the process-wide metadata of `ECSMetadata::global` with this builder, e.g. at
+    /// startup to fail before serving. Once it is set this returns it as is, without fetching.
+    pub async fn init_global(self) -> Result<&'static ECSMetadata, ECSMetadataError> {
+        GLOBAL.get_or_try_init(|| self.init()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::time::Duration;
+
+    // the only test setting the process-wide metadata
+    #[tokio::test]
+    async fn test_global() {
+        let agent = MockAgent::start().await;
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).metadata_policy(crate::client::RequestPolicy::new(None, 0));
+        assert!(builder.clone().init_global().await.is_err());
+        assert!(ECSMetadata::try_global().is_none());
+
+        // a dropped first call leaves nothing behind either
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON).with_delay(Duration::from_millis(500)));
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), builder.clone().init_global()).await;
+        assert!(cancelled.is_err(), "the fetch should still be in flight");
+        assert!(ECSMetadata::try_global().is_none());
+
+        // concurrent first calls wait on a single fetch
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON).with_delay(Duration::from_millis(100)));
+        let hits = agent.hits("/v4/abc");
+        let (first, second) = tokio::join!(builder.clone().init_global(), builder.clone().init_global());
+        let global = first.unwrap();
+        assert!(std::ptr::eq(second.unwrap(), global));
+        assert_eq!(agent.hits("/v4/abc"), hits + 1);
+        assert_eq!(global.container_name(), "streamer");
+        assert!(std::ptr::eq(ECSMetadata::global().await.unwrap(), global));
+        assert!(std::ptr::eq(builder.init_global().await.unwrap(), global));
+        assert!(std::ptr::eq(ECSMetadata::try_global().unwrap(), global));
+        assert_eq!(agent.hits("/v4/abc"), hits + 1);
+    }
+}
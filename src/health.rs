@@ -0,0 +1,209 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::time::SystemTime;
+use crate::timestamp::parse_rfc3339;
+
+/// Default for `ECSMetadataBuilder::max_health_output_len`
+pub(crate) const DEFAULT_MAX_HEALTH_OUTPUT_LEN: usize = 1024;
+
+/// Appended to a health check output cut at the maximum length
+pub(crate) const TRUNCATION_MARKER: &str = "…[truncated]";
+
+/// Status of a container health check
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HealthStatus {
+    Healthy,
+    Unhealthy,
+    /// No check completed yet, e.g. in the start period
+    Unknown,
+    /// A status this crate does not know, as served
+    Other(String),
+}
+
+impl HealthStatus {
+    pub(crate) fn parse(status: &str) -> Self {
+        match status.to_ascii_uppercase().as_str() {
+            "HEALTHY" => Self::Healthy,
+            "UNHEALTHY" => Self::Unhealthy,
+            "UNKNOWN" => Self::Unknown,
+            _ => Self::Other(status.to_string()),
+        }
+    }
+
+    /// The status as the agent spells it, e.g. `HEALTHY`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Healthy => "HEALTHY",
+            Self::Unhealthy => "UNHEALTHY",
+            Self::Unknown => "UNKNOWN",
+            Self::Other(status) => status,
+        }
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `Health` block of a container with a health check in its task definition
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ECSContainerHealth {
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i64>,
+    // whatever the check command printed: multi-line, terminal escapes, not necessarily UTF-8
+    #[serde(default, deserialize_with = "lossy_string", skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+}
+
+impl ECSContainerHealth {
+    /// Health status as served, e.g. `HEALTHY`, `UNHEALTHY` or `UNKNOWN`
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// `status` as a `HealthStatus`
+    pub fn status_kind(&self) -> HealthStatus {
+        HealthStatus::parse(&self.status)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.status_kind() == HealthStatus::Healthy
+    }
+
+    /// When the status last changed, an RFC 3339 timestamp as served
+    pub fn status_since(&self) -> Option<&str> {
+        self.status_since.as_deref()
+    }
+
+    /// `status_since` parsed, `None` when absent or malformed
+    pub fn status_changed_at(&self) -> Option<SystemTime> {
+        parse_rfc3339(self.status_since.as_deref()?)
+    }
+
+    /// Exit code of the last check command
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Output of the last check command, cut at `ECSMetadataBuilder::max_health_output_len` bytes
+    /// with a trailing `…[truncated]` marker
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+
+    pub(crate) fn truncate_output(&mut self, max_len: usize) {
+        if let Some(output) = &mut self.output {
+            truncate(output, max_len);
+        }
+    }
+}
+
+fn truncate(output: &mut String, max_len: usize) {
+    if output.len() <= max_len {
+        return;
+    }
+    let mut end = max_len;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    output.truncate(end);
+    output.push_str(TRUNCATION_MARKER);
+}
+
+// Strings are read as bytes so that escapes which are not valid UTF-8 (lone surrogates, as
+// written by a check printing binary) end up as replacement characters instead of failing
+fn lossy_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    struct LossyString;
+
+    impl<'de> Visitor<'de> for LossyString {
+        type Value = String;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_string<E: de::Error>(self, value: String) -> Result<String, E> {
+            Ok(value)
+        }
+
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<String, E> {
+            Ok(String::from_utf8_lossy(value).into_owned())
+        }
+    }
+
+    struct Lossy(String);
+
+    impl<'de> Deserialize<'de> for Lossy {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_bytes(LossyString).map(Lossy)
+        }
+    }
+
+    Ok(Option::<Lossy>::deserialize(deserializer)?.map(|lossy| lossy.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_output(output: &str) -> ECSContainerHealth {
+        serde_json::from_str(&format!(r#"{{"status": "UNHEALTHY", "statusSince": "2024-10-01T12:00:00Z", "exitCode": 1, "output": "{output}"}}"#))
+            .expect("health block should parse")
+    }
+
+    #[test]
+    fn test_multi_line_and_escaped_output() {
+        let health = with_output(r"curl: (7) Failed to connect\n\u001b[31mdown\u001b[0m\r\n\ttab é");
+        assert_eq!(health.output(), Some("curl: (7) Failed to connect\n\u{1b}[31mdown\u{1b}[0m\r\n\ttab é"));
+        assert_eq!(health.status_since(), Some("2024-10-01T12:00:00Z"));
+        assert_eq!(health.status_changed_at(), parse_rfc3339("2024-10-01T12:00:00Z"));
+        assert_eq!(health.status_kind(), HealthStatus::Unhealthy);
+        assert!(!health.is_healthy());
+        assert_eq!(health.exit_code(), Some(1));
+    }
+
+    #[test]
+    fn test_invalid_utf8_escape_is_replaced() {
+        let health = with_output(r"bin \ud800 \udc00\ud800 end");
+        assert_eq!(health.output(), Some("bin \u{fffd}\u{fffd}\u{fffd} \u{fffd}\u{fffd}\u{fffd}\u{fffd}\u{fffd}\u{fffd} end"));
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut health = with_output("0123456789");
+        health.truncate_output(10);
+        assert_eq!(health.output(), Some("0123456789"));
+        health.truncate_output(4);
+        assert_eq!(health.output(), Some("0123…[truncated]"));
+
+        // never cut inside a character
+        let mut health = with_output("ééé");
+        health.truncate_output(3);
+        assert_eq!(health.output(), Some("é…[truncated]"));
+    }
+
+    #[test]
+    fn test_minimal_block() {
+        let health: ECSContainerHealth = serde_json::from_str(r#"{"status": "UNKNOWN"}"#).unwrap();
+        assert_eq!(health.status(), "UNKNOWN");
+        assert_eq!((health.status_kind(), health.status_changed_at()), (HealthStatus::Unknown, None));
+        let health: ECSContainerHealth = serde_json::from_str(r#"{"status": "Healthy"}"#).unwrap();
+        assert!(health.is_healthy());
+        let health: ECSContainerHealth = serde_json::from_str(r#"{"status": "STARTING"}"#).unwrap();
+        assert_eq!(health.status_kind().to_string(), "STARTING");
+        assert_eq!(health.status_since(), None);
+        assert_eq!(health.output(), None);
+    }
+}
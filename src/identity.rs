@@ -0,0 +1,181 @@
+use std::fmt;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+
+/// Accepted values of one identity field
+#[derive(Debug, Clone)]
+pub enum Expected {
+    Exact(String),
+    OneOf(Vec<String>),
+    /// Matches anywhere unless anchored, e.g. `^billing-(api|worker)$`
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Expected {
+    fn matches(&self, actual: &str) -> bool {
+        match self {
+            Expected::Exact(expected) => expected == actual,
+            Expected::OneOf(expected) => expected.iter().any(|expected| expected == actual),
+            #[cfg(feature = "regex")]
+            Expected::Regex(regex) => regex.is_match(actual),
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Exact(expected) => write!(f, "{expected:?}"),
+            Expected::OneOf(expected) => write!(f, "one of {expected:?}"),
+            #[cfg(feature = "regex")]
+            Expected::Regex(regex) => write!(f, "matching /{regex}/"),
+        }
+    }
+}
+
+impl From<&str> for Expected {
+    fn from(expected: &str) -> Self {
+        Expected::Exact(expected.to_string())
+    }
+}
+
+impl From<String> for Expected {
+    fn from(expected: String) -> Self {
+        Expected::Exact(expected)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<regex::Regex> for Expected {
+    fn from(regex: regex::Regex) -> Self {
+        Expected::Regex(regex)
+    }
+}
+
+/// Service the metadata must belong to, see `ECSMetadata::assert_identity`. Fields left unset
+/// accept anything.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityExpectation {
+    task_definition_family: Option<Expected>,
+    cluster: Option<Expected>,
+    container_name: Option<Expected>,
+}
+
+impl IdentityExpectation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn task_definition_family(mut self, expected: impl Into<Expected>) -> Self {
+        self.task_definition_family = Some(expected.into());
+        self
+    }
+
+    /// Compared with the cluster name as well as the cluster label as served, which may be an ARN
+    pub fn cluster(mut self, expected: impl Into<Expected>) -> Self {
+        self.cluster = Some(expected.into());
+        self
+    }
+
+    pub fn container_name(mut self, expected: impl Into<Expected>) -> Self {
+        self.container_name = Some(expected.into());
+        self
+    }
+}
+
+/// One violated expectation of an `IdentityMismatch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityViolation {
+    /// `task_definition_family`, `cluster` or `container_name`
+    pub field: &'static str,
+    /// The expectation, e.g. `one of ["production", "staging"]`
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for IdentityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is {:?}, expected {}", self.field, self.actual, self.expected)
+    }
+}
+
+impl ECSMetadata {
+    /// Checks that this container runs as the expected service, e.g. at startup against a task
+    /// definition copied from another service. Fails with `IdentityMismatch` listing every
+    /// violated expectation. See `ECSMetadataBuilder::expect_identity` to check on every init.
+    pub fn assert_identity(&self, expected: &IdentityExpectation) -> Result<(), ECSMetadataError> {
+        let mut violations = Vec::new();
+        let mut check = |field, expected: &Option<Expected>, actual: &[&str]| {
+            if let Some(expected) = expected.as_ref().filter(|expected| !actual.iter().any(|value| expected.matches(value))) {
+                violations.push(IdentityViolation { field, expected: expected.to_string(), actual: actual[0].to_string() });
+            }
+        };
+        check("task_definition_family", &expected.task_definition_family, &[self.task_definition_family()]);
+        let cluster_name = self.cluster_name().unwrap_or_default();
+        check("cluster", &expected.cluster, &[cluster_name, self.cluster().unwrap_or_default()]);
+        check("container_name", &expected.container_name, &[self.container_name()]);
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(ECSMetadataError::IdentityMismatch(violations)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    fn metadata() -> ECSMetadata {
+        ECSMetadata::from_json(&CONTAINER_JSON.replace(r#""production""#, r#""arn:aws:ecs:us-east-1:939885537497:cluster/production""#)).unwrap()
+    }
+
+    #[test]
+    fn test_assert_identity() {
+        let metadata = metadata();
+        let matching = IdentityExpectation::new()
+            .task_definition_family("streamer")
+            .cluster(Expected::OneOf(vec!["staging".to_string(), "production".to_string()]))
+            .container_name("streamer");
+        metadata.assert_identity(&matching).unwrap();
+        metadata.assert_identity(&IdentityExpectation::new()).unwrap();
+        // the label as served matches too
+        metadata.assert_identity(&IdentityExpectation::new().cluster("arn:aws:ecs:us-east-1:939885537497:cluster/production")).unwrap();
+
+        let wrong = IdentityExpectation::new()
+            .task_definition_family("billing")
+            .cluster(Expected::OneOf(vec!["staging".to_string()]))
+            .container_name("streamer");
+        let err = metadata.assert_identity(&wrong).unwrap_err();
+        let ECSMetadataError::IdentityMismatch(violations) = &err else {
+            panic!("expected IdentityMismatch, got {err:?}");
+        };
+        assert_eq!(violations.iter().map(|violation| violation.field).collect::<Vec<_>>(), ["task_definition_family", "cluster"]);
+        assert_eq!(
+            err.to_string(),
+            r#"[phase=post-parse] Metadata does not belong to the expected service: task_definition_family is "streamer", expected "billing"; cluster is "production", expected one of ["staging"]"#
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_expectation() {
+        let metadata = metadata();
+        let family = |pattern| IdentityExpectation::new().task_definition_family(regex::Regex::new(pattern).unwrap());
+        metadata.assert_identity(&family("^stream(er|ing)$")).unwrap();
+        let err = metadata.assert_identity(&family("^billing-.*$")).unwrap_err();
+        assert!(err.to_string().ends_with(r#"task_definition_family is "streamer", expected matching /^billing-.*$/"#), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_builder_fails_startup() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc"));
+        let result = builder.clone().expect_identity(IdentityExpectation::new().container_name("envoy")).init().await;
+        assert!(matches!(result, Err(ECSMetadataError::IdentityMismatch(violations)) if violations.len() == 1));
+        builder.expect_identity(IdentityExpectation::new().container_name("streamer")).init().await.unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use crate::error::ECSMetadataError;
+
+// Private ECR registry hosts:
+// - `<account>.dkr.ecr.<region>.amazonaws.com`, also for GovCloud regions
+// - `<account>.dkr.ecr-fips.<region>.amazonaws.com`
+// - `<account>.dkr.ecr.<region>.amazonaws.com.cn` in the China regions
+// - `<account>.dkr-ecr.<region>.on.aws` and `<account>.dkr-ecr-fips.<region>.on.aws`, dual-stack
+
+/// Account ID and region of the private ECR registry the image is pulled from, `None` for any
+/// other registry, public ECR (`public.ecr.aws`) included
+pub(crate) fn ecr_registry(image: &str) -> Option<(&str, &str)> {
+    let (host, _) = image.split_once('/')?;
+    let labels: Vec<&str> = host.split('.').collect();
+    let (account, region) = match labels.as_slice() {
+        [account, "dkr", "ecr" | "ecr-fips", region, "amazonaws", "com"]
+        | [account, "dkr", "ecr" | "ecr-fips", region, "amazonaws", "com", "cn"]
+        | [account, "dkr-ecr" | "dkr-ecr-fips", region, "on", "aws"] => (*account, *region),
+        _ => return None,
+    };
+    let valid_account = account.len() == 12 && account.bytes().all(|byte| byte.is_ascii_digit());
+    (valid_account && !region.is_empty()).then_some((account, region))
+}
+
+/// `registry:5000/repo:tag` into repository and tag; a digest reference has no tag
+pub(crate) fn split_image(image: &str) -> (&str, Option<&str>) {
+    if let Some((repo, _digest)) = image.split_once('@') {
+        return (repo, None);
+    }
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    match image[name_start..].rfind(':') {
+        Some(colon) => (&image[..name_start + colon], Some(&image[name_start + colon + 1..])),
+        None => (image, None),
+    }
+}
+
+/// Naming convention of image tags, e.g. `{version}-{environment}`: placeholders in braces
+/// between literal separators. Matching is offline and deterministic, a placeholder takes as
+/// much of the tag as the rest of the pattern allows, so `v1.2.3-rc-staging` gives version
+/// `v1.2.3-rc` and environment `staging`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagConvention {
+    pattern: String,
+    parts: Vec<TagPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TagPart {
+    Literal(String),
+    Capture(String),
+}
+
+impl TagConvention {
+    /// Fails with `InvalidTagPattern` for unbalanced braces, empty or repeated placeholder names
+    /// and placeholders without a separator in between, which could split a tag anywhere
+    pub fn new(pattern: &str) -> Result<Self, ECSMetadataError> {
+        let invalid = |reason: &str| ECSMetadataError::InvalidTagPattern {
+            pattern: pattern.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            match rest.find(['{', '}']) {
+                Some(0) if rest.starts_with('{') => {
+                    let end = rest.find('}').ok_or_else(|| invalid("unclosed placeholder"))?;
+                    let name = &rest[1..end];
+                    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                        return Err(invalid("placeholder names are letters, digits and underscores"));
+                    }
+                    if parts.iter().any(|part| matches!(part, TagPart::Capture(other) if other == name)) {
+                        return Err(invalid("repeated placeholder"));
+                    }
+                    if matches!(parts.last(), Some(TagPart::Capture(_))) {
+                        return Err(invalid("placeholders need a separator in between"));
+                    }
+                    parts.push(TagPart::Capture(name.to_string()));
+                    rest = &rest[end + 1..];
+                }
+                Some(0) => return Err(invalid("unopened placeholder")),
+                Some(next) => {
+                    parts.push(TagPart::Literal(rest[..next].to_string()));
+                    rest = &rest[next..];
+                }
+                None => {
+                    parts.push(TagPart::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+        Ok(Self { pattern: pattern.to_string(), parts })
+    }
+
+    /// The pattern as given
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Placeholder values by name, `None` when the tag doesn't follow the convention (e.g.
+    /// `latest` for `{version}-{environment}`). Placeholders never match an empty string.
+    pub fn captures<'a>(&'a self, tag: &'a str) -> Option<BTreeMap<&'a str, &'a str>> {
+        let mut captures = BTreeMap::new();
+        match_parts(&self.parts, tag, &mut captures).then_some(captures)
+    }
+}
+
+// Backtracks over the occurrences of the separator following a placeholder, longest first.
+// Tags are at most 128 characters, so this stays cheap.
+fn match_parts<'a>(parts: &'a [TagPart], tag: &'a str, captures: &mut BTreeMap<&'a str, &'a str>) -> bool {
+    match parts {
+        [] => tag.is_empty(),
+        [TagPart::Literal(literal), rest @ ..] => {
+            tag.strip_prefix(literal.as_str()).is_some_and(|tag| match_parts(rest, tag, captures))
+        }
+        [TagPart::Capture(name), rest @ ..] => {
+            let ends: Vec<usize> = match rest.first() {
+                None => vec![tag.len()],
+                Some(TagPart::Literal(literal)) => tag.rmatch_indices(literal.as_str()).map(|(at, _)| at).collect(),
+                // rejected by `TagConvention::new`
+                Some(TagPart::Capture(_)) => return false,
+            };
+            // the rest only records its captures once it matched
+            for end in ends.into_iter().filter(|end| *end > 0) {
+                if match_parts(rest, &tag[end..], captures) {
+                    captures.insert(name.as_str(), &tag[..end]);
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_image() {
+        assert_eq!(split_image("nginx"), ("nginx", None));
+        assert_eq!(split_image("nginx:1.27"), ("nginx", Some("1.27")));
+        assert_eq!(split_image("registry.local:5000/team/app"), ("registry.local:5000/team/app", None));
+        assert_eq!(split_image("registry.local:5000/team/app:v2"), ("registry.local:5000/team/app", Some("v2")));
+        assert_eq!(split_image("app@sha256:0d5c"), ("app", None));
+    }
+
+    fn captures<'a>(convention: &'a TagConvention, tag: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+        convention.captures(tag).map(|captures| captures.into_iter().collect())
+    }
+
+    #[test]
+    fn test_tag_convention() {
+        let convention = TagConvention::new("{version}-{environment}").unwrap();
+        assert_eq!(captures(&convention, "latest-production"), Some(vec![("environment", "production"), ("version", "latest")]));
+        assert_eq!(captures(&convention, "v1.2.3-staging"), Some(vec![("environment", "staging"), ("version", "v1.2.3")]));
+        // the version takes the extra separators
+        assert_eq!(captures(&convention, "v1.2.3-rc.1-staging"), Some(vec![("environment", "staging"), ("version", "v1.2.3-rc.1")]));
+        for tag in ["latest", "-staging", "v1-", ""] {
+            assert_eq!(convention.captures(tag), None, "{tag}");
+        }
+
+        let prefixed = TagConvention::new("release_{version}__{region}.{channel}").unwrap();
+        assert_eq!(
+            captures(&prefixed, "release_2024.10__eu-west-1.beta"),
+            Some(vec![("channel", "beta"), ("region", "eu-west-1"), ("version", "2024.10")])
+        );
+        assert_eq!(prefixed.captures("2024.10__eu-west-1.beta"), None);
+
+        // no placeholder, only an exact match
+        assert_eq!(captures(&TagConvention::new("stable").unwrap(), "stable"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_invalid_tag_conventions() {
+        for pattern in ["{version", "version}", "{}-{env}", "{version}{env}", "{env}-{env}", "{env name}"] {
+            let err = TagConvention::new(pattern).unwrap_err();
+            assert!(matches!(&err, ECSMetadataError::InvalidTagPattern { pattern: given, .. } if given == pattern), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_ecr_registries() {
+        for (image, expected) in [
+            ("939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production", ("939885537497", "us-east-1")),
+            ("939885537497.dkr.ecr-fips.us-east-2.amazonaws.com/streamer@sha256:0123", ("939885537497", "us-east-2")),
+            ("123456789012.dkr.ecr.us-gov-west-1.amazonaws.com/team/service:1.2", ("123456789012", "us-gov-west-1")),
+            ("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn/service", ("123456789012", "cn-north-1")),
+            ("123456789012.dkr-ecr.eu-west-1.on.aws/service:v3", ("123456789012", "eu-west-1")),
+        ] {
+            assert_eq!(ecr_registry(image), Some(expected), "{image}");
+        }
+    }
+
+    #[test]
+    fn test_other_registries() {
+        for image in [
+            "public.ecr.aws/nginx/nginx:1.27",
+            "nginx:latest",
+            "library/nginx",
+            "docker.io/library/nginx:1.27",
+            "ghcr.io/sardusmatt/streamer:main",
+            "localhost:5000/streamer",
+            // not an account ID
+            "streamer.dkr.ecr.us-east-1.amazonaws.com/streamer",
+            "939885537497.dkr.ecr.us-east-1.amazonaws.com.evil.example/streamer",
+            "939885537497.dkr.ecr.us-east-1.amazonaws.com",
+        ] {
+            assert_eq!(ecr_registry(image), None, "{image}");
+        }
+    }
+}
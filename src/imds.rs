@@ -0,0 +1,233 @@
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use crate::client::{is_outside_ecs, ECSMetadataBuilder};
+use crate::error::{parse_document, ECSMetadataError};
+use crate::metadata::ECSMetadata;
+
+const TOKEN_PATH: &str = "latest/api/token";
+const IDENTITY_DOCUMENT_PATH: &str = "latest/dynamic/instance-identity/document";
+const TOKEN_TTL_HEADER: &str = "x-aws-ec2-metadata-token-ttl-seconds";
+const TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+/// The token is only used for the request that follows
+const TOKEN_TTL_SECONDS: &str = "60";
+
+/// Instance identity document of the EC2 instance metadata service
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EC2InstanceIdentity {
+    instance_id: String,
+    instance_type: String,
+    availability_zone: String,
+    region: String,
+    account_id: String,
+    #[serde(default)]
+    image_id: Option<String>,
+    #[serde(default)]
+    private_ip: Option<String>,
+    #[serde(default)]
+    architecture: Option<String>,
+}
+
+impl EC2InstanceIdentity {
+    /// e.g. `i-1234567890abcdef0`
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// e.g. `m6i.large`
+    pub fn instance_type(&self) -> &str {
+        &self.instance_type
+    }
+
+    pub fn availability_zone(&self) -> &str {
+        &self.availability_zone
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// AMI the instance was launched from
+    pub fn image_id(&self) -> Option<&str> {
+        self.image_id.as_deref()
+    }
+
+    pub fn private_ip(&self) -> Option<&str> {
+        self.private_ip.as_deref()
+    }
+
+    /// e.g. `x86_64` or `arm64`
+    pub fn architecture(&self) -> Option<&str> {
+        self.architecture.as_deref()
+    }
+}
+
+/// Where the process runs, with the identity fields both platforms share, see
+/// `ECSMetadataBuilder::init_runtime_identity`
+#[derive(Debug, Clone)]
+pub enum RuntimeIdentity {
+    /// On ECS, the metadata of this container along with the task document
+    Ecs(Box<ECSMetadata>),
+    /// On EC2 outside ECS, the identity of the instance
+    Ec2(EC2InstanceIdentity),
+}
+
+impl RuntimeIdentity {
+    pub fn region(&self) -> Option<&str> {
+        match self {
+            Self::Ecs(metadata) => metadata.region(),
+            Self::Ec2(instance) => Some(instance.region()),
+        }
+    }
+
+    pub fn availability_zone(&self) -> Option<&str> {
+        match self {
+            Self::Ecs(metadata) => metadata.availability_zone(),
+            Self::Ec2(instance) => Some(instance.availability_zone()),
+        }
+    }
+
+    pub fn account_id(&self) -> Option<&str> {
+        match self {
+            Self::Ecs(metadata) => metadata.account_id(),
+            Self::Ec2(instance) => Some(instance.account_id()),
+        }
+    }
+
+    /// EC2 instance ID, `None` on ECS where the task metadata does not tell the instance
+    pub fn instance_id(&self) -> Option<&str> {
+        self.ec2().map(EC2InstanceIdentity::instance_id)
+    }
+
+    /// EC2 instance type, `None` on ECS
+    pub fn instance_type(&self) -> Option<&str> {
+        self.ec2().map(EC2InstanceIdentity::instance_type)
+    }
+
+    /// ECS task ARN, `None` on EC2
+    pub fn task_arn(&self) -> Option<&str> {
+        self.ecs().map(ECSMetadata::task_arn)
+    }
+
+    pub fn ecs(&self) -> Option<&ECSMetadata> {
+        match self {
+            Self::Ecs(metadata) => Some(metadata),
+            Self::Ec2(_) => None,
+        }
+    }
+
+    pub fn ec2(&self) -> Option<&EC2InstanceIdentity> {
+        match self {
+            Self::Ecs(_) => None,
+            Self::Ec2(instance) => Some(instance),
+        }
+    }
+}
+
+impl ECSMetadataBuilder {
+    /// The ECS metadata with the task document as `init_with_task` fetches it or, when not on
+    /// ECS (see `try_init`), the identity of the EC2 instance from IMDSv2. Failures on ECS are
+    /// returned as is, without trying IMDS.
+    pub async fn init_runtime_identity(self) -> Result<RuntimeIdentity, ECSMetadataError> {
+        match self.clone().init_with_task().await {
+            Ok(metadata) => Ok(RuntimeIdentity::Ecs(Box::new(metadata))),
+            Err(err) if is_outside_ecs(&err) => self.fetch_instance_identity().await.map(RuntimeIdentity::Ec2),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Identity document of the EC2 instance, from IMDSv2 at `169.254.169.254` (see
+    /// `imds_endpoint`) under the IMDS policy. From a container, this needs the instance's
+    /// metadata hop limit to be at least 2, or host networking.
+    pub async fn fetch_instance_identity(&self) -> Result<EC2InstanceIdentity, ECSMetadataError> {
+        let (status, token) = self.send_to_imds(Method::PUT, self.imds_url(TOKEN_PATH)?, &[(TOKEN_TTL_HEADER, TOKEN_TTL_SECONDS)]).await?;
+        let token = imds_response(status, token)?;
+        let token = String::from_utf8_lossy(&token);
+        let url = self.imds_url(IDENTITY_DOCUMENT_PATH)?;
+        let (status, document) = self.send_to_imds(Method::GET, url, &[(TOKEN_HEADER, token.trim())]).await?;
+        parse_document(&imds_response(status, document)?)
+    }
+}
+
+fn imds_response(status: StatusCode, body: Vec<u8>) -> Result<Vec<u8>, ECSMetadataError> {
+    if status.is_success() {
+        return Ok(body);
+    }
+    Err(ECSMetadataError::ImdsRejected { code: status.to_string(), message: String::from_utf8_lossy(&body).trim().to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RequestPolicy;
+    use crate::error::Phase;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+    use crate::test_support::{FakeEnv, MockAgent, MockResponse};
+
+    const IDENTITY_JSON: &str = include_str!("../testdata/imds/instance_identity.json");
+
+    fn builder(agent: &MockAgent) -> ECSMetadataBuilder {
+        ECSMetadataBuilder::new().imds_endpoint(agent.url("/"))
+    }
+
+    fn serve_imds(agent: &MockAgent) {
+        agent.set("/latest/api/token", MockResponse::json("AQAEAFUsWK-token=="));
+        agent.set("/latest/dynamic/instance-identity/document", MockResponse::json(IDENTITY_JSON));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_instance_identity() {
+        let agent = MockAgent::start().await;
+        serve_imds(&agent);
+        let instance = builder(&agent).fetch_instance_identity().await.unwrap();
+        assert_eq!((instance.instance_id(), instance.instance_type()), ("i-1234567890abcdef0", "m6i.large"));
+        assert_eq!((instance.region(), instance.availability_zone(), instance.account_id()), ("us-west-2", "us-west-2b", "111122223333"));
+        assert_eq!((instance.image_id(), instance.private_ip(), instance.architecture()), (Some("ami-0abcdef1234567890"), Some("10.0.1.17"), Some("x86_64")));
+
+        let token = &agent.requests("/latest/api/token")[0];
+        assert_eq!((token.method.as_str(), token.header(TOKEN_TTL_HEADER)), ("PUT", Some("60")));
+        let document = &agent.requests("/latest/dynamic/instance-identity/document")[0];
+        assert_eq!((document.method.as_str(), document.header(TOKEN_HEADER)), ("GET", Some("AQAEAFUsWK-token==")));
+    }
+
+    #[tokio::test]
+    async fn test_imds_rejected() {
+        let agent = MockAgent::start().await;
+        agent.set("/latest/api/token", MockResponse::json("AQAEAFUsWK-token=="));
+        agent.set("/latest/dynamic/instance-identity/document", MockResponse::status(401, "Unauthorized"));
+        let err = builder(&agent).fetch_instance_identity().await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::ImdsRejected { code, message } if code == "401 Unauthorized" && message == "Unauthorized"), "{err:?}");
+        assert_eq!(err.phase(), Phase::Status);
+
+        let err = builder(&agent).imds_endpoint("http://metadata.internal").fetch_instance_identity().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::InvalidEndpoint { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_runtime_identity() {
+        let agent = MockAgent::start().await;
+        serve_imds(&agent);
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task_json(&[CONTAINER_JSON.to_string()])));
+
+        let identity = builder(&agent).endpoint(agent.url("/v4/abc")).init_runtime_identity().await.unwrap();
+        assert_eq!(identity.task_arn(), Some("arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0"));
+        assert_eq!((identity.region(), identity.availability_zone(), identity.instance_id()), (Some("us-east-1"), Some("us-east-1b"), None));
+        assert_eq!(agent.total_hits(), 2);
+
+        // a failure on ECS is not covered up by IMDS
+        let err = builder(&agent).endpoint(agent.url("/v4/missing")).metadata_policy(RequestPolicy::new(None, 0)).init_runtime_identity().await;
+        assert!(matches!(err, Err(ECSMetadataError::HttpStatus { status: 404, .. })), "{err:?}");
+        assert_eq!(agent.hits("/latest/api/token"), 0);
+
+        let _env = FakeEnv::new(&[]);
+        let identity = builder(&agent).init_runtime_identity().await.unwrap();
+        assert_eq!((identity.instance_type(), identity.account_id(), identity.task_arn()), (Some("m6i.large"), Some("111122223333"), None));
+        assert!(identity.ecs().is_none());
+    }
+}
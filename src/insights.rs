@@ -0,0 +1,66 @@
+use crate::metadata::ECSMetadata;
+
+impl ECSMetadata {
+    /// Log group of the Container Insights performance events of this cluster,
+    /// `/aws/ecs/containerinsights/<cluster>/performance`. `None` when the cluster is unknown.
+    pub fn container_insights_log_group(&self) -> Option<String> {
+        if self.is_degraded() {
+            return None;
+        }
+        Some(format!("/aws/ecs/containerinsights/{}/performance", self.cluster_name()?))
+    }
+
+    /// Log stream of this container within `container_insights_log_group`,
+    /// `<task-id>/<container-name>`. `None` when the task ID or container name is unknown.
+    pub fn container_insights_stream_name(&self) -> Option<String> {
+        let task_id = self.task_id()?;
+        match self.container_name() {
+            "" => None,
+            container_name => Some(format!("{task_id}/{container_name}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_container_insights_names() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(
+            metadata.container_insights_log_group().as_deref(),
+            Some("/aws/ecs/containerinsights/production/performance")
+        );
+        assert_eq!(
+            metadata.container_insights_stream_name().as_deref(),
+            Some("021447970bce4bd58069f1925cd87bc0/streamer")
+        );
+
+        // the cluster label may hold the ARN, the log group uses the short name
+        let arn_label = CONTAINER_JSON.replace(
+            r#""com.amazonaws.ecs.cluster": "production""#,
+            r#""com.amazonaws.ecs.cluster": "arn:aws:ecs:us-east-1:939885537497:cluster/production""#,
+        );
+        assert_eq!(
+            metadata_from_json(&arn_label, None).container_insights_log_group().as_deref(),
+            Some("/aws/ecs/containerinsights/production/performance")
+        );
+    }
+
+    #[test]
+    fn test_container_insights_names_missing_components() {
+        let degraded = ECSMetadata::degraded(None);
+        assert_eq!(degraded.container_insights_log_group(), None);
+        assert_eq!(degraded.container_insights_stream_name(), None);
+
+        let old_format = CONTAINER_JSON
+            .replace(r#""com.amazonaws.ecs.cluster": "production","#, "")
+            .replace("task/production/", "task/")
+            .replace(r#""com.amazonaws.ecs.container-name": "streamer""#, r#""com.amazonaws.ecs.container-name": """#);
+        let metadata = metadata_from_json(&old_format, None);
+        assert_eq!(metadata.container_insights_log_group(), None);
+        assert_eq!(metadata.container_insights_stream_name(), None);
+    }
+}
@@ -0,0 +1,126 @@
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::instrument::{Instrument, Instrumented};
+use crate::shared::SharedECSMetadata;
+
+/// Runs every request of the wrapped service inside an `ecs` span carrying the
+/// `ECSMetadata::as_fields` of the latest snapshot, so events logged while handling the request
+/// have the ECS context. The span is a child of the current one: tracing spans only take the
+/// fields declared when they are created, so the fields cannot be added to an existing span.
+/// Degraded metadata gets no span at all.
+#[derive(Debug, Clone)]
+pub struct ECSContextLayer {
+    metadata: SharedECSMetadata,
+}
+
+impl ECSContextLayer {
+    pub fn new(metadata: SharedECSMetadata) -> Self {
+        Self { metadata }
+    }
+}
+
+impl<S> Layer<S> for ECSContextLayer {
+    type Service = ECSContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ECSContextService { inner, metadata: self.metadata.clone() }
+    }
+}
+
+/// Service produced by `ECSContextLayer`
+#[derive(Debug, Clone)]
+pub struct ECSContextService<S> {
+    inner: S,
+    metadata: SharedECSMetadata,
+}
+
+impl<S, Request> Service<Request> for ECSContextService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.inner.call(request).instrument(self.metadata.snapshot().span())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ECSMetadata;
+    use tracing::Span;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use std::convert::Infallible;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tower::{service_fn, ServiceExt};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+
+    type Fields = Arc<Mutex<Vec<(String, String)>>>;
+
+    // keeps the fields recorded on `ecs` spans
+    struct SpanFields(Fields);
+
+    impl Visit for SpanFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.lock().unwrap().push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.lock().unwrap().push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanFields {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: LayerContext<'_, S>) {
+            if attrs.metadata().name() == "ecs" {
+                attrs.record(&mut SpanFields(self.0.clone()));
+            }
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: LayerContext<'_, S>) {
+            values.record(&mut SpanFields(self.0.clone()));
+        }
+    }
+
+    async fn handle(metadata: ECSMetadata) -> (Fields, bool) {
+        let fields = Fields::default();
+        let subscriber = tracing_subscriber::registry().with(SpanFields(fields.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let service = service_fn(|_request: ()| async { Ok::<_, Infallible>(!Span::current().is_none()) });
+        let in_span = ECSContextLayer::new(SharedECSMetadata::new(metadata))
+            .layer(service)
+            .oneshot(())
+            .await
+            .unwrap();
+        (fields, in_span)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_fields_are_on_the_request_span() {
+        let metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        let expected: Vec<(String, String)> =
+            metadata.as_fields().into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+
+        let (fields, in_span) = handle(metadata).await;
+        assert!(in_span);
+        assert_eq!(*fields.lock().unwrap(), expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_degraded_metadata_adds_no_span() {
+        let (fields, in_span) = handle(ECSMetadata::degraded(None)).await;
+        assert!(!in_span);
+        assert!(fields.lock().unwrap().is_empty());
+    }
+}
@@ -1,5 +1,133 @@
 mod metadata;
+mod container;
+mod client;
+mod connection;
 mod error;
+mod task;
+mod warning;
+mod banner;
+mod diff;
+mod refresh;
+mod context;
+mod describe;
+mod insights;
+mod network;
+mod fields;
+mod shared;
+mod record;
+mod health;
+mod log_options;
+mod image;
+mod cgroup;
+mod quantity;
+mod memory;
+mod timestamp;
+mod sync;
+mod runtime;
+mod readiness;
+mod response;
+mod capture;
+mod stats;
+mod partial;
+mod arn;
+mod xray;
+mod otel;
+mod version;
+mod workers;
+mod cache;
+mod cached_stats;
+mod utilization;
+mod identity;
+mod consistency;
+mod watcher;
+mod global;
+pub mod prelude;
+#[cfg(feature = "tower")]
+mod layer;
+#[cfg(feature = "schemars")]
+mod schema;
+#[cfg(feature = "log-mdc")]
+mod mdc;
+#[cfg(feature = "test-util")]
+mod failure;
+#[cfg(feature = "test-util")]
+mod mock;
+#[cfg(feature = "shutdown")]
+mod shutdown;
+#[cfg(feature = "task-protection")]
+mod protection;
+#[cfg(feature = "blocking")]
+mod blocking;
+#[cfg(feature = "tracing")]
+mod events;
+#[cfg(feature = "credentials")]
+mod credentials;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "imds")]
+mod imds;
 
-pub use metadata::{ECSMetadata, ECSContainerLimits};
-pub use error::ECSMetadataError;
\ No newline at end of file
+pub use metadata::ECSMetadata;
+pub use container::{ContainerStatus, ECSContainerLimits, ECSContainerMetadata};
+pub use task::{ECSAggregateLimits, ECSContainerStartupRecord, ECSLimitsHeadroom, ECSTaskLimits, ECSTaskMetadata, LaunchType};
+pub use network::{ECSNetwork, ECSPortMapping, NetworkMode};
+pub use client::{Backoff, ECSMetadataBuilder, EndpointSource, RequestPolicy, RetryOn};
+pub use connection::{ClientStats, ConnectionPolicy};
+pub use error::{ECSMetadataError, Phase};
+pub use warning::{ParseWarning, ParseWarningKind};
+pub use banner::BannerOptions;
+pub use fields::FieldOptions;
+pub use diff::{FieldChange, MetadataDiff};
+pub use refresh::{RefreshCooldown, RefreshOutcome};
+pub use context::{ECSContext, NoopECSContext};
+pub use shared::SharedECSMetadata;
+pub use readiness::{background_init, ReadinessHandle};
+pub use watcher::{ECSMetadataWatcher, Transition, TransitionField, DEFAULT_HISTORY_LEN};
+pub use response::ECSResponseInfo;
+pub use capture::{RawCapture, MAX_CAPTURE_LEN};
+pub use cache::CACHE_FORMAT_VERSION;
+pub use cached_stats::{CachedStats, DEFAULT_STATS_MAX_STALE};
+pub use utilization::{StatsDelta, UtilizationTracker};
+pub use stats::{ECSBlkioEntry, ECSBlkioStats, ECSContainerStats, ECSCpuStats, ECSMemoryStats, ECSNetworkStats};
+pub use record::{ECSFlatRecord, ObservabilityBundle};
+pub use health::{ECSContainerHealth, HealthStatus};
+pub use log_options::REDACTED_LOG_OPTION_KEYS;
+pub use image::TagConvention;
+pub use memory::MemorySize;
+pub use identity::{Expected, IdentityExpectation, IdentityViolation};
+pub use consistency::LabelMismatch;
+pub use partial::{ECSPartialMetadata, FieldSet};
+pub use arn::{ContainerArn, TaskArn};
+pub use xray::XRAY_ECS_ORIGIN;
+pub use otel::OTEL_ECS_PLATFORM;
+#[cfg(feature = "tower")]
+pub use layer::{ECSContextLayer, ECSContextService};
+#[cfg(feature = "schemars")]
+pub use schema::schema;
+#[cfg(feature = "log-mdc")]
+pub use mdc::ECSLogContextGuard;
+#[cfg(feature = "test-util")]
+pub use failure::FailurePolicy;
+#[cfg(feature = "test-util")]
+pub use mock::MockECSMetadata;
+#[cfg(feature = "shutdown")]
+pub use shutdown::{shutdown_signal, shutdown_signal_every, ShutdownReason, DEFAULT_SHUTDOWN_POLL_INTERVAL};
+#[cfg(feature = "task-protection")]
+pub use protection::ECSTaskProtection;
+#[cfg(feature = "tracing")]
+pub use events::ECSFieldsLayer;
+#[cfg(feature = "credentials")]
+pub use credentials::{ECSTaskCredentials, TaskCredentialsProvider, DEFAULT_CREDENTIALS_REFRESH_WINDOW};
+#[cfg(feature = "prometheus")]
+pub use prometheus::PROMETHEUS_CONTENT_TYPE;
+#[cfg(feature = "opentelemetry")]
+pub use otel::ECSResourceDetector;
+#[cfg(feature = "imds")]
+pub use imds::{EC2InstanceIdentity, RuntimeIdentity};
+
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod proptests;
+#[cfg(test)]
+mod compat;
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Log option keys whose values are masked by `log_options_redacted`, compared ignoring case.
+/// `ECSMetadataBuilder::redact_log_option` adds to them.
+pub const REDACTED_LOG_OPTION_KEYS: &[&str] = &[
+    "splunk-token",
+    "apikey",
+    "api_key",
+    "license_key",
+    "header",
+    "http_passwd",
+    "password",
+    "aws_secret_access_key",
+];
+
+// stands in for the masked values
+pub(crate) const REDACTED: &str = "[redacted]";
+
+/// `LogOptions` of a container. Debug output and serialization mask the sensitive values, the
+/// raw ones are only handed out by `log_options`.
+#[derive(Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub(crate) struct LogOptions {
+    options: BTreeMap<String, String>,
+    // added by the builder, on top of `REDACTED_LOG_OPTION_KEYS`
+    #[serde(skip)]
+    extra_keys: Arc<[String]>,
+}
+
+impl LogOptions {
+    pub(crate) fn raw(&self) -> &BTreeMap<String, String> {
+        &self.options
+    }
+
+    pub(crate) fn redacted(&self) -> BTreeMap<String, String> {
+        let masked = |key: &str| {
+            REDACTED_LOG_OPTION_KEYS.iter().copied().chain(self.extra_keys.iter().map(String::as_str)).any(|sensitive| sensitive.eq_ignore_ascii_case(key))
+        };
+        self.options
+            .iter()
+            .map(|(key, value)| (key.clone(), if masked(key) { REDACTED.to_string() } else { value.clone() }))
+            .collect()
+    }
+
+    pub(crate) fn set_extra_keys(&mut self, keys: Arc<[String]>) {
+        self.extra_keys = keys;
+    }
+}
+
+// The masking rules are configuration, not data
+impl PartialEq for LogOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.options == other.options
+    }
+}
+
+impl fmt::Debug for LogOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.redacted()).finish()
+    }
+}
+
+impl Serialize for LogOptions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.redacted().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::ECSMetadata;
+
+    const SPLUNK_LOGGING: &str = r#""LogDriver": "splunk", "LogOptions": {
+        "splunk-token": "00000000-1111-2222-3333-444444444444",
+        "splunk-url": "https://splunk.example.com:8088",
+        "X-Internal-Auth": "hunter2"
+    }"#;
+
+    fn container_json() -> String {
+        crate::metadata::tests::CONTAINER_JSON.replacen('{', &format!("{{{SPLUNK_LOGGING},"), 1)
+    }
+
+    #[test]
+    fn test_log_options_redacted() {
+        let metadata = ECSMetadata::from_json(&container_json()).unwrap();
+        assert_eq!(metadata.log_driver(), Some("splunk"));
+        let raw = metadata.log_options().unwrap();
+        assert_eq!(raw["splunk-token"], "00000000-1111-2222-3333-444444444444");
+
+        let redacted = metadata.log_options_redacted().unwrap();
+        assert_eq!(redacted.keys().collect::<Vec<_>>(), raw.keys().collect::<Vec<_>>());
+        assert_eq!(redacted["splunk-token"], "[redacted]");
+        assert_eq!(redacted["splunk-url"], "https://splunk.example.com:8088");
+        assert_eq!(redacted["X-Internal-Auth"], "hunter2");
+
+        let debug = format!("{metadata:?}");
+        let serialized = serde_json::to_string(&metadata).unwrap();
+        for output in [&debug, &serialized] {
+            assert!(!output.contains("00000000-1111"), "{output}");
+            assert!(output.contains("splunk-token"), "{output}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_adds_keys() {
+        let agent = crate::test_support::MockAgent::start().await;
+        agent.set("/v4/abc", crate::test_support::MockResponse::json(container_json().as_str()));
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .redact_log_option("x-internal-auth")
+            .init()
+            .await
+            .unwrap();
+        let redacted = metadata.log_options_redacted().unwrap();
+        assert_eq!(redacted["X-Internal-Auth"], "[redacted]");
+        assert_eq!(redacted["splunk-token"], "[redacted]");
+        assert_eq!(metadata.log_options().unwrap()["X-Internal-Auth"], "hunter2");
+        assert!(!format!("{metadata:?}").contains("hunter2"));
+    }
+}
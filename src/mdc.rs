@@ -0,0 +1,114 @@
+use std::marker::PhantomData;
+use crate::metadata::ECSMetadata;
+
+/// Context installed by `ECSMetadata::install_log_context`, restores the thread's previous
+/// values of the keys when dropped. Not `Send`: the context is thread-local.
+#[must_use = "the context is removed when the guard is dropped"]
+pub struct ECSLogContextGuard {
+    _previous: log_mdc::ExtendGuard,
+    _thread_local: PhantomData<*const ()>,
+}
+
+impl std::fmt::Debug for ECSLogContextGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ECSLogContextGuard").finish_non_exhaustive()
+    }
+}
+
+impl ECSMetadata {
+    /// Writes the `as_fields` keys into the `log_mdc` context of the current thread, read by
+    /// `log` formatters such as log4rs' `{X(ecs.task.id)}`, until the guard is dropped
+    pub fn install_log_context(&self) -> ECSLogContextGuard {
+        ECSLogContextGuard {
+            _previous: log_mdc::extend_scoped(self.as_fields()),
+            _thread_local: PhantomData,
+        }
+    }
+
+    /// Callback writing the `as_fields` keys into the `log_mdc` context of the thread it runs
+    /// on, for the whole life of the thread. Meant for the thread start hook of a pool, e.g.
+    /// tokio's `runtime::Builder::on_thread_start` or rayon's
+    /// `ThreadPoolBuilder::start_handler(move |_| install())`.
+    pub fn log_context_thread_start(&self) -> impl Fn() + Send + Sync + 'static {
+        let fields = self.as_fields();
+        move || log_mdc::extend(fields.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::sync::Once;
+    use log::{Log, Metadata, Record};
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    thread_local! {
+        static LINES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // Stand-in for a JSON formatter: the message followed by the sorted MDC entries
+    struct MdcLogger;
+
+    impl Log for MdcLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let mut entries = Vec::new();
+            log_mdc::iter(|key, value| entries.push(format!("{key}={value}")));
+            entries.sort();
+            LINES.with(|lines| lines.borrow_mut().push(format!("{} {}", record.args(), entries.join(" "))));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn logged(f: impl FnOnce()) -> Vec<String> {
+        static LOGGER: Once = Once::new();
+        LOGGER.call_once(|| {
+            log::set_logger(&MdcLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Info);
+        });
+        LINES.with(|lines| lines.borrow_mut().clear());
+        f();
+        LINES.with(|lines| lines.take())
+    }
+
+    const CONTEXT: &str = "container.image=939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production \
+                           ecs.cluster=production ecs.container.name=streamer ecs.task.id=021447970bce4bd58069f1925cd87bc0 \
+                           ecs.task_definition.family=streamer ecs.task_definition.revision=12";
+
+    #[test]
+    fn test_install_log_context() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        log_mdc::insert("ecs.cluster", "outer");
+        log_mdc::insert("request.id", "42");
+
+        let lines = logged(|| {
+            let guard = metadata.install_log_context();
+            log::info!("inside");
+            drop(guard);
+            log::info!("after");
+        });
+        assert_eq!(lines, [format!("inside {CONTEXT} request.id=42"), "after ecs.cluster=outer request.id=42".to_string()]);
+        log_mdc::clear();
+    }
+
+    #[test]
+    fn test_log_context_thread_start() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .on_thread_start(metadata.log_context_thread_start())
+            .build()
+            .unwrap();
+
+        let lines = runtime.block_on(async {
+            let worker = tokio::spawn(async { logged(|| log::info!("worker")) });
+            worker.await.unwrap()
+        });
+        assert_eq!(lines, [format!("worker {CONTEXT}")]);
+        assert_eq!(logged(|| log::info!("caller")), ["caller "]);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+const MIB: u64 = 1024 * 1024;
+const MB: u64 = 1000 * 1000;
+
+// Display units, each 1024 times the one before
+const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// An amount of memory, kept in bytes so that the unit is never guessed. ECS limits are in
+/// MiB (1024 × 1024 bytes), which `from_mib` takes; MB (1000 × 1000 bytes) only exist as
+/// `as_mb`, for the tools that report them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemorySize {
+    bytes: u64,
+}
+
+impl MemorySize {
+    pub const fn from_bytes(bytes: u64) -> Self {
+        Self { bytes }
+    }
+
+    /// Saturates at `u64::MAX` bytes, some 16 EiB
+    pub const fn from_mib(mib: u64) -> Self {
+        Self { bytes: mib.saturating_mul(MIB) }
+    }
+
+    pub const fn as_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// In MiB, the unit of the ECS limits, fractional for amounts that aren't whole MiB
+    pub fn as_mib(&self) -> f64 {
+        self.bytes as f64 / MIB as f64
+    }
+
+    /// In MB, 1000 × 1000 bytes: 1 MiB is about 1.05 MB
+    pub fn as_mb(&self) -> f64 {
+        self.bytes as f64 / MB as f64
+    }
+}
+
+/// In the largest binary unit the amount is at least one of, up to two decimals with the
+/// trailing zeros dropped, e.g. `512 B`, `1.5 KiB` or `4 GiB`
+impl fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut exponent = 0;
+        while exponent + 1 < UNITS.len() && self.bytes >= 1 << (10 * (exponent + 1)) {
+            exponent += 1;
+        }
+        // rounded to hundredths of the unit, which may round up to the next unit
+        let hundredths = |exponent: usize| {
+            let unit = 1u128 << (10 * exponent);
+            (u128::from(self.bytes) * 100 + unit / 2) / unit
+        };
+        let mut rounded = hundredths(exponent);
+        if rounded >= 1024 * 100 && exponent + 1 < UNITS.len() {
+            exponent += 1;
+            rounded = hundredths(exponent);
+        }
+        let (whole, fraction) = (rounded / 100, rounded % 100);
+        let unit = UNITS[exponent];
+        match fraction {
+            0 => write!(f, "{whole} {unit}"),
+            _ if fraction % 10 == 0 => write!(f, "{whole}.{} {unit}", fraction / 10),
+            _ => write!(f, "{whole}.{fraction:02} {unit}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let limit = MemorySize::from_mib(512);
+        assert_eq!(limit.as_bytes(), 536_870_912);
+        assert_eq!(limit.as_mib(), 512.0);
+        assert_eq!(limit.as_mb(), 536.870912);
+
+        // the confusion this type is for: 512 MiB is not 512 MB
+        assert_ne!(limit.as_bytes(), 512 * 1_000_000);
+        assert_eq!(MemorySize::from_bytes(512 * 1_000_000).as_mb(), 512.0);
+
+        assert_eq!(MemorySize::from_bytes(0).as_mib(), 0.0);
+        assert_eq!(MemorySize::from_bytes(MIB / 2).as_mib(), 0.5);
+        assert_eq!(MemorySize::from_bytes(1).as_bytes(), 1);
+        assert_eq!(MemorySize::from_mib(1), MemorySize::from_bytes(1_048_576));
+        assert_eq!(MemorySize::from_mib(u64::MAX).as_bytes(), u64::MAX);
+        assert_eq!(MemorySize::from_mib(u64::MAX / MIB).as_bytes(), u64::MAX / MIB * MIB);
+        assert!(MemorySize::from_mib(1) < MemorySize::from_bytes(1_048_577));
+        assert_eq!(MemorySize::default(), MemorySize::from_bytes(0));
+    }
+
+    #[test]
+    fn test_display() {
+        for (bytes, expected) in [
+            (0, "0 B"),
+            (1, "1 B"),
+            (1023, "1023 B"),
+            (1024, "1 KiB"),
+            (1536, "1.5 KiB"),
+            (1280, "1.25 KiB"),
+            (1034, "1.01 KiB"),
+            (1029, "1 KiB"),
+            // rounds up into the next unit rather than to 1024 of this one
+            (MIB - 1, "1 MiB"),
+            (MIB - 6, "1023.99 KiB"),
+            (MIB, "1 MiB"),
+            (128 * MIB, "128 MiB"),
+            (4096 * MIB, "4 GiB"),
+            (8192 * MIB + 512 * MIB, "8.5 GiB"),
+            (1 << 40, "1 TiB"),
+            // the largest unit, never past it
+            (5000 << 40, "5000 TiB"),
+            (u64::MAX, "16777216 TiB"),
+        ] {
+            assert_eq!(MemorySize::from_bytes(bytes).to_string(), expected, "{bytes} bytes");
+        }
+        assert_eq!(MemorySize::from_mib(512).to_string(), "512 MiB");
+        assert_eq!(MemorySize::from_mib(1536).to_string(), "1.5 GiB");
+    }
+}
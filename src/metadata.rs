@@ -1,106 +1,663 @@
-use serde::Deserialize;
-use std::env;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use crate::client::{ECSMetadataBuilder, EndpointSource, RequestPolicy};
+use crate::container::{ContainerStatus, ECSContainerLimits, ECSContainerMetadata};
 use crate::error::ECSMetadataError;
+use crate::health::{ECSContainerHealth, DEFAULT_MAX_HEALTH_OUTPUT_LEN};
+use crate::image::TagConvention;
+use crate::memory::MemorySize;
+use crate::network::{ECSNetwork, ECSPortMapping, NetworkMode};
+use crate::response::ECSResponseInfo;
+use crate::task::{ECSTaskLimits, ECSTaskMetadata, LaunchType};
+use crate::version::RawDocument;
+use crate::warning::{ParseWarning, ParseWarningKind};
 
-const ECS_METADATA_V4_ENV_VAR: &str = "ECS_CONTAINER_METADATA_URI_V4";
-
-// Initial information set (there is more available to extend it, format can be found at
-// https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-metadata-endpoint-v4-response.html
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct ECSContainerMetadataV4 {
-    docker_id: String,
-    image: String,
-    labels: ECSContainerLabels,
-    limits: ECSContainerLimits,
+/// Serializes as a snapshot: `container` and `task` hold the documents with the agent's field
+/// names, the other keys are this crate's
+///
+/// # Minimal metadata
+///
+/// Only `DockerId` and the `com.amazonaws.ecs.task-arn` label must be in the container document,
+/// so `docker_id` and `task_arn` always return what the agent served, as do `task_id` and
+/// `region` which only depend on the ARN. The other fields the agent always serves fall back
+/// when missing, each with a `MissingFieldDefaulted` warning: `image`, `container_name`,
+/// `task_definition_family` and `task_definition_revision` return an empty string, `limits`
+/// zero (unlimited). Everything returning an `Option` (cluster, networks, health, ...) is
+/// optional in the document as well. `ECSMetadataBuilder::strict` turns the fallbacks into
+/// `MissingField` errors.
+#[derive(Serialize, Debug, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSMetadata {
+    #[serde(rename = "container")]
+    metadata: ECSContainerMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task: Option<ECSTaskMetadata>,
+    degraded: bool,
+    pub(crate) warnings: Vec<ParseWarning>,
+    // bodies as served, so a refresh can tell an unchanged document apart without parsing it
+    #[serde(skip)]
+    pub(crate) raw: RawDocuments,
+    // where the documents came from, `None` if they were handed over (e.g. `from_json`)
+    #[serde(skip)]
+    pub(crate) source: Option<ECSMetadataBuilder>,
+    #[serde(skip)]
+    pub(crate) skipped_parses: u64,
+    // start of the latest fetch attempt, for `min_refresh_interval`
+    #[serde(skip)]
+    pub(crate) last_fetch: Option<Instant>,
+    // start of the latest successful fetch, see `fetched_at`
+    #[serde(skip)]
+    pub(crate) fetched_at: Option<Instant>,
+    #[serde(skip)]
+    pub(crate) endpoint_source: Option<EndpointSource>,
+    #[serde(skip)]
+    pub(crate) response_info: Option<ECSResponseInfo>,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct ECSContainerLabels {
-    #[serde(rename = "com.amazonaws.ecs.cluster")]
-    cluster: String,
-    #[serde(rename = "com.amazonaws.ecs.container-name")]
-    container_name: String,
-    #[serde(rename = "com.amazonaws.ecs.task-arn")]
-    task_arn: String,
-    #[serde(rename = "com.amazonaws.ecs.task-definition-family")]
-    task_definition_family: String,
-    #[serde(rename = "com.amazonaws.ecs.task-definition-version")]
-    task_definition_version: String,
+/// Snapshots compare equal when every parsed field matches, see `diff` for a per-field comparison
+impl PartialEq for ECSMetadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata && self.task == other.task && self.degraded == other.degraded
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ECSContainerLimits {
-    #[serde(rename = "CPU")]
-    pub cpu: u16,
-    #[serde(rename = "Memory")]
-    pub mem: u16,
+#[derive(Clone, Default, PartialEq)]
+pub(crate) struct RawDocuments {
+    pub(crate) container: Vec<u8>,
+    pub(crate) task: Option<Vec<u8>>,
+    // served by the v3 endpoint, see `RawDocument::V3`
+    pub(crate) v3: bool,
 }
 
-pub struct ECSMetadata {
-    metadata: ECSContainerMetadataV4,
+impl fmt::Debug for RawDocuments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawDocuments")
+            .field("container_len", &self.container.len())
+            .field("task_len", &self.task.as_ref().map(Vec::len))
+            .field("v3", &self.v3)
+            .finish()
+    }
 }
 
 impl ECSMetadata {
-    /// Initialize ECS metadata by fetching it from the AWS ECS metadata endpoint
+    /// Value returned by the string accessors of a degraded instance (see `init_or_default`)
+    pub const UNKNOWN: &'static str = "unknown";
+
+    /// Conventional length for `task_id_short`, keeps metric label cardinality bounded
+    pub const SHORT_TASK_ID_LEN: usize = 8;
+
+    /// Initialize ECS metadata by fetching it from the AWS ECS metadata endpoint.
+    /// Cancellation safe, see `ECSMetadataBuilder::init`.
     pub async fn init() -> Result<Self, ECSMetadataError> {
-        let metadata_url = env::var(ECS_METADATA_V4_ENV_VAR)
-            .map_err(|_| ECSMetadataError::EnvVarNotSet(ECS_METADATA_V4_ENV_VAR.to_string()))?;
+        Self::builder().init().await
+    }
+
+    /// Same as `init`, but also fetches the task-level document, which carries fields
+    /// (e.g. the availability zone) that the container endpoint does not expose
+    pub async fn init_with_task() -> Result<Self, ECSMetadataError> {
+        Self::builder().init_with_task().await
+    }
+
+    /// Builder to customize where and how the metadata is fetched
+    pub fn builder() -> ECSMetadataBuilder {
+        ECSMetadataBuilder::new()
+    }
+
+    /// Never fails: attempts the fetch within `timeout` and, on any failure, returns a degraded
+    /// instance instead. A degraded instance returns `ECSMetadata::UNKNOWN` from the identity
+    /// accessors (cluster, names, ARN, image), `None` from task ID, region, AZ and effective limits, and
+    /// zero (unlimited) limits. Meant for non-critical consumers such as log enrichment.
+    pub async fn init_or_default(timeout: Duration) -> Self {
+        let builder = Self::builder().metadata_policy(RequestPolicy::new(Some(timeout), 0));
+        let attempted_at = Instant::now();
+        match builder.clone().init().await {
+            Ok(metadata) => metadata,
+            Err(_) => Self {
+                last_fetch: Some(attempted_at),
+                ..Self::degraded(Some(builder))
+            },
+        }
+    }
+
+    /// `ECSMetadataBuilder::try_init` with the default builder, `None` outside ECS
+    pub async fn try_init() -> Result<Option<Self>, ECSMetadataError> {
+        Self::builder().try_init().await
+    }
 
-        let response = reqwest::get(&metadata_url)
-            .await?
-            .error_for_status()?; // bail if not successful
+    /// Builds an instance from an already fetched container metadata document, e.g. a canned
+    /// response in tests. The task document is not attached.
+    pub fn from_json(json: &str) -> Result<Self, ECSMetadataError> {
+        Self::from_documents(json.as_bytes().to_vec(), None, None)
+    }
+
+    /// Same as `from_json` for the documents of the v3 endpoint (`ECS_CONTAINER_METADATA_URI`),
+    /// along with the task document if given, as `init` does on agents predating v4. The
+    /// accessors of the fields v3 lacks return `None` or empty: `container_arn` (and
+    /// `parsed_container_arn`), `log_driver`, `log_options`, the task's `launch_type` and the ENI
+    /// details of the networks (MAC address, attachment index, DNS servers and search list,
+    /// subnet); `resource_attributes` leaves out the attributes built from them.
+    pub fn from_v3_json(container: &str, task: Option<&str>) -> Result<Self, ECSMetadataError> {
+        let task = task.map(|task| task.as_bytes().to_vec());
+        Self::from_versioned_documents(container.as_bytes().to_vec(), task, true, None)
+    }
+
+    /// Parses the v4 documents as served, keeping the bodies around for `refresh`
+    pub(crate) fn from_documents(
+        container: Vec<u8>,
+        task: Option<Vec<u8>>,
+        source: Option<ECSMetadataBuilder>,
+    ) -> Result<Self, ECSMetadataError> {
+        Self::from_versioned_documents(container, task, false, source)
+    }
+
+    pub(crate) fn from_versioned_documents(
+        container: Vec<u8>,
+        task: Option<Vec<u8>>,
+        v3: bool,
+        source: Option<ECSMetadataBuilder>,
+    ) -> Result<Self, ECSMetadataError> {
+        let mut metadata: ECSContainerMetadata = RawDocument::new(&container, v3).parse()?;
+        metadata.labels.require_task_arn()?;
+        if source.as_ref().is_some_and(|source| source.strict) {
+            if let Some(field) = metadata.missing_fields().first() {
+                return Err(ECSMetadataError::MissingField(field.to_string()));
+            }
+        }
+        let mut task_metadata: Option<ECSTaskMetadata> = task.as_deref().map(|task| RawDocument::new(task, v3).parse()).transpose()?;
+
+        let max_health_output_len = source.as_ref().map_or(DEFAULT_MAX_HEALTH_OUTPUT_LEN, |source| source.max_health_output_len);
+        metadata.truncate_health_output(max_health_output_len);
+        for container in task_metadata.iter_mut().flat_map(ECSTaskMetadata::containers_mut) {
+            container.truncate_health_output(max_health_output_len);
+        }
+        if let Some(keys) = source.as_ref().map(|source| &source.redacted_log_option_keys).filter(|keys| !keys.is_empty()) {
+            let keys: Arc<[String]> = keys.as_slice().into();
+            metadata.redact_log_options(&keys);
+            for container in task_metadata.iter_mut().flat_map(ECSTaskMetadata::containers_mut) {
+                container.redact_log_options(&keys);
+            }
+        }
+
+        let mut parsed = Self::from_parts(metadata, task_metadata);
+        if let Some(expected) = source.as_ref().and_then(|source| source.identity.as_ref()) {
+            parsed.assert_identity(expected)?;
+        }
+        parsed.raw = RawDocuments { container, task, v3 };
+        parsed.source = source;
+        Ok(parsed)
+    }
+
+    pub(crate) fn from_parts(metadata: ECSContainerMetadata, task: Option<ECSTaskMetadata>) -> Self {
+        let missing = metadata.missing_fields().into_iter().map(|field| {
+            ParseWarning::new(ParseWarningKind::MissingFieldDefaulted, format!("container document has no {field}, left empty"))
+        });
+        let unknown_mode = match metadata.network_mode() {
+            NetworkMode::Other(mode) => Some(ParseWarning::new(
+                ParseWarningKind::UnknownEnumValue,
+                format!("container document has NetworkMode {mode}, kept as Other"),
+            )),
+            _ => None,
+        };
+        // a task document without containers is already warned about
+        let self_missing = task
+            .as_ref()
+            .filter(|task| !task.containers().is_empty())
+            .filter(|task| task.self_container(metadata.docker_id(), metadata.container_name()).is_none())
+            .map(|_| {
+                ParseWarning::new(
+                    ParseWarningKind::SelfContainerNotFound,
+                    format!("task document has no entry for container {}", metadata.docker_id()),
+                )
+            });
+        // emitted by the task document already
+        let task_warnings = task.iter().flat_map(|task| task.warnings()).cloned();
+        let warnings = missing.chain(unknown_mode).chain(self_missing).map(ParseWarning::emit).chain(task_warnings).collect();
+        Self {
+            metadata,
+            task,
+            degraded: false,
+            warnings,
+            raw: RawDocuments::default(),
+            source: None,
+            skipped_parses: 0,
+            last_fetch: None,
+            fetched_at: None,
+            endpoint_source: None,
+            response_info: None,
+        }
+    }
+
+    pub(crate) fn degraded(source: Option<ECSMetadataBuilder>) -> Self {
+        Self {
+            metadata: ECSContainerMetadata::placeholder(),
+            task: None,
+            degraded: true,
+            warnings: Vec::new(),
+            raw: RawDocuments::default(),
+            source,
+            skipped_parses: 0,
+            last_fetch: None,
+            fetched_at: None,
+            endpoint_source: None,
+            response_info: None,
+        }
+    }
+
+    /// Fetches the task document and returns the entry of the container with the given Docker ID,
+    /// e.g. to inspect a sibling container. The agent has no per-sibling endpoint, so this is
+    /// the same as `task().container_by_docker_id()` on a freshly fetched task document.
+    pub async fn fetch_container(docker_id: &str) -> Result<ECSContainerMetadata, ECSMetadataError> {
+        Self::builder().fetch_container(docker_id, None).await
+    }
 
-        let metadata: ECSContainerMetadataV4 = response.json().await?;
+    /// Where the documents were fetched from, `None` if they were handed over (e.g. `from_json`)
+    pub fn endpoint_source(&self) -> Option<EndpointSource> {
+        self.endpoint_source
+    }
 
-        Ok(Self { metadata })
+    /// Status and selected headers of the latest container document response, e.g. to spot a
+    /// clock skew against the agent host. `None` if the documents were handed over.
+    pub fn response_info(&self) -> Option<&ECSResponseInfo> {
+        self.response_info.as_ref()
+    }
+
+    /// Anomalies tolerated while parsing the fetched documents (including the task document's)
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// True if the metadata could not be fetched and placeholder values are returned instead
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
     }
 
     pub fn task_arn(&self) -> &str {
-        &self.metadata.labels.task_arn
+        self.metadata.labels.task_arn()
     }
 
-    /// The ECS task ID is last portion of the ARN
+    /// The ECS task ID is last portion of the ARN, `None` when that is empty
     pub fn task_id(&self) -> Option<String> {
-        self.metadata.labels.task_arn.split('/').last().map(ToString::to_string)
+        if self.degraded {
+            return None;
+        }
+        let task_id = self.metadata.labels.task_arn().rsplit('/').next()?;
+        (!task_id.is_empty()).then(|| task_id.to_string())
+    }
+
+    /// Task ID truncated to its first `len` characters (clamped to the ID length, at least one).
+    /// Only the 32-hex IDs and the UUID-style ones are truncated, anything else is returned
+    /// whole since a prefix of it would not be meaningful.
+    pub fn task_id_short(&self, len: usize) -> Option<String> {
+        let task_id = self.task_id()?;
+        if !is_hex_task_id(&task_id) {
+            return Some(task_id);
+        }
+
+        let len = len.clamp(1, task_id.len()); // all ASCII at this point, so byte offsets are safe
+        Some(task_id[..len].trim_end_matches('-').to_string())
+    }
+
+    /// AWS region, taken from the task ARN (arn:aws:ecs:<region>:<account>:task/...)
+    pub fn region(&self) -> Option<&str> {
+        self.metadata.labels.task_arn().split(':').nth(3).filter(|region| !region.is_empty())
+    }
+
+    /// Availability zone the task landed in, only known when the task document was fetched
+    pub fn availability_zone(&self) -> Option<&str> {
+        self.task.as_ref()?.availability_zone()
+    }
+
+    /// Fargate, EC2 or External, only known when the task document was fetched from an agent
+    /// recent enough to serve it
+    pub fn launch_type(&self) -> Option<LaunchType> {
+        self.task.as_ref()?.launch_type_kind()
+    }
+
+    /// Whether the task runs on Fargate, where there is no host to reach (no host network
+    /// mode, no instance metadata, no privileged containers). `false` when unknown
+    pub fn is_fargate(&self) -> bool {
+        self.launch_type() == Some(LaunchType::Fargate)
     }
 
-    /// ECS cluster name
-    pub fn cluster(&self) -> &str {
-        &self.metadata.labels.cluster
+    /// Snapshot as it serializes: the documents under `container` and `task` with the agent's
+    /// field names, the keys this crate does not model included (see `raw`), so they can be
+    /// forwarded without fetching them again. Health check output is truncated and log options
+    /// redacted as configured on the builder.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("a snapshot always serializes")
+    }
+
+    /// See `ECSContainerMetadata::raw`
+    pub fn raw(&self) -> &BTreeMap<String, serde_json::Value> {
+        self.metadata.raw()
+    }
+
+    /// This container's own metadata document
+    pub fn container(&self) -> &ECSContainerMetadata {
+        &self.metadata
+    }
+
+    /// Task document, only available when fetched through `init_with_task`
+    pub fn task(&self) -> Option<&ECSTaskMetadata> {
+        self.task.as_ref()
+    }
+
+    /// ECS cluster, see `ECSContainerMetadata::cluster`
+    pub fn cluster(&self) -> Option<&str> {
+        self.metadata.cluster()
+    }
+
+    /// Short cluster name, see `ECSContainerMetadata::cluster_name`
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.metadata.cluster_name()
     }
 
     /// CPU & Memory resource limits
     pub fn limits(&self) -> &ECSContainerLimits {
-        &self.metadata.limits
+        self.metadata.limits()
+    }
+
+    /// Limits of the whole task, only known when the task document was fetched, see
+    /// `effective_memory_limit_mib` for the one that applies
+    pub fn task_limits(&self) -> Option<&ECSTaskLimits> {
+        self.task.as_ref()?.limits()
+    }
+
+    /// See `ECSContainerMetadata::networks`
+    pub fn networks(&self) -> &[ECSNetwork] {
+        self.metadata.networks()
+    }
+
+    /// See `ECSContainerMetadata::primary_network`
+    pub fn primary_network(&self) -> Option<&ECSNetwork> {
+        self.metadata.primary_network()
+    }
+
+    /// See `ECSContainerMetadata::network_mode`
+    pub fn network_mode(&self) -> NetworkMode {
+        self.metadata.network_mode()
+    }
+
+    /// See `ECSContainerMetadata::networks_by_mode`
+    pub fn networks_by_mode<'a>(&'a self, mode: &'a str) -> impl Iterator<Item = &'a ECSNetwork> + 'a {
+        self.metadata.networks_by_mode(mode)
+    }
+
+    /// See `ECSContainerMetadata::ipv4_addresses`
+    pub fn ipv4_addresses(&self) -> impl Iterator<Item = &str> {
+        self.metadata.ipv4_addresses()
+    }
+
+    /// See `ECSContainerMetadata::private_ipv4`
+    pub fn private_ipv4(&self) -> Option<Ipv4Addr> {
+        self.metadata.private_ipv4()
+    }
+
+    /// See `ECSContainerMetadata::private_ipv6`
+    pub fn private_ipv6(&self) -> Option<Ipv6Addr> {
+        self.metadata.private_ipv6()
+    }
+
+    /// See `ECSContainerMetadata::private_dns_name`
+    pub fn private_dns_name(&self) -> Option<&str> {
+        self.metadata.private_dns_name()
+    }
+
+    /// See `ECSContainerMetadata::mac_address`
+    pub fn mac_address(&self) -> Option<&str> {
+        self.metadata.mac_address()
+    }
+
+    /// See `ECSContainerMetadata::ports`
+    pub fn ports(&self) -> &[ECSPortMapping] {
+        self.metadata.ports()
+    }
+
+    /// See `ECSContainerMetadata::host_ip`
+    pub fn host_ip(&self) -> Option<IpAddr> {
+        self.metadata.host_ip()
+    }
+
+    /// See `ECSContainerMetadata::advertised_address`
+    pub fn advertised_address(&self, container_port: u16) -> Option<SocketAddr> {
+        self.metadata.advertised_address(container_port)
+    }
+
+    /// Whether this is the task's only normal container (see `ECSTaskMetadata::normal_containers`),
+    /// e.g. to decide on shutdown whether anything else of the task keeps running.
+    /// `None` when the task document was not fetched.
+    pub fn is_sole_application_container(&self) -> Option<bool> {
+        Some(self.task.as_ref()?.is_sole_application_container(self.docker_id()))
+    }
+
+    /// Entry of this container in the task document, see `ECSTaskMetadata::self_container`.
+    /// `None` when the task document was not fetched or has no such entry, with a
+    /// `SelfContainerNotFound` warning for the latter.
+    pub fn self_container(&self) -> Option<&ECSContainerMetadata> {
+        self.task.as_ref()?.self_container(self.docker_id(), self.container_name())
+    }
+
+    /// The other containers of the task, none when the task document was not fetched
+    pub fn sibling_containers(&self) -> impl Iterator<Item = &ECSContainerMetadata> {
+        self.task.iter().flat_map(|task| task.sibling_containers(self.docker_id(), self.container_name()))
+    }
+
+    /// See `ECSContainerMetadata::health`
+    pub fn health(&self) -> Option<&ECSContainerHealth> {
+        self.metadata.health()
+    }
+
+    /// See `ECSContainerMetadata::health_output`
+    pub fn health_output(&self) -> Option<&str> {
+        self.metadata.health_output()
+    }
+
+    /// See `ECSContainerMetadata::health_status_since`
+    pub fn health_status_since(&self) -> Option<&str> {
+        self.metadata.health_status_since()
+    }
+
+    /// Memory limit in MiB that actually applies to this container.
+    /// The container limit is used when set, falling back to the task limit (only known when the task
+    /// document was fetched). When both are set the smaller one wins, since the container can never
+    /// use more than its task. Zero means "not set" at either level; `None` if neither is set.
+    pub fn effective_memory_limit_mib(&self) -> Option<u64> {
+        self.effective_memory_limit_mib_with(self.task.as_ref())
+    }
+
+    /// `effective_memory_limit_mib` with its unit
+    pub fn memory_limit(&self) -> Option<MemorySize> {
+        self.effective_memory_limit_mib().map(MemorySize::from_mib)
+    }
+
+    /// vCPU limit that actually applies to this container, same precedence as
+    /// `effective_memory_limit_mib`
+    pub fn effective_cpu_limit_vcpus(&self) -> Option<f64> {
+        self.effective_cpu_limit_vcpus_with(self.task.as_ref())
+    }
+
+    pub(crate) fn effective_memory_limit_mib_with(&self, task: Option<&ECSTaskMetadata>) -> Option<u64> {
+        let task = task.and_then(ECSTaskMetadata::limits).and_then(ECSTaskLimits::memory_mib);
+        effective_limit(self.limits().memory_mib(), task.filter(|mem| *mem > 0))
+    }
+
+    pub(crate) fn effective_cpu_limit_vcpus_with(&self, task: Option<&ECSTaskMetadata>) -> Option<f64> {
+        let task = task.and_then(ECSTaskMetadata::limits).and_then(ECSTaskLimits::vcpus);
+        effective_limit(self.limits().vcpus(), task.filter(|cpu| *cpu > 0.0))
     }
 
     pub fn docker_id(&self) -> &str {
         &self.metadata.docker_id
     }
 
+    /// See `ECSContainerMetadata::log_driver`
+    pub fn log_driver(&self) -> Option<&str> {
+        self.metadata.log_driver()
+    }
+
+    /// See `ECSContainerMetadata::log_options`
+    pub fn log_options(&self) -> Option<&BTreeMap<String, String>> {
+        self.metadata.log_options()
+    }
+
+    /// See `ECSContainerMetadata::log_options_redacted`
+    pub fn log_options_redacted(&self) -> Option<BTreeMap<String, String>> {
+        self.metadata.log_options_redacted()
+    }
+
+    /// See `ECSContainerMetadata::name`
+    pub fn name(&self) -> Option<&str> {
+        self.metadata.name()
+    }
+
+    /// See `ECSContainerMetadata::container_arn`
+    pub fn container_arn(&self) -> Option<&str> {
+        self.metadata.container_arn()
+    }
+
     pub fn image(&self) -> &str {
-        &self.metadata.image
+        self.metadata.image()
+    }
+
+    /// See `ECSContainerMetadata::image_id`
+    pub fn image_id(&self) -> Option<&str> {
+        self.metadata.image_id()
+    }
+
+    /// See `ECSContainerMetadata::container_type`
+    pub fn container_type(&self) -> Option<&str> {
+        self.metadata.container_type()
+    }
+
+    /// See `ECSContainerMetadata::known_status`
+    pub fn known_status(&self) -> Option<&str> {
+        self.metadata.known_status()
+    }
+
+    /// See `ECSContainerMetadata::desired_status`
+    pub fn desired_status(&self) -> Option<&str> {
+        self.metadata.desired_status()
+    }
+
+    /// See `ECSContainerMetadata::known_status_kind`
+    pub fn known_status_kind(&self) -> Option<ContainerStatus> {
+        self.metadata.known_status_kind()
+    }
+
+    /// See `ECSContainerMetadata::desired_status_kind`
+    pub fn desired_status_kind(&self) -> Option<ContainerStatus> {
+        self.metadata.desired_status_kind()
+    }
+
+    /// See `ECSContainerMetadata::is_stopping`
+    pub fn is_stopping(&self) -> bool {
+        self.metadata.is_stopping()
+    }
+
+    /// See `ECSContainerMetadata::is_stopped`
+    pub fn is_stopped(&self) -> bool {
+        self.metadata.is_stopped()
+    }
+
+    /// See `ECSContainerMetadata::is_healthy`
+    pub fn is_healthy(&self) -> bool {
+        self.metadata.is_healthy()
+    }
+
+    /// See `ECSContainerMetadata::created_at`
+    pub fn created_at(&self) -> Option<&str> {
+        self.metadata.created_at()
+    }
+
+    /// See `ECSContainerMetadata::started_at`
+    pub fn started_at(&self) -> Option<&str> {
+        self.metadata.started_at()
+    }
+
+    /// See `ECSContainerMetadata::image_tag`
+    pub fn image_tag(&self) -> Option<&str> {
+        self.metadata.image_tag()
+    }
+
+    /// See `ECSContainerMetadata::image_tag_components`
+    pub fn image_tag_components(&self, separator: &str) -> Vec<&str> {
+        self.metadata.image_tag_components(separator)
+    }
+
+    /// See `ECSContainerMetadata::image_tag_captures`
+    pub fn image_tag_captures<'a>(&'a self, convention: &'a TagConvention) -> Option<BTreeMap<&'a str, &'a str>> {
+        self.metadata.image_tag_captures(convention)
+    }
+
+    /// See `ECSContainerMetadata::image_registry_account`
+    pub fn image_registry_account(&self) -> Option<&str> {
+        self.metadata.image_registry_account()
+    }
+
+    /// See `ECSContainerMetadata::image_registry_region`
+    pub fn image_registry_region(&self) -> Option<&str> {
+        self.metadata.image_registry_region()
     }
 
     pub fn task_definition_family(&self) -> &str {
-        &self.metadata.labels.task_definition_family
+        self.metadata.task_definition_family()
     }
 
     pub fn task_definition_revision(&self) -> &str {
-        &self.metadata.labels.task_definition_version
+        self.metadata.task_definition_revision()
     }
 
     pub fn container_name(&self) -> &str {
-        &self.metadata.labels.container_name
+        self.metadata.container_name()
     }
 }
 
+fn effective_limit<T: PartialOrd>(container: Option<T>, task: Option<T>) -> Option<T> {
+    match (container, task) {
+        (Some(container), Some(task)) => Some(if task < container { task } else { container }),
+        (container, task) => container.or(task),
+    }
+}
+
+// 32 hex digits, or a UUID as 8-4-4-4-12 hex digits
+fn is_hex_task_id(task_id: &str) -> bool {
+    let parts = task_id.split('-').collect::<Vec<_>>();
+    let lens: &[usize] = if parts.len() == 1 { &[32] } else { &[8, 4, 4, 4, 12] };
+    parts.len() == lens.len() && parts.iter().zip(lens).all(|(part, len)| part.len() == *len && part.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
-    use serde_json;
+    use crate::client::ECS_METADATA_V4_ENV_VAR;
+    use crate::test_support::FakeEnv;
+
+    pub(crate) const CONTAINER_JSON: &str = r#"
+        {
+            "DockerId": "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0",
+            "Image": "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production",
+            "Labels": {
+                "com.amazonaws.ecs.cluster": "production",
+                "com.amazonaws.ecs.container-name": "streamer",
+                "com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0",
+                "com.amazonaws.ecs.task-definition-family": "streamer",
+                "com.amazonaws.ecs.task-definition-version": "12"
+            },
+            "Limits": {"CPU": 2, "Memory": 4096}
+        }"#;
+
+    /// Builds an instance from fixtures, bypassing the endpoint
+    pub(crate) fn metadata_from_json(container: &str, task: Option<&str>) -> ECSMetadata {
+        ECSMetadata::from_parts(
+            serde_json::from_str(container).expect("invalid container fixture"),
+            task.map(|task| serde_json::from_str(task).expect("invalid task fixture")),
+        )
+    }
 
     #[tokio::test]
     async fn test_parse_ecs_metadata() {
@@ -118,15 +675,401 @@ mod tests {
             "Limits": {"CPU": 2, "Memory": 0}
         }"#;
 
-        let metadata: ECSContainerMetadataV4 = serde_json::from_str(json_data)
-            .expect("Failed to deserialize ECSContainerMetadataV4 JSON");
+        let metadata: ECSContainerMetadata = serde_json::from_str(json_data)
+            .expect("Failed to deserialize ECSContainerMetadata JSON");
 
         assert_eq!(metadata.docker_id, "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0");
-        assert_eq!(metadata.image, "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production");
-        assert_eq!(metadata.labels.cluster, "production");
-        assert_eq!(metadata.labels.container_name, "streamer");
-        assert_eq!(metadata.labels.task_arn, "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0");
-        assert_eq!(metadata.limits.cpu, 2);
-        assert_eq!(metadata.limits.mem, 0);
-    }
-}
\ No newline at end of file
+        assert_eq!(metadata.image(), "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production");
+        assert_eq!(metadata.labels.cluster.as_deref(), Some("production"));
+        assert_eq!(metadata.container_name(), "streamer");
+        assert_eq!(metadata.labels.task_arn(), "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0");
+        assert_eq!(metadata.limits().cpu, Some(2.0));
+        assert_eq!(metadata.limits().mem, Some(0));
+        assert!(metadata.missing_fields().is_empty());
+    }
+
+    // what a mock agent may serve at the least
+    const MINIMAL_JSON: &str = r#"{
+        "DockerId": "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0",
+        "Labels": {"com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0"}
+    }"#;
+
+    #[test]
+    fn test_v4_field_set() {
+        let metadata = ECSMetadata::from_json(include_str!("../testdata/versions/v4_container.json")).unwrap();
+        assert_eq!(metadata.name(), Some("curl"));
+        assert_eq!(metadata.container_arn(), Some("arn:aws:ecs:us-west-2:111122223333:container/05966557-f16c-49cb-9352-24b3a0dcd0e1"));
+        assert_eq!(metadata.image_id(), Some("sha256:25f3695bedfb454a50f12d127839a68ad3caf91e451c1da073db34c542c4d2cb"));
+        assert_eq!((metadata.desired_status(), metadata.known_status()), (Some("RUNNING"), Some("RUNNING")));
+        assert_eq!(metadata.container_type(), Some("NORMAL"));
+        assert_eq!((metadata.created_at(), metadata.started_at()), (Some("2020-10-08T20:09:11.44527186Z"), Some("2020-10-08T20:09:11.44527186Z")));
+        assert_eq!(metadata.ipv4_addresses().collect::<Vec<_>>(), ["10.0.2.106"]);
+        assert_eq!(metadata.log_driver(), Some("awslogs"));
+        assert_eq!(metadata.log_options().unwrap()["awslogs-group"], "/ecs/containerlogs");
+
+        let minimal = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        assert_eq!((minimal.name(), minimal.image_id(), minimal.started_at()), (None, None, None));
+    }
+
+    #[test]
+    fn test_minimal_document() {
+        let metadata = ECSMetadata::from_json(MINIMAL_JSON).unwrap();
+        assert_eq!(metadata.docker_id(), "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0");
+        assert_eq!(metadata.task_id().as_deref(), Some("021447970bce4bd58069f1925cd87bc0"));
+        assert_eq!(metadata.cluster(), Some("production"));
+        assert_eq!(metadata.region(), Some("us-east-1"));
+        assert_eq!(metadata.image(), "");
+        assert_eq!(metadata.container_name(), "");
+        assert_eq!(metadata.task_definition_family(), "");
+        assert_eq!(metadata.task_definition_revision(), "");
+        assert_eq!(metadata.limits(), &ECSContainerLimits::new(None, None));
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+        assert!(metadata.networks().is_empty());
+        assert!(!metadata.is_degraded());
+
+        let missing: Vec<_> = metadata.warnings().iter().map(|warning| (warning.kind, warning.message.as_str())).collect();
+        assert_eq!(missing.len(), 5);
+        assert!(missing.iter().all(|(kind, _)| *kind == ParseWarningKind::MissingFieldDefaulted));
+        assert_eq!(missing[0].1, "container document has no Image, left empty");
+
+        // absent fields stay absent in the snapshot
+        let snapshot = serde_json::to_value(&metadata).unwrap();
+        assert!(snapshot["container"].get("Image").is_none() && snapshot["container"].get("Limits").is_none());
+        assert_eq!(snapshot["container"]["Labels"].as_object().unwrap().len(), 1);
+
+        // DockerId and the task ARN are required
+        assert!(ECSMetadata::from_json(r#"{"DockerId": "abc", "Labels": {}}"#).is_err());
+        assert!(ECSMetadata::from_json(r#"{"DockerId": "abc"}"#).is_err());
+        assert!(ECSMetadata::from_json(r#"{"Labels": {"com.amazonaws.ecs.task-arn": "arn"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let strict = ECSMetadata::builder().strict(true);
+        let err = ECSMetadata::from_documents(MINIMAL_JSON.as_bytes().to_vec(), None, Some(strict.clone())).unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::MissingField(field) if field == "Image"), "{err:?}");
+
+        let metadata = ECSMetadata::from_documents(CONTAINER_JSON.as_bytes().to_vec(), None, Some(strict)).unwrap();
+        assert!(metadata.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_region_and_availability_zone() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.region(), Some("us-east-1"));
+        assert_eq!(metadata.availability_zone(), None);
+
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(r#"{"AvailabilityZone": "us-east-1b"}"#));
+        assert_eq!(metadata.availability_zone(), Some("us-east-1b"));
+        // task document warnings are surfaced on the metadata too
+        assert_eq!(metadata.warnings(), metadata.task().unwrap().warnings());
+        assert_eq!(metadata.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_launch_type() {
+        assert_eq!(metadata_from_json(CONTAINER_JSON, None).launch_type(), None);
+        assert_eq!(metadata_from_json(CONTAINER_JSON, Some("{}")).launch_type(), None);
+
+        let fargate = metadata_from_json(CONTAINER_JSON, Some(r#"{"LaunchType": "FARGATE", "AvailabilityZone": "us-east-1b"}"#));
+        assert_eq!((fargate.launch_type(), fargate.availability_zone()), (Some(LaunchType::Fargate), Some("us-east-1b")));
+        assert!(fargate.is_fargate());
+
+        let launch_type = |served: &str| metadata_from_json(CONTAINER_JSON, Some(&format!(r#"{{"LaunchType": "{served}"}}"#))).launch_type();
+        assert_eq!(launch_type("EC2"), Some(LaunchType::Ec2));
+        assert_eq!(launch_type("external"), Some(LaunchType::External));
+        assert_eq!(launch_type("OUTPOST").unwrap().to_string(), "OUTPOST");
+        assert!(!metadata_from_json(CONTAINER_JSON, Some(r#"{"LaunchType": "EC2"}"#)).is_fargate());
+    }
+
+    const NO_CLUSTER_LABEL: &str = r#""com.amazonaws.ecs.cluster": "production","#;
+
+    #[test]
+    fn test_cluster_from_label() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.cluster(), Some("production"));
+        assert_eq!(metadata.cluster_name(), Some("production"));
+
+        let arn_label = CONTAINER_JSON.replace(
+            r#""com.amazonaws.ecs.cluster": "production""#,
+            r#""com.amazonaws.ecs.cluster": "arn:aws:ecs:us-east-1:939885537497:cluster/production""#,
+        );
+        let metadata = metadata_from_json(&arn_label, None);
+        assert_eq!(metadata.cluster(), Some("arn:aws:ecs:us-east-1:939885537497:cluster/production"));
+        assert_eq!(metadata.cluster_name(), Some("production"));
+    }
+
+    #[test]
+    fn test_cluster_from_new_format_task_arn() {
+        let metadata = metadata_from_json(&CONTAINER_JSON.replace(NO_CLUSTER_LABEL, ""), None);
+        assert_eq!(metadata.cluster(), Some("production"));
+        assert_eq!(metadata.cluster_name(), Some("production"));
+    }
+
+    #[test]
+    fn test_cluster_unknown_with_old_format_task_arn() {
+        let old_format = CONTAINER_JSON
+            .replace(NO_CLUSTER_LABEL, "")
+            .replace("task/production/021447970bce4bd58069f1925cd87bc0", "task/5fc1b5e7-bc9b-4b3e-92a0-5b1d0b1e6d2e");
+        let metadata = metadata_from_json(&old_format, None);
+        assert_eq!(metadata.cluster(), None);
+        assert_eq!(metadata.cluster_name(), None);
+        // the task ID is still there
+        assert_eq!(metadata.task_id().as_deref(), Some("5fc1b5e7-bc9b-4b3e-92a0-5b1d0b1e6d2e"));
+    }
+
+    #[test]
+    fn test_serialized_snapshot() {
+        let task = crate::task::tests::task_json(&[CONTAINER_JSON.to_string()]);
+        let snapshot = serde_json::to_value(metadata_from_json(CONTAINER_JSON, Some(&task))).unwrap();
+        assert_eq!(snapshot["container"]["Labels"]["com.amazonaws.ecs.cluster"], "production");
+        assert_eq!(snapshot["container"]["Limits"], serde_json::json!({"CPU": 2, "Memory": 4096}));
+        assert_eq!(snapshot["task"]["AvailabilityZone"], "us-east-1b");
+        assert_eq!(snapshot["degraded"], false);
+        assert_eq!(snapshot.as_object().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_degraded_accessors() {
+        let metadata = ECSMetadata::degraded(None);
+        assert!(metadata.is_degraded());
+        assert_eq!(metadata.cluster(), Some(ECSMetadata::UNKNOWN));
+        assert_eq!(metadata.container_name(), ECSMetadata::UNKNOWN);
+        assert_eq!(metadata.task_arn(), ECSMetadata::UNKNOWN);
+        assert_eq!(metadata.task_id(), None);
+        assert_eq!(metadata.region(), None);
+        assert_eq!(metadata.availability_zone(), None);
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), None);
+        assert!(!metadata_from_json(CONTAINER_JSON, None).is_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_init_or_default_degrades() {
+        // env var missing altogether
+        let env = FakeEnv::new(&[]);
+        let metadata = ECSMetadata::init_or_default(Duration::from_millis(200)).await;
+        assert!(metadata.is_degraded());
+        drop(env);
+
+        // endpoint accepting connections but never answering must not block past the deadline
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _env = FakeEnv::new(&[(ECS_METADATA_V4_ENV_VAR, &format!("http://{}", listener.local_addr().unwrap()))]);
+        let started = std::time::Instant::now();
+        let metadata = ECSMetadata::init_or_default(Duration::from_millis(200)).await;
+        assert!(metadata.is_degraded());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_task_id_short() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.task_id_short(ECSMetadata::SHORT_TASK_ID_LEN).as_deref(), Some("02144797"));
+        assert_eq!(metadata.task_id_short(0).as_deref(), Some("0"));
+        assert_eq!(metadata.task_id_short(100).as_deref(), Some("021447970bce4bd58069f1925cd87bc0"));
+
+        let uuid_style = CONTAINER_JSON.replace(
+            "task/production/021447970bce4bd58069f1925cd87bc0",
+            "task/5fc1b5e7-bc9b-4b3e-92a0-5b1d0b1e6d2e",
+        );
+        let metadata = metadata_from_json(&uuid_style, None);
+        assert_eq!(metadata.task_id_short(8).as_deref(), Some("5fc1b5e7"));
+        // never ends on a dash
+        assert_eq!(metadata.task_id_short(9).as_deref(), Some("5fc1b5e7"));
+        assert_eq!(metadata.task_id_short(13).as_deref(), Some("5fc1b5e7-bc9b"));
+
+        let not_hex = CONTAINER_JSON.replace("021447970bce4bd58069f1925cd87bc0", "my-task");
+        let metadata = metadata_from_json(&not_hex, None);
+        assert_eq!(metadata.task_id_short(4).as_deref(), Some("my-task"));
+        // hex, but neither of the two formats
+        for task_id in ["abc", "--------"] {
+            let metadata = metadata_from_json(&CONTAINER_JSON.replace("021447970bce4bd58069f1925cd87bc0", task_id), None);
+            assert_eq!(metadata.task_id_short(2).as_deref(), Some(task_id));
+        }
+
+        assert_eq!(ECSMetadata::degraded(None).task_id_short(8), None);
+    }
+
+    fn with_container_limits(cpu: u16, mem: u16) -> String {
+        CONTAINER_JSON.replace(r#""Limits": {"CPU": 2, "Memory": 4096}"#, &format!(r#""Limits": {{"CPU": {cpu}, "Memory": {mem}}}"#))
+    }
+
+    #[test]
+    fn test_unknown_limit_kinds() {
+        let gpu = CONTAINER_JSON.replace(r#""Limits": {"CPU": 2, "Memory": 4096}"#, r#""Limits": {"CPU": 2, "Memory": 4096, "GPU": 1, "EphemeralStorage": 21.5}"#);
+        let metadata = metadata_from_json(&gpu, None);
+        let limits = metadata.limits();
+        assert_eq!((limits.cpu, limits.mem), (Some(2.0), Some(4096)));
+        assert_eq!(limits.limit("GPU"), Some(1.0));
+        assert_eq!(limits.limit("EphemeralStorage"), Some(21.5));
+        assert_eq!(limits.limit("Memory"), Some(4096.0));
+        assert_eq!(limits.limit("Inferentia"), None);
+
+        let serialized = serde_json::to_value(limits).unwrap();
+        assert_eq!(serialized, serde_json::json!({"CPU": 2, "Memory": 4096, "GPU": 1, "EphemeralStorage": 21.5}));
+        assert_eq!(&serde_json::from_value::<ECSContainerLimits>(serialized).unwrap(), limits);
+    }
+
+    #[test]
+    fn test_fractional_and_absent_limits() {
+        let fractional = CONTAINER_JSON.replace(r#""CPU": 2, "Memory": 4096"#, r#""CPU": 0.25, "Memory": 70000"#);
+        let limits = metadata_from_json(&fractional, None).limits().clone();
+        assert_eq!((limits.vcpus(), limits.cpu_millicores(), limits.cpu_as_k8s_quantity().as_deref()), (Some(0.25), Some(250), Some("250m")));
+        assert_eq!((limits.memory_mib(), limits.memory_bytes()), (Some(70000), Some(70000 * 1024 * 1024)));
+        assert_eq!(serde_json::to_value(&limits).unwrap(), serde_json::json!({"CPU": 0.25, "Memory": 70000}));
+
+        let absent = CONTAINER_JSON.replace(r#""CPU": 2, "Memory": 4096"#, r#""Memory": 512"#);
+        let task = crate::task::tests::task_json(std::slice::from_ref(&absent));
+        let metadata = metadata_from_json(&absent, Some(&task));
+        assert_eq!((metadata.limits().cpu, metadata.limits().cpu_millicores()), (None, None));
+        assert_eq!(metadata.task_limits().and_then(ECSTaskLimits::cpu_millicores), Some(4000));
+        assert_eq!(metadata.task_limits().and_then(ECSTaskLimits::memory_bytes), Some(8192 * 1024 * 1024));
+        assert_eq!((metadata.effective_cpu_limit_vcpus(), metadata.effective_memory_limit_mib()), (Some(4.0), Some(512)));
+        assert!(metadata.warnings().is_empty(), "{:?}", metadata.warnings());
+    }
+
+    fn with_health(output: &str) -> String {
+        CONTAINER_JSON.replace(
+            r#""Limits": {"CPU": 2, "Memory": 4096}"#,
+            &format!(r#""Limits": {{"CPU": 2, "Memory": 4096}}, "Health": {{"status": "UNHEALTHY", "statusSince": "2024-10-01T12:00:00.000Z", "exitCode": 1, "output": "{output}"}}"#),
+        )
+    }
+
+    #[test]
+    fn test_health_output_in_documents() {
+        let container = with_health(r"line 1\nbad \udfff\u001b[0m");
+        let task = crate::task::tests::task_json(std::slice::from_ref(&container));
+        let metadata = ECSMetadata::from_documents(container.into_bytes(), Some(task.into_bytes()), None)
+            .expect("odd health output must not fail the documents");
+
+        assert_eq!(metadata.health_output(), Some("line 1\nbad \u{fffd}\u{fffd}\u{fffd}\u{1b}[0m"));
+        assert_eq!(metadata.health_status_since(), Some("2024-10-01T12:00:00.000Z"));
+        assert_eq!(metadata.health().map(ECSContainerHealth::status), Some("UNHEALTHY"));
+        let task = metadata.task().unwrap();
+        assert_eq!(task.containers().len(), 1);
+        assert_eq!(task.containers()[0].health_output(), metadata.health_output());
+
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.health(), None);
+        assert_eq!(metadata.health_output(), None);
+    }
+
+    #[test]
+    fn test_health_output_truncation() {
+        let container = with_health(&"x".repeat(5000));
+        let task = crate::task::tests::task_json(std::slice::from_ref(&container));
+        let metadata = ECSMetadata::from_documents(container.clone().into_bytes(), Some(task.clone().into_bytes()), None).unwrap();
+        assert_eq!(metadata.health_output(), Some(format!("{}…[truncated]", "x".repeat(1024)).as_str()));
+        assert_eq!(metadata.task().unwrap().containers()[0].health_output(), metadata.health_output());
+
+        let builder = ECSMetadata::builder().max_health_output_len(16);
+        let metadata = ECSMetadata::from_documents(container.into_bytes(), Some(task.into_bytes()), Some(builder)).unwrap();
+        assert_eq!(metadata.health_output(), Some("xxxxxxxxxxxxxxxx…[truncated]"));
+        assert_eq!(metadata.task().unwrap().containers()[0].health_output(), metadata.health_output());
+    }
+
+    #[test]
+    fn test_image_registry() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.image_registry_account(), Some("939885537497"));
+        assert_eq!(metadata.image_registry_region(), Some("us-east-1"));
+
+        let public = CONTAINER_JSON.replace(
+            "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production",
+            "public.ecr.aws/sardusmatt/streamer:latest",
+        );
+        let metadata = metadata_from_json(&public, None);
+        assert_eq!((metadata.image_registry_account(), metadata.image_registry_region()), (None, None));
+    }
+
+    #[test]
+    fn test_image_tag() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.image_tag(), Some("latest-production"));
+        assert_eq!(metadata.image_tag_components("-"), ["latest", "production"]);
+        assert_eq!(metadata.image_tag_components("_"), ["latest-production"]);
+
+        let convention = TagConvention::new("{version}-{environment}").unwrap();
+        let captures = metadata.image_tag_captures(&convention).unwrap();
+        assert_eq!((captures["version"], captures["environment"]), ("latest", "production"));
+
+        let untagged = CONTAINER_JSON.replace("streamer:latest-production", "streamer@sha256:0d5c");
+        let metadata = metadata_from_json(&untagged, None);
+        assert_eq!(metadata.image_tag(), None);
+        assert!(metadata.image_tag_components("-").is_empty());
+        assert_eq!(metadata.image_tag_captures(&convention), None);
+    }
+
+    #[test]
+    fn test_container_limits_as_k8s_quantities() {
+        let limits = metadata_from_json(CONTAINER_JSON, None).limits().clone();
+        assert_eq!(limits.cpu_as_k8s_quantity().as_deref(), Some("2"));
+        assert_eq!(limits.memory_as_k8s_quantity().as_deref(), Some("4096Mi"));
+
+        let unlimited = ECSContainerLimits::new(Some(0.0), Some(0));
+        assert_eq!((unlimited.cpu_as_k8s_quantity(), unlimited.memory_as_k8s_quantity()), (None, None));
+        assert_eq!(limits.memory_limit().map(|limit| limit.as_bytes()), Some(4096 * 1024 * 1024));
+        assert_eq!(unlimited.memory_limit(), None);
+    }
+
+    #[test]
+    fn test_effective_limits_container_only() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.effective_memory_limit_mib(), Some(4096));
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), Some(2.0));
+    }
+
+    #[test]
+    fn test_effective_limits_fall_back_to_task() {
+        // Fargate style: nothing at container level, real limits at task level
+        let metadata = metadata_from_json(&with_container_limits(0, 0), Some(r#"{"Limits": {"CPU": 0.25, "Memory": 512}}"#));
+        assert_eq!(metadata.effective_memory_limit_mib(), Some(512));
+        assert_eq!(metadata.memory_limit(), Some(MemorySize::from_mib(512)));
+        assert_eq!(metadata.task().unwrap().limits().unwrap().memory_limit().unwrap().to_string(), "512 MiB");
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), Some(0.25));
+    }
+
+    #[test]
+    fn test_effective_limits_prefer_smaller_task_limit() {
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(r#"{"Limits": {"CPU": 1, "Memory": 2048}}"#));
+        assert_eq!(metadata.effective_memory_limit_mib(), Some(2048));
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), Some(1.0));
+
+        // task limits larger than the container ones leave the container limits in place
+        let metadata = metadata_from_json(&with_container_limits(1, 512), Some(r#"{"Limits": {"CPU": 4, "Memory": 8192}}"#));
+        assert_eq!(metadata.effective_memory_limit_mib(), Some(512));
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), Some(1.0));
+    }
+
+    #[test]
+    fn test_effective_limits_none_when_unset() {
+        let metadata = metadata_from_json(&with_container_limits(0, 0), None);
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), None);
+
+        let metadata = metadata_from_json(&with_container_limits(0, 0), Some(r#"{"Limits": {"CPU": 0, "Memory": 0}}"#));
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+        assert_eq!(metadata.effective_cpu_limit_vcpus(), None);
+
+        let metadata = metadata_from_json(&with_container_limits(0, 0), Some(r#"{"AvailabilityZone": "us-east-1b"}"#));
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+    }
+
+    #[test]
+    fn test_to_json_forwards_unknown_keys() {
+        let container = include_str!("../testdata/versions/v4_container.json");
+        let task = include_str!("../testdata/versions/v4_task.json");
+        let metadata = ECSMetadata::from_documents(container.into(), Some(task.into()), None).unwrap();
+        assert_eq!(metadata.raw().get("DockerName"), Some(&serde_json::json!("curl")));
+        assert_eq!(metadata.task().unwrap().raw()["PullStartedAt"], "2020-10-08T20:09:08.316310817Z");
+
+        let mut json = metadata.to_json();
+        // the only key added, lists serialize even when not served
+        assert_eq!(json["container"].as_object_mut().unwrap().remove("Ports"), Some(serde_json::json!([])));
+        assert_eq!(json["container"], serde_json::from_str::<serde_json::Value>(container).unwrap());
+        assert_eq!((&json["task"]["PullStartedAt"], &json["task"]["Containers"][0]["DockerName"]), (&"2020-10-08T20:09:08.316310817Z".into(), &"curl".into()));
+        assert_eq!(metadata.private_dns_name(), Some("ip-10-0-2-106.us-west-2.compute.internal"));
+        assert!(metadata_from_json(CONTAINER_JSON, None).raw().is_empty());
+    }
+}
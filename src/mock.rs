@@ -0,0 +1,201 @@
+use serde_json::{json, Value};
+use crate::metadata::ECSMetadata;
+
+/// Canned `ECSMetadata` for the tests of code that takes one, built without an endpoint from
+/// the documents the agent would serve. Every field has a placeholder until set:
+///
+/// ```
+/// # use ecs_metadata::MockECSMetadata;
+/// let metadata = MockECSMetadata::new().cluster("production").container_name("web").with_task().build();
+/// assert_eq!(metadata.cluster_name(), Some("production"));
+/// assert_eq!(metadata.self_container().unwrap().container_name(), "web");
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockECSMetadata {
+    cluster: String,
+    region: String,
+    account_id: String,
+    task_id: String,
+    docker_id: String,
+    container_name: String,
+    image: String,
+    family: String,
+    revision: String,
+    limits: (f64, u64),
+    with_task: bool,
+    availability_zone: Option<String>,
+}
+
+impl Default for MockECSMetadata {
+    fn default() -> Self {
+        Self {
+            cluster: "test-cluster".to_string(),
+            region: "us-east-1".to_string(),
+            account_id: "123456789012".to_string(),
+            task_id: "0123456789abcdef0123456789abcdef".to_string(),
+            docker_id: "0123456789abcdef0123456789abcdef-1234567890".to_string(),
+            container_name: "app".to_string(),
+            image: "123456789012.dkr.ecr.us-east-1.amazonaws.com/app:latest".to_string(),
+            family: "app".to_string(),
+            revision: "1".to_string(),
+            limits: (0.0, 0),
+            with_task: false,
+            availability_zone: None,
+        }
+    }
+}
+
+impl MockECSMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Short cluster name, also part of the task ARN
+    pub fn cluster(mut self, cluster: impl Into<String>) -> Self {
+        self.cluster = cluster.into();
+        self
+    }
+
+    /// Region of the task ARN
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    /// Account of the task ARN
+    pub fn account_id(mut self, account_id: impl Into<String>) -> Self {
+        self.account_id = account_id.into();
+        self
+    }
+
+    pub fn task_id(mut self, task_id: impl Into<String>) -> Self {
+        self.task_id = task_id.into();
+        self
+    }
+
+    pub fn docker_id(mut self, docker_id: impl Into<String>) -> Self {
+        self.docker_id = docker_id.into();
+        self
+    }
+
+    pub fn container_name(mut self, name: impl Into<String>) -> Self {
+        self.container_name = name.into();
+        self
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = image.into();
+        self
+    }
+
+    pub fn task_definition(mut self, family: impl Into<String>, revision: impl Into<String>) -> Self {
+        self.family = family.into();
+        self.revision = revision.into();
+        self
+    }
+
+    /// Container limits, in vCPUs and MiB, none (0) by default
+    pub fn limits(mut self, vcpus: f64, memory_mib: u64) -> Self {
+        self.limits = (vcpus, memory_mib);
+        self
+    }
+
+    /// Attaches a task document with this container as its only one, as `init_with_task` would
+    pub fn with_task(mut self) -> Self {
+        self.with_task = true;
+        self
+    }
+
+    /// Availability zone of the task document, attached by this call
+    pub fn availability_zone(mut self, availability_zone: impl Into<String>) -> Self {
+        self.availability_zone = Some(availability_zone.into());
+        self.with_task()
+    }
+
+    /// Container document as the v4 endpoint would serve it
+    pub fn container_json(&self) -> Value {
+        json!({
+            "DockerId": self.docker_id,
+            "Name": self.container_name,
+            "DockerName": format!("ecs-{}-{}-{}", self.family, self.revision, self.container_name),
+            "Image": self.image,
+            "Labels": {
+                "com.amazonaws.ecs.cluster": self.cluster,
+                "com.amazonaws.ecs.container-name": self.container_name,
+                "com.amazonaws.ecs.task-arn": self.task_arn(),
+                "com.amazonaws.ecs.task-definition-family": self.family,
+                "com.amazonaws.ecs.task-definition-version": self.revision,
+            },
+            "DesiredStatus": "RUNNING",
+            "KnownStatus": "RUNNING",
+            "Limits": {"CPU": self.limits.0, "Memory": self.limits.1},
+            "Type": "NORMAL",
+        })
+    }
+
+    /// Task document as the v4 endpoint would serve it, `None` unless attached
+    pub fn task_json(&self) -> Option<Value> {
+        if !self.with_task {
+            return None;
+        }
+        let mut task = json!({
+            "Cluster": self.cluster,
+            "TaskARN": self.task_arn(),
+            "Family": self.family,
+            "Revision": self.revision,
+            "DesiredStatus": "RUNNING",
+            "KnownStatus": "RUNNING",
+            "Containers": [self.container_json()],
+        });
+        if let Some(availability_zone) = &self.availability_zone {
+            task["AvailabilityZone"] = availability_zone.as_str().into();
+        }
+        Some(task)
+    }
+
+    /// The snapshot, which can't be refreshed as it wasn't fetched
+    pub fn build(&self) -> ECSMetadata {
+        let container = self.container_json().to_string().into_bytes();
+        let task = self.task_json().map(|task| task.to_string().into_bytes());
+        ECSMetadata::from_versioned_documents(container, task, false, None).expect("mock documents should parse")
+    }
+
+    fn task_arn(&self) -> String {
+        format!("arn:aws:ecs:{}:{}:task/{}/{}", self.region, self.account_id, self.cluster, self.task_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ECSContext;
+
+    // what a consumer's code under test may look like
+    fn log_prefix(context: &dyn ECSContext) -> String {
+        format!("[{}/{}] ", context.cluster_name().unwrap_or("-"), context.task_id().unwrap_or_default())
+    }
+
+    #[test]
+    fn test_mock() {
+        let metadata = MockECSMetadata::new().build();
+        assert_eq!(log_prefix(&metadata), "[test-cluster/0123456789abcdef0123456789abcdef] ");
+        assert_eq!((metadata.region(), metadata.account_id(), metadata.container_name()), (Some("us-east-1"), Some("123456789012"), "app"));
+        assert_eq!(metadata.effective_memory_limit_mib(), None);
+        assert!(metadata.task().is_none() && metadata.warnings().is_empty(), "{:?}", metadata.warnings());
+
+        let mock = MockECSMetadata::new()
+            .cluster("production")
+            .region("eu-west-1")
+            .task_id("abc")
+            .task_definition("streamer", "12")
+            .limits(0.5, 1024)
+            .availability_zone("eu-west-1a");
+        let metadata = mock.build();
+        assert_eq!(metadata.task_arn(), "arn:aws:ecs:eu-west-1:123456789012:task/production/abc");
+        assert_eq!((metadata.task_definition_family(), metadata.task_definition_revision()), ("streamer", "12"));
+        assert_eq!((metadata.availability_zone(), metadata.effective_memory_limit_mib()), (Some("eu-west-1a"), Some(1024)));
+        assert_eq!(metadata.self_container().unwrap().docker_id(), metadata.docker_id());
+        assert_eq!(ECSMetadata::from_json(&mock.container_json().to_string()).unwrap().container_name(), "app");
+        assert_eq!(metadata.client_stats(), None);
+    }
+}
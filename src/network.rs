@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Entry of the container's `Networks` list
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "PascalCase")]
+pub struct ECSNetwork {
+    // Fargate and awsvpc on EC2 number the ENIs, bridge and host mode documents don't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attachment_index: Option<u32>,
+    network_mode: String,
+    #[serde(rename = "IPv4Addresses", default)]
+    ipv4_addresses: Vec<String>,
+    #[serde(rename = "IPv6Addresses", default, skip_serializing_if = "Vec::is_empty")]
+    ipv6_addresses: Vec<String>,
+    #[serde(rename = "MACAddress", skip_serializing_if = "Option::is_none")]
+    mac_address: Option<String>,
+    // only set for awsvpc, bridge and host mode documents don't carry the DNS keys
+    #[serde(default)]
+    domain_name_servers: Vec<String>,
+    #[serde(rename = "DomainNameSearchList", alias = "DNSSearchDomains", default)]
+    dns_search_domains: Vec<String>,
+    #[serde(rename = "PrivateDNSName", default, skip_serializing_if = "Option::is_none")]
+    private_dns_name: Option<String>,
+    #[serde(rename = "IPv4SubnetCIDRBlock", default, skip_serializing_if = "Option::is_none")]
+    ipv4_subnet_cidr_block: Option<String>,
+    #[serde(rename = "IPv6SubnetCIDRBlock", default, skip_serializing_if = "Option::is_none")]
+    ipv6_subnet_cidr_block: Option<String>,
+    // served with the subnet's prefix length, e.g. `10.0.2.1/24`
+    #[serde(rename = "SubnetGatewayIpv4Address", default, skip_serializing_if = "Option::is_none")]
+    subnet_gateway_ipv4_address: Option<String>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl ECSNetwork {
+    /// Keys of the entry this crate does not model, see
+    /// `ECSContainerMetadata::raw`
+    pub fn raw(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    /// Index of the ENI attachment, only served for awsvpc interfaces
+    pub fn attachment_index(&self) -> Option<u32> {
+        self.attachment_index
+    }
+
+    /// Network mode as served, e.g. `awsvpc`, `bridge` or `host`
+    pub fn network_mode(&self) -> &str {
+        &self.network_mode
+    }
+
+    /// `network_mode` as a `NetworkMode`
+    pub fn network_mode_kind(&self) -> NetworkMode {
+        NetworkMode::parse(&self.network_mode)
+    }
+
+    pub fn ipv4_addresses(&self) -> &[String] {
+        &self.ipv4_addresses
+    }
+
+    /// Empty unless the task's subnet is dual-stack or IPv6-only
+    pub fn ipv6_addresses(&self) -> &[String] {
+        &self.ipv6_addresses
+    }
+
+    /// First of `ipv4_addresses` that parses, the ENI address in awsvpc mode
+    pub fn private_ipv4(&self) -> Option<Ipv4Addr> {
+        self.ipv4_addresses.iter().find_map(|address| address.parse().ok())
+    }
+
+    /// First of `ipv6_addresses` that parses
+    pub fn private_ipv6(&self) -> Option<Ipv6Addr> {
+        self.ipv6_addresses.iter().find_map(|address| address.parse().ok())
+    }
+
+    /// MAC address of the ENI, awsvpc only
+    pub fn mac_address(&self) -> Option<&str> {
+        self.mac_address.as_deref()
+    }
+
+    /// Resolvers of the task's ENI, as served (IP literals or host names), empty when absent
+    pub fn domain_name_servers(&self) -> &[String] {
+        &self.domain_name_servers
+    }
+
+    /// DNS search domains of the task's ENI, empty when absent
+    pub fn dns_search_domains(&self) -> &[String] {
+        &self.dns_search_domains
+    }
+
+    /// Host name of the ENI in the VPC, e.g. `ip-10-0-2-106.us-west-2.compute.internal`,
+    /// awsvpc only
+    pub fn private_dns_name(&self) -> Option<&str> {
+        self.private_dns_name.as_deref()
+    }
+
+    /// CIDR block of the ENI's subnet, e.g. `10.0.2.0/24`, awsvpc only
+    pub fn ipv4_subnet_cidr_block(&self) -> Option<&str> {
+        self.ipv4_subnet_cidr_block.as_deref()
+    }
+
+    /// IPv6 CIDR block of the ENI's subnet, when dual-stack or IPv6-only
+    pub fn ipv6_subnet_cidr_block(&self) -> Option<&str> {
+        self.ipv6_subnet_cidr_block.as_deref()
+    }
+
+    /// Gateway of the ENI's subnet as served, with the prefix length, e.g. `10.0.2.1/24`
+    pub fn subnet_gateway_ipv4_address(&self) -> Option<&str> {
+        self.subnet_gateway_ipv4_address.as_deref()
+    }
+
+    /// `subnet_gateway_ipv4_address` without the prefix length, `None` when it does not parse
+    pub fn subnet_gateway(&self) -> Option<Ipv4Addr> {
+        let address = self.subnet_gateway_ipv4_address.as_deref()?;
+        address.split_once('/').map_or(address, |(address, _)| address).parse().ok()
+    }
+}
+
+/// Entry of the container's `Ports` list
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "PascalCase")]
+pub struct ECSPortMapping {
+    container_port: u16,
+    #[serde(default = "default_protocol")]
+    protocol: String,
+    // awsvpc documents list the container port only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host_ip: Option<String>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+fn default_protocol() -> String {
+    "tcp".to_string()
+}
+
+impl ECSPortMapping {
+    /// Keys of the entry this crate does not model, see `ECSContainerMetadata::raw`
+    pub fn raw(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    pub fn container_port(&self) -> u16 {
+        self.container_port
+    }
+
+    /// `tcp` or `udp`
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Port on the host, `None` in awsvpc mode where the container port is reached directly
+    pub fn host_port(&self) -> Option<u16> {
+        self.host_port
+    }
+
+    /// Address the port is bound to on the host, typically `0.0.0.0` (all interfaces);
+    /// absent in awsvpc mode
+    pub fn host_ip(&self) -> Option<&str> {
+        self.host_ip.as_deref()
+    }
+
+    // the binding address if it names a single interface
+    pub(crate) fn specific_host_ip(&self) -> Option<IpAddr> {
+        self.host_ip.as_deref()?.parse().ok().filter(|ip: &IpAddr| !ip.is_unspecified())
+    }
+}
+
+/// Docker network mode of a container or task, see `ECSContainerMetadata::network_mode`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NetworkMode {
+    Awsvpc,
+    Bridge,
+    Host,
+    /// No networking, also what a document without networks nor ports maps to
+    None,
+    /// A mode this crate does not know, as served
+    Other(String),
+}
+
+impl NetworkMode {
+    pub(crate) fn parse(mode: &str) -> Self {
+        match mode.to_ascii_lowercase().as_str() {
+            "awsvpc" => Self::Awsvpc,
+            "bridge" => Self::Bridge,
+            "host" => Self::Host,
+            "none" => Self::None,
+            _ => Self::Other(mode.to_string()),
+        }
+    }
+
+    /// The mode as the agent spells it, e.g. `awsvpc`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Awsvpc => "awsvpc",
+            Self::Bridge => "bridge",
+            Self::Host => "host",
+            Self::None => "none",
+            Self::Other(mode) => mode,
+        }
+    }
+}
+
+impl fmt::Display for NetworkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Mode of the first network entry. Host and none mode documents may list no networks at all:
+/// ports can only be mapped in host mode then, so mapped ports mean `Host` and none `None`.
+/// A host mode container without port mappings is indistinguishable from one with no networking.
+pub(crate) fn network_mode(first_network: Option<&ECSNetwork>, has_ports: bool) -> NetworkMode {
+    match first_network {
+        Some(network) => NetworkMode::parse(&network.network_mode),
+        None if has_ports => NetworkMode::Host,
+        None => NetworkMode::None,
+    }
+}
+
+/// Interface to register the container with: the first awsvpc interface with an IPv4 address,
+/// falling back to the first interface of any kind (bridge, host or an IPv6-only ENI)
+pub(crate) fn primary_network(networks: &[ECSNetwork]) -> Option<&ECSNetwork> {
+    networks
+        .iter()
+        .find(|network| network.network_mode == "awsvpc" && !network.ipv4_addresses.is_empty())
+        .or_else(|| networks.first())
+}
+
+/// First port binding to a specific host address, i.e. not `0.0.0.0` or `::`
+pub(crate) fn host_ip(ports: &[ECSPortMapping]) -> Option<IpAddr> {
+    ports.iter().find_map(ECSPortMapping::specific_host_ip)
+}
+
+/// Address other hosts reach `container_port` at: the binding's host address and port when the
+/// binding names one, the ENI address in awsvpc mode, otherwise another binding's host address.
+/// `None` when all bindings are on all interfaces and the network is not awsvpc, the host's own
+/// address is not part of the metadata then.
+pub(crate) fn advertised_address(
+    ports: &[ECSPortMapping],
+    primary_network: Option<&ECSNetwork>,
+    container_port: u16,
+) -> Option<SocketAddr> {
+    let mapping = ports.iter().find(|mapping| mapping.container_port == container_port)?;
+    let port = mapping.host_port.unwrap_or(container_port);
+    let eni_ip = || {
+        let network = primary_network.filter(|network| network.network_mode == "awsvpc")?;
+        network.ipv4_addresses.first().or_else(|| network.ipv6_addresses.first())?.parse().ok()
+    };
+    let ip = mapping.specific_host_ip().or_else(eni_ip).or_else(|| host_ip(ports))?;
+    Some(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NetworkMode;
+    use std::net::Ipv4Addr;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use crate::warning::ParseWarningKind;
+
+    const AWSVPC_NETWORK: &str = r#"{
+        "AttachmentIndex": 0,
+        "NetworkMode": "awsvpc",
+        "IPv4Addresses": ["10.0.2.106"],
+        "MACAddress": "12:22:d1:6b:f5:27",
+        "DomainNameServers": ["10.0.0.2", "resolver.internal.example"],
+        "DomainNameSearchList": ["us-east-1.compute.internal", "svc.example"],
+        "PrivateDNSName": "ip-10-0-2-106.us-east-1.compute.internal",
+        "IPv4SubnetCIDRBlock": "10.0.2.0/24",
+        "SubnetGatewayIpv4Address": "10.0.2.1/24"
+    }"#;
+
+    fn with_networks(networks: &str) -> String {
+        CONTAINER_JSON.replace(r#""DockerId""#, &format!(r#""Networks": [{networks}], "DockerId""#))
+    }
+
+    #[test]
+    fn test_awsvpc_dns_fields() {
+        let metadata = metadata_from_json(&with_networks(AWSVPC_NETWORK), None);
+        let network = metadata.primary_network().expect("network should be parsed");
+        assert_eq!(network.network_mode(), "awsvpc");
+        // IP literals and host names pass through untouched
+        assert_eq!(network.domain_name_servers(), ["10.0.0.2", "resolver.internal.example"]);
+        assert_eq!(network.dns_search_domains(), ["us-east-1.compute.internal", "svc.example"]);
+        assert_eq!((network.network_mode_kind(), network.private_ipv4()), (NetworkMode::Awsvpc, Some(Ipv4Addr::new(10, 0, 2, 106))));
+        assert_eq!((network.ipv4_subnet_cidr_block(), network.ipv6_subnet_cidr_block()), (Some("10.0.2.0/24"), None));
+        assert_eq!((network.subnet_gateway_ipv4_address(), network.subnet_gateway()), (Some("10.0.2.1/24"), Some(Ipv4Addr::new(10, 0, 2, 1))));
+        assert_eq!(metadata.private_dns_name(), Some("ip-10-0-2-106.us-east-1.compute.internal"));
+        assert_eq!((metadata.private_ipv4(), metadata.mac_address()), (Some(Ipv4Addr::new(10, 0, 2, 106)), Some("12:22:d1:6b:f5:27")));
+
+        let legacy_key = r#"{"NetworkMode": "awsvpc", "DNSSearchDomains": ["svc.example"]}"#;
+        let metadata = metadata_from_json(&with_networks(legacy_key), None);
+        assert_eq!(metadata.primary_network().unwrap().dns_search_domains(), ["svc.example"]);
+    }
+
+    fn with_ports(ports: &str, networks: &str) -> String {
+        with_networks(networks).replace(r#""DockerId""#, &format!(r#""Ports": [{ports}], "DockerId""#))
+    }
+
+    #[test]
+    fn test_bridge_host_ip() {
+        let ports = r#"{"ContainerPort": 8080, "Protocol": "tcp", "HostPort": 32768, "HostIp": "0.0.0.0"},
+                       {"ContainerPort": 9090, "Protocol": "tcp", "HostPort": 32769, "HostIp": "10.0.1.25"}"#;
+        let bridge = r#"{"NetworkMode": "bridge", "IPv4Addresses": ["172.17.0.2"]}"#;
+        let metadata = metadata_from_json(&with_ports(ports, bridge), None);
+
+        assert_eq!(metadata.ports().len(), 2);
+        assert_eq!(metadata.ports()[0].host_ip(), Some("0.0.0.0"));
+        assert_eq!(metadata.host_ip(), Some("10.0.1.25".parse().unwrap()));
+        assert_eq!(metadata.advertised_address(9090), Some("10.0.1.25:32769".parse().unwrap()));
+        // bound to all interfaces, the other binding tells the host address
+        assert_eq!(metadata.advertised_address(8080), Some("10.0.1.25:32768".parse().unwrap()));
+        assert_eq!(metadata.advertised_address(22), None);
+
+        let wildcard_only = r#"{"ContainerPort": 8080, "HostPort": 32768, "HostIp": "0.0.0.0"}"#;
+        let metadata = metadata_from_json(&with_ports(wildcard_only, bridge), None);
+        assert_eq!(metadata.ports()[0].protocol(), "tcp");
+        assert_eq!(metadata.host_ip(), None);
+        assert_eq!(metadata.advertised_address(8080), None);
+    }
+
+    #[test]
+    fn test_awsvpc_advertised_address() {
+        let ports = r#"{"ContainerPort": 8080, "Protocol": "tcp"}"#;
+        let metadata = metadata_from_json(&with_ports(ports, AWSVPC_NETWORK), None);
+        assert_eq!(metadata.ports()[0].host_port(), None);
+        assert_eq!(metadata.host_ip(), None);
+        assert_eq!(metadata.advertised_address(8080), Some("10.0.2.106:8080".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_bridge_network_without_dns_fields() {
+        let bridge = r#"{"NetworkMode": "bridge", "IPv4Addresses": ["172.17.0.2"]}"#;
+        let metadata = metadata_from_json(&with_networks(bridge), None);
+        let network = metadata.primary_network().expect("network should be parsed");
+        assert_eq!(network.ipv4_addresses(), ["172.17.0.2"]);
+        assert!(network.domain_name_servers().is_empty());
+        assert!(network.dns_search_domains().is_empty());
+        assert_eq!((network.private_dns_name(), network.subnet_gateway()), (None, None));
+        assert_eq!(metadata.private_ipv4(), Some(Ipv4Addr::new(172, 17, 0, 2)));
+
+        // documents without Networks at all still parse
+        assert!(metadata_from_json(CONTAINER_JSON, None).networks().is_empty());
+    }
+
+    #[test]
+    fn test_two_interfaces_keep_agent_order() {
+        // the agent lists the service connect ENI first here
+        let service_connect = r#"{"AttachmentIndex": 1, "NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.3.7", "10.0.3.8"]}"#;
+        let metadata = metadata_from_json(&with_networks(&format!("{service_connect}, {AWSVPC_NETWORK}")), None);
+
+        let indexes: Vec<_> = metadata.networks().iter().map(|network| network.attachment_index()).collect();
+        assert_eq!(indexes, [Some(1), Some(0)]);
+        assert_eq!(metadata.ipv4_addresses().collect::<Vec<_>>(), ["10.0.3.7", "10.0.3.8", "10.0.2.106"]);
+        assert_eq!(metadata.networks_by_mode("awsvpc").count(), 2);
+        assert_eq!(metadata.networks_by_mode("bridge").count(), 0);
+        assert_eq!(metadata.primary_network().unwrap().attachment_index(), Some(1));
+        assert_eq!(metadata.networks()[1].mac_address(), Some("12:22:d1:6b:f5:27"));
+    }
+
+    #[test]
+    fn test_ipv6_only_interface() {
+        let ipv6_only = r#"{"AttachmentIndex": 0, "NetworkMode": "awsvpc", "IPv6Addresses": ["2600:1f18:619e:f900:8467:78b2:81c4:207d"]}"#;
+        let ports = r#"{"ContainerPort": 8080, "Protocol": "tcp"}"#;
+        let metadata = metadata_from_json(&with_ports(ports, ipv6_only), None);
+
+        let network = metadata.primary_network().expect("an IPv6-only ENI is still the primary network");
+        assert!(network.ipv4_addresses().is_empty());
+        assert_eq!(network.ipv6_addresses(), ["2600:1f18:619e:f900:8467:78b2:81c4:207d"]);
+        assert_eq!(metadata.ipv4_addresses().count(), 0);
+        assert_eq!((metadata.private_ipv4(), metadata.private_ipv6()), (None, "2600:1f18:619e:f900:8467:78b2:81c4:207d".parse().ok()));
+        assert_eq!(metadata.advertised_address(8080), Some("[2600:1f18:619e:f900:8467:78b2:81c4:207d]:8080".parse().unwrap()));
+
+        // an ENI with an IPv4 address wins over an earlier IPv6-only one
+        let dual = format!(r#"{ipv6_only}, {{"AttachmentIndex": 1, "NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.2.106"]}}"#);
+        let metadata = metadata_from_json(&with_networks(&dual), None);
+        assert_eq!(metadata.primary_network().unwrap().attachment_index(), Some(1));
+    }
+
+    #[test]
+    fn test_network_modes() {
+        let host_ports = r#"{"ContainerPort": 8080, "Protocol": "tcp", "HostPort": 8080, "HostIp": "0.0.0.0"}"#;
+        let bridge = r#"{"NetworkMode": "bridge", "IPv4Addresses": ["172.17.0.2"]}"#;
+        for (json, mode) in [
+            (with_networks(AWSVPC_NETWORK), NetworkMode::Awsvpc),
+            (with_networks(bridge), NetworkMode::Bridge),
+            (with_ports(host_ports, r#"{"NetworkMode": "host"}"#), NetworkMode::Host),
+            (with_networks(r#"{"NetworkMode": "none"}"#), NetworkMode::None),
+            // host and none mode documents may come without networks
+            (with_ports(host_ports, ""), NetworkMode::Host),
+            (with_networks(""), NetworkMode::None),
+            (CONTAINER_JSON.to_string(), NetworkMode::None),
+        ] {
+            let metadata = metadata_from_json(&json, None);
+            assert_eq!(metadata.network_mode(), mode, "{json}");
+            assert_eq!(metadata.container().network_mode(), mode);
+            assert!(!metadata.warnings().iter().any(|warning| warning.kind == ParseWarningKind::UnknownEnumValue));
+        }
+
+        let metadata = metadata_from_json(&with_networks(r#"{"NetworkMode": "nat"}"#), None);
+        assert_eq!(metadata.network_mode(), NetworkMode::Other("nat".to_string()));
+        assert_eq!(metadata.network_mode().to_string(), "nat");
+        assert!(metadata.warnings().iter().any(|warning| warning.kind == ParseWarningKind::UnknownEnumValue));
+        assert_eq!(NetworkMode::parse("AWSVPC"), NetworkMode::Awsvpc);
+    }
+}
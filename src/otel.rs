@@ -0,0 +1,151 @@
+#[cfg(feature = "opentelemetry")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "opentelemetry")]
+use opentelemetry_sdk::resource::{Resource, ResourceDetector};
+use crate::metadata::ECSMetadata;
+
+/// `cloud.platform` of an ECS container in the OpenTelemetry semantic conventions
+pub const OTEL_ECS_PLATFORM: &str = "aws_ecs";
+
+/// `opentelemetry_sdk` resource detector adding the `resource_attributes` of a container, e.g.
+/// `Resource::builder().with_detector(Box::new(ECSResourceDetector::new(&metadata))).build()`.
+/// The attributes are taken once, they don't change over the life of a container; degraded
+/// metadata detects an empty resource.
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug, Clone)]
+pub struct ECSResourceDetector {
+    attributes: Vec<(&'static str, String)>,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl ECSResourceDetector {
+    pub fn new(metadata: &ECSMetadata) -> Self {
+        Self { attributes: metadata.resource_attributes() }
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl ResourceDetector for ECSResourceDetector {
+    fn detect(&self) -> Resource {
+        let attributes = self.attributes.iter().map(|(key, value)| KeyValue::new(*key, value.clone()));
+        Resource::builder_empty().with_attributes(attributes).build()
+    }
+}
+
+impl ECSMetadata {
+    /// OpenTelemetry resource attributes of this container, keyed as in the semantic
+    /// conventions for AWS ECS, in the order the SDK detectors add them: `cloud.provider`,
+    /// `cloud.platform`, `cloud.account.id`, `cloud.region`, `cloud.availability_zone`,
+    /// `aws.ecs.cluster.arn`, `aws.ecs.container.arn`, `aws.ecs.launchtype` (lower case),
+    /// `aws.ecs.task.arn`, `aws.ecs.task.family`, `aws.ecs.task.revision`, `aws.ecs.task.id`,
+    /// `container.id`, `container.name`, `container.image.name` and, for the `awslogs` driver,
+    /// `aws.log.group.names` and `aws.log.stream.names`.
+    ///
+    /// Each pair maps to a `KeyValue` of an `opentelemetry_sdk::Resource` as is, see
+    /// `ECSResourceDetector` with the `opentelemetry` feature. Unknown components are left out,
+    /// and degraded metadata has none at all, like `trace_annotations`. The availability zone
+    /// and launch type need the task document.
+    pub fn resource_attributes(&self) -> Vec<(&'static str, String)> {
+        if self.is_degraded() {
+            return Vec::new();
+        }
+        let task = self.task();
+        let owned = |value: &str| Some(value.to_string());
+        let awslogs = |key: &str| match self.log_driver() {
+            Some("awslogs") => self.log_options()?.get(key).cloned(),
+            _ => None,
+        };
+        let mut attributes = vec![("cloud.provider", "aws".to_string()), ("cloud.platform", OTEL_ECS_PLATFORM.to_string())];
+        for (key, value) in [
+            ("cloud.account.id", self.account_id().and_then(owned)),
+            ("cloud.region", self.region().and_then(owned)),
+            ("cloud.availability_zone", self.availability_zone().and_then(owned)),
+            ("aws.ecs.cluster.arn", self.cluster_arn()),
+            ("aws.ecs.container.arn", self.container_arn().and_then(owned)),
+            ("aws.ecs.launchtype", task.and_then(|task| task.launch_type()).map(str::to_ascii_lowercase)),
+            ("aws.ecs.task.arn", owned(self.task_arn())),
+            ("aws.ecs.task.family", task.and_then(|task| task.family()).or(Some(self.task_definition_family())).and_then(owned)),
+            ("aws.ecs.task.revision", task.and_then(|task| task.revision()).or(Some(self.task_definition_revision())).and_then(owned)),
+            ("aws.ecs.task.id", self.task_id()),
+            ("container.id", owned(self.docker_id())),
+            ("container.name", owned(self.container_name())),
+            ("container.image.name", owned(crate::image::split_image(self.image()).0)),
+            ("aws.log.group.names", awslogs("awslogs-group")),
+            ("aws.log.stream.names", awslogs("awslogs-stream")),
+        ] {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                attributes.push((key, value));
+            }
+        }
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_resource_attributes() {
+        let container = include_str!("../testdata/versions/v4_container.json");
+        let task = include_str!("../testdata/versions/v4_task.json");
+        let attributes = metadata_from_json(container, Some(task)).resource_attributes();
+        let expected = [
+            ("cloud.provider", "aws"),
+            ("cloud.platform", "aws_ecs"),
+            ("cloud.account.id", "111122223333"),
+            ("cloud.region", "us-west-2"),
+            ("cloud.availability_zone", "us-west-2d"),
+            ("aws.ecs.cluster.arn", "arn:aws:ecs:us-west-2:111122223333:cluster/default"),
+            ("aws.ecs.container.arn", "arn:aws:ecs:us-west-2:111122223333:container/05966557-f16c-49cb-9352-24b3a0dcd0e1"),
+            ("aws.ecs.launchtype", "ec2"),
+            ("aws.ecs.task.arn", "arn:aws:ecs:us-west-2:111122223333:task/default/cd189a933e5849daa93386466019ab50"),
+            ("aws.ecs.task.family", "curltest"),
+            ("aws.ecs.task.revision", "2"),
+            ("aws.ecs.task.id", "cd189a933e5849daa93386466019ab50"),
+            ("container.id", "cd189a933e5849daa93386466019ab50-2495160603"),
+            ("container.name", "curl"),
+            ("container.image.name", "111122223333.dkr.ecr.us-west-2.amazonaws.com/curltest"),
+            ("aws.log.group.names", "/ecs/containerlogs"),
+            ("aws.log.stream.names", "ecs/curl/cd189a933e5849daa93386466019ab50"),
+        ];
+        assert_eq!(attributes, expected.map(|(key, value)| (key, value.to_string())));
+    }
+
+    #[test]
+    fn test_resource_attributes_without_task() {
+        let keys: Vec<_> = metadata_from_json(CONTAINER_JSON, None).resource_attributes().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "cloud.provider",
+                "cloud.platform",
+                "cloud.account.id",
+                "cloud.region",
+                "aws.ecs.cluster.arn",
+                "aws.ecs.task.arn",
+                "aws.ecs.task.family",
+                "aws.ecs.task.revision",
+                "aws.ecs.task.id",
+                "container.id",
+                "container.name",
+                "container.image.name",
+            ]
+        );
+        assert!(ECSMetadata::degraded(None).resource_attributes().is_empty());
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[test]
+    fn test_resource_detector() {
+        use opentelemetry::{Key, Value};
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let resource = Resource::builder_empty().with_detector(Box::new(ECSResourceDetector::new(&metadata))).build();
+        assert_eq!(resource.len(), metadata.resource_attributes().len());
+        assert_eq!(resource.get(&Key::new("cloud.platform")), Some(Value::from(OTEL_ECS_PLATFORM)));
+        assert_eq!(resource.get(&Key::new("aws.ecs.task.id")), Some(Value::from("021447970bce4bd58069f1925cd87bc0")));
+
+        assert_eq!(ECSResourceDetector::new(&ECSMetadata::degraded(None)).detect().len(), 0);
+    }
+}
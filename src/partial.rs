@@ -0,0 +1,356 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use crate::client::ECSMetadataBuilder;
+use crate::container::{cluster_from_task_arn, ECSContainerLabels, ECSContainerLimits};
+use crate::error::{parse_document, ECSMetadataError};
+use crate::health::{ECSContainerHealth, DEFAULT_MAX_HEALTH_OUTPUT_LEN};
+use crate::metadata::ECSMetadata;
+use crate::network::{ECSNetwork, ECSPortMapping};
+
+/// Sections of the container document to parse with `init_partial`, combined with `|`,
+/// e.g. `FieldSet::LIMITS | FieldSet::LABELS`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FieldSet(u8);
+
+impl FieldSet {
+    /// `Labels`: the task ARN, cluster, container name and task definition
+    pub const LABELS: Self = Self(1);
+    pub const LIMITS: Self = Self(1 << 1);
+    pub const IMAGE: Self = Self(1 << 2);
+    pub const NETWORKS: Self = Self(1 << 3);
+    pub const PORTS: Self = Self(1 << 4);
+    pub const HEALTH: Self = Self(1 << 5);
+    /// `Type`, `KnownStatus` and `DesiredStatus`
+    pub const STATUS: Self = Self(1 << 6);
+    pub const ALL: Self = Self((1 << 7) - 1);
+
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::LABELS, "Labels"),
+        (Self::LIMITS, "Limits"),
+        (Self::IMAGE, "Image"),
+        (Self::NETWORKS, "Networks"),
+        (Self::PORTS, "Ports"),
+        (Self::HEALTH, "Health"),
+        (Self::STATUS, "Status"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// True if every section of `other` is in this set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for FieldSet {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for FieldSet {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+// e.g. `FieldSet(Labels | Limits)`
+impl fmt::Debug for FieldSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = Self::NAMES.iter().filter(|(set, _)| self.contains(*set)).map(|(_, name)| *name).collect();
+        write!(f, "FieldSet({})", names.join(" | "))
+    }
+}
+
+// Sections are kept as raw slices of the body, only the selected ones get deserialized
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawSections<'a> {
+    docker_id: String,
+    #[serde(borrow, default)]
+    labels: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    limits: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    image: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    networks: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    ports: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    health: Option<&'a RawValue>,
+    #[serde(rename = "Type", borrow, default)]
+    container_type: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    known_status: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    desired_status: Option<&'a RawValue>,
+}
+
+/// Container metadata with only the sections of a `FieldSet` parsed, see `init_partial`.
+/// Accessors of the other sections fail with `ECSMetadataError::NotFetched`, while a selected
+/// section missing from the document gives `Ok(None)` or an empty list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ECSPartialMetadata {
+    fields: FieldSet,
+    docker_id: String,
+    labels: Option<ECSContainerLabels>,
+    limits: Option<ECSContainerLimits>,
+    image: Option<String>,
+    networks: Vec<ECSNetwork>,
+    ports: Vec<ECSPortMapping>,
+    health: Option<ECSContainerHealth>,
+    container_type: Option<String>,
+    known_status: Option<String>,
+    desired_status: Option<String>,
+}
+
+impl ECSPartialMetadata {
+    /// Parses the selected sections of a container metadata document
+    pub fn from_json(json: &str, fields: FieldSet) -> Result<Self, ECSMetadataError> {
+        Self::from_slice(json.as_bytes(), fields, DEFAULT_MAX_HEALTH_OUTPUT_LEN)
+    }
+
+    fn from_slice(json: &[u8], fields: FieldSet, max_health_output_len: usize) -> Result<Self, ECSMetadataError> {
+        let raw: RawSections = parse_document(json)?;
+        let labels: Option<ECSContainerLabels> = parse_section(fields, FieldSet::LABELS, raw.labels)?;
+        if fields.contains(FieldSet::LABELS) && labels.is_none() {
+            // required in a full parse as well
+            return Err(ECSMetadataError::MissingField("Labels".to_string()));
+        }
+        labels.as_ref().map_or(Ok(()), ECSContainerLabels::require_task_arn)?;
+        let mut health: Option<ECSContainerHealth> = parse_section(fields, FieldSet::HEALTH, raw.health)?;
+        if let Some(health) = &mut health {
+            health.truncate_output(max_health_output_len);
+        }
+        Ok(Self {
+            fields,
+            docker_id: raw.docker_id,
+            labels,
+            limits: parse_section(fields, FieldSet::LIMITS, raw.limits)?,
+            image: parse_section(fields, FieldSet::IMAGE, raw.image)?,
+            networks: parse_section(fields, FieldSet::NETWORKS, raw.networks)?.unwrap_or_default(),
+            ports: parse_section(fields, FieldSet::PORTS, raw.ports)?.unwrap_or_default(),
+            health,
+            container_type: parse_section(fields, FieldSet::STATUS, raw.container_type)?,
+            known_status: parse_section(fields, FieldSet::STATUS, raw.known_status)?,
+            desired_status: parse_section(fields, FieldSet::STATUS, raw.desired_status)?,
+        })
+    }
+
+    fn fetched<T>(&self, set: FieldSet, value: T) -> Result<T, ECSMetadataError> {
+        if self.fields.contains(set) {
+            return Ok(value);
+        }
+        let name = FieldSet::NAMES.iter().find(|(named, _)| *named == set).map_or("", |(_, name)| *name);
+        Err(ECSMetadataError::NotFetched(name.to_string()))
+    }
+
+    /// The sections that were parsed
+    pub fn fields(&self) -> FieldSet {
+        self.fields
+    }
+
+    /// Always parsed, like in a full parse
+    pub fn docker_id(&self) -> &str {
+        &self.docker_id
+    }
+
+    fn labels(&self) -> Result<&ECSContainerLabels, ECSMetadataError> {
+        // always set once `LABELS` is selected
+        self.fetched(FieldSet::LABELS, ())?;
+        self.labels.as_ref().ok_or_else(|| ECSMetadataError::MissingField("Labels".to_string()))
+    }
+
+    pub fn task_arn(&self) -> Result<&str, ECSMetadataError> {
+        Ok(self.labels()?.task_arn())
+    }
+
+    /// Cluster as labelled or taken from the task ARN, see `ECSContainerMetadata::cluster`
+    pub fn cluster(&self) -> Result<Option<&str>, ECSMetadataError> {
+        let labels = self.labels()?;
+        Ok(labels.cluster.as_deref().or_else(|| cluster_from_task_arn(labels.task_arn())))
+    }
+
+    pub fn container_name(&self) -> Result<Option<&str>, ECSMetadataError> {
+        Ok(self.labels()?.container_name.as_deref())
+    }
+
+    pub fn task_definition_family(&self) -> Result<Option<&str>, ECSMetadataError> {
+        Ok(self.labels()?.task_definition_family.as_deref())
+    }
+
+    pub fn task_definition_revision(&self) -> Result<Option<&str>, ECSMetadataError> {
+        Ok(self.labels()?.task_definition_version.as_deref())
+    }
+
+    pub fn limits(&self) -> Result<Option<&ECSContainerLimits>, ECSMetadataError> {
+        self.fetched(FieldSet::LIMITS, self.limits.as_ref())
+    }
+
+    pub fn image(&self) -> Result<Option<&str>, ECSMetadataError> {
+        self.fetched(FieldSet::IMAGE, self.image.as_deref())
+    }
+
+    pub fn networks(&self) -> Result<&[ECSNetwork], ECSMetadataError> {
+        self.fetched(FieldSet::NETWORKS, &self.networks)
+    }
+
+    pub fn ports(&self) -> Result<&[ECSPortMapping], ECSMetadataError> {
+        self.fetched(FieldSet::PORTS, &self.ports)
+    }
+
+    /// Health of the container, `Ok(None)` without a health check
+    pub fn health(&self) -> Result<Option<&ECSContainerHealth>, ECSMetadataError> {
+        self.fetched(FieldSet::HEALTH, self.health.as_ref())
+    }
+
+    /// `Type` as served, e.g. `NORMAL`
+    pub fn container_type(&self) -> Result<Option<&str>, ECSMetadataError> {
+        self.fetched(FieldSet::STATUS, self.container_type.as_deref())
+    }
+
+    pub fn known_status(&self) -> Result<Option<&str>, ECSMetadataError> {
+        self.fetched(FieldSet::STATUS, self.known_status.as_deref())
+    }
+
+    pub fn desired_status(&self) -> Result<Option<&str>, ECSMetadataError> {
+        self.fetched(FieldSet::STATUS, self.desired_status.as_deref())
+    }
+}
+
+fn parse_section<T: DeserializeOwned>(
+    fields: FieldSet,
+    set: FieldSet,
+    value: Option<&RawValue>,
+) -> Result<Option<T>, ECSMetadataError> {
+    match value {
+        Some(value) if fields.contains(set) => Ok(Some(serde_json::from_str(value.get())?)),
+        _ => Ok(None),
+    }
+}
+
+impl ECSMetadata {
+    /// `ECSMetadataBuilder::init_partial` with the default builder
+    pub async fn init_partial(fields: FieldSet) -> Result<ECSPartialMetadata, ECSMetadataError> {
+        Self::builder().init_partial(fields).await
+    }
+}
+
+impl ECSMetadataBuilder {
+    /// Fetches the container document like `init`, but only parses the sections in `fields`,
+    /// e.g. `FieldSet::LIMITS` for a consumer sizing itself from the limits. The other sections
+    /// are skipped without being materialized.
+    pub async fn init_partial(self, fields: FieldSet) -> Result<ECSPartialMetadata, ECSMetadataError> {
+        let documents = self.fetch_documents(false).await?;
+        ECSPartialMetadata::from_slice(&documents.container, fields, self.max_health_output_len).inspect_err(|err| {
+            if let Some(hook) = &self.parse_failure_hook {
+                hook.capture(&documents.container_meta, &documents.container, err);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    const FULL_JSON: &str = r#"{
+        "DockerId": "abc",
+        "Image": "streamer:v1",
+        "Labels": {"com.amazonaws.ecs.task-arn": "arn:aws:ecs:us-east-1:939885537497:task/production/abc"},
+        "Limits": {"CPU": 2, "Memory": 512},
+        "Networks": [{"NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.2.106"]}],
+        "Ports": [{"ContainerPort": 8080}],
+        "Health": {"status": "HEALTHY", "output": "0123456789abcdef0123456789"},
+        "Type": "NORMAL",
+        "KnownStatus": "RUNNING",
+        "DesiredStatus": "RUNNING",
+        "Volumes": [{"DockerName": "data", "Source": "/var/lib/data", "Destination": "/data"}]
+    }"#;
+
+    #[test]
+    fn test_limits_only() {
+        let partial = ECSPartialMetadata::from_json(FULL_JSON, FieldSet::LIMITS).unwrap();
+        assert_eq!(partial.docker_id(), "abc");
+        assert_eq!(partial.limits().unwrap(), Some(&ECSContainerLimits::new(Some(2.0), Some(512))));
+        for err in [partial.image().unwrap_err(), partial.task_arn().unwrap_err(), partial.health().unwrap_err()] {
+            assert!(matches!(err, ECSMetadataError::NotFetched(_)), "{err:?}");
+        }
+        assert!(matches!(partial.networks(), Err(ECSMetadataError::NotFetched(section)) if section == "Networks"));
+        assert!(matches!(partial.known_status(), Err(ECSMetadataError::NotFetched(section)) if section == "Status"));
+
+        // absent from the document is not the same as not fetched
+        let without_limits = r#"{"DockerId": "abc", "Labels": {"com.amazonaws.ecs.task-arn": "t"}}"#;
+        assert_eq!(ECSPartialMetadata::from_json(without_limits, FieldSet::LIMITS).unwrap().limits().unwrap(), None);
+    }
+
+    #[test]
+    fn test_skipped_sections_are_not_parsed() {
+        // invalid in a full parse, never looked into here
+        let invalid = r#"{"DockerId": "abc", "Networks": "none", "Ports": [{"ContainerPort": -1}], "Limits": {"CPU": 1, "Memory": 128}}"#;
+        assert!(ECSMetadata::from_json(invalid).is_err());
+        let partial = ECSPartialMetadata::from_json(invalid, FieldSet::LIMITS).unwrap();
+        assert_eq!(partial.limits().unwrap().map(|limits| limits.mem), Some(Some(128)));
+
+        assert!(ECSPartialMetadata::from_json(invalid, FieldSet::LIMITS | FieldSet::NETWORKS).is_err());
+        assert!(matches!(
+            ECSPartialMetadata::from_json(invalid, FieldSet::LABELS),
+            Err(ECSMetadataError::MissingField(field)) if field == "Labels"
+        ));
+    }
+
+    #[test]
+    fn test_all_matches_full_parse() {
+        let partial = ECSPartialMetadata::from_json(FULL_JSON, FieldSet::ALL).unwrap();
+        let full = ECSMetadata::from_json(FULL_JSON).unwrap();
+        assert_eq!(partial.task_arn().unwrap(), full.task_arn());
+        assert_eq!(partial.cluster().unwrap(), full.cluster());
+        assert_eq!(partial.container_name().unwrap(), None);
+        assert_eq!(partial.image().unwrap(), Some(full.image()));
+        assert_eq!(partial.networks().unwrap(), full.networks());
+        assert_eq!(partial.ports().unwrap(), full.ports());
+        assert_eq!(partial.health().unwrap(), full.health());
+        assert_eq!(partial.container_type().unwrap(), Some("NORMAL"));
+        assert_eq!(partial.desired_status().unwrap(), Some("RUNNING"));
+    }
+
+    #[test]
+    fn test_field_set() {
+        let mut fields = FieldSet::LIMITS | FieldSet::LABELS;
+        assert!(fields.contains(FieldSet::LIMITS) && !fields.contains(FieldSet::IMAGE));
+        assert!(!fields.contains(FieldSet::LIMITS | FieldSet::IMAGE));
+        fields |= FieldSet::IMAGE;
+        assert_eq!(format!("{fields:?}"), "FieldSet(Labels | Limits | Image)");
+        assert!(FieldSet::ALL.contains(fields) && FieldSet::empty().is_empty());
+        assert_eq!(FieldSet::NAMES.iter().fold(FieldSet::empty(), |all, (set, _)| all | *set), FieldSet::ALL);
+    }
+
+    #[tokio::test]
+    async fn test_init_partial() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let partial = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .init_partial(FieldSet::LABELS | FieldSet::LIMITS)
+            .await
+            .unwrap();
+        assert_eq!(partial.container_name().unwrap(), Some("streamer"));
+        assert_eq!(partial.cluster().unwrap(), Some("production"));
+        assert!(partial.limits().unwrap().is_some());
+        assert!(partial.image().is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! The commonly used types, for a single glob import:
+//!
+//! ```
+//! use ecs_metadata::prelude::*;
+//! ```
+//!
+//! Everything here is also exported at the crate root, under the same name.
+
+pub use crate::client::{ECSMetadataBuilder, RequestPolicy};
+pub use crate::container::{ContainerStatus, ECSContainerLimits, ECSContainerMetadata};
+pub use crate::context::ECSContext;
+pub use crate::error::ECSMetadataError;
+pub use crate::health::{ECSContainerHealth, HealthStatus};
+pub use crate::memory::MemorySize;
+pub use crate::metadata::ECSMetadata;
+pub use crate::network::{ECSNetwork, ECSPortMapping, NetworkMode};
+pub use crate::readiness::ReadinessHandle;
+pub use crate::refresh::RefreshOutcome;
+pub use crate::shared::SharedECSMetadata;
+pub use crate::stats::ECSContainerStats;
+pub use crate::task::{ECSTaskLimits, ECSTaskMetadata, LaunchType};
+pub use crate::warning::ParseWarning;
+pub use crate::watcher::ECSMetadataWatcher;
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    fn same<A: 'static, B: 'static>() -> bool {
+        TypeId::of::<A>() == TypeId::of::<B>()
+    }
+
+    // The root paths are public API since the first release: moving a type between modules
+    // must keep them resolving, to the same type the prelude exports
+    #[test]
+    fn test_root_paths_resolve() {
+        assert!(same::<crate::ECSMetadata, super::ECSMetadata>());
+        assert!(same::<crate::ECSContainerLimits, super::ECSContainerLimits>());
+        assert!(same::<crate::ECSContainerMetadata, super::ECSContainerMetadata>());
+        assert!(same::<crate::ECSMetadataError, super::ECSMetadataError>());
+        assert!(same::<crate::ECSMetadataBuilder, super::ECSMetadataBuilder>());
+        assert!(same::<crate::RequestPolicy, super::RequestPolicy>());
+        assert!(same::<crate::ECSTaskMetadata, super::ECSTaskMetadata>());
+        assert!(same::<crate::ECSTaskLimits, super::ECSTaskLimits>());
+        assert!(same::<crate::ECSNetwork, super::ECSNetwork>());
+        assert!(same::<crate::ECSPortMapping, super::ECSPortMapping>());
+        assert!(same::<crate::NetworkMode, super::NetworkMode>());
+        assert!(same::<crate::LaunchType, super::LaunchType>());
+        assert!(same::<crate::ECSContainerHealth, super::ECSContainerHealth>());
+        assert!(same::<crate::HealthStatus, super::HealthStatus>());
+        assert!(same::<crate::ContainerStatus, super::ContainerStatus>());
+        assert!(same::<crate::SharedECSMetadata, super::SharedECSMetadata>());
+        assert!(same::<crate::ReadinessHandle, super::ReadinessHandle>());
+        assert!(same::<crate::RefreshOutcome, super::RefreshOutcome>());
+        assert!(same::<crate::ParseWarning, super::ParseWarning>());
+        assert!(same::<crate::ECSContainerStats, super::ECSContainerStats>());
+        assert!(same::<crate::ECSMetadataWatcher, super::ECSMetadataWatcher>());
+        assert!(same::<crate::MemorySize, super::MemorySize>());
+        assert!(same::<crate::NoopECSContext, crate::context::NoopECSContext>());
+    }
+}
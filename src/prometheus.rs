@@ -0,0 +1,183 @@
+use std::fmt::{Display, Write};
+use crate::container::ECSContainerMetadata;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+use crate::stats::ECSContainerStats;
+
+/// `Content-Type` to serve `prometheus_text` with, the text exposition format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Metric family of the exposition, left out without samples
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    samples: Vec<(String, String)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Self {
+        Self { name, kind, help, samples: Vec::new() }
+    }
+
+    fn push(&mut self, labels: &[(&str, &str)], value: impl Display) {
+        let labels = labels.iter().map(|(name, value)| format!("{name}=\"{}\"", escape(value))).collect::<Vec<_>>();
+        self.samples.push((labels.join(","), value.to_string()));
+    }
+}
+
+impl ECSMetadata {
+    /// Fetches the stats and renders them with `prometheus_text`: of every container of the task
+    /// when the task document was fetched, of this container otherwise
+    pub async fn prometheus_metrics(&self) -> Result<String, ECSMetadataError> {
+        let stats = match self.task() {
+            Some(_) => self.task_stats().await?.into_values().collect(),
+            None => vec![self.container_stats().await?],
+        };
+        Ok(self.prometheus_text(&stats))
+    }
+
+    /// Metrics in the Prometheus text exposition format, labeled with `task_id`, `cluster` and
+    /// `container_name`:
+    /// - `ecs_container_cpu_usage_percent`, of one CPU, see `ECSContainerStats::cpu_usage_percent`
+    /// - `ecs_container_memory_usage_bytes` and `ecs_container_memory_limit_bytes`
+    /// - `ecs_container_network_receive_bytes_total` and `ecs_container_network_transmit_bytes_total`,
+    ///   per `interface`
+    /// - `ecs_container_status`, 1 for the `status` the agent knows the container in
+    /// - `ecs_container_health_status`, 1 for the `status` of the health check
+    /// - `ecs_container_restarts`, for containers with a restart policy
+    ///
+    /// The samples of `stats` are matched to the containers of the task document (or to this one)
+    /// by Docker ID, the statuses are from the documents and cover stopped containers too.
+    pub fn prometheus_text(&self, stats: &[ECSContainerStats]) -> String {
+        let task_id = self.task_id().unwrap_or_default();
+        let cluster = self.cluster_name().unwrap_or_default();
+        let containers = match self.task() {
+            Some(task) => task.containers(),
+            None => std::slice::from_ref(self.container()),
+        };
+        let identity = |container_name| [("task_id", task_id.as_str()), ("cluster", cluster), ("container_name", container_name)];
+
+        let mut cpu = Family::new("ecs_container_cpu_usage_percent", "gauge", "CPU usage since the previous sample, in percent of one CPU");
+        let mut memory = Family::new("ecs_container_memory_usage_bytes", "gauge", "Memory in use without the page cache");
+        let mut memory_limit = Family::new("ecs_container_memory_limit_bytes", "gauge", "Memory limit of the container");
+        let mut received = Family::new("ecs_container_network_receive_bytes_total", "counter", "Bytes received per interface");
+        let mut transmitted = Family::new("ecs_container_network_transmit_bytes_total", "counter", "Bytes sent per interface");
+        for stats in stats {
+            let container = containers.iter().find(|container| Some(container.docker_id()) == stats.id());
+            let name = container.map_or_else(|| stats.name().unwrap_or_default().trim_start_matches('/'), ECSContainerMetadata::container_name);
+            let labels = identity(name);
+            if let Some(percent) = stats.cpu_usage_percent() {
+                cpu.push(&labels, percent);
+            }
+            if let Some(bytes) = stats.memory_usage_bytes() {
+                memory.push(&labels, bytes);
+            }
+            if let Some(bytes) = stats.memory_stats().limit() {
+                memory_limit.push(&labels, bytes);
+            }
+            for (interface, network) in stats.networks() {
+                let labels = [&labels[..], &[("interface", interface.as_str())]].concat();
+                received.push(&labels, network.rx_bytes());
+                transmitted.push(&labels, network.tx_bytes());
+            }
+        }
+
+        let mut status = Family::new("ecs_container_status", "gauge", "Status the agent knows the container in");
+        let mut health = Family::new("ecs_container_health_status", "gauge", "Status of the container health check");
+        let mut restarts = Family::new("ecs_container_restarts", "gauge", "Restarts of the container under its restart policy");
+        for container in containers {
+            let labels = identity(container.container_name());
+            if let Some(known_status) = container.known_status() {
+                status.push(&[&labels[..], &[("status", known_status)]].concat(), 1);
+            }
+            if let Some(check) = container.health() {
+                health.push(&[&labels[..], &[("status", check.status())]].concat(), 1);
+            }
+            if let Some(count) = container.restart_count() {
+                restarts.push(&labels, count);
+            }
+        }
+
+        let mut text = String::new();
+        for family in [cpu, memory, memory_limit, received, transmitted, status, health, restarts] {
+            if family.samples.is_empty() {
+                continue;
+            }
+            let _ = writeln!(text, "# HELP {} {}\n# TYPE {} {}", family.name, family.help, family.name, family.kind);
+            for (labels, value) in &family.samples {
+                let _ = writeln!(text, "{}{{{labels}}} {value}", family.name);
+            }
+        }
+        text
+    }
+}
+
+// Label values are quoted, with backslashes, quotes and line feeds escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use crate::task::tests::{sidecar_json, task_json};
+    use crate::test_support::{MockAgent, MockResponse};
+
+    const CGROUP_V1_JSON: &str = include_str!("../testdata/stats/cgroup_v1.json");
+
+    fn sample<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+        text.lines().find(|line| line.starts_with(prefix)).map(|line| line.rsplit_once(' ').unwrap().1)
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let text = metadata.prometheus_text(&[serde_json::from_str(CGROUP_V1_JSON).unwrap()]);
+        let labels = r#"task_id="021447970bce4bd58069f1925cd87bc0",cluster="production",container_name="streamer""#;
+        assert!(text.starts_with("# HELP ecs_container_cpu_usage_percent "), "{text}");
+        assert!(text.contains("# TYPE ecs_container_network_receive_bytes_total counter\n"), "{text}");
+        let percent = sample(&text, &format!("ecs_container_cpu_usage_percent{{{labels}}}")).unwrap();
+        assert!((percent.parse::<f64>().unwrap() - 0.973_161_59).abs() < 1e-6, "{percent}");
+        assert_eq!(sample(&text, &format!("ecs_container_memory_usage_bytes{{{labels}}}")), Some("27623424"));
+        assert_eq!(sample(&text, &format!("ecs_container_memory_limit_bytes{{{labels}}}")), Some("536870912"));
+        assert_eq!(sample(&text, &format!(r#"ecs_container_network_receive_bytes_total{{{labels},interface="eth0"}}"#)), Some("5338"));
+        assert_eq!(sample(&text, &format!(r#"ecs_container_network_transmit_bytes_total{{{labels},interface="eth0"}}"#)), Some("648"));
+        // no statuses in the container document
+        assert!(!text.contains("ecs_container_status"), "{text}");
+    }
+
+    #[test]
+    fn test_statuses_of_task_containers() {
+        let running = CONTAINER_JSON.replace(r#""Limits""#, r#""KnownStatus": "RUNNING", "Health": {"status": "HEALTHY"}, "RestartCount": 2, "Limits""#);
+        let stopped = sidecar_json().replace(r#""Limits""#, r#""KnownStatus": "STOPPED", "Limits""#);
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(&task_json(&[running, stopped])));
+        let containers = metadata.task().unwrap().containers();
+        assert_eq!(containers.iter().map(ECSContainerMetadata::restart_count).collect::<Vec<_>>(), [Some(2), None]);
+        let text = metadata.prometheus_text(&[]);
+        let labels = |name: &str| format!(r#"task_id="021447970bce4bd58069f1925cd87bc0",cluster="production",container_name="{name}""#);
+        assert_eq!(sample(&text, &format!(r#"ecs_container_status{{{},status="RUNNING"}}"#, labels("streamer"))), Some("1"));
+        assert_eq!(sample(&text, &format!(r#"ecs_container_status{{{},status="STOPPED"}}"#, labels("envoy"))), Some("1"));
+        assert_eq!(sample(&text, &format!(r#"ecs_container_health_status{{{},status="HEALTHY"}}"#, labels("streamer"))), Some("1"));
+        assert_eq!(sample(&text, &format!("ecs_container_restarts{{{}}}", labels("streamer"))), Some("2"));
+        assert!(!text.contains("ecs_container_cpu_usage_percent"), "{text}");
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        assert_eq!(escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+        assert_eq!(escape("line\nfeed"), r"line\nfeed");
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/stats", MockResponse::json(CGROUP_V1_JSON));
+        let metadata = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).init().await.unwrap();
+        let text = metadata.prometheus_metrics().await.unwrap();
+        assert_eq!(text, metadata.prometheus_text(&[serde_json::from_str(CGROUP_V1_JSON).unwrap()]));
+        assert_eq!(agent.hits("/v4/abc/task/stats"), 0);
+    }
+}
@@ -0,0 +1,266 @@
+//! No accessor panics, whatever the shape of the documents: arbitrary JSON loosely shaped like
+//! the v4 responses goes through the lenient parse, then through every public accessor.
+//! `PROPTEST_CASES=100000 cargo test --release proptests` for a longer run.
+
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use serde_json::{Map, Value};
+use crate::banner::BannerOptions;
+use crate::container::ECSContainerMetadata;
+use crate::image::TagConvention;
+use crate::metadata::ECSMetadata;
+use crate::task::ECSTaskMetadata;
+
+// Strings favouring what the accessors split and slice on
+fn text() -> BoxedStrategy<String> {
+    prop_oneof![
+        Just(String::new()),
+        "[a-z0-9:/@._~-]{0,24}",
+        "arn:aws:ecs:[a-z0-9-]{0,12}:[0-9]{0,12}:(task|cluster)?/?[a-z0-9/:-]{0,40}",
+        "[0-9a-f-]{0,64}",
+        "([0-9]{12}\\.dkr\\.ecr\\.[a-z0-9-]{0,12}\\.amazonaws\\.com/)?[a-z/]{0,12}(:[a-z0-9._-]{0,16})?(@sha256:[0-9a-f]{0,8})?",
+        "[0-9a-f.:%]{0,40}",
+        ".{0,16}",
+    ]
+    .boxed()
+}
+
+fn number() -> BoxedStrategy<Value> {
+    prop_oneof![
+        40 => any::<u16>().prop_map(Value::from),
+        1 => any::<i64>().prop_map(Value::from),
+        1 => any::<u64>().prop_map(Value::from),
+        1 => any::<f64>().prop_map(|number| serde_json::Number::from_f64(number).map_or(Value::Null, Value::Number)),
+    ]
+    .boxed()
+}
+
+fn any_value() -> BoxedStrategy<Value> {
+    let scalar = prop_oneof![Just(Value::Null), any::<bool>().prop_map(Value::from), number(), text().prop_map(Value::from)];
+    scalar
+        .prop_recursive(3, 16, 4, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..4).prop_map(Value::from),
+                btree_map("[A-Za-z]{1,8}", inner, 0..4).prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+        .boxed()
+}
+
+fn one_of(values: &'static [&'static str]) -> BoxedStrategy<Value> {
+    prop_oneof![4 => proptest::sample::select(values).prop_map(Value::from), 1 => text().prop_map(Value::from)].boxed()
+}
+
+fn list(item: BoxedStrategy<Value>) -> BoxedStrategy<Value> {
+    vec(item, 0..3).prop_map(Value::from).boxed()
+}
+
+// Every key is left out, set to a plausible value or, rarely since it likely fails the whole
+// parse, to anything at all. About half of the generated documents parse.
+fn object(fields: Vec<(&'static str, BoxedStrategy<Value>)>) -> BoxedStrategy<Value> {
+    let entries: Vec<_> = fields
+        .into_iter()
+        .map(|(key, plausible)| {
+            let absent = if REQUIRED.contains(&key) { 1 } else { 30 };
+            prop_oneof![absent => Just(None), 150 => plausible.prop_map(Some), 1 => any_value().prop_map(Some)]
+                .prop_map(move |value| value.map(|value| (key.to_string(), value)))
+        })
+        .collect();
+    entries.prop_map(|entries| Value::Object(entries.into_iter().flatten().collect::<Map<_, _>>())).boxed()
+}
+
+const REQUIRED: [&str; 6] = ["DockerId", "Labels", "com.amazonaws.ecs.task-arn", "NetworkMode", "ContainerPort", "status"];
+
+fn container() -> BoxedStrategy<Value> {
+    let string = || text().prop_map(Value::from).boxed();
+    let labels = object(vec![
+        ("com.amazonaws.ecs.cluster", string()),
+        ("com.amazonaws.ecs.container-name", string()),
+        ("com.amazonaws.ecs.task-arn", string()),
+        ("com.amazonaws.ecs.task-definition-family", string()),
+        ("com.amazonaws.ecs.task-definition-version", string()),
+    ]);
+    let limits = object(vec![("CPU", number()), ("Memory", number()), ("GPU", number())]);
+    let network = object(vec![
+        ("AttachmentIndex", number()),
+        ("NetworkMode", one_of(&["awsvpc", "bridge", "host", "none"])),
+        ("IPv4Addresses", list(one_of(&["10.0.2.106", "127.0.0.1", "0.0.0.0"]))),
+        ("IPv6Addresses", list(one_of(&["2001:db8::1", "fe80::1%eth0", "::"]))),
+        ("MACAddress", string()),
+        ("DomainNameServers", list(string())),
+        ("DomainNameSearchList", list(string())),
+        ("PrivateDNSName", string()),
+        ("IPv4SubnetCIDRBlock", string()),
+        ("SubnetGatewayIpv4Address", one_of(&["10.0.2.1/24", "10.0.2.1", "/", ""])),
+    ]);
+    let port = object(vec![
+        ("ContainerPort", number()),
+        ("Protocol", one_of(&["tcp", "udp"])),
+        ("HostPort", number()),
+        ("HostIp", one_of(&["0.0.0.0", "::", "192.168.1.7", "10.0.0.1"])),
+    ]);
+    let health = object(vec![
+        ("status", one_of(&["HEALTHY", "UNHEALTHY", "UNKNOWN"])),
+        ("statusSince", string()),
+        ("exitCode", number()),
+        ("output", string()),
+    ]);
+    object(vec![
+        ("DockerId", string()),
+        ("Name", string()),
+        ("Image", string()),
+        ("Labels", labels),
+        ("Limits", limits),
+        ("Networks", list(network)),
+        ("Ports", list(port)),
+        ("Health", health),
+        ("Type", one_of(&["NORMAL", "CNI_PAUSE", "EMPTY_HOST_VOLUME"])),
+        ("KnownStatus", one_of(&["PENDING", "RUNNING", "STOPPED"])),
+        ("DesiredStatus", one_of(&["RUNNING", "STOPPED"])),
+        ("LogDriver", one_of(&["awslogs", "splunk", "awsfirelens"])),
+        ("LogOptions", object(vec![("splunk-token", string()), ("awslogs-group", string()), ("Header", string())])),
+    ])
+}
+
+fn task() -> BoxedStrategy<Value> {
+    let limits = object(vec![
+        ("CPU", prop_oneof![number(), one_of(&["0.25 vCPU", "256", "1 vcpu"])].boxed()),
+        ("Memory", prop_oneof![number(), one_of(&["512", "2 GB", "0.5gb"])].boxed()),
+    ]);
+    object(vec![
+        ("AvailabilityZone", text().prop_map(Value::from).boxed()),
+        ("LaunchType", one_of(&["EC2", "FARGATE", "EXTERNAL"])),
+        ("Limits", limits),
+        ("Containers", vec(container(), 0..4).prop_map(Value::from).boxed()),
+    ])
+}
+
+fn exercise_container(container: &ECSContainerMetadata, probe: &str, port: u16) {
+    let convention = TagConvention::new("{version}-{environment}").unwrap();
+    let _ = (container.docker_id(), container.image(), container.image_tag(), container.image_tag_captures(&convention));
+    let _ = (container.image_tag_components("-"), container.image_tag_components(""), container.image_tag_components(probe));
+    let _ = (container.image_registry_account(), container.image_registry_region(), container.container_name());
+    let _ = (container.cluster(), container.cluster_name(), container.task_arn());
+    let _ = (container.task_definition_family(), container.task_definition_revision());
+    let limits = container.limits();
+    let _ = (limits.limit("CPU"), limits.limit("GPU"), limits.limit(probe), limits.cpu_as_k8s_quantity(), limits.memory_as_k8s_quantity(), limits.memory_limit(), limits.cpu_millicores(), limits.memory_bytes());
+    for network in container.networks() {
+        let _ = (network.attachment_index(), network.network_mode(), network.ipv4_addresses(), network.ipv6_addresses());
+        let _ = (network.mac_address(), network.domain_name_servers(), network.dns_search_domains());
+        let _ = (network.network_mode_kind(), network.private_ipv4(), network.private_ipv6(), network.private_dns_name(), network.ipv4_subnet_cidr_block(), network.ipv6_subnet_cidr_block(), network.subnet_gateway_ipv4_address(), network.subnet_gateway());
+    }
+    for mapping in container.ports() {
+        let _ = (mapping.container_port(), mapping.protocol(), mapping.host_port(), mapping.host_ip());
+    }
+    let _ = (container.network_mode(), container.primary_network(), container.networks_by_mode("awsvpc").count(), container.ipv4_addresses().count());
+    let _ = (container.host_ip(), container.advertised_address(port));
+    let _ = (container.private_ipv4(), container.private_ipv6(), container.private_dns_name(), container.mac_address());
+    if let Some(health) = container.health() {
+        let _ = (health.status(), health.status_since(), health.exit_code(), health.output(), health.status_kind(), health.is_healthy(), health.status_changed_at());
+    }
+    let _ = (container.health_output(), container.health_status_since());
+    let _ = (container.name(), container.image_id(), container.container_type(), container.known_status(), container.desired_status(), container.created_at(), container.started_at(), container.restart_count(), container.is_normal());
+    let _ = (container.known_status_kind(), container.desired_status_kind(), container.is_stopping(), container.is_stopped(), container.is_healthy());
+    let _ = (container.log_driver(), container.log_options(), container.log_options_redacted());
+}
+
+fn exercise_task(task: &ECSTaskMetadata, probe: &str) {
+    let _ = (task.cluster(), task.task_arn(), task.known_status(), task.family(), task.revision(), task.availability_zone(), task.launch_type(), task.launch_type_kind(), task.desired_status(), task.stop_code(), task.warnings(), task.aggregate_container_limits(), task.headroom(), task.startup_timeline(), task.slowest_to_start());
+    if let Some(limits) = task.limits() {
+        let _ = (limits.vcpus(), limits.cpu_units(), limits.memory_mib(), limits.cpu_as_k8s_quantity(), limits.memory_as_k8s_quantity(), limits.memory_limit(), limits.cpu_millicores(), limits.memory_bytes());
+    }
+    let _ = (task.network_mode(), task.normal_containers().count(), task.running_containers().count(), task.is_sole_application_container(probe));
+    let _ = (task.container_by_docker_id(probe), task.container_by_name(probe), task.self_container(probe, probe), task.sibling_containers(probe, "").count());
+    for container in task.containers() {
+        let _ = (task.container_by_docker_id(container.docker_id()), task.container_by_name(container.container_name()));
+        exercise_container(container, probe, 80);
+    }
+}
+
+fn exercise(metadata: &ECSMetadata, probe: &str, len: usize, port: u16) {
+    exercise_container(metadata.container(), probe, port);
+    if let Some(task) = metadata.task() {
+        exercise_task(task, probe);
+    }
+    let _ = (metadata.task_arn(), metadata.task_id(), metadata.task_id_short(len), metadata.region(), metadata.availability_zone(), metadata.launch_type(), metadata.is_fargate());
+    let _ = (metadata.parsed_task_arn(), metadata.parsed_container_arn(), metadata.account_id(), metadata.to_json(), metadata.raw());
+    let _ = (metadata.cluster(), metadata.cluster_name(), metadata.limits(), metadata.task_limits(), metadata.networks(), metadata.primary_network());
+    let _ = (metadata.networks_by_mode("bridge").count(), metadata.ipv4_addresses().count(), metadata.ports());
+    let _ = (metadata.host_ip(), metadata.advertised_address(port), metadata.is_sole_application_container());
+    let _ = (metadata.private_ipv4(), metadata.private_ipv6(), metadata.private_dns_name(), metadata.mac_address());
+    let _ = (metadata.health(), metadata.health_output(), metadata.health_status_since(), metadata.is_healthy());
+    let _ = (metadata.known_status_kind(), metadata.desired_status_kind(), metadata.is_stopping(), metadata.is_stopped());
+    let _ = (metadata.fetched_at(), metadata.age(), metadata.is_stale());
+    let _ = (metadata.effective_memory_limit_mib(), metadata.memory_limit(), metadata.effective_cpu_limit_vcpus(), metadata.docker_id(), metadata.image());
+    let _ = (metadata.image_tag(), metadata.image_tag_components("-"), metadata.image_registry_account(), metadata.image_registry_region());
+    let _ = (metadata.task_definition_family(), metadata.task_definition_revision(), metadata.container_name());
+    let _ = (metadata.task_definition(), metadata.consistency_check());
+    let _ = (metadata.container_insights_log_group(), metadata.container_insights_stream_name(), metadata.as_fields(), metadata.select_fields(&["ecs.task.id"], &crate::FieldOptions::default()));
+    let _ = metadata.as_fields_with(&crate::FieldOptions { short_task_id: true });
+    let _ = (metadata.trace_annotations(), metadata.resource_attributes(), metadata.name(), metadata.image_id(), metadata.container_type(), metadata.known_status(), metadata.desired_status(), metadata.created_at(), metadata.started_at(), metadata.container_arn(), metadata.log_driver(), metadata.log_options(), metadata.log_options_redacted());
+    let _ = (metadata.self_container(), metadata.sibling_containers().count());
+    let _ = (metadata.recommended_worker_count(len, port as f64 / 7.0), metadata.recommended_worker_count(0, f64::MAX));
+    let _ = (metadata.to_flat_record(None), metadata.observability_bundle(None), metadata.to_describe_tasks_like(), metadata.warnings(), metadata.is_degraded());
+    let all = BannerOptions { limits: true, image: true, availability_zone: true, region: true, short_task_id: false };
+    let _ = (metadata.startup_banner(&all), metadata.startup_banner(&BannerOptions::default()));
+    let _ = (metadata.diff(&ECSMetadata::degraded(None)), ECSMetadata::degraded(None).diff(metadata), metadata.transitions(&ECSMetadata::degraded(None)));
+    serde_json::to_string(metadata).expect("a parsed snapshot always serializes");
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn test_accessors_never_panic(
+        container in container(),
+        task in proptest::option::of(task()),
+        probe in text(),
+        len in any::<usize>(),
+        port in any::<u16>(),
+    ) {
+        let container = serde_json::to_vec(&container).unwrap();
+        let task = task.map(|task| serde_json::to_vec(&task).unwrap());
+        if let Ok(mut metadata) = ECSMetadata::from_documents(container.clone(), task.clone(), None) {
+            exercise(&metadata, &probe, len, port);
+            let restored = ECSMetadata::from_cache_bytes(&metadata.to_cache_bytes()).expect("a snapshot always restores");
+            prop_assert_eq!(&restored, &metadata);
+            // the same documents again, and ones that differ
+            let _ = metadata.refresh_from_json(&container, task.as_deref());
+            let _ = metadata.refresh_from_json(b"{}", None);
+            exercise(&metadata, &probe, len, port);
+        }
+        if let Some(Ok(task)) = task.map(|task| serde_json::from_slice::<ECSTaskMetadata>(&task)) {
+            exercise_task(&task, &probe);
+        }
+    }
+
+    #[test]
+    fn test_tag_conventions_never_panic(pattern in "[a-z{}_.-]{0,16}", tag in text()) {
+        if let Ok(convention) = TagConvention::new(&pattern) {
+            let _ = convention.captures(&tag);
+        }
+    }
+}
+
+// Shapes that once were, or easily could be, a slice or split away from a panic
+#[test]
+fn test_edge_documents() {
+    for container in [
+        r#"{"DockerId": "", "Labels": {"com.amazonaws.ecs.task-arn": ""}}"#,
+        r#"{"DockerId": "x", "Labels": {"com.amazonaws.ecs.task-arn": "/"}}"#,
+        r#"{"DockerId": "x", "Labels": {"com.amazonaws.ecs.task-arn": "arn:aws:ecs:::task/"}}"#,
+        r#"{"DockerId": "x", "Labels": {"com.amazonaws.ecs.task-arn": ":task//"}}"#,
+        r#"{"DockerId": "x", "Image": ":", "Labels": {"com.amazonaws.ecs.task-arn": "€/€"}}"#,
+        r#"{"DockerId": "x", "Image": "@", "Labels": {"com.amazonaws.ecs.task-arn": "-"}, "Ports": [{"ContainerPort": 0, "HostIp": ""}]}"#,
+    ] {
+        let metadata = ECSMetadata::from_json(container).unwrap();
+        for len in [0, 1, 8, usize::MAX] {
+            exercise(&metadata, "", len, 0);
+        }
+    }
+
+    // an ARN ending in a slash has no task ID, rather than an empty one
+    let trailing_slash = r#"{"DockerId": "x", "Labels": {"com.amazonaws.ecs.task-arn": "arn:aws:ecs:::task/", "com.amazonaws.ecs.container-name": "app"}}"#;
+    let metadata = ECSMetadata::from_json(trailing_slash).unwrap();
+    assert_eq!((metadata.task_id(), metadata.container_insights_stream_name()), (None, None));
+}
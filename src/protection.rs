@@ -0,0 +1,258 @@
+use std::time::SystemTime;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use crate::client::ECSMetadataBuilder;
+use crate::error::{parse_document, ECSMetadataError};
+use crate::timestamp::parse_rfc3339;
+
+const TASK_PROTECTION_PATH: &str = "task-protection/v1/state";
+/// Bounds of `ExpiresInMinutes` in the ECS API, 48 hours at most
+const PROTECTION_EXPIRY_MINUTES: std::ops::RangeInclusive<u32> = 1..=2880;
+const THROTTLING_CODE: &str = "ThrottlingException";
+
+/// Scale-in protection state of the task, as returned by the agent
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ECSTaskProtection {
+    protection_enabled: bool,
+    #[serde(default)]
+    expiration_date: Option<String>,
+    #[serde(default)]
+    task_arn: Option<String>,
+}
+
+impl ECSTaskProtection {
+    pub fn protection_enabled(&self) -> bool {
+        self.protection_enabled
+    }
+
+    /// RFC 3339, only set while the protection is enabled
+    pub fn expiration_date(&self) -> Option<&str> {
+        self.expiration_date.as_deref()
+    }
+
+    /// `expiration_date` parsed, `None` when missing or malformed
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        parse_rfc3339(self.expiration_date.as_deref()?)
+    }
+
+    pub fn task_arn(&self) -> Option<&str> {
+        self.task_arn.as_deref()
+    }
+}
+
+/// Body of the `PUT`, ECS defaults the expiry to 2 hours when it is left out
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ProtectionRequest {
+    protection_enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in_minutes: Option<u32>,
+}
+
+// One of the three is set: the state, a failure of the ECS API call, or an error of the agent
+// or the ECS API
+#[derive(Deserialize, Debug)]
+struct ProtectionResponse {
+    #[serde(default)]
+    protection: Option<ECSTaskProtection>,
+    #[serde(default)]
+    failure: Option<ProtectionFailure>,
+    #[serde(default)]
+    error: Option<ProtectionError>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ProtectionFailure {
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    detail: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ProtectionError {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl ECSMetadataBuilder {
+    /// Scale-in protection state of the task, from the agent at `ECS_AGENT_URI` (see
+    /// `agent_endpoint`) under the protection policy. Needs `ecs:GetTaskProtection` on the task
+    /// role.
+    pub async fn get_protection_state(&self) -> Result<ECSTaskProtection, ECSMetadataError> {
+        let (status, body) = self.send_to_agent(Method::GET, self.agent_url(TASK_PROTECTION_PATH)?, None).await?;
+        protection_response(status, &body)
+    }
+
+    /// Enables or disables the scale-in protection of the task, enabled for `expires_in_minutes`
+    /// (1 to 2880, 120 when `None`), and returns the new state. Needs `ecs:UpdateTaskProtection`
+    /// on the task role. An expiry out of bounds fails with `InvalidProtectionExpiry` before
+    /// anything is sent, throttling with `ProtectionThrottled`.
+    pub async fn set_protection(&self, enabled: bool, expires_in_minutes: Option<u32>) -> Result<ECSTaskProtection, ECSMetadataError> {
+        if let Some(minutes) = expires_in_minutes.filter(|minutes| !PROTECTION_EXPIRY_MINUTES.contains(minutes)) {
+            return Err(ECSMetadataError::InvalidProtectionExpiry(minutes));
+        }
+        let request = ProtectionRequest { protection_enabled: enabled, expires_in_minutes };
+        let url = self.agent_url(TASK_PROTECTION_PATH)?;
+        let (status, body) = self.send_to_agent(Method::PUT, url, Some(serde_json::to_vec(&request)?)).await?;
+        protection_response(status, &body)
+    }
+
+    /// Alias of `get_protection_state`
+    pub async fn get_task_protection(&self) -> Result<ECSTaskProtection, ECSMetadataError> {
+        self.get_protection_state().await
+    }
+
+    /// Alias of `set_protection`
+    pub async fn set_task_protection(&self, enabled: bool, expires_in_minutes: Option<u32>) -> Result<ECSTaskProtection, ECSMetadataError> {
+        self.set_protection(enabled, expires_in_minutes).await
+    }
+}
+
+fn protection_response(status: StatusCode, body: &[u8]) -> Result<ECSTaskProtection, ECSMetadataError> {
+    let response = match parse_document::<ProtectionResponse>(body) {
+        Ok(response) => response,
+        // e.g. the plain text 404 of an agent predating task protection
+        Err(_) if status == StatusCode::TOO_MANY_REQUESTS => return Err(ECSMetadataError::ProtectionThrottled(text(body))),
+        Err(_) if !status.is_success() => {
+            return Err(ECSMetadataError::ProtectionRejected { code: status.to_string(), message: text(body) })
+        }
+        Err(err) => return Err(err),
+    };
+    if let Some(error) = response.error {
+        let message = error.message.unwrap_or_default();
+        return Err(match error.code {
+            Some(code) if code == THROTTLING_CODE => ECSMetadataError::ProtectionThrottled(message),
+            code => ECSMetadataError::ProtectionRejected { code: code.unwrap_or_else(|| status.to_string()), message },
+        });
+    }
+    if let Some(failure) = response.failure {
+        return Err(ECSMetadataError::ProtectionRejected {
+            code: failure.reason.unwrap_or_else(|| status.to_string()),
+            message: failure.detail.unwrap_or_default(),
+        });
+    }
+    match response.protection {
+        Some(protection) if status.is_success() => Ok(protection),
+        Some(_) => Err(ECSMetadataError::ProtectionRejected { code: status.to_string(), message: text(body) }),
+        None => Err(<serde_json::Error as serde::de::Error>::missing_field("protection").into()),
+    }
+}
+
+fn text(body: &[u8]) -> String {
+    String::from_utf8_lossy(body).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RequestPolicy;
+    use crate::error::Phase;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    const STATE_PATH: &str = "/task-protection/v1/state";
+    const TASK_ARN: &str = "arn:aws:ecs:us-west-2:111122223333:task/default/1234567890abcdef0";
+
+    fn builder(agent: &MockAgent) -> ECSMetadataBuilder {
+        ECSMetadataBuilder::new().agent_endpoint(agent.url("/"))
+    }
+
+    #[tokio::test]
+    async fn test_get_protection_state() {
+        let agent = MockAgent::start().await;
+        let state = format!(r#"{{"protection": {{"ExpirationDate": "2023-12-20T21:57:44Z", "ProtectionEnabled": true, "TaskArn": "{TASK_ARN}"}}}}"#);
+        agent.set(STATE_PATH, MockResponse::json(state));
+        let protection = builder(&agent).get_protection_state().await.unwrap();
+        assert!(protection.protection_enabled());
+        assert_eq!(protection.task_arn(), Some(TASK_ARN));
+        assert_eq!(protection.expires_at(), parse_rfc3339("2023-12-20T21:57:44Z"));
+
+        agent.set(STATE_PATH, MockResponse::json(r#"{"protection": {"ExpirationDate": null, "ProtectionEnabled": false}}"#));
+        let protection = builder(&agent).get_protection_state().await.unwrap();
+        assert!(!protection.protection_enabled());
+        assert_eq!(protection.expires_at(), None);
+        assert_eq!(agent.requests(STATE_PATH)[0].method, "GET");
+    }
+
+    #[tokio::test]
+    async fn test_set_protection() {
+        let agent = MockAgent::start().await;
+        let state = format!(r#"{{"protection": {{"ExpirationDate": "2023-12-20T21:57:44Z", "ProtectionEnabled": true, "TaskArn": "{TASK_ARN}"}}}}"#);
+        agent.set(STATE_PATH, MockResponse::json(state));
+        assert!(builder(&agent).set_protection(true, Some(60)).await.unwrap().protection_enabled());
+        builder(&agent).set_protection(false, None).await.unwrap();
+
+        let requests = agent.requests(STATE_PATH);
+        assert_eq!(requests[0].method, "PUT");
+        let bodies = requests.iter().map(|request| serde_json::from_slice(&request.body).unwrap()).collect::<Vec<serde_json::Value>>();
+        assert_eq!(bodies[0], serde_json::json!({"ProtectionEnabled": true, "ExpiresInMinutes": 60}));
+        assert_eq!(bodies[1], serde_json::json!({"ProtectionEnabled": false}));
+
+        // the aliases
+        assert_eq!(builder(&agent).get_task_protection().await.unwrap(), builder(&agent).set_task_protection(true, Some(60)).await.unwrap());
+        assert_eq!(agent.requests(STATE_PATH).iter().map(|request| request.method.as_str()).collect::<Vec<_>>(), ["PUT", "PUT", "GET", "PUT"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_expiry_is_not_sent() {
+        let agent = MockAgent::start().await;
+        for minutes in [0, 2881] {
+            let err = builder(&agent).set_protection(true, Some(minutes)).await.unwrap_err();
+            assert!(matches!(err, ECSMetadataError::InvalidProtectionExpiry(found) if found == minutes), "{err:?}");
+            assert_eq!(err.phase(), Phase::Validate);
+        }
+        assert_eq!(agent.total_hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failure_modes() {
+        let agent = MockAgent::start().await;
+        let protection = builder(&agent);
+
+        agent.set(STATE_PATH, MockResponse::status(400, r#"{"error": {"Arn": "arn", "Code": "ThrottlingException", "Message": "Rate exceeded"}}"#));
+        let err = protection.set_protection(true, Some(60)).await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::ProtectionThrottled(message) if message == "Rate exceeded"), "{err:?}");
+        assert_eq!(err.phase(), Phase::Status);
+        agent.set(STATE_PATH, MockResponse::status(429, "Too many requests"));
+        assert!(matches!(protection.get_protection_state().await, Err(ECSMetadataError::ProtectionThrottled(_))));
+
+        let denied = r#"{"error": {"Code": "AccessDeniedException", "Message": "not authorized to perform ecs:UpdateTaskProtection"}}"#;
+        agent.set(STATE_PATH, MockResponse::status(400, denied));
+        let err = protection.set_protection(true, None).await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::ProtectionRejected { code, .. } if code == "AccessDeniedException"), "{err:?}");
+
+        agent.set(STATE_PATH, MockResponse::json(r#"{"failure": {"Arn": "arn", "Detail": null, "Reason": "TASK_NOT_VALID"}}"#));
+        let err = protection.get_protection_state().await.unwrap_err();
+        assert!(matches!(&err, ECSMetadataError::ProtectionRejected { code, message } if code == "TASK_NOT_VALID" && message.is_empty()));
+
+        // an agent without task protection
+        agent.set(STATE_PATH, MockResponse::status(404, "404 page not found\n"));
+        let err = protection.get_protection_state().await.unwrap_err();
+        assert_eq!(err.to_string(), "[phase=status] Task protection request rejected with 404 Not Found: 404 page not found");
+
+        agent.set(STATE_PATH, MockResponse::json("{}"));
+        assert!(matches!(protection.get_protection_state().await, Err(ECSMetadataError::ParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let agent = MockAgent::start().await;
+        agent.set(STATE_PATH, MockResponse::status(500, "agent restarting"));
+        let protection = builder(&agent).protection_policy(RequestPolicy::new(None, 2));
+        assert!(matches!(protection.get_protection_state().await, Err(ECSMetadataError::ProtectionRejected { .. })));
+        assert_eq!(agent.hits(STATE_PATH), 3);
+    }
+
+    #[tokio::test]
+    async fn test_agent_endpoint() {
+        let err = ECSMetadataBuilder::new().env_lookup(false).get_protection_state().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::EndpointNotConfigured));
+        let err = ECSMetadataBuilder::new().agent_endpoint("http://agent.internal").get_protection_state().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::InvalidEndpoint { .. }));
+    }
+}
@@ -0,0 +1,47 @@
+// Kubernetes resource quantities, for platforms running both ECS and EKS
+
+/// CPU as a Kubernetes quantity: whole vCPUs as a plain number (`2`), anything else in
+/// millicores rounded to the nearest one (`0.25` is `250m`, `1.5` is `1500m`)
+pub(crate) fn cpu_quantity(vcpus: f64) -> String {
+    let millicores = millicores(vcpus);
+    match millicores % 1000 {
+        0 => (millicores / 1000).to_string(),
+        _ => format!("{millicores}m"),
+    }
+}
+
+/// vCPUs in millicores, rounded to the nearest one
+pub(crate) fn millicores(vcpus: f64) -> u64 {
+    (vcpus * 1000.0).round() as u64
+}
+
+/// Memory as a Kubernetes quantity, always in `Mi` (4096 MiB is `4096Mi`, not `4Gi`): lossless,
+/// and one unit keeps the strings comparable without parsing them
+pub(crate) fn memory_quantity(mib: u64) -> String {
+    format!("{mib}Mi")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_quantity() {
+        assert_eq!(cpu_quantity(0.25), "250m");
+        assert_eq!(cpu_quantity(0.5), "500m");
+        assert_eq!(cpu_quantity(1.0), "1");
+        assert_eq!(cpu_quantity(1.5), "1500m");
+        assert_eq!(cpu_quantity(2.0), "2");
+        assert_eq!(cpu_quantity(16.0), "16");
+        // 1 CPU unit, below a millicore's precision
+        assert_eq!(cpu_quantity(1.0 / 1024.0), "1m");
+        assert_eq!(cpu_quantity(0.0), "0");
+    }
+
+    #[test]
+    fn test_memory_quantity() {
+        assert_eq!(memory_quantity(512), "512Mi");
+        assert_eq!(memory_quantity(4096), "4096Mi");
+        assert_eq!(memory_quantity(1), "1Mi");
+    }
+}
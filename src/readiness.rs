@@ -0,0 +1,176 @@
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use crate::client::ECSMetadataBuilder;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+use crate::shared::SharedECSMetadata;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// `ECSMetadataBuilder::background_init` with the default builder
+pub fn background_init() -> ReadinessHandle {
+    ECSMetadata::builder().background_init()
+}
+
+/// Handle to a background initialization, cheap to clone, e.g. into a readiness probe handler
+#[derive(Debug, Clone)]
+pub struct ReadinessHandle {
+    state: watch::Receiver<ReadinessState>,
+}
+
+#[derive(Debug, Default)]
+struct ReadinessState {
+    metadata: Option<SharedECSMetadata>,
+    last_error: Option<ECSMetadataError>,
+    gave_up: bool,
+}
+
+impl ReadinessHandle {
+    /// True once the metadata is loaded
+    pub fn is_ready(&self) -> bool {
+        self.state.borrow().metadata.is_some()
+    }
+
+    /// The metadata, `None` until loaded
+    pub fn get(&self) -> Option<SharedECSMetadata> {
+        self.state.borrow().metadata.clone()
+    }
+
+    /// Error of the latest failed attempt, also after a later success
+    pub fn last_error(&self) -> Option<ECSMetadataError> {
+        self.state.borrow().last_error.clone()
+    }
+
+    /// True if the initialization stopped retrying without loading the metadata
+    pub fn has_given_up(&self) -> bool {
+        self.state.borrow().gave_up
+    }
+
+    /// Waits until the metadata is loaded, or fails with the last error once the initialization
+    /// gives up
+    pub async fn wait_ready(&self) -> Result<SharedECSMetadata, ECSMetadataError> {
+        let mut state = self.state.clone();
+        // only fails when the task is gone, e.g. with the runtime, the state is final then
+        let _ = state.wait_for(|state| state.metadata.is_some() || state.gave_up).await;
+        let state = state.borrow();
+        match (&state.metadata, &state.last_error) {
+            (Some(metadata), _) => Ok(metadata.clone()),
+            (None, Some(err)) => Err(err.clone()),
+            (None, None) => Err(ECSMetadataError::InitAborted),
+        }
+    }
+}
+
+impl ECSMetadataBuilder {
+    /// Starts `init` on a tokio task and returns right away, e.g. to serve "not ready" from a
+    /// readiness probe instead of delaying the server start. Failed attempts are retried with
+    /// an exponential backoff (100ms doubling up to 10s) until one succeeds or `give_up_after`
+    /// passes; an unset env var or a refused endpoint is not retried. Attempts stop early once
+    /// every handle is dropped. Must be called within a tokio runtime without the
+    /// `runtime-agnostic` feature.
+    pub fn background_init(self) -> ReadinessHandle {
+        let (sender, state) = watch::channel(ReadinessState::default());
+        crate::runtime::spawn(run(self, sender));
+        ReadinessHandle { state }
+    }
+}
+
+async fn run(builder: ECSMetadataBuilder, state: watch::Sender<ReadinessState>) {
+    let give_up_at = builder.give_up_after.map(|after| Instant::now() + after);
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        // the deadline also bounds an attempt that hangs, e.g. without a request timeout
+        let attempt = builder.clone().init();
+        let result = match give_up_at {
+            Some(give_up_at) => tokio::time::timeout_at(give_up_at, attempt).await,
+            None => Ok(attempt.await),
+        };
+        let err = match result {
+            Ok(Ok(metadata)) => {
+                state.send_modify(|state| state.metadata = Some(metadata.into()));
+                return;
+            }
+            Ok(Err(err)) => err,
+            Err(_) => {
+                // the error of the previous attempt says more than the timeout
+                state.send_modify(|state| {
+                    state.last_error.get_or_insert(ECSMetadataError::InitAborted);
+                    state.gave_up = true;
+                });
+                return;
+            }
+        };
+
+        let retry_at = Instant::now() + backoff;
+        let permanent = matches!(err, ECSMetadataError::EnvVarNotSet { .. } | ECSMetadataError::EndpointNotConfigured | ECSMetadataError::InvalidEndpoint { .. });
+        let gave_up = permanent || give_up_at.is_some_and(|give_up_at| retry_at >= give_up_at);
+        state.send_modify(|state| {
+            state.last_error = Some(err);
+            state.gave_up = gave_up;
+        });
+        if gave_up || state.is_closed() {
+            return;
+        }
+        tokio::time::sleep_until(retry_at).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RequestPolicy;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    #[tokio::test]
+    async fn test_ready_after_retries() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::status(503, "starting"));
+        let handle = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).metadata_policy(RequestPolicy::new(None, 0)).background_init();
+        let probe = handle.clone();
+
+        while agent.hits("/v4/abc") < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!probe.is_ready());
+        assert!(probe.get().is_none());
+        assert!(matches!(probe.last_error(), Some(ECSMetadataError::HttpStatus { status: 503, .. })));
+
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = handle.wait_ready().await.unwrap();
+        assert_eq!(metadata.snapshot().container_name(), "streamer");
+        assert!(probe.is_ready());
+        assert!(!probe.has_given_up());
+        assert_eq!(probe.get().unwrap().snapshot().task_arn(), metadata.snapshot().task_arn());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_deadline() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::status(500, "down"));
+        let handle = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            // one fetch per attempt
+            .metadata_policy(RequestPolicy::new(None, 0))
+            .give_up_after(Duration::from_secs(1))
+            .background_init();
+
+        let err = handle.wait_ready().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::HttpStatus { status: 500, .. }), "{err:?}");
+        assert!(handle.has_given_up() && !handle.is_ready());
+        assert!(matches!(handle.last_error(), Some(ECSMetadataError::HttpStatus { .. })));
+        // at 0ms, 100ms, 300ms and 700ms, the next one would be past the deadline
+        assert!((1..=4).contains(&agent.hits("/v4/abc")));
+    }
+
+    #[tokio::test]
+    async fn test_refused_endpoint_is_not_retried() {
+        let handle = ECSMetadata::builder().endpoint("http://example.com/v4/abc").background_init();
+        let err = handle.wait_ready().await.unwrap_err();
+        assert!(matches!(err, ECSMetadataError::InvalidEndpoint { .. }), "{err:?}");
+        assert!(handle.has_given_up());
+    }
+}
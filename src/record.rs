@@ -0,0 +1,203 @@
+use serde::Serialize;
+use crate::image;
+use crate::metadata::ECSMetadata;
+use crate::task::ECSTaskMetadata;
+
+/// One flat row per container for analytics pipelines. The serialized field names are part of
+/// the API: they only change with a major release.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSFlatRecord {
+    pub cluster: Option<String>,
+    pub region: Option<String>,
+    pub account: Option<String>,
+    pub task_id: Option<String>,
+    pub family: Option<String>,
+    pub revision: Option<String>,
+    pub container: Option<String>,
+    /// Image without tag or digest, registry included
+    pub image_repo: Option<String>,
+    pub image_tag: Option<String>,
+    /// Effective vCPU limit, see `ECSMetadata::effective_cpu_limit_vcpus`
+    pub cpu: Option<f64>,
+    /// Effective memory limit, see `ECSMetadata::effective_memory_limit_mib`
+    pub memory_mib: Option<u64>,
+    pub az: Option<String>,
+    pub launch_type: Option<String>,
+}
+
+impl ECSMetadata {
+    /// Flattens the container metadata and the task document into one record. `task` takes
+    /// precedence over the task document fetched with `init_with_task`, e.g. for a task document
+    /// fetched separately. A degraded instance gives an empty record.
+    pub fn to_flat_record(&self, task: Option<&ECSTaskMetadata>) -> ECSFlatRecord {
+        if self.is_degraded() {
+            return ECSFlatRecord::default();
+        }
+        let task = task.or(self.task());
+        let (image_repo, image_tag) = image::split_image(self.image());
+        ECSFlatRecord {
+            cluster: self.cluster_name().map(ToString::to_string),
+            region: self.region().map(ToString::to_string),
+            account: self.account_id().map(ToString::to_string),
+            task_id: self.task_id(),
+            family: non_empty(self.task_definition_family()),
+            revision: non_empty(self.task_definition_revision()),
+            container: non_empty(self.container_name()),
+            image_repo: non_empty(image_repo),
+            image_tag: image_tag.and_then(non_empty),
+            cpu: self.effective_cpu_limit_vcpus_with(task),
+            memory_mib: self.effective_memory_limit_mib_with(task),
+            az: task.and_then(ECSTaskMetadata::availability_zone).map(ToString::to_string),
+            launch_type: task.and_then(ECSTaskMetadata::launch_type).map(ToString::to_string),
+        }
+    }
+}
+
+/// What an observability SDK tags its signals with, from `ECSMetadata::observability_bundle`.
+/// The fields of `ECSFlatRecord` plus the task ARN and short ID, with the task definition taken
+/// from the task document when there is one.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ObservabilityBundle {
+    pub cluster: Option<String>,
+    pub region: Option<String>,
+    pub account: Option<String>,
+    pub task_arn: Option<String>,
+    pub task_id: Option<String>,
+    /// `ECSMetadata::task_id_short` with `SHORT_TASK_ID_LEN`
+    pub task_id_short: Option<String>,
+    pub family: Option<String>,
+    pub revision: Option<String>,
+    pub container: Option<String>,
+    /// Image without tag or digest, registry included
+    pub image_repo: Option<String>,
+    pub image_tag: Option<String>,
+    pub az: Option<String>,
+    pub launch_type: Option<String>,
+    /// Effective vCPU limit, see `ECSMetadata::effective_cpu_limit_vcpus`
+    pub cpu: Option<f64>,
+    /// Effective memory limit, see `ECSMetadata::effective_memory_limit_mib`
+    pub memory_mib: Option<u64>,
+}
+
+impl ECSMetadata {
+    /// Every field of `ObservabilityBundle` in one call. `task` takes precedence over the task
+    /// document fetched with `init_with_task`, like for `to_flat_record`. A degraded instance
+    /// gives an empty bundle.
+    pub fn observability_bundle(&self, task: Option<&ECSTaskMetadata>) -> ObservabilityBundle {
+        if self.is_degraded() {
+            return ObservabilityBundle::default();
+        }
+        let record = self.to_flat_record(task);
+        let task = task.or(self.task());
+        ObservabilityBundle {
+            cluster: record.cluster,
+            region: record.region,
+            account: record.account,
+            task_arn: non_empty(self.task_arn()),
+            task_id: record.task_id,
+            task_id_short: self.task_id_short(ECSMetadata::SHORT_TASK_ID_LEN),
+            // the labels lag behind the task document during an agent upgrade
+            family: task.and_then(ECSTaskMetadata::family).and_then(non_empty).or(record.family),
+            revision: task.and_then(ECSTaskMetadata::revision).and_then(non_empty).or(record.revision),
+            container: record.container,
+            image_repo: record.image_repo,
+            image_tag: record.image_tag,
+            az: record.az,
+            launch_type: record.launch_type,
+            cpu: record.cpu,
+            memory_mib: record.memory_mib,
+        }
+    }
+}
+
+impl From<&ECSMetadata> for ECSFlatRecord {
+    fn from(metadata: &ECSMetadata) -> Self {
+        metadata.to_flat_record(None)
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    Some(value).filter(|value| !value.is_empty()).map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    const TASK_JSON: &str = r#"{"AvailabilityZone": "us-east-1b", "LaunchType": "FARGATE", "Limits": {"CPU": 1, "Memory": 2048}, "Containers": []}"#;
+
+    #[test]
+    fn test_flat_record_snapshot() {
+        let metadata = metadata_from_json(CONTAINER_JSON, Some(TASK_JSON));
+        assert_eq!(
+            serde_json::to_string(&ECSFlatRecord::from(&metadata)).unwrap(),
+            concat!(
+                r#"{"cluster":"production","region":"us-east-1","account":"939885537497","#,
+                r#""task_id":"021447970bce4bd58069f1925cd87bc0","family":"streamer","revision":"12","#,
+                r#""container":"streamer","image_repo":"939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer","#,
+                r#""image_tag":"latest-production","cpu":1.0,"memory_mib":2048,"az":"us-east-1b","launch_type":"FARGATE"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_flat_record_without_task() {
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        let record = metadata.to_flat_record(None);
+        assert_eq!((record.cpu, record.memory_mib), (Some(2.0), Some(4096)));
+        assert_eq!((record.az, record.launch_type), (None, None));
+
+        // a separately fetched task document fills in the task-level fields
+        let task: ECSTaskMetadata = serde_json::from_str(TASK_JSON).unwrap();
+        let record = metadata.to_flat_record(Some(&task));
+        assert_eq!(record.launch_type.as_deref(), Some("FARGATE"));
+        assert_eq!(record.memory_mib, Some(2048));
+
+        assert_eq!(ECSMetadata::degraded(None).to_flat_record(None), ECSFlatRecord::default());
+    }
+
+    #[test]
+    fn test_observability_bundle() {
+        let v4_task: ECSTaskMetadata = serde_json::from_slice(include_bytes!("../testdata/versions/v4_task.json")).unwrap();
+        let cni_task = include_str!("../testdata/tasks/cni_pause.json");
+        let v4 = ECSMetadata::from_json(include_str!("../testdata/versions/v4_container.json")).unwrap();
+        let v3 = ECSMetadata::from_v3_json(include_str!("../testdata/versions/v3_container.json"), Some(include_str!("../testdata/versions/v3_task.json"))).unwrap();
+        let retagged = CONTAINER_JSON.replace("streamer:latest-production", "streamer@sha256:abcd").replace(r#""12""#, r#""11""#);
+
+        let matrix = [
+            ("labels only", metadata_from_json(CONTAINER_JSON, None), None),
+            ("with task", metadata_from_json(CONTAINER_JSON, Some(TASK_JSON)), None),
+            ("v4 fixture", v4.clone(), None),
+            ("v4 fixture, task handed over", v4, Some(&v4_task)),
+            ("v3 fixture", v3, None),
+            ("cni task, stale labels", metadata_from_json(&retagged, Some(cni_task)), None),
+        ];
+        let bundles = matrix.iter().map(|(case, metadata, task)| (*case, metadata.observability_bundle(*task))).collect::<Vec<_>>();
+        let field = |pick: fn(&ObservabilityBundle) -> Option<String>| bundles.iter().map(|(case, bundle)| (*case, pick(bundle))).collect::<Vec<_>>();
+        let expect = |values: [Option<&str>; 6]| matrix.iter().zip(values).map(|((case, _, _), value)| (*case, value.map(ToString::to_string))).collect::<Vec<_>>();
+
+        assert_eq!(field(|bundle| bundle.cluster.clone()), expect([Some("production"), Some("production"), Some("default"), Some("default"), Some("default"), Some("production")]));
+        assert_eq!(field(|bundle| bundle.region.clone()), expect([Some("us-east-1"), Some("us-east-1"), Some("us-west-2"), Some("us-west-2"), Some("us-west-2"), Some("us-east-1")]));
+        assert_eq!(field(|bundle| bundle.account.clone()), expect([Some("939885537497"), Some("939885537497"), Some("111122223333"), Some("111122223333"), Some("111122223333"), Some("939885537497")]));
+        assert_eq!(field(|bundle| bundle.task_id_short.clone()), expect([Some("02144797"), Some("02144797"), Some("cd189a93"), Some("cd189a93"), Some("cd189a93"), Some("02144797")]));
+        assert_eq!(field(|bundle| bundle.family.clone()), expect([Some("streamer"), Some("streamer"), Some("curltest"), Some("curltest"), Some("curltest"), Some("curltest")]));
+        assert_eq!(field(|bundle| bundle.revision.clone()), expect([Some("12"), Some("12"), Some("2"), Some("2"), Some("2"), Some("2")]));
+        assert_eq!(field(|bundle| bundle.image_tag.clone()), expect([Some("latest-production"), Some("latest-production"), Some("latest"), Some("latest"), Some("latest"), None]));
+        assert_eq!(field(|bundle| bundle.az.clone()), expect([None, Some("us-east-1b"), None, Some("us-west-2d"), Some("us-west-2d"), Some("us-west-2d")]));
+        assert_eq!(field(|bundle| bundle.launch_type.clone()), expect([None, Some("FARGATE"), None, Some("EC2"), None, Some("EC2")]));
+
+        for (case, bundle) in &bundles {
+            let metadata = &matrix.iter().find(|(name, _, _)| name == case).unwrap().1;
+            assert_eq!(bundle.task_arn.as_deref(), Some(metadata.task_arn()), "{case}");
+            assert_eq!(bundle.task_id, metadata.task_id(), "{case}");
+            assert_eq!(bundle.container.as_deref(), Some(metadata.container_name()), "{case}");
+            assert!(bundle.image_repo.as_deref().is_some_and(|repo| metadata.image().starts_with(repo)), "{case}");
+        }
+        assert_eq!(bundles[1].1.cpu, Some(1.0));
+        assert_eq!(bundles[1].1.memory_mib, Some(2048));
+        assert_eq!(ECSMetadata::degraded(None).observability_bundle(None), ObservabilityBundle::default());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::connection::ConnectionPolicy;
+use crate::diff::MetadataDiff;
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Result of re-reading the metadata
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefreshOutcome {
+    /// The endpoint served byte-identical documents, nothing was parsed
+    Unchanged,
+    /// Too soon after the previous fetch, the snapshot was kept without contacting the agent
+    Throttled,
+    /// The documents differ and were parsed again, `diff` compares the old and new stable fields
+    Changed(Box<MetadataDiff>),
+}
+
+/// Behavior of a refresh arriving within the builder's `min_refresh_interval`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshCooldown {
+    /// Return `RefreshOutcome::Throttled` right away, keeping the current snapshot
+    ReturnCached,
+    /// Wait for the rest of the interval, then fetch
+    Wait,
+}
+
+impl ECSMetadata {
+    /// Fetches the documents again from the endpoint this instance was built from (including the
+    /// task document if it was fetched initially) and updates the snapshot in place.
+    /// The agent response is byte-stable, so identical bodies skip deserialization altogether,
+    /// see `skipped_parses`. A degraded instance from `init_or_default` recovers on success.
+    /// Fetches are at least `min_refresh_interval` apart, see `RefreshCooldown`.
+    ///
+    /// Cancellation safe: nothing is updated until the fetch completes, so dropping the future
+    /// (e.g. the losing branch of a `tokio::select!`) leaves the instance as it was.
+    pub async fn refresh(&mut self) -> Result<RefreshOutcome, ECSMetadataError> {
+        let source = self.source.as_ref().ok_or(ECSMetadataError::NotRefreshable)?;
+        if source.connection_policy == ConnectionPolicy::CloseAfterInit {
+            return Err(ECSMetadataError::ClosedAfterInit);
+        }
+        let (interval, cooldown) = (source.min_refresh_interval, source.refresh_cooldown);
+        if let Some(ready_at) = self.last_fetch.map(|last_fetch| last_fetch + interval) {
+            if Instant::now() < ready_at {
+                match cooldown {
+                    RefreshCooldown::ReturnCached => return Ok(RefreshOutcome::Throttled),
+                    RefreshCooldown::Wait => crate::runtime::sleep_until(ready_at).await,
+                }
+            }
+        }
+
+        let fetched_at = Instant::now();
+        let fetched = source.fetch_documents(self.raw.task.is_some()).await;
+        // a failed fetch counts towards the interval too, a down agent must not be hammered
+        self.last_fetch = Some(fetched_at);
+        let documents = fetched?;
+        let hook = source.parse_failure_hook.clone();
+        let outcome = self.refresh_from_json(&documents.container, documents.task.as_deref()).inspect_err(|err| {
+            let container = (&documents.container_meta, documents.container.as_slice());
+            let task = documents.task_meta.as_ref().zip(documents.task.as_deref());
+            let panicked = hook.and_then(|hook| hook.capture_documents(container, task, self.raw.v3, err));
+            self.warnings.extend(panicked);
+        })?;
+        self.response_info = Some(documents.response);
+        self.fetched_at = Some(fetched_at);
+        Ok(outcome)
+    }
+
+    /// `refresh` once the snapshot is stale, see `is_stale`; `None` without contacting the
+    /// agent while it is fresh
+    pub async fn refresh_if_stale(&mut self) -> Result<Option<RefreshOutcome>, ECSMetadataError> {
+        match self.is_stale() {
+            true => self.refresh().await.map(Some),
+            false => Ok(None),
+        }
+    }
+
+    /// When the documents of this snapshot were fetched (the start of the request), by `init`
+    /// or the latest successful `refresh`. `None` for documents handed over, e.g. `from_json`,
+    /// and for a degraded instance that never fetched.
+    pub fn fetched_at(&self) -> Option<Instant> {
+        self.fetched_at
+    }
+
+    /// Time since `fetched_at`
+    pub fn age(&self) -> Option<Duration> {
+        Some(self.fetched_at?.elapsed())
+    }
+
+    /// Whether the snapshot is older than the builder's `snapshot_ttl`, its statuses, health and
+    /// networks possibly out of date. Always `false` without a TTL, always `true` with one when
+    /// nothing was fetched yet.
+    pub fn is_stale(&self) -> bool {
+        let Some(ttl) = self.source.as_ref().and_then(|source| source.snapshot_ttl) else {
+            return false;
+        };
+        self.age().is_none_or(|age| age >= ttl)
+    }
+
+    /// Same as `refresh` but with documents fetched by the caller. On error the instance is left
+    /// as it was.
+    pub fn refresh_from_json(&mut self, container: &[u8], task: Option<&[u8]>) -> Result<RefreshOutcome, ECSMetadataError> {
+        if !self.is_degraded() && container == self.raw.container && task == self.raw.task.as_deref() {
+            self.skipped_parses += 1;
+            return Ok(RefreshOutcome::Unchanged);
+        }
+
+        let (container, task) = (container.to_vec(), task.map(<[u8]>::to_vec));
+        let mut refreshed = ECSMetadata::from_versioned_documents(container, task, self.raw.v3, self.source.clone())?;
+        refreshed.skipped_parses = self.skipped_parses;
+        refreshed.last_fetch = self.last_fetch;
+        refreshed.fetched_at = self.fetched_at;
+        refreshed.endpoint_source = self.endpoint_source;
+        refreshed.response_info = self.response_info.clone();
+        let diff = self.diff(&refreshed);
+        *self = refreshed;
+        Ok(RefreshOutcome::Changed(Box::new(diff)))
+    }
+
+    /// Number of refreshes that found byte-identical documents and skipped parsing
+    pub fn skipped_parses(&self) -> u64 {
+        self.skipped_parses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    #[test]
+    fn test_unchanged_body_skips_parse() {
+        let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        assert_eq!(metadata.refresh_from_json(CONTAINER_JSON.as_bytes(), None).unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(metadata.refresh_from_json(CONTAINER_JSON.as_bytes(), None).unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(metadata.skipped_parses(), 2);
+    }
+
+    #[test]
+    fn test_changed_body_is_parsed_and_diffed() {
+        let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        let deployed = CONTAINER_JSON.replace("latest-production", "v2-production");
+
+        match metadata.refresh_from_json(deployed.as_bytes(), None).unwrap() {
+            RefreshOutcome::Changed(diff) => {
+                assert!(diff.image.is_changed());
+                assert!(!diff.cluster.is_changed());
+            }
+            other => panic!("image change must be detected, got {other:?}"),
+        }
+        assert_eq!(metadata.image(), "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:v2-production");
+        assert_eq!(metadata.raw.container, deployed.as_bytes());
+        assert_eq!(metadata.skipped_parses(), 0);
+
+        // whitespace-only differences are not byte-identical, but the diff shows no change
+        let reformatted = deployed.replace("        ", "  ");
+        match metadata.refresh_from_json(reformatted.as_bytes(), None).unwrap() {
+            RefreshOutcome::Changed(diff) => assert!(!diff.has_changes()),
+            other => panic!("bodies differ, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_body_keeps_snapshot() {
+        let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        assert!(metadata.refresh_from_json(b"{", None).is_err());
+        assert_eq!(metadata.cluster(), Some("production"));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_refresh_keeps_instance() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let mut metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init()
+            .await
+            .unwrap();
+        let initial_fetch = metadata.last_fetch;
+
+        let deployed = CONTAINER_JSON.replace("latest-production", "v2-production");
+        agent.set("/v4/abc", MockResponse::json(deployed.as_str()).with_delay(Duration::from_millis(500)));
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), metadata.refresh()).await;
+        assert!(cancelled.is_err(), "the refresh should still be in flight");
+        assert_eq!(metadata.last_fetch, initial_fetch);
+        assert!(metadata.image().ends_with("latest-production"));
+
+        // a parse failure doesn't cost the endpoint either
+        agent.set("/v4/abc", MockResponse::json("{"));
+        assert!(matches!(metadata.refresh().await, Err(ECSMetadataError::UnexpectedContent { .. })));
+
+        agent.set("/v4/abc", MockResponse::json(deployed));
+        assert!(matches!(metadata.refresh().await.unwrap(), RefreshOutcome::Changed(_)));
+        assert!(metadata.image().ends_with("v2-production"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_from_endpoint() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+
+        let mut metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init()
+            .await
+            .unwrap();
+        assert_eq!(metadata.refresh().await.unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(metadata.skipped_parses(), 1);
+
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON.replace(r#""Memory": 4096"#, r#""Memory": 8192"#)));
+        assert!(matches!(metadata.refresh().await.unwrap(), RefreshOutcome::Changed(diff) if diff.limits.is_changed()));
+        assert_eq!(metadata.limits().mem, Some(8192));
+        assert_eq!(agent.hits("/v4/abc"), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_too_soon_returns_cached() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let mut metadata = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).init().await.unwrap();
+
+        // a hot loop over 10s (0.0s to 9.9s), at most one fetch per second gets through
+        let mut throttled = 0;
+        for _ in 0..100 {
+            if metadata.refresh().await.unwrap() == RefreshOutcome::Throttled {
+                throttled += 1;
+            }
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        assert_eq!(agent.hits("/v4/abc"), 1 + 9);
+        assert_eq!(throttled, 91);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_too_soon_waits() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let mut metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::from_secs(2))
+            .refresh_cooldown(RefreshCooldown::Wait)
+            .init()
+            .await
+            .unwrap();
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(metadata.refresh().await.unwrap(), RefreshOutcome::Unchanged);
+        }
+        assert!(started.elapsed() >= Duration::from_secs(6));
+        assert_eq!(agent.hits("/v4/abc"), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_snapshot_ttl() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadata::builder().endpoint(agent.url("/v4/abc")).min_refresh_interval(Duration::ZERO);
+        let mut metadata = builder.snapshot_ttl(Duration::from_secs(30)).init().await.unwrap();
+        let fetched_at = metadata.fetched_at().unwrap();
+        assert!(!metadata.is_stale());
+        assert_eq!(metadata.refresh_if_stale().await.unwrap(), None);
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(metadata.age(), Some(Duration::from_secs(30)));
+        assert!(metadata.is_stale());
+        assert_eq!(metadata.refresh_if_stale().await.unwrap(), Some(RefreshOutcome::Unchanged));
+        assert_eq!(metadata.fetched_at(), Some(fetched_at + Duration::from_secs(30)));
+        assert!(!metadata.is_stale());
+        assert_eq!(agent.hits("/v4/abc"), 2);
+
+        // a failed refresh leaves the snapshot as old as it was
+        tokio::time::advance(Duration::from_secs(31)).await;
+        agent.set("/v4/abc", MockResponse::status(503, "busy"));
+        assert!(metadata.refresh_if_stale().await.is_err());
+        assert!(metadata.is_stale());
+
+        // without a TTL nothing goes stale
+        assert!(!ECSMetadata::from_json(CONTAINER_JSON).unwrap().is_stale());
+        assert_eq!(ECSMetadata::from_json(CONTAINER_JSON).unwrap().fetched_at(), None);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_requires_endpoint() {
+        let mut metadata = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        assert!(matches!(metadata.refresh().await, Err(ECSMetadataError::NotRefreshable)));
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use reqwest::header::{HeaderMap, HeaderName, CONTENT_LENGTH, DATE, SERVER};
+
+/// Envelope of the container document response, kept for debugging, see `ECSMetadata::response_info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ECSResponseInfo {
+    status: u16,
+    content_length: Option<u64>,
+    server: Option<String>,
+    date: Option<SystemTime>,
+    received_at: SystemTime,
+}
+
+impl ECSResponseInfo {
+    pub(crate) fn new(status: u16, headers: &HeaderMap, received_at: SystemTime) -> Self {
+        let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+        Self {
+            status,
+            content_length: header(CONTENT_LENGTH).and_then(|length| length.trim().parse().ok()),
+            server: header(SERVER).map(ToString::to_string),
+            date: header(DATE).and_then(parse_http_date),
+            received_at,
+        }
+    }
+
+    /// HTTP status, a success one since failed responses are errors
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// `Content-Length` as served. `None` without one, e.g. for a compressed body, which loses
+    /// the header when decoded.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// `Server` header, the agent leaves it out but proxies and mock agents often set one
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    /// `Date` header, `None` when missing or not in the IMF-fixdate format every HTTP/1.1 server
+    /// sends (`Sun, 06 Nov 1994 08:49:37 GMT`)
+    pub fn date(&self) -> Option<SystemTime> {
+        self.date
+    }
+
+    /// Local time the response headers arrived at
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// How far the serving clock is ahead of the local one in whole seconds, negative when
+    /// behind, `None` without a usable `Date`. The header has a one-second resolution, so
+    /// anything within ±1 is in sync.
+    pub fn clock_skew_secs(&self) -> Option<i64> {
+        Some(skew_secs(self.date?, self.received_at))
+    }
+}
+
+fn skew_secs(date: SystemTime, local: SystemTime) -> i64 {
+    match date.duration_since(local) {
+        Ok(ahead) => ahead.as_secs() as i64,
+        Err(behind) => -(behind.duration().as_secs() as i64),
+    }
+}
+
+/// IMF-fixdate, the only format senders may use (RFC 9110 §5.6.7)
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let [weekday, day, month, year, time, "GMT"] = date.split_ascii_whitespace().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    if weekday.len() != 4 || !weekday.ends_with(',') || day.len() != 2 || year.len() != 4 {
+        return None;
+    }
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let (day, year): (u32, i64) = (day.parse().ok()?, year.parse().ok()?);
+    let [hour, minute, second] = time.split(':').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?[..] else {
+        return None;
+    };
+    // 60 for a leap second
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = i64::from((month + 9) % 12); // March is 0
+    let day_of_year = (153 * month_index + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(at(784_111_777)));
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(at(0)));
+        assert_eq!(parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"), Some(at(1_709_251_199)));
+        assert_eq!(parse_http_date("Wed, 01 Mar 2100 12:00:00 GMT"), Some(at(4_107_585_600)));
+
+        for date in [
+            "",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "1994-11-06T08:49:37Z",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{date:?}");
+        }
+    }
+
+    #[test]
+    fn test_response_info() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"));
+        headers.insert(SERVER, HeaderValue::from_static("mock-agent/1.0"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("734"));
+
+        let info = ECSResponseInfo::new(200, &headers, at(784_111_777 + 90));
+        assert_eq!((info.status(), info.content_length(), info.server()), (200, Some(734), Some("mock-agent/1.0")));
+        assert_eq!(info.date(), Some(at(784_111_777)));
+        assert_eq!(info.clock_skew_secs(), Some(-90));
+        assert_eq!(ECSResponseInfo::new(200, &headers, at(784_111_777 - 5)).clock_skew_secs(), Some(5));
+
+        headers.insert(DATE, HeaderValue::from_static("yesterday"));
+        headers.remove(SERVER);
+        let info = ECSResponseInfo::new(200, &headers, at(0));
+        assert_eq!((info.date(), info.clock_skew_secs(), info.server()), (None, None, None));
+        assert_eq!(ECSResponseInfo::new(200, &HeaderMap::new(), at(0)).clock_skew_secs(), None);
+    }
+}
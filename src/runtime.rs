@@ -0,0 +1,139 @@
+//! The tokio runtime behind the async calls. reqwest's connections, the timers and the background
+//! tasks need one: the caller's, or with the `runtime-agnostic` feature the crate's when there is
+//! none, so that the async API can be awaited on any executor, e.g. async-std's. The sync
+//! primitives of `tokio::sync` need no runtime.
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Started on first use with a single worker thread. Runs every blocking call, and keeps running
+/// the connections of the pooled clients between them, as the runtime of `reqwest::blocking`
+/// does; with `runtime-agnostic` also the connections, timers and tasks of calls made outside of
+/// a runtime.
+#[cfg(any(feature = "blocking", feature = "runtime-agnostic"))]
+pub(crate) fn crate_runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("ecs-metadata")
+            .enable_all()
+            .build()
+            .expect("failed to start the ecs_metadata runtime")
+    })
+}
+
+/// The current runtime, panics outside of one
+#[cfg(not(feature = "runtime-agnostic"))]
+pub(crate) fn handle() -> Handle {
+    Handle::current()
+}
+
+/// The current runtime, the crate's outside of one
+#[cfg(feature = "runtime-agnostic")]
+pub(crate) fn handle() -> Handle {
+    Handle::try_current().unwrap_or_else(|_| crate_runtime().handle().clone())
+}
+
+/// `future` polled within the context of `handle`, for what it creates on the runtime as it
+/// goes: reqwest's connections and timeouts, sleeps, tasks
+#[cfg(not(feature = "runtime-agnostic"))]
+pub(crate) async fn enter<F: Future>(future: F) -> F::Output {
+    future.await
+}
+
+/// `future` polled within the context of `handle`, for what it creates on the runtime as it
+/// goes: reqwest's connections and timeouts, sleeps, tasks
+#[cfg(feature = "runtime-agnostic")]
+pub(crate) async fn enter<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx| {
+        // within a runtime already, e.g. of an outer `enter`, there is nothing to enter
+        let _context = Handle::try_current().is_err().then(|| crate_runtime().enter());
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    enter(async { tokio::time::sleep(duration).await }).await;
+}
+
+pub(crate) async fn sleep_until(deadline: Instant) {
+    enter(async { tokio::time::sleep_until(deadline).await }).await;
+}
+
+/// `tokio::spawn` on `handle`
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    handle().spawn(future)
+}
+
+// without the feature every call is within the caller's runtime, as in all the other tests
+#[cfg(all(test, feature = "runtime-agnostic"))]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+    use crate::{ECSMetadata, ECSMetadataError, ECSMetadataWatcher, RefreshCooldown, RefreshOutcome, RequestPolicy, SharedECSMetadata};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    #[test]
+    fn test_async_calls_outside_of_a_runtime() {
+        // an executor of another runtime, say async-std's, as small as they come
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = std::pin::pin!(future);
+            let waker = Waker::from(Arc::new(Unpark(thread::current())));
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    return output;
+                }
+                thread::park();
+            }
+        }
+
+        // the agent needs a runtime of its own, the calls run outside of it
+        let agent_runtime = tokio::runtime::Runtime::new().unwrap();
+        let agent = agent_runtime.block_on(MockAgent::start());
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let builder = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .metadata_policy(RequestPolicy::new(Some(Duration::from_secs(5)), 2))
+            .min_refresh_interval(Duration::from_millis(50))
+            .refresh_cooldown(RefreshCooldown::Wait);
+        assert!(Handle::try_current().is_err());
+
+        block_on(async {
+            // connections and the request timeout, then the wait of the cooldown
+            let mut metadata = builder.clone().init().await.unwrap();
+            assert_eq!(metadata.refresh().await.unwrap(), RefreshOutcome::Unchanged);
+
+            // the backoff between the attempts
+            agent.set("/v4/abc", MockResponse::status(503, "unavailable"));
+            assert!(matches!(builder.clone().init().await, Err(ECSMetadataError::HttpStatus { status: 503, .. })));
+            assert_eq!(agent.hits("/v4/abc"), 2 + 3);
+
+            // the poller of a watcher
+            let deployed = CONTAINER_JSON.replace("latest-production", "v2-production");
+            agent.set("/v4/abc", MockResponse::json(deployed));
+            let watcher = ECSMetadataWatcher::start(SharedECSMetadata::new(metadata), Duration::from_millis(20)).unwrap();
+            let mut updates = watcher.subscribe();
+            updates.changed().await.unwrap();
+            assert!(updates.borrow().image().ends_with("v2-production"));
+        });
+    }
+}
@@ -0,0 +1,58 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use crate::container::ECSContainerLimits;
+use crate::metadata::ECSMetadata;
+
+/// JSON Schema of a serialized `ECSMetadata` snapshot. The output is deterministic, so it can be
+/// pinned per crate version; `schema/ecs_metadata.schema.json` holds the current one.
+pub fn schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(ECSMetadata)).expect("a schema always serializes")
+}
+
+// The derived schema leaves out `extra`: schemars merges a flattened map away, so the unknown
+// limit keys are added back as `additionalProperties`
+impl JsonSchema for ECSContainerLimits {
+    fn schema_name() -> String {
+        "ECSContainerLimits".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct KnownLimits {
+            #[schemars(rename = "CPU")]
+            cpu: Option<f64>,
+            #[schemars(rename = "Memory")]
+            mem: Option<u64>,
+        }
+
+        let mut schema = KnownLimits::json_schema(gen).into_object();
+        schema.object().additional_properties = Some(Box::new(gen.subschema_for::<serde_json::Number>()));
+        Schema::Object(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/schema/ecs_metadata.schema.json");
+
+    // Run with UPDATE_SCHEMA=1 to accept an intended shape change
+    #[test]
+    fn test_schema_snapshot() {
+        let generated = serde_json::to_string_pretty(&schema()).unwrap() + "\n";
+        if env::var_os("UPDATE_SCHEMA").is_some() {
+            fs::write(SNAPSHOT, &generated).unwrap();
+        }
+        let pinned = fs::read_to_string(SNAPSHOT).expect("schema snapshot missing, run with UPDATE_SCHEMA=1");
+        assert_eq!(generated, pinned, "the serialized shape changed, review and run with UPDATE_SCHEMA=1");
+    }
+
+    #[test]
+    fn test_schema_is_deterministic() {
+        assert_eq!(schema(), schema());
+    }
+}
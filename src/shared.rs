@@ -0,0 +1,125 @@
+use crate::error::ECSMetadataError;
+use crate::metadata::ECSMetadata;
+use crate::refresh::RefreshOutcome;
+use crate::sync::{Arc, SnapshotCell};
+
+/// Cheaply cloneable handle to one `ECSMetadata`, for the many places of a service that want
+/// the same metadata while one of them refreshes it. Readers get the latest snapshot and are
+/// never blocked by a refresh in flight.
+#[derive(Debug, Clone)]
+pub struct SharedECSMetadata {
+    current: Arc<SnapshotCell<ECSMetadata>>,
+    // serializes refreshes, so the refresh interval holds across clones of the handle
+    refreshing: std::sync::Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SharedECSMetadata {
+    pub fn new(metadata: ECSMetadata) -> Self {
+        Self {
+            current: Arc::new(SnapshotCell::new(metadata)),
+            refreshing: std::sync::Arc::default(),
+        }
+    }
+
+    /// Latest snapshot
+    pub fn snapshot(&self) -> Arc<ECSMetadata> {
+        self.current.load()
+    }
+
+    /// `ECSMetadata::refresh` on the shared snapshot, visible to every clone of the handle once done.
+    /// Works on a copy that only replaces the snapshot once the refresh returns, so a dropped
+    /// refresh leaves the handle untouched and doesn't block the next one.
+    pub async fn refresh(&self) -> Result<RefreshOutcome, ECSMetadataError> {
+        let _refreshing = self.refreshing.lock().await;
+        let mut metadata = ECSMetadata::clone(&self.snapshot());
+        let outcome = metadata.refresh().await;
+        // also when unchanged or failed, to keep the bookkeeping (last fetch, skipped parses): a
+        // failed refresh leaves the copy as it was but for the fetch counting towards the interval
+        self.current.store(metadata);
+        outcome
+    }
+}
+
+impl From<ECSMetadata> for SharedECSMetadata {
+    fn from(metadata: ECSMetadata) -> Self {
+        Self::new(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::test_support::{MockAgent, MockResponse};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_refresh_is_visible_to_clones() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init()
+            .await
+            .unwrap();
+        let shared = SharedECSMetadata::new(metadata);
+        let reader = shared.clone();
+
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON.replace(r#""Memory": 4096"#, r#""Memory": 8192"#)));
+        assert!(matches!(shared.refresh().await.unwrap(), RefreshOutcome::Changed(_)));
+        assert_eq!(reader.snapshot().limits().mem, Some(8192));
+
+        assert_eq!(shared.refresh().await.unwrap(), RefreshOutcome::Unchanged);
+        assert_eq!(reader.snapshot().skipped_parses(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_refresh_leaves_snapshot() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init()
+            .await
+            .unwrap();
+        let shared = SharedECSMetadata::new(metadata);
+        let before = shared.snapshot();
+
+        let deployed = CONTAINER_JSON.replace(r#""Memory": 4096"#, r#""Memory": 8192"#);
+        agent.set("/v4/abc", MockResponse::json(deployed.as_str()).with_delay(Duration::from_millis(500)));
+        assert!(tokio::time::timeout(Duration::from_millis(50), shared.refresh()).await.is_err());
+        assert!(Arc::ptr_eq(&shared.snapshot(), &before));
+
+        agent.set("/v4/abc", MockResponse::json(deployed));
+        assert!(matches!(shared.refresh().await.unwrap(), RefreshOutcome::Changed(_)));
+        assert_eq!(shared.snapshot().limits().mem, Some(8192));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_refresh_counts_towards_interval() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .metadata_policy(crate::client::RequestPolicy::new(None, 0))
+            .init()
+            .await
+            .unwrap();
+        let shared = SharedECSMetadata::new(metadata);
+
+        // a hot loop over 10s (0.0s to 9.9s) against a down agent, at most one fetch per second
+        agent.set("/v4/abc", MockResponse::status(503, "unavailable"));
+        let mut failed = 0;
+        for _ in 0..100 {
+            if shared.refresh().await.is_err() {
+                failed += 1;
+            }
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        assert_eq!(agent.hits("/v4/abc"), 1 + 9);
+        assert_eq!(failed, 9);
+        assert_eq!(shared.snapshot().limits().mem, Some(4096));
+    }
+}
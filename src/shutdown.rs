@@ -0,0 +1,174 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::metadata::ECSMetadata;
+use crate::shared::SharedECSMetadata;
+use crate::sync::Arc;
+use crate::watcher::ECSMetadataWatcher;
+
+/// Poll interval of `shutdown_signal`
+pub const DEFAULT_SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Trigger that resolved `shutdown_signal` first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// SIGTERM, sent by ECS when it stops the task (ctrl-c off unix)
+    Signal,
+    /// The task or this container is desired `STOPPED`, e.g. the service is scaling in
+    Draining,
+    /// The task document has a stop code, e.g. `SpotInterruption`
+    StopCode(String),
+}
+
+/// `shutdown_signal_every` with the default poll interval
+pub fn shutdown_signal(metadata: SharedECSMetadata) -> impl Future<Output = ShutdownReason> + Send + 'static {
+    shutdown_signal_every(metadata, DEFAULT_SHUTDOWN_POLL_INTERVAL)
+}
+
+/// Resolves on SIGTERM or once a poll of `metadata` every `interval` finds the task draining
+/// or stopping, whichever comes first, to start draining before `stopTimeout` runs out. The
+/// stop code and the task desired status are only seen when `metadata` fetches the task
+/// document, see `ECSMetadataBuilder::init_with_task`.
+///
+/// The signal handler is installed and the polling started right away, so call it at startup
+/// from within a tokio runtime, or anywhere with the `runtime-agnostic` feature. A snapshot
+/// built with `ConnectionPolicy::CloseAfterInit` can't be polled, only SIGTERM resolves it then.
+/// Dropping the future stops the polling. For axum, which wants a future of `()`, pass
+/// `async move { shutdown_signal(metadata).await; }` to `with_graceful_shutdown`.
+pub fn shutdown_signal_every(metadata: SharedECSMetadata, interval: Duration) -> impl Future<Output = ShutdownReason> + Send + 'static {
+    let terminated = terminated();
+    let watcher = ECSMetadataWatcher::start_with_history(metadata, interval, 0).ok();
+    let updates = watcher.as_ref().map(ECSMetadataWatcher::subscribe);
+    async move {
+        let _watcher = watcher;
+        let stopping = async {
+            match updates {
+                Some(updates) => stopping(updates).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = terminated => ShutdownReason::Signal,
+            reason = stopping => reason,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn terminated() -> impl Future<Output = ()> + Send + 'static {
+    let signal = {
+        let _context = crate::runtime::handle().enter();
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+    };
+    async move {
+        match signal {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            // without a handler only the metadata can tell
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn terminated() -> impl Future<Output = ()> + Send + 'static {
+    crate::runtime::enter(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending().await
+        }
+    })
+}
+
+async fn stopping(mut updates: watch::Receiver<Arc<ECSMetadata>>) -> ShutdownReason {
+    loop {
+        let reason = stop_reason(&updates.borrow_and_update());
+        if let Some(reason) = reason {
+            return reason;
+        }
+        if updates.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+    }
+}
+
+// The stop code says more than the desired status that comes with it
+fn stop_reason(metadata: &ECSMetadata) -> Option<ShutdownReason> {
+    let task = metadata.task();
+    if let Some(stop_code) = task.and_then(|task| task.stop_code()) {
+        return Some(ShutdownReason::StopCode(stop_code.to_string()));
+    }
+    let stopped = |status: Option<&str>| status == Some("STOPPED");
+    (stopped(task.and_then(|task| task.desired_status())) || stopped(metadata.container().desired_status())).then_some(ShutdownReason::Draining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+    use crate::test_support::{MockAgent, MockResponse};
+
+    // A raised SIGTERM reaches every handler of the process, the tests take turns
+    static TRIGGERS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    fn task(extra: &str) -> String {
+        task_json(&[CONTAINER_JSON.to_string()]).replacen('{', &format!("{{{extra}"), 1)
+    }
+
+    async fn watch(agent: &MockAgent) -> impl Future<Output = ShutdownReason> {
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/task", MockResponse::json(task(r#""DesiredStatus": "RUNNING","#)));
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init_with_task()
+            .await
+            .unwrap();
+        shutdown_signal_every(metadata.into(), Duration::from_millis(5))
+    }
+
+    async fn resolve(signal: impl Future<Output = ShutdownReason>) -> ShutdownReason {
+        tokio::time::timeout(Duration::from_secs(5), signal).await.expect("the signal should resolve")
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let _turn = TRIGGERS.lock().await;
+        let agent = MockAgent::start().await;
+        let signal = watch(&agent).await;
+        agent.set("/v4/abc/task", MockResponse::json(task(r#""DesiredStatus": "STOPPED","#)));
+        assert_eq!(resolve(signal).await, ShutdownReason::Draining);
+    }
+
+    #[tokio::test]
+    async fn test_stop_code() {
+        let _turn = TRIGGERS.lock().await;
+        let agent = MockAgent::start().await;
+        let signal = watch(&agent).await;
+        agent.set("/v4/abc/task", MockResponse::json(task(r#""DesiredStatus": "STOPPED", "StopCode": "SpotInterruption","#)));
+        assert_eq!(resolve(signal).await, ShutdownReason::StopCode("SpotInterruption".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sigterm() {
+        let _turn = TRIGGERS.lock().await;
+        let agent = MockAgent::start().await;
+        let signal = watch(&agent).await;
+        // SAFETY: raise has no preconditions, the handler is installed by now
+        assert_eq!(unsafe { libc::raise(libc::SIGTERM) }, 0);
+        assert_eq!(resolve(signal).await, ShutdownReason::Signal);
+    }
+
+    #[test]
+    fn test_axum_compatible() {
+        fn graceful_shutdown<F: Future<Output = ()> + Send + 'static>(_: F) {}
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let _guard = runtime.enter();
+        let metadata = SharedECSMetadata::from(crate::metadata::tests::metadata_from_json(CONTAINER_JSON, None));
+        graceful_shutdown(async move {
+            shutdown_signal(metadata).await;
+        });
+    }
+}
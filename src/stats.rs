@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::error::ECSMetadataError;
+use crate::memory::MemorySize;
+use crate::metadata::ECSMetadata;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Docker stats document of a container, as served by `${URI}/stats`, e.g. from
+/// `serde_json::from_value(builder.fetch_stats(None).await?)`. Counters are `u64` and default to 0
+/// when absent, as many are on cgroup v2 hosts; integral floats, which some kernels report, are
+/// accepted too.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSContainerStats {
+    read: String,
+    #[serde(default)]
+    preread: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    cpu_stats: ECSCpuStats,
+    #[serde(default)]
+    precpu_stats: ECSCpuStats,
+    #[serde(default)]
+    memory_stats: ECSMemoryStats,
+    #[serde(default)]
+    blkio_stats: ECSBlkioStats,
+    // absent with the `none` network mode
+    #[serde(default)]
+    networks: BTreeMap<String, ECSNetworkStats>,
+}
+
+impl ECSContainerStats {
+    /// Time of the sample, RFC 3339 with nanoseconds
+    pub fn read(&self) -> &str {
+        &self.read
+    }
+
+    /// Time of the previous sample, `precpu_stats`; the zero time on the first one
+    pub fn preread(&self) -> &str {
+        &self.preread
+    }
+
+    /// Docker container name, with a leading `/`
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn cpu_stats(&self) -> &ECSCpuStats {
+        &self.cpu_stats
+    }
+
+    /// CPU counters of the previous sample, to compute a usage rate from
+    pub fn precpu_stats(&self) -> &ECSCpuStats {
+        &self.precpu_stats
+    }
+
+    pub fn memory_stats(&self) -> &ECSMemoryStats {
+        &self.memory_stats
+    }
+
+    pub fn blkio_stats(&self) -> &ECSBlkioStats {
+        &self.blkio_stats
+    }
+
+    /// Counters per interface name
+    pub fn networks(&self) -> &BTreeMap<String, ECSNetworkStats> {
+        &self.networks
+    }
+
+    /// Memory in use without the reclaimable page cache, as `docker stats` shows it: `usage`
+    /// minus `total_inactive_file` on cgroup v1 and `inactive_file` on v2. `None` without a
+    /// `usage`, e.g. for a stopped container.
+    pub fn memory_usage_bytes(&self) -> Option<u64> {
+        let memory = &self.memory_stats;
+        let usage = memory.usage?;
+        let inactive_file = ["total_inactive_file", "inactive_file"]
+            .iter()
+            .find_map(|key| memory.stats.get(*key))
+            .filter(|inactive_file| **inactive_file < usage);
+        Some(usage - inactive_file.unwrap_or(&0))
+    }
+
+    /// `memory_usage_bytes` rounded to the nearest MiB
+    pub fn memory_usage_mib(&self) -> Option<u64> {
+        self.memory_usage_bytes().map(|bytes| bytes / MIB + u64::from(bytes % MIB >= MIB / 2))
+    }
+
+    /// `memory_usage_bytes` with its unit
+    pub fn memory_usage(&self) -> Option<MemorySize> {
+        self.memory_usage_bytes().map(MemorySize::from_bytes)
+    }
+
+    /// `ECSMemoryStats::limit` with its unit
+    pub fn memory_limit(&self) -> Option<MemorySize> {
+        self.memory_stats.limit.map(MemorySize::from_bytes)
+    }
+
+    /// CPU usage between `precpu_stats` and `cpu_stats` as `docker stats` computes it, in percent
+    /// of one CPU (200 for two busy CPUs). `None` on the first sample, whose previous counters
+    /// are zero, and without `system_cpu_usage`.
+    pub fn cpu_usage_percent(&self) -> Option<f64> {
+        let (cpu, precpu) = (&self.cpu_stats, &self.precpu_stats);
+        let system_delta = cpu.system_cpu_usage?.checked_sub(precpu.system_cpu_usage.filter(|usage| *usage > 0)?)?;
+        let cpu_delta = cpu.cpu_usage.total_usage.saturating_sub(precpu.cpu_usage.total_usage);
+        let online_cpus = cpu.online_cpus.filter(|cpus| *cpus > 0).unwrap_or(cpu.cpu_usage.percpu_usage.len() as u32);
+        (system_delta > 0).then(|| cpu_delta as f64 / system_delta as f64 * f64::from(online_cpus) * 100.0)
+    }
+}
+
+/// `cpu_stats` of a stats document, times in nanoseconds
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSCpuStats {
+    #[serde(default)]
+    cpu_usage: ECSCpuUsage,
+    // not served on Windows
+    #[serde(default, deserialize_with = "optional_counter", skip_serializing_if = "Option::is_none")]
+    system_cpu_usage: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    online_cpus: Option<u32>,
+    #[serde(default)]
+    throttling_data: ECSThrottlingData,
+}
+
+impl ECSCpuStats {
+    pub fn total_usage(&self) -> u64 {
+        self.cpu_usage.total_usage
+    }
+
+    /// Usage per CPU, cgroup v1 only, empty on v2
+    pub fn percpu_usage(&self) -> &[u64] {
+        &self.cpu_usage.percpu_usage
+    }
+
+    pub fn usage_in_kernelmode(&self) -> u64 {
+        self.cpu_usage.usage_in_kernelmode
+    }
+
+    pub fn usage_in_usermode(&self) -> u64 {
+        self.cpu_usage.usage_in_usermode
+    }
+
+    /// Host CPU time of all CPUs, `None` on Windows
+    pub fn system_cpu_usage(&self) -> Option<u64> {
+        self.system_cpu_usage
+    }
+
+    pub fn online_cpus(&self) -> Option<u32> {
+        self.online_cpus
+    }
+
+    pub fn throttled_periods(&self) -> u64 {
+        self.throttling_data.throttled_periods
+    }
+
+    pub fn throttled_time(&self) -> u64 {
+        self.throttling_data.throttled_time
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct ECSCpuUsage {
+    #[serde(default, deserialize_with = "counter")]
+    total_usage: u64,
+    #[serde(default, deserialize_with = "counters", skip_serializing_if = "Vec::is_empty")]
+    percpu_usage: Vec<u64>,
+    #[serde(default, deserialize_with = "counter")]
+    usage_in_kernelmode: u64,
+    #[serde(default, deserialize_with = "counter")]
+    usage_in_usermode: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct ECSThrottlingData {
+    #[serde(default, deserialize_with = "counter")]
+    periods: u64,
+    #[serde(default, deserialize_with = "counter")]
+    throttled_periods: u64,
+    #[serde(default, deserialize_with = "counter")]
+    throttled_time: u64,
+}
+
+/// `memory_stats` of a stats document, in bytes
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSMemoryStats {
+    // the whole object is empty for a stopped container
+    #[serde(default, deserialize_with = "optional_counter", skip_serializing_if = "Option::is_none")]
+    usage: Option<u64>,
+    #[serde(default, deserialize_with = "optional_counter", skip_serializing_if = "Option::is_none")]
+    max_usage: Option<u64>,
+    #[serde(default, deserialize_with = "optional_counter", skip_serializing_if = "Option::is_none")]
+    limit: Option<u64>,
+    #[serde(default, deserialize_with = "counter_map")]
+    stats: BTreeMap<String, u64>,
+}
+
+impl ECSMemoryStats {
+    /// Usage in bytes including the page cache, see `ECSContainerStats::memory_usage_bytes`
+    pub fn usage(&self) -> Option<u64> {
+        self.usage
+    }
+
+    /// Peak usage in bytes, cgroup v1 only
+    pub fn max_usage(&self) -> Option<u64> {
+        self.max_usage
+    }
+
+    /// Limit of the container in bytes, the host memory without one
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Kernel memory counters as served, the keys differ between cgroup v1 and v2
+    pub fn stats(&self) -> &BTreeMap<String, u64> {
+        &self.stats
+    }
+}
+
+/// `blkio_stats` of a stats document, one entry per device and operation. Only the
+/// `io_service_bytes_recursive` and `io_serviced_recursive` lists are served on cgroup v2.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSBlkioStats {
+    #[serde(default, deserialize_with = "blkio_entries")]
+    io_service_bytes_recursive: Vec<ECSBlkioEntry>,
+    #[serde(default, deserialize_with = "blkio_entries")]
+    io_serviced_recursive: Vec<ECSBlkioEntry>,
+}
+
+impl ECSBlkioStats {
+    /// Bytes transferred, per device and operation
+    pub fn io_service_bytes(&self) -> &[ECSBlkioEntry] {
+        &self.io_service_bytes_recursive
+    }
+
+    /// I/O operations, per device and operation
+    pub fn io_serviced(&self) -> &[ECSBlkioEntry] {
+        &self.io_serviced_recursive
+    }
+
+    /// Bytes read from all devices
+    pub fn read_bytes(&self) -> u64 {
+        total(&self.io_service_bytes_recursive, "read")
+    }
+
+    /// Bytes written to all devices
+    pub fn write_bytes(&self) -> u64 {
+        total(&self.io_service_bytes_recursive, "write")
+    }
+}
+
+/// Counter of a block device for one operation
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSBlkioEntry {
+    #[serde(default)]
+    major: u64,
+    #[serde(default)]
+    minor: u64,
+    #[serde(default)]
+    op: String,
+    #[serde(default, deserialize_with = "counter")]
+    value: u64,
+}
+
+impl ECSBlkioEntry {
+    pub fn major(&self) -> u64 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u64 {
+        self.minor
+    }
+
+    /// Operation as served, capitalized on cgroup v1 (`Read`) and lowercase on v2 (`read`)
+    pub fn op(&self) -> &str {
+        &self.op
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+// cgroup v1 also lists `Total` per device, which would count the bytes twice
+fn total(entries: &[ECSBlkioEntry], op: &str) -> u64 {
+    entries.iter().filter(|entry| entry.op.eq_ignore_ascii_case(op)).fold(0, |total, entry| total.saturating_add(entry.value))
+}
+
+/// Counters of a network interface
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSNetworkStats {
+    #[serde(default, deserialize_with = "counter")]
+    rx_bytes: u64,
+    #[serde(default, deserialize_with = "counter")]
+    rx_packets: u64,
+    #[serde(default, deserialize_with = "counter")]
+    rx_errors: u64,
+    #[serde(default, deserialize_with = "counter")]
+    rx_dropped: u64,
+    #[serde(default, deserialize_with = "counter")]
+    tx_bytes: u64,
+    #[serde(default, deserialize_with = "counter")]
+    tx_packets: u64,
+    #[serde(default, deserialize_with = "counter")]
+    tx_errors: u64,
+    #[serde(default, deserialize_with = "counter")]
+    tx_dropped: u64,
+}
+
+impl ECSNetworkStats {
+    pub fn rx_bytes(&self) -> u64 {
+        self.rx_bytes
+    }
+
+    pub fn rx_packets(&self) -> u64 {
+        self.rx_packets
+    }
+
+    pub fn rx_errors(&self) -> u64 {
+        self.rx_errors
+    }
+
+    pub fn rx_dropped(&self) -> u64 {
+        self.rx_dropped
+    }
+
+    pub fn tx_bytes(&self) -> u64 {
+        self.tx_bytes
+    }
+
+    pub fn tx_packets(&self) -> u64 {
+        self.tx_packets
+    }
+
+    pub fn tx_errors(&self) -> u64 {
+        self.tx_errors
+    }
+
+    pub fn tx_dropped(&self) -> u64 {
+        self.tx_dropped
+    }
+}
+
+/// Counter as served: an integer, or a float on some kernels
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Counter {
+    Integer(u64),
+    Float(f64),
+}
+
+impl Counter {
+    fn value<E: serde::de::Error>(self) -> Result<u64, E> {
+        match self {
+            Counter::Integer(value) => Ok(value),
+            // `u64::MAX as f64` rounds up to 2^64, itself out of range
+            Counter::Float(value) if value >= 0.0 && value < u64::MAX as f64 && value.fract() == 0.0 => Ok(value as u64),
+            Counter::Float(value) => Err(E::custom(format!("invalid counter {value}"))),
+        }
+    }
+}
+
+fn optional_counter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Option::<Counter>::deserialize(deserializer)?.map(Counter::value).transpose()
+}
+
+// null as 0, e.g. the throttling data of a container without a CPU limit on some hosts
+fn counter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Ok(optional_counter(deserializer)?.unwrap_or_default())
+}
+
+fn counters<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+    let counters = Option::<Vec<Counter>>::deserialize(deserializer)?.unwrap_or_default();
+    counters.into_iter().map(Counter::value).collect()
+}
+
+// null entries are left out
+fn counter_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, u64>, D::Error> {
+    let counters = Option::<BTreeMap<String, Option<Counter>>>::deserialize(deserializer)?.unwrap_or_default();
+    counters
+        .into_iter()
+        .filter_map(|(key, counter)| Some(counter?.value().map(|value| (key, value))))
+        .collect()
+}
+
+// null on cgroup v2 for the lists it doesn't serve
+fn blkio_entries<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ECSBlkioEntry>, D::Error> {
+    Ok(Option::<Vec<ECSBlkioEntry>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl ECSMetadata {
+    /// Stats of this container, fetched from the endpoint this instance was built from with its
+    /// stats policy
+    pub async fn container_stats(&self) -> Result<ECSContainerStats, ECSMetadataError> {
+        let source = self.source.as_ref().ok_or(ECSMetadataError::NotRefreshable)?;
+        Ok(serde_json::from_value(source.fetch_stats(None).await?)?)
+    }
+
+    /// Stats of every container of the task keyed by Docker ID, fetched like `container_stats`.
+    /// Containers without stats, which the agent serves as `null` once they stopped, are left out.
+    pub async fn task_stats(&self) -> Result<BTreeMap<String, ECSContainerStats>, ECSMetadataError> {
+        let source = self.source.as_ref().ok_or(ECSMetadataError::NotRefreshable)?;
+        let stats: BTreeMap<String, Option<ECSContainerStats>> = serde_json::from_value(source.fetch_task_stats(None).await?)?;
+        Ok(stats.into_iter().filter_map(|(docker_id, stats)| Some((docker_id, stats?))).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CGROUP_V1_JSON: &str = include_str!("../testdata/stats/cgroup_v1.json");
+    const CGROUP_V2_JSON: &str = include_str!("../testdata/stats/cgroup_v2.json");
+
+    fn stats(json: &str) -> ECSContainerStats {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_cgroup_v1() {
+        let stats = stats(CGROUP_V1_JSON);
+        assert_eq!(stats.id(), Some("2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0"));
+        assert_eq!(stats.cpu_stats().total_usage(), 2_004_301_458);
+        assert_eq!(stats.cpu_stats().percpu_usage(), [1_023_118_690, 981_182_768]);
+        assert_eq!(stats.cpu_stats().system_cpu_usage(), Some(6_380_640_000_000));
+        assert_eq!(stats.precpu_stats().total_usage(), 1_994_521_184);
+        assert_eq!(stats.memory_stats().max_usage(), Some(41_705_472));
+        assert_eq!(stats.memory_stats().limit(), Some(536_870_912));
+        assert_eq!(stats.networks()["eth0"].rx_bytes(), 5338);
+        // 35700736 - 8077312 bytes, 26.34 MiB
+        assert_eq!(stats.memory_usage_bytes(), Some(27_623_424));
+        assert_eq!(stats.memory_usage_mib(), Some(26));
+        assert_eq!(stats.memory_usage().unwrap().to_string(), "26.34 MiB");
+        assert_eq!(stats.memory_limit(), Some(MemorySize::from_mib(512)));
+        assert_eq!((stats.blkio_stats().read_bytes(), stats.blkio_stats().write_bytes()), (11_153_408, 0));
+        assert_eq!(stats.blkio_stats().io_serviced()[0].value(), 301);
+        // 9780274ns of 2.01s on 2 CPUs
+        assert!((stats.cpu_usage_percent().unwrap() - 0.973_161_59).abs() < 1e-6, "{:?}", stats.cpu_usage_percent());
+    }
+
+    #[test]
+    fn test_cgroup_v2() {
+        let stats = stats(CGROUP_V2_JSON);
+        assert!(stats.cpu_stats().percpu_usage().is_empty());
+        assert_eq!(stats.cpu_stats().throttled_periods(), 3);
+        assert_eq!(stats.cpu_stats().throttled_time(), 41_204_000);
+        assert_eq!(stats.memory_stats().max_usage(), None);
+        assert_eq!(stats.memory_stats().stats()["anon"], 19_038_208);
+        assert_eq!(stats.networks()["eth1"].tx_packets(), 803);
+        // 31096832 - 4308992 bytes, 25.55 MiB
+        assert_eq!(stats.memory_usage_bytes(), Some(26_787_840));
+        assert_eq!(stats.memory_usage_mib(), Some(26));
+        assert_eq!((stats.blkio_stats().read_bytes(), stats.blkio_stats().write_bytes()), (10_989_568, 4096));
+        assert!(stats.blkio_stats().io_serviced().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetched_stats() {
+        use crate::metadata::tests::CONTAINER_JSON;
+        use crate::test_support::{MockAgent, MockResponse};
+
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        agent.set("/v4/abc/stats", MockResponse::json(CGROUP_V1_JSON));
+        let task_stats = format!(r#"{{"2969e5e20eda": {CGROUP_V1_JSON}, "731a0d6a3b42": {CGROUP_V2_JSON}, "f00d": null}}"#);
+        agent.set("/v4/abc/task/stats", MockResponse::json(task_stats));
+        let metadata = crate::client::ECSMetadataBuilder::new().endpoint(agent.url("/v4/abc")).init().await.unwrap();
+
+        assert_eq!(metadata.container_stats().await.unwrap(), stats(CGROUP_V1_JSON));
+        let task_stats = metadata.task_stats().await.unwrap();
+        assert_eq!(task_stats.keys().collect::<Vec<_>>(), ["2969e5e20eda", "731a0d6a3b42"]);
+        assert_eq!(task_stats["731a0d6a3b42"].networks()["eth1"].tx_packets(), 803);
+
+        let from_json = ECSMetadata::from_json(CONTAINER_JSON).unwrap();
+        assert!(matches!(from_json.container_stats().await, Err(ECSMetadataError::NotRefreshable)));
+    }
+
+    #[test]
+    fn test_counters() {
+        let big = r#"{"read": "now", "memory_stats": {"usage": 18446744073709551615, "limit": 9.223372036854776e18, "stats": {"inactive_file": null}}}"#;
+        let stats = stats(big);
+        assert_eq!(stats.memory_stats().usage(), Some(u64::MAX));
+        assert_eq!(stats.memory_stats().limit(), Some(1 << 63));
+        assert!(stats.memory_stats().stats().is_empty());
+        assert_eq!(stats.memory_usage_mib(), Some(u64::MAX / MIB + 1));
+
+        let stopped = ECSContainerStats::deserialize(serde_json::json!({"read": "now", "memory_stats": {}})).unwrap();
+        assert_eq!((stopped.memory_usage_bytes(), stopped.memory_usage_mib()), (None, None));
+        assert_eq!(stopped.cpu_stats(), &ECSCpuStats::default());
+        assert_eq!(stopped.cpu_usage_percent(), None);
+
+        for invalid in ["-1", "1.5", "1e20", "\"12\""] {
+            let json = format!(r#"{{"read": "now", "memory_stats": {{"usage": {invalid}}}}}"#);
+            assert!(serde_json::from_str::<ECSContainerStats>(&json).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_memory_usage_mib_rounding() {
+        let usage = |bytes: u64| {
+            let json = format!(r#"{{"read": "now", "memory_stats": {{"usage": {bytes}}}}}"#);
+            stats(&json).memory_usage_mib().unwrap()
+        };
+        assert_eq!(usage(0), 0);
+        assert_eq!(usage(MIB / 2 - 1), 0);
+        assert_eq!(usage(MIB / 2), 1);
+        assert_eq!(usage(3 * MIB - 1), 3);
+        assert_eq!(usage(3 * MIB + MIB / 2 - 1), 3);
+    }
+}
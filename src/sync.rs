@@ -0,0 +1,115 @@
+//! Sync primitives behind the shared state, loom's when model checking:
+//!
+//! `RUSTFLAGS="--cfg ecs_metadata_loom" cargo test --release --lib loom`
+//!
+//! The cfg is not the conventional `loom`, under which tokio leaves out `tokio::net` and the
+//! HTTP stack no longer builds.
+
+#[cfg(ecs_metadata_loom)]
+pub(crate) use loom::sync::{Arc, RwLock};
+#[cfg(not(ecs_metadata_loom))]
+pub(crate) use std::sync::{Arc, RwLock};
+
+use std::sync::PoisonError;
+
+/// Holds the current snapshot. Readers clone the `Arc` under a short read lock; a writer builds
+/// the next value aside and only swaps in the complete one, so readers observe either the old
+/// or the new snapshot and nothing in between.
+#[derive(Debug)]
+pub(crate) struct SnapshotCell<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> SnapshotCell<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self { current: RwLock::new(Arc::new(value)) }
+    }
+
+    pub(crate) fn load(&self) -> Arc<T> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub(crate) fn store(&self, value: T) {
+        let value = Arc::new(value);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
+
+#[cfg(all(test, ecs_metadata_loom))]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::metadata::ECSMetadata;
+    use loom::thread;
+
+    // both halves written together, a torn snapshot would hold different ones
+    #[derive(Clone)]
+    struct Pair(u64, u64);
+
+    #[test]
+    fn loom_readers_see_whole_snapshots() {
+        loom::model(|| {
+            let cell = Arc::new(SnapshotCell::new(Pair(0, 0)));
+            let writer = {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    cell.store(Pair(1, 1));
+                    cell.store(Pair(2, 2));
+                })
+            };
+
+            let mut seen = Vec::new();
+            for _ in 0..2 {
+                let Pair(first, second) = *cell.load();
+                assert_eq!(first, second, "torn snapshot");
+                seen.push(first);
+            }
+            // snapshots never go back in time for a reader
+            assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]), "{seen:?}");
+
+            writer.join().unwrap();
+            assert_eq!(cell.load().0, 2);
+        });
+    }
+
+    #[test]
+    fn loom_failed_refresh_keeps_snapshot() {
+        loom::model(|| {
+            let cell = Arc::new(SnapshotCell::new(ECSMetadata::from_json(CONTAINER_JSON).unwrap()));
+            // what `SharedECSMetadata::refresh` does: work on a copy, only store on success
+            let refresher = {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    let mut next = ECSMetadata::clone(&cell.load());
+                    if next.refresh_from_json(b"{", None).is_ok() {
+                        cell.store(next);
+                    }
+                })
+            };
+
+            assert_eq!(cell.load().cluster(), Some("production"));
+            refresher.join().unwrap();
+            assert_eq!(cell.load().cluster(), Some("production"));
+            assert!(!cell.load().is_degraded());
+        });
+    }
+
+    #[test]
+    fn loom_concurrent_stores_leave_one_complete_snapshot() {
+        loom::model(|| {
+            let cell = Arc::new(SnapshotCell::new(Pair(0, 0)));
+            let writers: Vec<_> = [1, 2]
+                .into_iter()
+                .map(|value| {
+                    let cell = cell.clone();
+                    thread::spawn(move || cell.store(Pair(value, value)))
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            let Pair(first, second) = *cell.load();
+            assert!(first == second && first != 0);
+        });
+    }
+}
@@ -0,0 +1,851 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use serde_json::value::RawValue;
+use crate::error::ECSMetadataError;
+use crate::container::ECSContainerMetadata;
+use crate::memory::MemorySize;
+use crate::network::{self, NetworkMode};
+use crate::quantity;
+use crate::timestamp::parse_rfc3339;
+use crate::warning::{ParseWarning, ParseWarningKind};
+
+/// Task metadata document, listing every container of the task
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(from = "ECSTaskMetadataV4", rename_all = "PascalCase")]
+pub struct ECSTaskMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    #[serde(rename = "TaskARN", skip_serializing_if = "Option::is_none")]
+    task_arn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revision: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability_zone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    launch_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desired_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    known_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<ECSTaskLimits>,
+    containers: Vec<ECSContainerMetadata>,
+    // keys this crate does not model, see `raw`
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+    // already part of `ECSMetadata::warnings`
+    #[serde(skip)]
+    warnings: Vec<ParseWarning>,
+}
+
+// Document as served; container entries are parsed one by one so that a single malformed
+// entry (typically an infrastructure container) doesn't fail the whole task
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ECSTaskMetadataV4 {
+    cluster: Option<String>,
+    #[serde(rename = "TaskARN")]
+    task_arn: Option<String>,
+    family: Option<String>,
+    revision: Option<String>,
+    availability_zone: Option<String>,
+    launch_type: Option<String>,
+    desired_status: Option<String>,
+    known_status: Option<String>,
+    stop_code: Option<String>,
+    limits: Option<ECSTaskLimits>,
+    // raw rather than `Value`, which rejects strings that aren't valid UTF-8 such as a health
+    // check output with a lone surrogate escape
+    containers: Option<Vec<Box<RawValue>>>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+// Just enough of a container entry to name it in a warning when the full parse fails
+#[derive(Deserialize)]
+struct ContainerName {
+    #[serde(rename = "Name")]
+    name: Option<String>,
+}
+
+impl From<ECSTaskMetadataV4> for ECSTaskMetadata {
+    fn from(raw: ECSTaskMetadataV4) -> Self {
+        let mut warnings = Vec::new();
+        let entries = raw.containers.unwrap_or_else(|| {
+            warnings.push(ParseWarning::new(
+                ParseWarningKind::MissingFieldDefaulted,
+                "task document has no Containers, assuming none",
+            ));
+            Vec::new()
+        });
+
+        let mut containers = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.into_iter().enumerate() {
+            let name = serde_json::from_str::<ContainerName>(entry.get()).ok().and_then(|entry| entry.name);
+            match serde_json::from_str(entry.get()) {
+                Ok(container) => containers.push(container),
+                Err(err) => warnings.push(ParseWarning::new(
+                    ParseWarningKind::ContainerSkipped,
+                    format!(
+                        "skipped container #{index} ({}) of the task document: {err}",
+                        name.as_deref().unwrap_or("unnamed")
+                    ),
+                )),
+            }
+        }
+
+        Self {
+            cluster: raw.cluster,
+            task_arn: raw.task_arn,
+            family: raw.family,
+            revision: raw.revision,
+            availability_zone: raw.availability_zone,
+            launch_type: raw.launch_type,
+            desired_status: raw.desired_status,
+            known_status: raw.known_status,
+            stop_code: raw.stop_code,
+            limits: raw.limits,
+            containers,
+            extra: raw.extra,
+            warnings: warnings.into_iter().map(ParseWarning::emit).collect(),
+        }
+    }
+}
+
+/// CPU units per vCPU, the convention used by the ECS APIs
+pub(crate) const CPU_UNITS_PER_VCPU: f64 = 1024.0;
+
+/// Task-level limits, normalized from whichever convention the document uses.
+///
+/// The agent serves `CPU` as a (possibly fractional) number of vCPUs, while task definitions and
+/// the ECS APIs use strings. Accepted forms:
+/// - a number: vCPUs, e.g. `0.25`
+/// - a string with a `vCPU` suffix: vCPUs, e.g. `"0.25 vCPU"`
+/// - a plain integer string: CPU units, 1024 per vCPU, e.g. `"256"`
+///
+/// `Memory` is MiB, as a number, an integer string or a string with a `GB` suffix (1 GB = 1024 MiB).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSTaskLimits {
+    #[serde(rename = "CPU", default, deserialize_with = "vcpus_from_any", skip_serializing_if = "Option::is_none")]
+    vcpus: Option<f64>,
+    #[serde(rename = "Memory", default, deserialize_with = "mib_from_any", skip_serializing_if = "Option::is_none")]
+    memory_mib: Option<u64>,
+}
+
+impl ECSTaskLimits {
+    /// CPU limit in vCPUs, exact for anything the endpoint serves
+    pub fn vcpus(&self) -> Option<f64> {
+        self.vcpus
+    }
+
+    /// CPU limit in CPU units (1024 per vCPU), rounded to the nearest unit:
+    /// 0.25 vCPU is 256 units, 4 vCPU is 4096 units
+    pub fn cpu_units(&self) -> Option<u32> {
+        self.vcpus.map(vcpus_to_cpu_units)
+    }
+
+    /// Memory limit in MiB, see `memory_limit`
+    pub fn memory_mib(&self) -> Option<u64> {
+        self.memory_mib
+    }
+
+    /// `memory_mib` with its unit, `None` when unlimited (zero)
+    pub fn memory_limit(&self) -> Option<MemorySize> {
+        self.memory_mib.filter(|mib| *mib > 0).map(MemorySize::from_mib)
+    }
+
+    /// `vcpus` in millicores, rounded to the nearest one: 0.25 vCPU is 250
+    pub fn cpu_millicores(&self) -> Option<u64> {
+        self.vcpus.filter(|vcpus| *vcpus > 0.0).map(quantity::millicores)
+    }
+
+    /// `memory_mib` in bytes, `None` when unlimited (zero)
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_limit().map(|memory| memory.as_bytes())
+    }
+
+    /// CPU limit as a Kubernetes quantity: whole vCPUs as a number, otherwise millicores,
+    /// e.g. `2` or `250m`
+    pub fn cpu_as_k8s_quantity(&self) -> Option<String> {
+        self.vcpus.filter(|vcpus| *vcpus > 0.0).map(quantity::cpu_quantity)
+    }
+
+    /// Memory limit as a Kubernetes quantity, always in `Mi`, e.g. `4096Mi`
+    pub fn memory_as_k8s_quantity(&self) -> Option<String> {
+        self.memory_mib.filter(|mib| *mib > 0).map(quantity::memory_quantity)
+    }
+}
+
+/// Limits of the task's normal containers added up, see
+/// `ECSTaskMetadata::aggregate_container_limits`
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSAggregateLimits {
+    /// CPU limits of the counted containers, in vCPUs
+    pub total_cpu_vcpus: f64,
+    /// Memory limits of the counted containers, in MiB
+    pub total_memory_mib: u64,
+    /// Containers with a CPU or a memory limit, a zero one adds nothing to its total
+    pub containers_counted: usize,
+    /// Containers with neither limit: they can use whatever the task has left, so the totals
+    /// understate the demand when this is not zero
+    pub containers_without_limits: usize,
+}
+
+/// Task limits minus the container totals, see `ECSTaskMetadata::headroom`. Negative when the
+/// containers were given more than the task, `None` without a task-level limit.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSLimitsHeadroom {
+    pub cpu_vcpus: Option<f64>,
+    pub memory_mib: Option<i64>,
+}
+
+/// One container of `ECSTaskMetadata::startup_timeline`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ECSContainerStartupRecord {
+    pub container_name: String,
+    pub docker_id: String,
+    /// `CreatedAt` as served
+    pub created_at: Option<String>,
+    /// `StartedAt` as served
+    pub started_at: Option<String>,
+    /// From `CreatedAt` to `StartedAt`, `None` when either is missing or doesn't parse.
+    /// Serialized as fractional seconds.
+    #[serde(rename = "startup_secs", serialize_with = "secs")]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<f64>"))]
+    pub startup: Option<Duration>,
+}
+
+fn secs<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.map(|duration| duration.as_secs_f64()).serialize(serializer)
+}
+
+impl ECSAggregateLimits {
+    /// Headroom left by these totals under the given task limits
+    pub fn headroom(&self, task: &ECSTaskLimits) -> ECSLimitsHeadroom {
+        ECSLimitsHeadroom {
+            cpu_vcpus: task.vcpus().map(|vcpus| vcpus - self.total_cpu_vcpus),
+            memory_mib: task.memory_mib().map(|mib| mib as i64 - self.total_memory_mib as i64),
+        }
+    }
+}
+
+pub(crate) fn vcpus_to_cpu_units(vcpus: f64) -> u32 {
+    (vcpus * CPU_UNITS_PER_VCPU).round() as u32
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(f64),
+    String(String),
+}
+
+fn vcpus_from_any<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let vcpus = match Option::<NumberOrString>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(NumberOrString::Number(vcpus)) => vcpus,
+        Some(NumberOrString::String(cpu)) => parse_cpu(&cpu).map_err(serde::de::Error::custom)?,
+    };
+    if !vcpus.is_finite() || vcpus < 0.0 {
+        return Err(serde::de::Error::custom(format!("invalid task CPU limit {vcpus}")));
+    }
+    Ok(Some(vcpus))
+}
+
+fn parse_cpu(cpu: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid task CPU limit {cpu:?}");
+    let trimmed = cpu.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    match lower.strip_suffix("vcpu") {
+        Some(vcpus) => vcpus.trim().parse().map_err(|_| invalid()),
+        None => trimmed.parse::<u32>().map(|units| f64::from(units) / CPU_UNITS_PER_VCPU).map_err(|_| invalid()),
+    }
+}
+
+fn mib_from_any<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let mib = match Option::<NumberOrString>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(NumberOrString::Number(mib)) => mib,
+        Some(NumberOrString::String(memory)) => parse_memory(&memory).map_err(serde::de::Error::custom)?,
+    };
+    if mib < 0.0 || mib.fract() != 0.0 || mib > u64::MAX as f64 {
+        return Err(serde::de::Error::custom(format!("invalid task memory limit {mib}")));
+    }
+    Ok(Some(mib as u64))
+}
+
+fn parse_memory(memory: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid task memory limit {memory:?}");
+    let trimmed = memory.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    match lower.strip_suffix("gb") {
+        Some(gb) => gb.trim().parse::<f64>().map(|gb| gb * 1024.0).map_err(|_| invalid()),
+        None => trimmed.parse::<u64>().map(|mib| mib as f64).map_err(|_| invalid()),
+    }
+}
+
+/// Infrastructure the task runs on, from the task document's `LaunchType`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LaunchType {
+    Ec2,
+    Fargate,
+    /// ECS Anywhere, an instance registered from outside AWS
+    External,
+    /// A launch type this crate does not know, as served
+    Other(String),
+}
+
+impl LaunchType {
+    pub(crate) fn parse(launch_type: &str) -> Self {
+        match launch_type.to_ascii_uppercase().as_str() {
+            "EC2" => Self::Ec2,
+            "FARGATE" => Self::Fargate,
+            "EXTERNAL" => Self::External,
+            _ => Self::Other(launch_type.to_string()),
+        }
+    }
+
+    /// The launch type as the agent spells it, e.g. `FARGATE`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Ec2 => "EC2",
+            Self::Fargate => "FARGATE",
+            Self::External => "EXTERNAL",
+            Self::Other(launch_type) => launch_type,
+        }
+    }
+}
+
+impl fmt::Display for LaunchType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ECSTaskMetadata {
+    /// Cluster ARN (or short name on older agents) the task runs in
+    pub fn cluster(&self) -> Option<&str> {
+        self.cluster.as_deref()
+    }
+
+    /// Full task ARN
+    pub fn task_arn(&self) -> Option<&str> {
+        self.task_arn.as_deref()
+    }
+
+    /// Availability zone the task landed in
+    pub fn availability_zone(&self) -> Option<&str> {
+        self.availability_zone.as_deref()
+    }
+
+    /// Launch type as served, e.g. `EC2` or `FARGATE`; older agents leave it out
+    pub fn launch_type(&self) -> Option<&str> {
+        self.launch_type.as_deref()
+    }
+
+    /// Typed `launch_type`
+    pub fn launch_type_kind(&self) -> Option<LaunchType> {
+        self.launch_type().map(LaunchType::parse)
+    }
+
+    /// Task definition family, prefer `ECSMetadata::task_definition` over the container labels
+    pub fn family(&self) -> Option<&str> {
+        self.family.as_deref()
+    }
+
+    /// Task definition revision, e.g. `"26"`
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
+    /// Status the scheduler wants for the task, `STOPPED` once it is draining it
+    pub fn desired_status(&self) -> Option<&str> {
+        self.desired_status.as_deref()
+    }
+
+    /// Status the agent last saw the task in, e.g. `RUNNING`
+    pub fn known_status(&self) -> Option<&str> {
+        self.known_status.as_deref()
+    }
+
+    /// Why the task is stopping, e.g. `SpotInterruption` or `UserInitiated`; only served once it is
+    pub fn stop_code(&self) -> Option<&str> {
+        self.stop_code.as_deref()
+    }
+
+    /// Task-level limits, `None` when the document has no `Limits`
+    pub fn limits(&self) -> Option<&ECSTaskLimits> {
+        self.limits.as_ref()
+    }
+
+    /// Anomalies tolerated while parsing the document
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// All containers of the task (this container and its sidecars), in the agent's order
+    pub fn containers(&self) -> &[ECSContainerMetadata] {
+        &self.containers
+    }
+
+    /// Top-level keys of the document that this crate does not model (yet), as served, e.g.
+    /// `PullStartedAt`, see `ECSContainerMetadata::raw`
+    pub fn raw(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    pub(crate) fn containers_mut(&mut self) -> &mut [ECSContainerMetadata] {
+        &mut self.containers
+    }
+
+    /// Containers of the task definition, leaving out the ones the agent adds such as the pause
+    /// container, see `ECSContainerMetadata::is_normal`
+    pub fn normal_containers(&self) -> impl Iterator<Item = &ECSContainerMetadata> {
+        self.containers.iter().filter(|container| container.is_normal())
+    }
+
+    /// Containers of any type the agent last saw `RUNNING`
+    pub fn running_containers(&self) -> impl Iterator<Item = &ECSContainerMetadata> {
+        self.containers.iter().filter(|container| container.known_status() == Some("RUNNING"))
+    }
+
+    /// Whether the container with this Docker ID is the task's only normal container,
+    /// regardless of the status of either
+    pub fn is_sole_application_container(&self, docker_id: &str) -> bool {
+        let mut normal = self.normal_containers();
+        match (normal.next(), normal.next()) {
+            (Some(container), None) => container.docker_id() == docker_id,
+            _ => false,
+        }
+    }
+
+    /// Network mode of the task, shared by all its containers: the mode of the first network
+    /// entry of a normal container, with the fallback of `ECSContainerMetadata::network_mode`
+    /// when none lists a network
+    pub fn network_mode(&self) -> NetworkMode {
+        let first_network = self.normal_containers().find_map(|container| container.networks().first());
+        network::network_mode(first_network, self.normal_containers().any(|container| !container.ports().is_empty()))
+    }
+
+    /// CPU and memory limits of the normal containers added up, e.g. to compare what the
+    /// containers reserve against the task limits. The agent's containers reserve nothing and are
+    /// left out.
+    pub fn aggregate_container_limits(&self) -> ECSAggregateLimits {
+        self.normal_containers().fold(ECSAggregateLimits::default(), |mut aggregate, container| {
+            let limits = container.limits();
+            match (limits.vcpus(), limits.memory_mib()) {
+                (None, None) => aggregate.containers_without_limits += 1,
+                (vcpus, mib) => {
+                    aggregate.containers_counted += 1;
+                    aggregate.total_cpu_vcpus += vcpus.unwrap_or_default();
+                    aggregate.total_memory_mib = aggregate.total_memory_mib.saturating_add(mib.unwrap_or_default());
+                }
+            }
+            aggregate
+        })
+    }
+
+    /// Every container of the task by `StartedAt`, with the time each took from created to
+    /// started, e.g. to find the one that held up a slow start. Containers that haven't started,
+    /// or whose `StartedAt` doesn't parse, come last in document order.
+    pub fn startup_timeline(&self) -> Vec<ECSContainerStartupRecord> {
+        let mut timeline = self
+            .containers
+            .iter()
+            .map(|container| {
+                let created = container.created_at().and_then(parse_rfc3339);
+                let started = container.started_at().and_then(parse_rfc3339);
+                let startup = created.zip(started).and_then(|(created, started)| started.duration_since(created).ok());
+                let record = ECSContainerStartupRecord {
+                    container_name: container.container_name().to_string(),
+                    docker_id: container.docker_id().to_string(),
+                    created_at: container.created_at().map(ToString::to_string),
+                    started_at: container.started_at().map(ToString::to_string),
+                    startup,
+                };
+                (started, record)
+            })
+            .collect::<Vec<_>>();
+        // stable, `None` sorts first otherwise
+        timeline.sort_by_key(|(started, _)| (started.is_none(), *started));
+        timeline.into_iter().map(|(_, record)| record).collect()
+    }
+
+    /// The record of `startup_timeline` with the longest startup, `None` when no container has
+    /// both timestamps
+    pub fn slowest_to_start(&self) -> Option<ECSContainerStartupRecord> {
+        self.startup_timeline().into_iter().filter(|record| record.startup.is_some()).max_by_key(|record| record.startup)
+    }
+
+    /// Task limits minus `aggregate_container_limits`, `None` when the document has no task `Limits`
+    pub fn headroom(&self) -> Option<ECSLimitsHeadroom> {
+        Some(self.aggregate_container_limits().headroom(self.limits.as_ref()?))
+    }
+
+    /// The entry of the container the metadata was fetched for, which the task document doesn't
+    /// mark: the entry with its Docker ID, else the only entry with its name. `None` when
+    /// neither matches, which happens during teardown when the entry is already gone.
+    pub fn self_container(&self, docker_id: &str, name: &str) -> Option<&ECSContainerMetadata> {
+        let by_id = || self.containers.iter().find(|container| !docker_id.is_empty() && container.docker_id() == docker_id);
+        by_id().or_else(|| self.container_by_name(name).ok().filter(|_| !name.is_empty()))
+    }
+
+    /// Every entry but `self_container`, all of them when it isn't found
+    pub fn sibling_containers<'a>(&'a self, docker_id: &str, name: &str) -> impl Iterator<Item = &'a ECSContainerMetadata> {
+        let this = self.self_container(docker_id, name);
+        self.containers.iter().filter(move |container| !this.is_some_and(|this| std::ptr::eq(this, *container)))
+    }
+
+    /// The container with the given Docker ID.
+    /// Fails with `ContainerNotFound` when no entry matches (or the ID is empty) and with
+    /// `AmbiguousContainer` when several entries claim the same ID.
+    pub fn container_by_docker_id(&self, docker_id: &str) -> Result<&ECSContainerMetadata, ECSMetadataError> {
+        if docker_id.is_empty() {
+            return Err(ECSMetadataError::ContainerNotFound(docker_id.to_string()));
+        }
+        self.single_container(docker_id, |container| container.docker_id() == docker_id)
+    }
+
+    /// The container with the given name, same failure modes as `container_by_docker_id`
+    pub fn container_by_name(&self, name: &str) -> Result<&ECSContainerMetadata, ECSMetadataError> {
+        self.single_container(name, |container| container.container_name() == name)
+    }
+
+    fn single_container(
+        &self,
+        key: &str,
+        predicate: impl Fn(&ECSContainerMetadata) -> bool,
+    ) -> Result<&ECSContainerMetadata, ECSMetadataError> {
+        let mut matches = self.containers.iter().filter(|container| predicate(container));
+        match (matches.next(), matches.next()) {
+            (Some(container), None) => Ok(container),
+            (Some(_), Some(_)) => Err(ECSMetadataError::AmbiguousContainer(key.to_string())),
+            (None, _) => Err(ECSMetadataError::ContainerNotFound(key.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+
+    pub(crate) fn task_json(containers: &[String]) -> String {
+        format!(
+            r#"{{"AvailabilityZone": "us-east-1b", "Limits": {{"CPU": 4, "Memory": 8192}}, "Containers": [{}]}}"#,
+            containers.join(",")
+        )
+    }
+
+    pub(crate) fn sidecar_json() -> String {
+        CONTAINER_JSON
+            .replace("2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0", "8d5fc1b5e7bc9b4b3e92a05b1d0b1e6d")
+            .replace(r#""com.amazonaws.ecs.container-name": "streamer""#, r#""com.amazonaws.ecs.container-name": "envoy""#)
+    }
+
+    fn task_limits(json: &str) -> ECSTaskLimits {
+        serde_json::from_str(json).unwrap_or_else(|err| panic!("{json} should parse: {err}"))
+    }
+
+    #[test]
+    fn test_task_limits_conversions() {
+        let quarter = task_limits(r#"{"CPU": 0.25, "Memory": 512}"#);
+        assert_eq!(quarter.vcpus(), Some(0.25));
+        assert_eq!(quarter.cpu_units(), Some(256));
+        assert_eq!(quarter.memory_mib(), Some(512));
+
+        let four = task_limits(r#"{"CPU": 4, "Memory": 8192}"#);
+        assert_eq!(four.vcpus(), Some(4.0));
+        assert_eq!(four.cpu_units(), Some(4096));
+
+        // string forms used by task definitions and the ECS APIs
+        assert_eq!(task_limits(r#"{"CPU": "256"}"#).vcpus(), Some(0.25));
+        assert_eq!(task_limits(r#"{"CPU": "4096"}"#).cpu_units(), Some(4096));
+        assert_eq!(task_limits(r#"{"CPU": "0.25 vCPU"}"#).cpu_units(), Some(256));
+        assert_eq!(task_limits(r#"{"CPU": "2 vcpu", "Memory": "4 GB"}"#).memory_mib(), Some(4096));
+        assert_eq!(task_limits(r#"{"Memory": "2048"}"#), ECSTaskLimits { vcpus: None, memory_mib: Some(2048) });
+
+        // rounded to the nearest unit
+        assert_eq!(ECSTaskLimits { vcpus: Some(0.1), memory_mib: None }.cpu_units(), Some(102));
+        assert_eq!(task_limits("{}"), ECSTaskLimits { vcpus: None, memory_mib: None });
+    }
+
+    #[test]
+    fn test_task_limits_as_k8s_quantities() {
+        let quarter = task_limits(r#"{"CPU": "256", "Memory": "4 GB"}"#);
+        assert_eq!(quarter.cpu_as_k8s_quantity().as_deref(), Some("250m"));
+        assert_eq!(quarter.memory_as_k8s_quantity().as_deref(), Some("4096Mi"));
+        assert_eq!(task_limits(r#"{"CPU": 2}"#).cpu_as_k8s_quantity().as_deref(), Some("2"));
+
+        let unset = task_limits(r#"{"CPU": 0, "Memory": 0}"#);
+        assert_eq!((unset.cpu_as_k8s_quantity(), unset.memory_as_k8s_quantity()), (None, None));
+    }
+
+    #[test]
+    fn test_task_limits_rejects_garbage() {
+        for json in [r#"{"CPU": "lots"}"#, r#"{"CPU": -1}"#, r#"{"Memory": 1.5}"#, r#"{"Memory": "2 TB"}"#] {
+            assert!(serde_json::from_str::<ECSTaskLimits>(json).is_err(), "{json} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_task_wide_fields() {
+        let task: ECSTaskMetadata = serde_json::from_slice(include_bytes!("../testdata/versions/v4_task.json")).unwrap();
+        assert_eq!(task.cluster(), Some("arn:aws:ecs:us-west-2:111122223333:cluster/default"));
+        assert_eq!(task.task_arn(), Some("arn:aws:ecs:us-west-2:111122223333:task/default/cd189a933e5849daa93386466019ab50"));
+        assert_eq!((task.family(), task.revision()), (Some("curltest"), Some("2")));
+        assert_eq!((task.desired_status(), task.known_status()), (Some("RUNNING"), Some("RUNNING")));
+        assert_eq!((task.availability_zone(), task.launch_type()), (Some("us-west-2d"), Some("EC2")));
+        assert_eq!(task.containers().iter().map(ECSContainerMetadata::container_name).collect::<Vec<_>>(), ["curl"]);
+
+        // the serialized form reads back
+        let reparsed: ECSTaskMetadata = serde_json::from_value(serde_json::to_value(&task).unwrap()).unwrap();
+        assert_eq!(reparsed, task);
+    }
+
+    #[test]
+    fn test_container_by_docker_id() {
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[CONTAINER_JSON.to_string(), sidecar_json()]))
+            .expect("task fixture should parse");
+        assert_eq!(task.containers().len(), 2);
+
+        let sidecar = task.container_by_docker_id("8d5fc1b5e7bc9b4b3e92a05b1d0b1e6d").expect("sidecar should be found");
+        assert_eq!(sidecar.container_name(), "envoy");
+        assert_eq!(task.container_by_name("streamer").unwrap().image(), "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production");
+    }
+
+    #[test]
+    fn test_container_lookup_errors() {
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[CONTAINER_JSON.to_string(), CONTAINER_JSON.to_string()]))
+            .expect("task fixture should parse");
+
+        assert!(matches!(task.container_by_docker_id("nope"), Err(ECSMetadataError::ContainerNotFound(id)) if id == "nope"));
+        assert!(matches!(task.container_by_docker_id(""), Err(ECSMetadataError::ContainerNotFound(_))));
+        assert!(matches!(task.container_by_name("streamer"), Err(ECSMetadataError::AmbiguousContainer(name)) if name == "streamer"));
+        assert!(matches!(
+            task.container_by_docker_id("2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0"),
+            Err(ECSMetadataError::AmbiguousContainer(_))
+        ));
+    }
+
+    #[test]
+    fn test_self_container() {
+        let streamer = "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0";
+        let task = serde_json::from_str::<ECSTaskMetadata>(&task_json(&[CONTAINER_JSON.to_string(), sidecar_json()])).unwrap();
+        fn names<'a>(containers: impl Iterator<Item = &'a ECSContainerMetadata>) -> Vec<&'a str> {
+            containers.map(ECSContainerMetadata::container_name).collect()
+        }
+
+        assert_eq!(task.self_container(streamer, "streamer").map(ECSContainerMetadata::container_name), Some("streamer"));
+        assert_eq!(names(task.sibling_containers(streamer, "streamer")), ["envoy"]);
+        // the ID changes across a restart, the name doesn't
+        assert_eq!(task.self_container("restarted", "envoy").map(ECSContainerMetadata::container_name), Some("envoy"));
+        assert_eq!(task.self_container("", "").map(ECSContainerMetadata::container_name), None);
+        assert_eq!(names(task.sibling_containers("gone", "gone")), ["streamer", "envoy"]);
+
+        // seen from the metadata, warning when the entry is gone
+        let metadata = crate::metadata::tests::metadata_from_json(CONTAINER_JSON, Some(&task_json(&[sidecar_json()])));
+        assert!(metadata.self_container().is_none());
+        assert_eq!(names(metadata.sibling_containers()), ["envoy"]);
+        assert_eq!(metadata.warnings()[0].kind, ParseWarningKind::SelfContainerNotFound);
+
+        let metadata = crate::metadata::tests::metadata_from_json(CONTAINER_JSON, Some(&task_json(&[sidecar_json(), CONTAINER_JSON.to_string()])));
+        assert_eq!(metadata.self_container().map(ECSContainerMetadata::docker_id), Some(streamer));
+        assert!(metadata.warnings().is_empty());
+        let no_task = crate::metadata::tests::metadata_from_json(CONTAINER_JSON, None);
+        assert!(no_task.self_container().is_none() && no_task.sibling_containers().next().is_none());
+    }
+
+    #[test]
+    fn test_malformed_container_is_skipped_with_warning() {
+        let pause = r#"{"DockerId": "f00d", "Name": "~internal~ecs~pause", "Labels": {"com.amazonaws.ecs.task-arn": 7}}"#.to_string();
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[CONTAINER_JSON.to_string(), pause]))
+            .expect("a malformed entry must not fail the task");
+
+        assert_eq!(task.containers().len(), 1);
+        assert_eq!(task.warnings().len(), 1);
+        let warning = &task.warnings()[0];
+        assert_eq!(warning.kind, ParseWarningKind::ContainerSkipped);
+        assert!(warning.message.starts_with("skipped container #1 (~internal~ecs~pause) of the task document"));
+    }
+
+    fn with_state(container: &str, container_type: Option<&str>, known_status: &str) -> String {
+        let container_type = container_type.map_or(String::new(), |container_type| format!(r#""Type": "{container_type}", "#));
+        container.replacen('{', &format!(r#"{{{container_type}"KnownStatus": "{known_status}", "DesiredStatus": "RUNNING", "#), 1)
+    }
+
+    #[test]
+    fn test_aggregate_container_limits() {
+        let unlimited = sidecar_json()
+            .replace("8d5fc1b5e7bc9b4b3e92a05b1d0b1e6d", "5c1d0a7e2f")
+            .replace(r#""CPU": 2, "Memory": 4096"#, r#""CPU": 0, "Memory": 0"#);
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[
+            CONTAINER_JSON.to_string(),
+            sidecar_json().replace(r#""Memory": 4096"#, r#""Memory": 0"#),
+            unlimited,
+            pause_json().replace(r#""CPU": 2, "Memory": 4096"#, r#""CPU": 0, "Memory": 0"#),
+        ]))
+        .unwrap();
+
+        let aggregate = task.aggregate_container_limits();
+        assert_eq!(
+            aggregate,
+            ECSAggregateLimits { total_cpu_vcpus: 4.0, total_memory_mib: 4096, containers_counted: 2, containers_without_limits: 1 }
+        );
+        // task limits are 4 vCPU and 8192 MiB
+        assert_eq!(task.headroom(), Some(ECSLimitsHeadroom { cpu_vcpus: Some(0.0), memory_mib: Some(4096) }));
+
+        let oversubscribed = ECSAggregateLimits { total_memory_mib: 9000, ..aggregate };
+        assert_eq!(oversubscribed.headroom(task.limits().unwrap()).memory_mib, Some(-808));
+
+        let no_task_limits: ECSTaskMetadata = serde_json::from_str(&format!(r#"{{"Containers": [{CONTAINER_JSON}]}}"#)).unwrap();
+        assert_eq!(no_task_limits.aggregate_container_limits().containers_counted, 1);
+        assert_eq!(no_task_limits.headroom(), None);
+    }
+
+    fn pause_json() -> String {
+        CONTAINER_JSON
+            .replace("2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0", "731a0d6a3b4210e2448339bc7015aaa79bfe4fa256384f4102db86ef94cbbc4c")
+            .replace(r#""com.amazonaws.ecs.container-name": "streamer""#, r#""com.amazonaws.ecs.container-name": "~internal~ecs~pause""#)
+    }
+
+    #[test]
+    fn test_network_mode() {
+        let awsvpc = r#""Networks": [{"NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.2.106"]}], "DockerId""#;
+        let on_eni = |json: &str| json.replace(r#""DockerId""#, awsvpc);
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[pause_json(), on_eni(CONTAINER_JSON), on_eni(&sidecar_json())])).unwrap();
+        assert_eq!(task.network_mode(), NetworkMode::Awsvpc);
+
+        // the pause container's networks don't count, and without any a mapped port means host mode
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[on_eni(&pause_json()), CONTAINER_JSON.to_string()])).unwrap();
+        assert_eq!(task.network_mode(), NetworkMode::None);
+        let host = CONTAINER_JSON.replace(r#""DockerId""#, r#""Ports": [{"ContainerPort": 80, "HostPort": 80}], "DockerId""#);
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[sidecar_json(), host])).unwrap();
+        assert_eq!(task.network_mode(), NetworkMode::Host);
+        assert_eq!(serde_json::from_str::<ECSTaskMetadata>(&task_json(&[])).unwrap().network_mode(), NetworkMode::None);
+    }
+
+    #[test]
+    fn test_container_roles_and_states() {
+        const APP_ID: &str = "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0";
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[
+            with_state(&pause_json(), Some("CNI_PAUSE"), "RESOURCES_PROVISIONED"),
+            with_state(CONTAINER_JSON, Some("NORMAL"), "RUNNING"),
+            with_state(&sidecar_json(), Some("NORMAL"), "PENDING"),
+        ]))
+        .unwrap();
+
+        let normal: Vec<_> = task.normal_containers().map(ECSContainerMetadata::container_name).collect();
+        assert_eq!(normal, ["streamer", "envoy"]);
+        assert_eq!(task.running_containers().count(), 1);
+        assert_eq!(task.containers()[2].known_status(), Some("PENDING"));
+        assert_eq!(task.containers()[2].desired_status(), Some("RUNNING"));
+        assert!(!task.is_sole_application_container(APP_ID));
+
+        // the sidecar stopped: still a normal container of the task
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[
+            with_state(&pause_json(), Some("CNI_PAUSE"), "RUNNING"),
+            with_state(CONTAINER_JSON, Some("NORMAL"), "RUNNING"),
+            with_state(&sidecar_json(), Some("NORMAL"), "STOPPED"),
+        ]))
+        .unwrap();
+        assert_eq!(task.running_containers().count(), 2);
+        assert!(!task.is_sole_application_container(APP_ID));
+
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[
+            with_state(&pause_json(), Some("CNI_PAUSE"), "RUNNING"),
+            with_state(CONTAINER_JSON, Some("NORMAL"), "RUNNING"),
+        ]))
+        .unwrap();
+        assert!(task.is_sole_application_container(APP_ID));
+        assert!(!task.is_sole_application_container("8d5fc1b5e7bc9b4b3e92a05b1d0b1e6d"));
+
+        // older agents without Type, the pause container is told apart by its name
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[pause_json(), CONTAINER_JSON.to_string()])).unwrap();
+        assert_eq!(task.normal_containers().count(), 1);
+        assert!(task.is_sole_application_container(APP_ID));
+        assert_eq!(task.running_containers().count(), 0);
+    }
+
+    #[test]
+    fn test_missing_containers_warning() {
+        let task: ECSTaskMetadata = serde_json::from_str(r#"{"AvailabilityZone": "us-east-1b"}"#).unwrap();
+        assert!(task.containers().is_empty());
+        assert_eq!(
+            task.warnings(),
+            [ParseWarning::new(ParseWarningKind::MissingFieldDefaulted, "task document has no Containers, assuming none")]
+        );
+
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[CONTAINER_JSON.to_string()])).unwrap();
+        assert!(task.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_startup_timeline() {
+        let timed = |container: String, created: Option<&str>, started: Option<&str>| {
+            let timestamps = [("CreatedAt", created), ("StartedAt", started)]
+                .iter()
+                .filter_map(|(key, value)| value.map(|value| format!(r#""{key}": "{value}","#)))
+                .collect::<String>();
+            container.replacen('{', &format!("{{{timestamps}"), 1)
+        };
+        let app = timed(CONTAINER_JSON.to_string(), Some("2024-10-01T12:00:01Z"), Some("2024-10-01T12:00:09.5Z"));
+        let envoy = timed(sidecar_json(), Some("2024-10-01T12:00:00Z"), Some("2024-10-01T12:00:02Z"));
+        let pending = timed(
+            CONTAINER_JSON.replace("2969e5e2", "5fe1b0c3").replace(r#""streamer""#, r#""migrate""#),
+            Some("2024-10-01T12:00:01Z"),
+            None,
+        );
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[pending, app, envoy])).unwrap();
+
+        let timeline = task.startup_timeline();
+        assert_eq!(timeline.iter().map(|record| record.container_name.as_str()).collect::<Vec<_>>(), ["envoy", "streamer", "migrate"]);
+        assert_eq!(timeline[0].startup, Some(Duration::from_secs(2)));
+        assert_eq!(timeline[1].startup, Some(Duration::from_millis(8500)));
+        assert_eq!(timeline[2].startup, None);
+        assert_eq!(timeline[2].created_at.as_deref(), Some("2024-10-01T12:00:01Z"));
+        assert_eq!(task.slowest_to_start().unwrap().container_name, "streamer");
+
+        let serialized = serde_json::to_value(&timeline[1]).unwrap();
+        assert_eq!(serialized["startup_secs"], 8.5);
+        assert_eq!(serialized["started_at"], "2024-10-01T12:00:09.5Z");
+        assert!(serde_json::to_value(&timeline[2]).unwrap()["startup_secs"].is_null());
+
+        let untimed: ECSTaskMetadata = serde_json::from_str(&task_json(&[CONTAINER_JSON.to_string()])).unwrap();
+        assert_eq!(untimed.slowest_to_start(), None);
+    }
+
+    #[test]
+    fn test_sparse_labels_of_agent_containers() {
+        let task: ECSTaskMetadata = serde_json::from_str(include_str!("../testdata/tasks/cni_pause.json")).unwrap();
+        assert!(task.warnings().is_empty(), "{:?}", task.warnings());
+        assert_eq!(task.containers().len(), 2);
+        let pause = &task.containers()[0];
+        assert_eq!(pause.container_type(), Some("CNI_PAUSE"));
+        assert_eq!((pause.task_arn(), pause.task_definition_family(), pause.cluster()), ("", "", None));
+        assert_eq!(task.normal_containers().map(ECSContainerMetadata::container_name).collect::<Vec<_>>(), ["curl"]);
+
+        let container = include_str!("../testdata/versions/v4_container.json");
+        let task = include_str!("../testdata/tasks/cni_pause.json");
+        let metadata = crate::metadata::ECSMetadata::from_documents(container.into(), Some(task.into()), None).unwrap();
+        assert_eq!(metadata.self_container().unwrap().container_name(), "curl");
+        assert_eq!(metadata.sibling_containers().map(ECSContainerMetadata::container_type).collect::<Vec<_>>(), [Some("CNI_PAUSE")]);
+
+        // even without any label
+        let unlabelled = r#"{"DockerId": "1860542829", "Type": "CNI_PAUSE"}"#.to_string();
+        let task: ECSTaskMetadata = serde_json::from_str(&task_json(&[unlabelled, CONTAINER_JSON.to_string()])).unwrap();
+        assert!(task.warnings().is_empty());
+        assert_eq!(task.normal_containers().count(), 1);
+    }
+}
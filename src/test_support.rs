@@ -0,0 +1,289 @@
+//! Minimal stand-in for the agent endpoint, serving canned responses over plain HTTP/1.1
+//! (or HTTPS with the `rustls` feature)
+#![allow(dead_code)] // shared by the test modules, not every helper is used by all of them
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Root CA of the `start_tls` certificate
+#[cfg(feature = "rustls")]
+pub(crate) const TEST_CA_PEM: &[u8] = include_bytes!("../testdata/tls/ca.pem");
+
+thread_local! {
+    static ENV_READS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    static FAKE_ENV: RefCell<Option<HashMap<String, String>>> = const { RefCell::new(None) };
+}
+
+/// Records the env vars the crate reads on this thread until dropped. `#[tokio::test]` runs
+/// the test on a single thread, the reads of other tests are not seen.
+pub(crate) struct EnvReadGuard;
+
+impl EnvReadGuard {
+    pub(crate) fn start() -> Self {
+        ENV_READS.with(|reads| *reads.borrow_mut() = Some(Vec::new()));
+        Self
+    }
+
+    pub(crate) fn reads(&self) -> Vec<String> {
+        ENV_READS.with(|reads| reads.borrow().clone().unwrap_or_default())
+    }
+}
+
+impl Drop for EnvReadGuard {
+    fn drop(&mut self) {
+        ENV_READS.with(|reads| *reads.borrow_mut() = None);
+    }
+}
+
+pub(crate) fn record_env_read(name: &str) {
+    ENV_READS.with(|reads| {
+        if let Some(reads) = reads.borrow_mut().as_mut() {
+            reads.push(name.to_string());
+        }
+    });
+}
+
+/// Replaces the environment the crate reads on this thread with `vars` until dropped, any other
+/// var being unset. Tests never touch the process-wide environment the parallel ones read.
+pub(crate) struct FakeEnv;
+
+impl FakeEnv {
+    pub(crate) fn new(vars: &[(&str, &str)]) -> Self {
+        let vars = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        FAKE_ENV.with(|env| *env.borrow_mut() = Some(vars));
+        Self
+    }
+}
+
+impl Drop for FakeEnv {
+    fn drop(&mut self) {
+        FAKE_ENV.with(|env| *env.borrow_mut() = None);
+    }
+}
+
+/// The value of `name` under the `FakeEnv` of this thread, `None` without one
+pub(crate) fn fake_env_var(name: &str) -> Option<Result<String, std::env::VarError>> {
+    FAKE_ENV.with(|env| Some(env.borrow().as_ref()?.get(name).cloned().ok_or(std::env::VarError::NotPresent)))
+}
+
+#[derive(Clone)]
+pub(crate) struct MockResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+    pub(crate) delay: Option<Duration>,
+}
+
+impl MockResponse {
+    pub(crate) fn json(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into(),
+            delay: None,
+        }
+    }
+
+    pub(crate) fn status(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self { status, headers: Vec::new(), body: body.into(), delay: None }
+    }
+
+    pub(crate) fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub(crate) fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct MockRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// First value of the header, names compared case-insensitively
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Routes are matched on the exact request path whatever the method, unknown paths get a 404
+#[derive(Clone)]
+pub(crate) struct MockAgent {
+    base: String,
+    routes: Arc<Mutex<HashMap<String, MockResponse>>>,
+    hits: Arc<Mutex<HashMap<String, usize>>>,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    total_hits: Arc<AtomicUsize>,
+}
+
+impl MockAgent {
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind mock agent");
+        let agent = Self::new(format!("http://{}", listener.local_addr().unwrap()));
+
+        let server = agent.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(server.clone().serve(stream));
+            }
+        });
+        agent
+    }
+
+    /// Same over HTTPS, with the certificate of `testdata/tls/localhost.pem` (issued by `TEST_CA_PEM`
+    /// for `localhost` and `127.0.0.1`)
+    #[cfg(feature = "rustls")]
+    pub(crate) async fn start_tls() -> Self {
+        use tokio_rustls::rustls::pki_types::pem::PemObject;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use tokio_rustls::rustls::{crypto, ServerConfig};
+
+        let certificates = CertificateDer::pem_slice_iter(include_bytes!("../testdata/tls/localhost.pem"))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(include_bytes!("../testdata/tls/localhost.key")).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .expect("invalid test certificate");
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind mock agent");
+        let agent = Self::new(format!("https://{}", listener.local_addr().unwrap()));
+        let server = agent.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (server, acceptor) = (server.clone(), acceptor.clone());
+                tokio::spawn(async move {
+                    // clients rejecting the certificate end here
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        server.serve(stream).await;
+                    }
+                });
+            }
+        });
+        agent
+    }
+
+    fn new(base: String) -> Self {
+        Self {
+            base,
+            routes: Arc::default(),
+            hits: Arc::default(),
+            requests: Arc::default(),
+            total_hits: Arc::default(),
+        }
+    }
+
+    async fn serve(self, mut stream: impl AsyncRead + AsyncWrite + Unpin) {
+        let Some(request) = read_request(&mut stream).await else { return };
+        let response = self.respond(&request.path);
+        self.requests.lock().unwrap().push(request);
+        if let Some(delay) = response.delay {
+            tokio::time::sleep(delay).await;
+        }
+        let _ = stream.write_all(&encode(&response)).await;
+        let _ = stream.shutdown().await;
+    }
+
+    pub(crate) fn set(&self, path: &str, response: MockResponse) {
+        self.routes.lock().unwrap().insert(path.to_string(), response);
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    pub(crate) fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+
+    /// Requests received on `path`, oldest first
+    pub(crate) fn requests(&self, path: &str) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().iter().filter(|request| request.path == path).cloned().collect()
+    }
+
+    pub(crate) fn total_hits(&self) -> usize {
+        self.total_hits.load(Ordering::SeqCst)
+    }
+
+    fn respond(&self, path: &str) -> MockResponse {
+        self.total_hits.fetch_add(1, Ordering::SeqCst);
+        *self.hits.lock().unwrap().entry(path.to_string()).or_default() += 1;
+        self.routes
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| MockResponse::status(404, "not found"))
+    }
+}
+
+pub(crate) async fn read_request_path(stream: &mut (impl AsyncRead + Unpin)) -> Option<String> {
+    read_request(stream).await.map(|request| request.path)
+}
+
+pub(crate) async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Option<MockRequest> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let head_len = loop {
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = stream.read(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buf[..read]);
+    };
+    let head = String::from_utf8_lossy(&request[..head_len]).into_owned();
+    let mut request_line = head.lines().next()?.split_whitespace();
+    let (method, path) = (request_line.next()?.to_string(), request_line.next()?.to_string());
+    let headers = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = request[head_len..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut buf).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(content_length);
+    Some(MockRequest { method, path, headers, body })
+}
+
+fn encode(response: &MockResponse) -> Vec<u8> {
+    let mut encoded = format!("HTTP/1.1 {} MOCK\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+    for (name, value) in &response.headers {
+        encoded.push_str(&format!("{name}: {value}\r\n"));
+    }
+    encoded.push_str("\r\n");
+    let mut encoded = encoded.into_bytes();
+    encoded.extend_from_slice(&response.body);
+    encoded
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, SystemTime};
+
+/// RFC 3339 timestamp as the agent serves them, e.g. `2020-10-08T20:09:11.44527186Z`, with any
+/// number of fraction digits (nanoseconds kept) and a `Z` or `±hh:mm` offset
+pub(crate) fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    let bytes = timestamp.as_bytes();
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || !matches!(bytes[10], b'T' | b't' | b' ') || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = timestamp.get(range)?;
+        digits.bytes().all(|byte| byte.is_ascii_digit()).then(|| digits.parse().ok())?
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &timestamp[19..];
+    let mut nanos = 0u32;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        // digits past the nanoseconds are dropped
+        nanos = fraction[..digits.min(9)].bytes().fold(0, |nanos, digit| nanos * 10 + u32::from(digit - b'0'));
+        nanos *= 10u32.pow(9 - digits.min(9) as u32);
+        rest = &fraction[digits..];
+    }
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (hours, minutes) = (rest.get(1..3)?.parse::<i64>().ok()?, rest.get(4..6)?.parse::<i64>().ok()?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'+' { offset } else { -offset }
+        }
+        _ => return None,
+    };
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    let since_epoch = Duration::new(secs.unsigned_abs(), 0);
+    let whole = match secs >= 0 {
+        true => SystemTime::UNIX_EPOCH.checked_add(since_epoch)?,
+        false => SystemTime::UNIX_EPOCH.checked_sub(since_epoch)?,
+    };
+    whole.checked_add(Duration::from_nanos(u64::from(nanos)))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, after Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn since_epoch(timestamp: &str) -> Duration {
+        parse_rfc3339(timestamp).unwrap().duration_since(SystemTime::UNIX_EPOCH).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(since_epoch("1970-01-01T00:00:00Z"), Duration::ZERO);
+        assert_eq!(since_epoch("2020-10-08T20:09:11.44527186Z"), Duration::new(1_602_187_751, 445_271_860));
+        assert_eq!(since_epoch("2024-02-29T12:00:00.123456789123Z"), Duration::new(1_709_208_000, 123_456_789));
+        assert_eq!(since_epoch("2020-10-08T22:09:11+02:00"), since_epoch("2020-10-08T20:09:11Z"));
+        assert_eq!(since_epoch("2020-10-08t18:39:11-01:30"), since_epoch("2020-10-08T20:09:11Z"));
+        // the Go zero time, served for a container that never started
+        assert!(parse_rfc3339("0001-01-01T00:00:00Z").is_some());
+
+        for invalid in ["", "2020-10-08", "2020-10-08T20:09:11", "2020-02-30T20:09:11Z", "2020-10-08T24:00:00Z", "2020-10-08T20:09:11.Z", "2020-10-08T20:09:11+0200", "+020-10-08T20:09:11Z"] {
+            assert_eq!(parse_rfc3339(invalid), None, "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use crate::metadata::ECSMetadata;
+use crate::stats::{ECSContainerStats, ECSNetworkStats};
+use crate::timestamp::parse_rfc3339;
+
+/// Utilization of a container between two of its stats samples, e.g. two `container_stats`
+/// calls a minute apart, see `StatsDelta::new` and `UtilizationTracker`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsDelta {
+    interval: Duration,
+    cpu_percent: Option<f64>,
+    memory_percent: Option<f64>,
+    rx_bytes_per_sec: Option<f64>,
+    tx_bytes_per_sec: Option<f64>,
+    read_bytes_per_sec: Option<f64>,
+    write_bytes_per_sec: Option<f64>,
+}
+
+impl StatsDelta {
+    /// Delta from `previous` to `current`, `None` unless `current` was read after `previous`.
+    /// The rates are `None` when a counter went backwards, i.e. the container restarted in between.
+    pub fn new(previous: &ECSContainerStats, current: &ECSContainerStats) -> Option<Self> {
+        let interval = parse_rfc3339(current.read())?.duration_since(parse_rfc3339(previous.read())?).ok()?;
+        if interval.is_zero() {
+            return None;
+        }
+        let per_sec = |previous: u64, current: u64| Some(current.checked_sub(previous)? as f64 / interval.as_secs_f64());
+        let network = |bytes: fn(&ECSNetworkStats) -> u64| {
+            let total = |stats: &ECSContainerStats| stats.networks().values().map(bytes).sum::<u64>();
+            per_sec(total(previous), total(current))
+        };
+        Some(Self {
+            interval,
+            cpu_percent: cpu_percent(previous, current, interval),
+            memory_percent: memory_percent(current),
+            rx_bytes_per_sec: network(ECSNetworkStats::rx_bytes),
+            tx_bytes_per_sec: network(ECSNetworkStats::tx_bytes),
+            read_bytes_per_sec: per_sec(previous.blkio_stats().read_bytes(), current.blkio_stats().read_bytes()),
+            write_bytes_per_sec: per_sec(previous.blkio_stats().write_bytes(), current.blkio_stats().write_bytes()),
+        })
+    }
+
+    /// Time between the two samples, from their `read` timestamps
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// CPU usage over the interval in percent of one CPU (200 for two busy CPUs), as
+    /// `ECSContainerStats::cpu_usage_percent` computes it for a single sample
+    pub fn cpu_percent(&self) -> Option<f64> {
+        self.cpu_percent
+    }
+
+    /// CPU usage in percent of a limit of `vcpus`, 100 for a container using all of it
+    pub fn cpu_percent_of(&self, vcpus: f64) -> Option<f64> {
+        (vcpus > 0.0).then_some(self.cpu_percent? / vcpus)
+    }
+
+    /// Memory in use at the second sample (without the page cache, see
+    /// `ECSContainerStats::memory_usage_bytes`) in percent of the cgroup limit
+    pub fn memory_percent(&self) -> Option<f64> {
+        self.memory_percent
+    }
+
+    /// Bytes received per second, over all interfaces
+    pub fn rx_bytes_per_sec(&self) -> Option<f64> {
+        self.rx_bytes_per_sec
+    }
+
+    /// Bytes sent per second, over all interfaces
+    pub fn tx_bytes_per_sec(&self) -> Option<f64> {
+        self.tx_bytes_per_sec
+    }
+
+    /// Bytes read from block devices per second
+    pub fn read_bytes_per_sec(&self) -> Option<f64> {
+        self.read_bytes_per_sec
+    }
+
+    /// Bytes written to block devices per second
+    pub fn write_bytes_per_sec(&self) -> Option<f64> {
+        self.write_bytes_per_sec
+    }
+}
+
+// `docker stats` over the two samples' counters, from the wall clock time where the host's
+// `system_cpu_usage` is not served (Windows)
+fn cpu_percent(previous: &ECSContainerStats, current: &ECSContainerStats, interval: Duration) -> Option<f64> {
+    let (cpu, precpu) = (current.cpu_stats(), previous.cpu_stats());
+    let cpu_delta = cpu.total_usage().checked_sub(precpu.total_usage())? as f64;
+    match (cpu.system_cpu_usage(), precpu.system_cpu_usage()) {
+        (Some(system), Some(presystem)) => {
+            let system_delta = system.checked_sub(presystem).filter(|delta| *delta > 0)?;
+            let online_cpus = cpu.online_cpus().filter(|cpus| *cpus > 0).unwrap_or(cpu.percpu_usage().len() as u32);
+            Some(cpu_delta / system_delta as f64 * f64::from(online_cpus) * 100.0)
+        }
+        _ => Some(cpu_delta / interval.as_nanos() as f64 * 100.0),
+    }
+}
+
+fn memory_percent(stats: &ECSContainerStats) -> Option<f64> {
+    let limit = stats.memory_stats().limit().filter(|limit| *limit > 0)?;
+    Some(stats.memory_usage_bytes()? as f64 / limit as f64 * 100.0)
+}
+
+/// Keeps the latest stats sample of each container to compute a `StatsDelta` from the next one,
+/// e.g. fed by `task_stats` on every scrape
+#[derive(Debug, Clone, Default)]
+pub struct UtilizationTracker {
+    // by Docker ID for `update`, by the `task_stats` key for `update_task`
+    previous: BTreeMap<String, ECSContainerStats>,
+}
+
+impl UtilizationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delta from the previous sample of the same container to `stats`, which replaces it.
+    /// `None` for the first sample of a container, and for samples without an `id`.
+    pub fn update(&mut self, stats: ECSContainerStats) -> Option<StatsDelta> {
+        let id = stats.id()?.to_string();
+        self.update_keyed(id, stats)
+    }
+
+    /// `update` with every sample of `task_stats`, under the keys of `stats` which the agent may
+    /// shorten from the full Docker ID. The containers missing from `stats`, e.g. once stopped,
+    /// are forgotten.
+    pub fn update_task(&mut self, stats: BTreeMap<String, ECSContainerStats>) -> BTreeMap<String, StatsDelta> {
+        self.previous.retain(|key, _| stats.contains_key(key));
+        stats.into_iter().filter_map(|(key, stats)| Some((key.clone(), self.update_keyed(key, stats)?))).collect()
+    }
+
+    fn update_keyed(&mut self, key: String, stats: ECSContainerStats) -> Option<StatsDelta> {
+        let delta = self.previous.get(&key).and_then(|previous| StatsDelta::new(previous, &stats));
+        self.previous.insert(key, stats);
+        delta
+    }
+}
+
+impl ECSMetadata {
+    /// CPU usage of `delta` in percent of the CPU limit that applies to this container, see
+    /// `effective_cpu_limit_vcpus`; `None` without a limit
+    pub fn cpu_utilization_percent(&self, delta: &StatsDelta) -> Option<f64> {
+        delta.cpu_percent_of(self.effective_cpu_limit_vcpus()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    const CGROUP_V1_JSON: &str = include_str!("../testdata/stats/cgroup_v1.json");
+
+    // the fixture ten seconds later, `changes` applied to its JSON
+    fn later(changes: &[(&str, u64)]) -> ECSContainerStats {
+        let mut json: serde_json::Value = serde_json::from_str(CGROUP_V1_JSON).unwrap();
+        json["read"] = "2024-06-11T09:41:37.437496163Z".into();
+        for (pointer, value) in changes {
+            *json.pointer_mut(pointer).unwrap() = (*value).into();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_delta() {
+        let previous: ECSContainerStats = serde_json::from_str(CGROUP_V1_JSON).unwrap();
+        // half a CPU busy: 5s of CPU time over 20s of host time on 2 CPUs
+        let current = later(&[
+            ("/cpu_stats/cpu_usage/total_usage", 2_004_301_458 + 5_000_000_000),
+            ("/cpu_stats/system_cpu_usage", 6_380_640_000_000 + 20_000_000_000),
+            ("/networks/eth0/rx_bytes", 5338 + 10_240),
+            ("/blkio_stats/io_service_bytes_recursive/1/value", 4096),
+        ]);
+        let delta = StatsDelta::new(&previous, &current).unwrap();
+        assert_eq!(delta.interval(), Duration::from_secs(10));
+        assert_eq!(delta.cpu_percent(), Some(50.0));
+        assert_eq!(delta.cpu_percent_of(0.25), Some(200.0));
+        assert_eq!((delta.rx_bytes_per_sec(), delta.tx_bytes_per_sec()), (Some(1024.0), Some(0.0)));
+        assert_eq!((delta.read_bytes_per_sec(), delta.write_bytes_per_sec()), (Some(0.0), Some(409.6)));
+        let memory = delta.memory_percent().unwrap();
+        assert!((memory - 27_623_424.0 / 536_870_912.0 * 100.0).abs() < 1e-9, "{memory}");
+
+        // the limit of the container document is 0.25 vCPUs
+        let metadata = metadata_from_json(CONTAINER_JSON, None);
+        assert_eq!(metadata.cpu_utilization_percent(&delta), delta.cpu_percent_of(metadata.effective_cpu_limit_vcpus().unwrap()));
+
+        // out of order, and a restart resetting the counters
+        assert_eq!(StatsDelta::new(&current, &previous), None);
+        let restarted = StatsDelta::new(&previous, &later(&[("/networks/eth0/rx_bytes", 10), ("/cpu_stats/cpu_usage/total_usage", 10)])).unwrap();
+        assert_eq!((restarted.cpu_percent(), restarted.rx_bytes_per_sec()), (None, None));
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = UtilizationTracker::new();
+        let previous: ECSContainerStats = serde_json::from_str(CGROUP_V1_JSON).unwrap();
+        let docker_id = previous.id().unwrap().to_string();
+        assert_eq!(tracker.update(previous.clone()), None);
+        // no CPU percent, the host's system_cpu_usage did not advance
+        let delta = tracker.update(later(&[])).unwrap();
+        assert_eq!((delta.interval(), delta.cpu_percent()), (Duration::from_secs(10), None));
+
+        // the task stats are keyed by the short Docker ID
+        let key = docker_id[..12].to_string();
+        let mut tracker = UtilizationTracker::new();
+        assert!(tracker.update_task(BTreeMap::from([(key.clone(), previous.clone())])).is_empty());
+        let deltas = tracker.update_task(BTreeMap::from([(key.clone(), later(&[]))]));
+        assert_eq!(deltas.keys().collect::<Vec<_>>(), [&key]);
+        // a container gone from the task stats starts over
+        assert!(tracker.update_task(BTreeMap::new()).is_empty());
+        assert!(tracker.update_task(BTreeMap::from([(key, previous)])).is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::de::DeserializeOwned;
+use crate::error::{parse_document, ECSMetadataError};
+
+/// A document as served by one version of the endpoint. The accessors only read the common
+/// model (the v4 types), every version is normalized into it here.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RawDocument<'a> {
+    V4(&'a [u8]),
+    /// `${ECS_CONTAINER_METADATA_URI}`. The agent serves v3 documents from the v2 response
+    /// types, which v4 extended: the v3 keys are a subset of the v4 ones, with the same names
+    /// and shapes, and the keys added since (`ContainerARN`, `LaunchType`, `LogDriver`,
+    /// `LogOptions`, the ENI details of `Networks`) are absent. The v4 model defaults all of
+    /// them, so their accessors return `None` or empty.
+    V3(&'a [u8]),
+}
+
+impl<'a> RawDocument<'a> {
+    pub(crate) fn new(body: &'a [u8], v3: bool) -> Self {
+        if v3 {
+            Self::V3(body)
+        } else {
+            Self::V4(body)
+        }
+    }
+
+    /// Container or task document in the common model
+    pub(crate) fn parse<T: DeserializeOwned>(self) -> Result<T, ECSMetadataError> {
+        match self {
+            Self::V4(body) | Self::V3(body) => parse_document(body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::ECSMetadata;
+
+    const V4_CONTAINER: &str = include_str!("../testdata/versions/v4_container.json");
+    const V4_TASK: &str = include_str!("../testdata/versions/v4_task.json");
+    const V3_CONTAINER: &str = include_str!("../testdata/versions/v3_container.json");
+    const V3_TASK: &str = include_str!("../testdata/versions/v3_task.json");
+
+    type Accessor = fn(&ECSMetadata) -> String;
+
+    // What each version yields, field by field, from the documents of the same task
+    const FIELDS: &[(&str, Accessor, &str, &str)] = &[
+        ("docker_id", |m| m.docker_id().to_string(), "cd189a933e5849daa93386466019ab50-2495160603", "same"),
+        ("container_name", |m| m.container_name().to_string(), "curl", "same"),
+        ("container_arn", |m| format!("{:?}", m.container_arn()), r#"Some("arn:aws:ecs:us-west-2:111122223333:container/05966557-f16c-49cb-9352-24b3a0dcd0e1")"#, "None"),
+        ("image", |m| m.image().to_string(), "111122223333.dkr.ecr.us-west-2.amazonaws.com/curltest:latest", "same"),
+        ("image_tag", |m| format!("{:?}", m.image_tag()), r#"Some("latest")"#, "same"),
+        ("cluster", |m| format!("{:?}", m.cluster()), r#"Some("arn:aws:ecs:us-west-2:111122223333:cluster/default")"#, "same"),
+        ("cluster_name", |m| format!("{:?}", m.cluster_name()), r#"Some("default")"#, "same"),
+        ("task_arn", |m| m.task_arn().to_string(), "arn:aws:ecs:us-west-2:111122223333:task/default/cd189a933e5849daa93386466019ab50", "same"),
+        ("task_id", |m| format!("{:?}", m.task_id()), r#"Some("cd189a933e5849daa93386466019ab50")"#, "same"),
+        ("region", |m| format!("{:?}", m.region()), r#"Some("us-west-2")"#, "same"),
+        ("task_definition_family", |m| m.task_definition_family().to_string(), "curltest", "same"),
+        ("task_definition_revision", |m| m.task_definition_revision().to_string(), "2", "same"),
+        ("limits", |m| format!("{:?}/{:?}", m.limits().cpu, m.limits().mem), "Some(10.0)/Some(128)", "same"),
+        ("network_mode", |m| m.network_mode().to_string(), "awsvpc", "same"),
+        ("ipv4_addresses", |m| format!("{:?}", m.ipv4_addresses().collect::<Vec<_>>()), r#"["10.0.2.106"]"#, "same"),
+        ("mac_address", |m| format!("{:?}", m.primary_network().and_then(|n| n.mac_address())), r#"Some("06:1a:e7:7c:9c:9f")"#, "None"),
+        ("attachment_index", |m| format!("{:?}", m.primary_network().and_then(|n| n.attachment_index())), "Some(0)", "None"),
+        ("domain_name_servers", |m| format!("{:?}", m.primary_network().map(|n| n.domain_name_servers())), r#"Some(["10.0.0.2"])"#, "Some([])"),
+        ("known_status", |m| format!("{:?}", m.container().known_status()), r#"Some("RUNNING")"#, "same"),
+        ("log_driver", |m| format!("{:?}", m.log_driver()), r#"Some("awslogs")"#, "None"),
+        ("availability_zone", |m| format!("{:?}", m.availability_zone()), r#"Some("us-west-2d")"#, "same"),
+        ("launch_type", |m| format!("{:?}", m.task().and_then(|t| t.launch_type())), r#"Some("EC2")"#, "None"),
+        ("task_limits", |m| format!("{:?}", m.task().and_then(|t| t.limits()).map(|l| (l.vcpus(), l.memory_mib()))), "Some((Some(0.25), Some(512)))", "same"),
+        ("task_containers", |m| m.task().map_or(0, |t| t.containers().len()).to_string(), "1", "same"),
+        ("effective_memory_limit_mib", |m| format!("{:?}", m.effective_memory_limit_mib()), "Some(128)", "same"),
+        ("warnings", |m| m.warnings().len().to_string(), "0", "same"),
+    ];
+
+    #[test]
+    fn test_v3_documents_map_onto_the_v4_accessors() {
+        let v4 = ECSMetadata::from_documents(V4_CONTAINER.into(), Some(V4_TASK.into()), None).unwrap();
+        let v3 = ECSMetadata::from_v3_json(V3_CONTAINER, Some(V3_TASK)).unwrap();
+        for &(field, accessor, v4_expected, v3_expected) in FIELDS {
+            let v3_expected = if v3_expected == "same" { v4_expected } else { v3_expected };
+            assert_eq!(accessor(&v4), v4_expected, "v4 {field}");
+            assert_eq!(accessor(&v3), v3_expected, "v3 {field}");
+        }
+    }
+
+    #[test]
+    fn test_v3_refresh_stays_v3() {
+        let mut v3 = ECSMetadata::from_v3_json(V3_CONTAINER, None).unwrap();
+        let restarted = V3_CONTAINER.replace("RUNNING", "STOPPED");
+        v3.refresh_from_json(restarted.as_bytes(), None).unwrap();
+        assert_eq!(v3.container().known_status(), Some("STOPPED"));
+        assert!(v3.raw.v3);
+    }
+}
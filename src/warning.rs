@@ -0,0 +1,59 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Category of a tolerated anomaly, stable enough to match on or to alert from
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ParseWarningKind {
+    /// An enum field held a value this crate does not know, it was mapped to `Other`
+    UnknownEnumValue,
+    /// A field was absent from the document and its default was used
+    MissingFieldDefaulted,
+    /// A container entry of the task document could not be parsed and was left out
+    ContainerSkipped,
+    /// The task document has no entry for the container the metadata was fetched for
+    SelfContainerNotFound,
+    /// The `on_parse_failure` hook panicked, the capture may be incomplete
+    CaptureHookPanicked,
+}
+
+impl ParseWarningKind {
+    /// Serialized name of the kind, e.g. `container_skipped`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnknownEnumValue => "unknown_enum_value",
+            Self::MissingFieldDefaulted => "missing_field_defaulted",
+            Self::ContainerSkipped => "container_skipped",
+            Self::SelfContainerNotFound => "self_container_not_found",
+            Self::CaptureHookPanicked => "capture_hook_panicked",
+        }
+    }
+}
+
+/// Anomaly found while parsing a document that did not fail the parse
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ParseWarning {
+    pub kind: ParseWarningKind,
+    pub message: String,
+}
+
+impl ParseWarning {
+    pub(crate) fn new(kind: ParseWarningKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+
+    /// With the `tracing` feature, `warn!` event of this warning with its `kind` as a field
+    pub(crate) fn emit(self) -> Self {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(kind = self.kind.as_str(), "{}", self.message);
+        self
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+#[cfg(feature = "stream")]
+use crate::diff::MetadataDiff;
+use crate::error::ECSMetadataError;
+use crate::connection::ConnectionPolicy;
+use crate::container::ContainerStatus;
+use crate::metadata::ECSMetadata;
+use crate::refresh::RefreshOutcome;
+use crate::shared::SharedECSMetadata;
+use crate::sync::Arc;
+
+/// Snapshots kept by `ECSMetadataWatcher::start`
+pub const DEFAULT_HISTORY_LEN: usize = 10;
+
+/// Field of a `Transition`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionField {
+    /// `KnownStatus` of this container
+    KnownStatus,
+    /// `DesiredStatus` of this container
+    DesiredStatus,
+    /// Status of the `Health` block of this container
+    HealthStatus,
+    /// `DesiredStatus` of the task document, `STOPPED` once the task drains
+    TaskDesiredStatus,
+    /// IPv4 addresses of this container over all its networks, comma separated
+    Ipv4Addresses,
+}
+
+/// A field of this container that moves over its lifetime, with its values in two snapshots,
+/// `None` when absent. See `ECSMetadata::transitions` and `ECSMetadataWatcher::on_transition`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub field: TransitionField,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Transition {
+    /// Whether a status of the container, or the task's desired status, moved to `STOPPED`: the
+    /// cue to drain before the stop timeout runs out, e.g. `known_status` going from `RUNNING`
+    pub fn is_stop(&self) -> bool {
+        let status = matches!(self.field, TransitionField::KnownStatus | TransitionField::DesiredStatus | TransitionField::TaskDesiredStatus);
+        status && self.new.as_deref().map(ContainerStatus::parse) == Some(ContainerStatus::Stopped)
+    }
+}
+
+impl ECSMetadata {
+    /// Statuses, health and addresses that differ between this snapshot (old) and `other` (new),
+    /// the fields `diff` leaves out. The task's desired status is only compared when both
+    /// snapshots have a task document.
+    pub fn transitions(&self, other: &ECSMetadata) -> Vec<Transition> {
+        let addresses = |metadata: &ECSMetadata| Some(metadata.ipv4_addresses().collect::<Vec<_>>().join(",")).filter(|joined| !joined.is_empty());
+        let health = |metadata: &ECSMetadata| metadata.health().map(|health| health.status().to_string());
+        let owned = |value: Option<&str>| value.map(str::to_string);
+        let mut fields = vec![
+            (TransitionField::KnownStatus, owned(self.known_status()), owned(other.known_status())),
+            (TransitionField::DesiredStatus, owned(self.desired_status()), owned(other.desired_status())),
+            (TransitionField::HealthStatus, health(self), health(other)),
+            (TransitionField::Ipv4Addresses, addresses(self), addresses(other)),
+        ];
+        if let (Some(old), Some(new)) = (self.task(), other.task()) {
+            fields.push((TransitionField::TaskDesiredStatus, owned(old.desired_status()), owned(new.desired_status())));
+        }
+        fields.into_iter().filter(|(_, old, new)| old != new).map(|(field, old, new)| Transition { field, old, new }).collect()
+    }
+}
+
+/// Refreshes a `SharedECSMetadata` in the background every `interval`, keeping the snapshots of
+/// the last polls with the time they were taken. Polling stops on `shutdown` or when the
+/// watcher is dropped.
+#[derive(Debug)]
+pub struct ECSMetadataWatcher {
+    metadata: SharedECSMetadata,
+    history: std::sync::Arc<Mutex<History>>,
+    // latest snapshot, updated when the documents change
+    updates: watch::Receiver<Arc<ECSMetadata>>,
+    poller: JoinHandle<()>,
+}
+
+impl ECSMetadataWatcher {
+    /// Starts polling with the default history length. Needs a tokio runtime without the
+    /// `runtime-agnostic` feature.
+    pub fn start(metadata: SharedECSMetadata, interval: Duration) -> Result<Self, ECSMetadataError> {
+        Self::start_with_history(metadata, interval, DEFAULT_HISTORY_LEN)
+    }
+
+    /// Starts polling, keeping at most `history_len` snapshots, with the runtime `start` needs.
+    /// Fails with `ClosedAfterInit`, as `refresh` does, for a snapshot built with
+    /// `ConnectionPolicy::CloseAfterInit`.
+    pub fn start_with_history(metadata: SharedECSMetadata, interval: Duration, history_len: usize) -> Result<Self, ECSMetadataError> {
+        let policy = metadata.snapshot().client_stats().map(|stats| stats.policy);
+        if policy == Some(ConnectionPolicy::CloseAfterInit) {
+            return Err(ECSMetadataError::ClosedAfterInit);
+        }
+        let mut history = History::new(history_len);
+        history.push(Instant::now(), metadata.snapshot());
+        let history = std::sync::Arc::new(Mutex::new(history));
+        let (sender, updates) = watch::channel(metadata.snapshot());
+        let poller = crate::runtime::spawn(poll(metadata.clone(), interval, history.clone(), sender));
+        Ok(Self { metadata, history, updates, poller })
+    }
+
+    /// Stops polling, which ends the streams of the watcher. The handle keeps the latest snapshot.
+    pub fn shutdown(&self) {
+        self.poller.abort();
+    }
+
+    /// Receiver of the latest snapshot, marked changed when a poll finds changed documents.
+    /// Closed once the watcher is shut down or dropped.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ECSMetadata>> {
+        self.updates.clone()
+    }
+
+    /// Calls `callback` with each `transitions` of a new snapshot against the one before it,
+    /// e.g. to stop taking traffic once the health check fails, on a task that ends with the
+    /// watcher. Conflated like `subscribe`: a transition spans the snapshots replaced before
+    /// the task got to them. A panic of `callback` ends the task, and only it.
+    pub fn on_transition(&self, callback: impl Fn(&Transition) + Send + 'static) -> JoinHandle<()> {
+        let mut updates = self.subscribe();
+        let mut seen = updates.borrow_and_update().clone();
+        crate::runtime::spawn(async move {
+            while updates.changed().await.is_ok() {
+                let snapshot = updates.borrow_and_update().clone();
+                seen.transitions(&snapshot).iter().for_each(&callback);
+                seen = snapshot;
+            }
+        })
+    }
+
+    /// The watched handle, holding the latest snapshot
+    pub fn metadata(&self) -> &SharedECSMetadata {
+        &self.metadata
+    }
+
+    /// Snapshots of the successful polls, oldest first, starting with the one the watcher
+    /// started from until it is pushed out
+    pub fn history(&self) -> Vec<(Instant, Arc<ECSMetadata>)> {
+        self.history.lock().unwrap_or_else(PoisonError::into_inner).entries.iter().cloned().collect()
+    }
+
+    /// Snapshots taken after `since` whose `diff` against the snapshot before them has changes.
+    /// The oldest snapshot kept has nothing to compare against and is never returned.
+    pub fn changes_since(&self, since: Instant) -> Vec<(Instant, Arc<ECSMetadata>)> {
+        let history = self.history();
+        history
+            .windows(2)
+            .filter(|pair| pair[1].0 > since && pair[0].1.diff(&pair[1].1).has_changes())
+            .map(|pair| pair[1].clone())
+            .collect()
+    }
+}
+
+#[cfg(feature = "stream")]
+impl ECSMetadataWatcher {
+    /// The current snapshot, then a new one each time a poll finds changed documents, until
+    /// the watcher is shut down or dropped. Latest value wins: a consumer slower than the
+    /// polls skips the snapshots replaced before it asked for the next item.
+    pub fn stream(&self) -> impl futures_util::Stream<Item = Arc<ECSMetadata>> + Send + 'static {
+        let mut updates = self.subscribe();
+        updates.mark_changed();
+        futures_util::stream::unfold(updates, |mut updates| async move {
+            updates.changed().await.ok()?;
+            let snapshot = updates.borrow_and_update().clone();
+            Some((snapshot, updates))
+        })
+    }
+
+    /// `diff` of each snapshot of `stream` against the one before it, only the ones with
+    /// changes. Conflated like `stream`: the diff spans every snapshot skipped in between.
+    pub fn changes(&self) -> impl futures_util::Stream<Item = MetadataDiff> + Send + 'static {
+        let mut updates = self.subscribe();
+        let seen = updates.borrow_and_update().clone();
+        futures_util::stream::unfold((updates, seen), |(mut updates, mut seen)| async move {
+            loop {
+                updates.changed().await.ok()?;
+                let snapshot = updates.borrow_and_update().clone();
+                let diff = seen.diff(&snapshot);
+                seen = snapshot;
+                if diff.has_changes() {
+                    return Some((diff, (updates, seen)));
+                }
+            }
+        })
+    }
+}
+
+impl Drop for ECSMetadataWatcher {
+    fn drop(&mut self) {
+        self.poller.abort();
+    }
+}
+
+async fn poll(
+    metadata: SharedECSMetadata,
+    interval: Duration,
+    history: std::sync::Arc<Mutex<History>>,
+    updates: watch::Sender<Arc<ECSMetadata>>,
+) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        // a failed poll keeps the previous snapshot, the next tick tries again
+        let outcome = match metadata.refresh().await {
+            Ok(RefreshOutcome::Throttled) | Err(_) => continue,
+            Ok(outcome) => outcome,
+        };
+        let snapshot = metadata.snapshot();
+        history.lock().unwrap_or_else(PoisonError::into_inner).push(Instant::now(), snapshot.clone());
+        if let RefreshOutcome::Changed(_) = outcome {
+            updates.send_replace(snapshot);
+        }
+    }
+}
+
+// Ring buffer of the last `len` snapshots, allocated once
+#[derive(Debug)]
+struct History {
+    len: usize,
+    entries: VecDeque<(Instant, Arc<ECSMetadata>)>,
+}
+
+impl History {
+    fn new(len: usize) -> Self {
+        Self { len, entries: VecDeque::with_capacity(len) }
+    }
+
+    fn push(&mut self, at: Instant, snapshot: Arc<ECSMetadata>) {
+        if self.len == 0 {
+            return;
+        }
+        if self.entries.len() == self.len {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((at, snapshot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+    use crate::test_support::{MockAgent, MockResponse};
+
+    #[test]
+    fn test_history_is_bounded() {
+        let snapshot = Arc::new(metadata_from_json(CONTAINER_JSON, None));
+        let mut history = History::new(3);
+        let allocated = history.entries.capacity();
+        let started = Instant::now();
+        for tick in 0..10_000 {
+            history.push(started + Duration::from_secs(tick), snapshot.clone());
+        }
+        assert_eq!(history.entries.len(), 3);
+        assert_eq!(history.entries.capacity(), allocated);
+        assert_eq!(history.entries.front().unwrap().0, started + Duration::from_secs(9_997));
+        // only the kept entries hold on to the snapshot
+        assert_eq!(Arc::strong_count(&snapshot), 4);
+
+        let mut disabled = History::new(0);
+        disabled.push(started, snapshot);
+        assert!(disabled.entries.is_empty());
+    }
+
+    async fn watch(agent: &MockAgent, history_len: usize) -> ECSMetadataWatcher {
+        let metadata = ECSMetadata::builder()
+            .endpoint(agent.url("/v4/abc"))
+            .min_refresh_interval(Duration::ZERO)
+            .init()
+            .await
+            .unwrap();
+        ECSMetadataWatcher::start_with_history(metadata.into(), Duration::from_millis(5), history_len).unwrap()
+    }
+
+    async fn wait_for_hits(agent: &MockAgent, hits: usize) {
+        while agent.hits("/v4/abc") < hits {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_run_keeps_history_len() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let watcher = watch(&agent, 4).await;
+        wait_for_hits(&agent, 30).await;
+        let history = watcher.history();
+        assert_eq!(history.len(), 4);
+        assert!(history.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[tokio::test]
+    async fn test_changes_since() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let watcher = watch(&agent, DEFAULT_HISTORY_LEN).await;
+        wait_for_hits(&agent, 3).await;
+        let before_deploy = Instant::now();
+        assert!(watcher.changes_since(watcher.history()[0].0).is_empty());
+
+        let deployed = CONTAINER_JSON.replace("latest-production", "v2-production");
+        agent.set("/v4/abc", MockResponse::json(deployed.as_str()));
+        let hits = agent.hits("/v4/abc");
+        wait_for_hits(&agent, hits + 3).await;
+
+        let changes = watcher.changes_since(before_deploy);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].0 > before_deploy);
+        assert!(changes[0].1.image().ends_with("v2-production"));
+        assert!(watcher.metadata().snapshot().image().ends_with("v2-production"));
+        assert!(watcher.changes_since(changes[0].0).is_empty());
+    }
+
+    #[test]
+    fn test_transitions() {
+        let with_state = |known_status: &str, health: &str, address: &str| {
+            let state = format!(
+                r#""KnownStatus": "{known_status}", "Health": {{"status": "{health}"}}, "Networks": [{{"NetworkMode": "awsvpc", "IPv4Addresses": ["{address}"]}}], "DockerId""#
+            );
+            metadata_from_json(&CONTAINER_JSON.replace(r#""DockerId""#, &state), None)
+        };
+        let running = with_state("RUNNING", "HEALTHY", "10.0.2.106");
+        assert!(running.transitions(&running).is_empty());
+        // the stable fields are left to `diff`
+        let plain = metadata_from_json(CONTAINER_JSON, None);
+        assert!(plain.transitions(&metadata_from_json(&CONTAINER_JSON.replace("latest-production", "v2"), None)).is_empty());
+
+        let failing = with_state("RUNNING", "UNHEALTHY", "10.0.3.17");
+        assert_eq!(
+            running.transitions(&failing),
+            vec![
+                Transition { field: TransitionField::HealthStatus, old: Some("HEALTHY".to_string()), new: Some("UNHEALTHY".to_string()) },
+                Transition { field: TransitionField::Ipv4Addresses, old: Some("10.0.2.106".to_string()), new: Some("10.0.3.17".to_string()) },
+            ]
+        );
+
+        let task = |desired_status: &str| crate::task::tests::task_json(&[CONTAINER_JSON.to_string()]).replacen('{', &format!(r#"{{"DesiredStatus": "{desired_status}","#), 1);
+        let draining = metadata_from_json(CONTAINER_JSON, Some(&task("STOPPED")));
+        assert_eq!(
+            metadata_from_json(CONTAINER_JSON, Some(&task("RUNNING"))).transitions(&draining),
+            vec![Transition { field: TransitionField::TaskDesiredStatus, old: Some("RUNNING".to_string()), new: Some("STOPPED".to_string()) }]
+        );
+        assert!(metadata_from_json(CONTAINER_JSON, None).transitions(&draining).is_empty());
+        assert!(failing.transitions(&running).iter().all(|transition| !transition.is_stop()));
+
+        // SIGTERM sent, the container still runs its shutdown
+        let stopping = with_state(r#"RUNNING", "DesiredStatus": "STOPPED"#, "HEALTHY", "10.0.2.106");
+        assert!(stopping.is_stopping() && !stopping.is_stopped() && stopping.is_healthy());
+        assert_eq!(stopping.known_status_kind(), Some(ContainerStatus::Running));
+        let stop = running.transitions(&stopping);
+        assert_eq!((stop.len(), stop[0].field, stop[0].is_stop()), (1, TransitionField::DesiredStatus, true));
+        let stopped = with_state("STOPPED", "UNKNOWN", "10.0.2.106");
+        assert!(stopped.is_stopped() && !stopped.is_stopping() && !stopped.is_healthy());
+        assert!(running.transitions(&stopped).iter().any(Transition::is_stop));
+    }
+
+    #[tokio::test]
+    async fn test_on_transition() {
+        let agent = MockAgent::start().await;
+        let health = |status: &str| CONTAINER_JSON.replace(r#""DockerId""#, &format!(r#""Health": {{"status": "{status}"}}, "DockerId""#));
+        agent.set("/v4/abc", MockResponse::json(health("HEALTHY")));
+        let watcher = watch(&agent, 1).await;
+        let (sender, mut transitions) = tokio::sync::mpsc::unbounded_channel();
+        let listener = watcher.on_transition(move |transition| sender.send(transition.clone()).unwrap());
+
+        agent.set("/v4/abc", MockResponse::json(health("UNHEALTHY")));
+        let transition = transitions.recv().await.unwrap();
+        assert_eq!((transition.field, transition.old.as_deref(), transition.new.as_deref()), (TransitionField::HealthStatus, Some("HEALTHY"), Some("UNHEALTHY")));
+
+        // a change of the stable fields only is no transition
+        agent.set("/v4/abc", MockResponse::json(health("UNHEALTHY").replace("latest-production", "v2")));
+        let hits = agent.hits("/v4/abc");
+        wait_for_hits(&agent, hits + 2).await;
+        assert!(transitions.try_recv().is_err());
+
+        watcher.shutdown();
+        listener.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_polling() {
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let watcher = watch(&agent, 1).await;
+        wait_for_hits(&agent, 3).await;
+        drop(watcher);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let hits = agent.hits("/v4/abc");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(agent.hits("/v4/abc"), hits);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_streams_conflate_and_end() {
+        use crate::diff::FieldChange;
+        use futures_util::StreamExt;
+
+        let agent = MockAgent::start().await;
+        agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON));
+        let watcher = watch(&agent, 1).await;
+        let mut snapshots = Box::pin(watcher.stream());
+        let mut changes = Box::pin(watcher.changes());
+        assert!(snapshots.next().await.unwrap().image().ends_with("latest-production"));
+
+        // a slow consumer only gets the latest of the deploys
+        for tag in ["v2", "v3"] {
+            let hits = agent.hits("/v4/abc");
+            agent.set("/v4/abc", MockResponse::json(CONTAINER_JSON.replace("latest-production", tag).as_str()));
+            wait_for_hits(&agent, hits + 2).await;
+        }
+        assert!(snapshots.next().await.unwrap().image().ends_with(":v3"));
+        let diff = changes.next().await.unwrap();
+        assert_eq!(
+            diff.image,
+            FieldChange::Changed {
+                old: "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:latest-production".to_string(),
+                new: "939885537497.dkr.ecr.us-east-1.amazonaws.com/streamer:v3".to_string(),
+            }
+        );
+
+        watcher.shutdown();
+        assert!(snapshots.next().await.is_none());
+        assert!(changes.next().await.is_none());
+    }
+}
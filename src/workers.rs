@@ -0,0 +1,60 @@
+use std::thread;
+use crate::metadata::ECSMetadata;
+
+impl ECSMetadata {
+    /// Size for a pool of `per_vcpu` workers per vCPU of this container:
+    ///
+    /// 1. vCPUs from `effective_cpu_limit_vcpus` (container limit, else the task limit, the
+    ///    smaller of both when set), else `std::thread::available_parallelism` (1 if unknown),
+    ///    e.g. for a CPU limit of 0 (unlimited) without a task limit or a degraded instance
+    /// 2. times `per_vcpu`, rounded half up: 0.25 vCPU at 2 per vCPU is 1 worker, not 0
+    /// 3. at least `min`, which also applies when `per_vcpu` is zero, negative or NaN
+    pub fn recommended_worker_count(&self, min: usize, per_vcpu: f64) -> usize {
+        let vcpus = self
+            .effective_cpu_limit_vcpus()
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |cpus| cpus.get()) as f64);
+        // saturating, NaN casts to 0
+        let workers = (vcpus * per_vcpu + 0.5).floor() as usize;
+        workers.max(min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::CONTAINER_JSON;
+    use crate::task::tests::task_json;
+
+    fn with_cpu(container_cpu: u16, task_cpu: Option<&str>) -> ECSMetadata {
+        let container = CONTAINER_JSON.replace(r#""CPU": 2"#, &format!(r#""CPU": {container_cpu}"#));
+        let task = task_cpu.map(|cpu| task_json(std::slice::from_ref(&container)).replace(r#""CPU": 4"#, &format!(r#""CPU": {cpu}"#)));
+        ECSMetadata::from_documents(container.into_bytes(), task.map(String::into_bytes), None).unwrap()
+    }
+
+    #[test]
+    fn test_recommended_worker_count() {
+        let available = thread::available_parallelism().unwrap().get();
+        for (container_cpu, task_cpu, per_vcpu, expected) in [
+            (0, Some("0.25"), 1.0, 0),
+            (0, Some("0.25"), 2.0, 1),
+            (0, Some("0.5"), 1.0, 1),
+            (0, Some("\"0.5 vCPU\""), 3.0, 2),
+            (2, None, 1.0, 2),
+            (2, Some("4"), 4.0, 8),
+            // the container can't use more than its task
+            (4, Some("2"), 1.0, 2),
+            (2, None, -1.0, 0),
+            (2, None, f64::NAN, 0),
+        ] {
+            assert_eq!(with_cpu(container_cpu, task_cpu).recommended_worker_count(0, per_vcpu), expected, "{container_cpu} {task_cpu:?} {per_vcpu}");
+        }
+
+        // nothing known: unlimited container without task document, degraded
+        assert_eq!(with_cpu(0, None).recommended_worker_count(1, 1.0), available);
+        assert_eq!(with_cpu(0, Some("0")).recommended_worker_count(1, 2.0), 2 * available);
+        assert_eq!(ECSMetadata::degraded(None).recommended_worker_count(1, 1.0), available);
+
+        assert_eq!(with_cpu(0, Some("0.25")).recommended_worker_count(2, 1.0), 2);
+        assert_eq!(with_cpu(2, None).recommended_worker_count(1, 0.0), 1);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::metadata::ECSMetadata;
+
+/// `origin` of the segments of an ECS container, as set by the X-Ray SDKs' ECS plugin
+pub const XRAY_ECS_ORIGIN: &str = "AWS::ECS::Container";
+
+impl ECSMetadata {
+    /// X-Ray annotations of this container: `origin` first, then the keys of the segment's
+    /// `aws.ecs` object in the order the X-Ray SDKs write them: `container` (the container name),
+    /// `container_id`, `container_arn`, `cluster_arn`, `task_arn`, `task_family` and
+    /// `launch_type` (lower case, e.g. `fargate`). Unknown components are left out, and degraded
+    /// metadata, which may well not be from ECS, has none at all. The launch type needs the task
+    /// document, see `init_with_task`.
+    pub fn trace_annotations(&self) -> Vec<(&'static str, String)> {
+        if self.is_degraded() {
+            return Vec::new();
+        }
+        let mut annotations = vec![("origin", XRAY_ECS_ORIGIN.to_string())];
+        let launch_type = self.task().and_then(|task| task.launch_type()).map(str::to_ascii_lowercase);
+        for (key, value) in [
+            ("container", Some(self.container_name().to_string())),
+            ("container_id", Some(self.docker_id().to_string())),
+            ("container_arn", self.container_arn().map(ToString::to_string)),
+            ("cluster_arn", self.cluster_arn()),
+            ("task_arn", Some(self.task_arn().to_string())),
+            ("task_family", Some(self.task_definition_family().to_string())),
+            ("launch_type", launch_type),
+        ] {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                annotations.push((key, value));
+            }
+        }
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::{metadata_from_json, CONTAINER_JSON};
+
+    #[test]
+    fn test_trace_annotations() {
+        let container = CONTAINER_JSON.replace(
+            r#""DockerId""#,
+            r#""ContainerARN": "arn:aws:ecs:us-east-1:939885537497:container/production/021447970bce4bd58069f1925cd87bc0/5e7a37e0-6d7b-4ab1-8a89-2d6b5bbc9f3d", "DockerId""#,
+        );
+        let task = r#"{"LaunchType": "FARGATE", "Containers": []}"#;
+        assert_eq!(
+            metadata_from_json(&container, Some(task)).trace_annotations(),
+            [
+                ("origin", "AWS::ECS::Container".to_string()),
+                ("container", "streamer".to_string()),
+                ("container_id", "2969e5e20eda3af46d590cd7adfed899862bbcce424ae438a51a2a0b0edfcda0".to_string()),
+                (
+                    "container_arn",
+                    "arn:aws:ecs:us-east-1:939885537497:container/production/021447970bce4bd58069f1925cd87bc0/5e7a37e0-6d7b-4ab1-8a89-2d6b5bbc9f3d"
+                        .to_string()
+                ),
+                ("cluster_arn", "arn:aws:ecs:us-east-1:939885537497:cluster/production".to_string()),
+                ("task_arn", "arn:aws:ecs:us-east-1:939885537497:task/production/021447970bce4bd58069f1925cd87bc0".to_string()),
+                ("task_family", "streamer".to_string()),
+                ("launch_type", "fargate".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_annotations_missing_components() {
+        // no container ARN, no task document and an old format ARN without the cluster
+        let old_format = CONTAINER_JSON.replace(r#""com.amazonaws.ecs.cluster": "production","#, "").replace("task/production/", "task/");
+        let keys: Vec<_> = metadata_from_json(&old_format, None).trace_annotations().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["origin", "container", "container_id", "task_arn", "task_family"]);
+
+        assert!(ECSMetadata::degraded(None).trace_annotations().is_empty());
+    }
+}